@@ -0,0 +1,93 @@
+// Curve, LP-mint and reward math across fee tiers and reserve scales. Off-chain only:
+//
+//     cargo bench --features bench
+//
+// Besides timings, the swap group prints what an A -> B -> A round trip keeps of its input for
+// every curve, fee tier and scale, so a rounding change shows up as a moved number in the output.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use vesting_locked_amm::{bench, compute_swap_quote, CURVE_CONSTANT_PRODUCT, CURVE_STABLE, REWARD_SCALE};
+
+const FEE_TIERS_BPS: [u16; 4] = [1, 5, 30, 100];
+const RESERVE_SCALES: [u64; 3] = [1_000_000, 1_000_000_000_000, 1_000_000_000_000_000_000];
+// The stable invariant's u128 intermediates overflow before 1e18 reserves, so it stops a scale early.
+const STABLE_RESERVE_SCALES: [u64; 2] = [1_000_000, 1_000_000_000_000];
+const CURVES: [(u8, &str, &[u64]); 2] = [
+    (CURVE_CONSTANT_PRODUCT, "constant_product", &RESERVE_SCALES),
+    (CURVE_STABLE, "stable", &STABLE_RESERVE_SCALES),
+];
+const STABLE_AMP: u32 = 100;
+
+fn swap_quotes(c: &mut Criterion) {
+    for (curve_type, name, scales) in CURVES {
+        let mut group = c.benchmark_group(format!("swap/{name}"));
+        for fee_bps in FEE_TIERS_BPS {
+            let pool = bench::pool(curve_type, fee_bps, STABLE_AMP);
+            for &reserve in scales {
+                let amount_in = reserve / 1_000;
+                let out = compute_swap_quote(&pool, amount_in, reserve, reserve, 0).unwrap();
+                let back = compute_swap_quote(&pool, out.amount_out, reserve - out.amount_out, reserve + amount_in, 0)
+                    .unwrap();
+                println!(
+                    "round trip {name} {fee_bps}bps reserve {reserve}: in {amount_in}, back {} ({} ppm kept)",
+                    back.amount_out,
+                    u128::from(back.amount_out) * 1_000_000 / u128::from(amount_in)
+                );
+
+                group.bench_with_input(BenchmarkId::new(format!("{fee_bps}bps"), reserve), &reserve, |b, &r| {
+                    b.iter(|| compute_swap_quote(black_box(&pool), black_box(amount_in), r, r, 0).unwrap())
+                });
+            }
+        }
+        group.finish();
+    }
+}
+
+fn lp_mint(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lp_mint");
+    for (decimals_a, decimals_b) in [(9, 9), (6, 9), (6, 6)] {
+        group.bench_function(format!("initial/{decimals_a}-{decimals_b}"), |b| {
+            b.iter(|| {
+                bench::lp_mint_amount(black_box(1_000_000_000), black_box(2_000_000_000), 0, 0, 0, decimals_a, decimals_b)
+                    .unwrap()
+            })
+        });
+    }
+    for reserve in RESERVE_SCALES {
+        let deposit = reserve / 100;
+        group.bench_with_input(BenchmarkId::new("proportional", reserve), &reserve, |b, &r| {
+            b.iter(|| bench::lp_mint_amount(black_box(deposit), black_box(deposit), r, r, r, 9, 9).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn rewards(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rewards");
+    let amount = 1_000_000_000_000u64;
+    for acc_per_lp in [1u128, 1_000, 1_000_000] {
+        let acc = acc_per_lp * REWARD_SCALE;
+        let debt = u128::from(amount) * acc / REWARD_SCALE / 2;
+        group.bench_with_input(BenchmarkId::new("pending", acc_per_lp), &acc, |b, &acc| {
+            b.iter(|| bench::pending_reward(black_box(amount), acc, debt).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("exited", acc_per_lp), &acc, |b, &acc| {
+            b.iter(|| bench::exited_rewards(black_box(amount), black_box(amount / 3), debt, acc).unwrap())
+        });
+    }
+
+    let mut pool = bench::pool(CURVE_CONSTANT_PRODUCT, 30, STABLE_AMP);
+    pool.reward_emission_per_second = 1_000_000;
+    pool.total_locked_lp = amount;
+    group.bench_function("accrue_emissions", |b| {
+        let mut now = 0i64;
+        b.iter(|| {
+            now += 1;
+            bench::accrue_emissions(black_box(&mut pool), now).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, swap_quotes, lp_mint, rewards);
+criterion_main!(benches);
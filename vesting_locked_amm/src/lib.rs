@@ -1,46 +1,591 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer, MintTo, Burn, SetAuthority};
+use anchor_spl::associated_token::{self, get_associated_token_address, AssociatedToken};
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer, MintTo, Burn, SetAuthority, CloseAccount};
 use spl_token::instruction::AuthorityType as SplAuthorityType;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::instruction::{get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT};
+use anchor_lang::solana_program::sysvar::instructions as sysvar_instructions;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::program::{invoke, invoke_signed};
+use anchor_lang::solana_program::hash::{hash, hashv};
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+use anchor_lang::system_program;
+use pda::*;
 
 declare_id!("sbH7oanT87wMjAxwv6GHsBFiDAHA6GvHF8TWxALRiQS");
 
-const REWARD_SCALE: u128 = 1_000_000_000_000u128; // scaling for acc rewards to keep precision
+// Built with the `no-entrypoint` / `cpi` features, the crate exposes the Anchor-generated `cpi`,
+// `accounts` and `instruction` modules plus the account types and PDA helpers below, so other
+// programs can depend on it directly. Events go through `emit_cpi!`, so anchor-lang must be built
+// with its `event-cpi` feature.
+
+pub const REWARD_SCALE: u128 = 1_000_000_000_000u128; // scaling for acc rewards to keep precision
+pub const ACC_REWARD_PER_LP_CAP: u128 = u128::MAX / u64::MAX as u128; // amount * acc fits u128 for any u64 amount
+pub const ACC_REWARD_REBASE_THRESHOLD: u128 = ACC_REWARD_PER_LP_CAP / 2; // rebase_reward_accumulator allowed from here
+pub const CURVE_CONSTANT_PRODUCT: u8 = 0; // x * y = k
+pub const CURVE_STABLE: u8 = 1; // Curve-style stable swap (2 coins, amplification coefficient)
+pub const MAX_AMP: u32 = 10_000; // highest amplification coefficient a stable pool can use
+pub const MAX_AMP_CHANGE: u32 = 10; // one ramp can move amp by at most 10x either way
+pub const MIN_RAMP_DURATION: i64 = 24 * 3600; // shortest ramp, and minimum spacing between ramp starts
+pub const LP_DECIMALS: u8 = 9; // every LP mint uses 9 decimals so LP amounts compare across pools
+pub const MIN_MULTI_RESERVES: usize = 3; // multi-asset pools hold 3..=MAX_MULTI_RESERVES tokens
+pub const MAX_MULTI_RESERVES: usize = 4;
+pub const MAX_LP_NAME_LEN: usize = 32; // Metaplex token-metadata field limits
+pub const MAX_LP_SYMBOL_LEN: usize = 10;
+pub const MAX_LP_URI_LEN: usize = 200;
+pub const HARD_MAX_PROTOCOL_FEE_BPS: u16 = 1_000; // 10%: no config can allow more
+pub const HARD_MAX_PENALTY_BPS: u16 = 5_000; // 50%: early exit never costs more than half the position
+pub const MAX_RELOCK_BONUS_BPS: u64 = 5_000; // relock streak bonus never adds more than 50% to rewards
+pub const MAX_PENALTY_GRACE_SECONDS: i64 = 7 * 24 * 3600; // longest penalty-free window before vesting_end
+pub const MAX_CLAIM_RECEIPT_PERIOD: i64 = 365 * 24 * 3600; // longest a ClaimReceipt is kept before it may be closed
+pub const MIN_VESTING_SECONDS: i64 = 30 * 24 * 3600;
+pub const MAX_VESTING_SECONDS: i64 = 180 * 24 * 3600;
+pub const SNAPSHOT_EPOCH_SECONDS: i64 = 24 * 3600;
+pub const STATS_DAY_BUCKETS: usize = 7; // daily fee buckets kept on PoolStats (rolling 7d window)
+pub const SNAPSHOT_RING_SIZE: usize = 32; // epochs retained in the rolling EpochSnapshot
+pub const OBSERVATION_RING_SIZE: usize = 32; // price observations retained in PriceObservations
+pub const REWARD_EPOCH_RING_SIZE: usize = 16; // relock bonus epochs retained in RewardEpochs
+pub const REWARD_LEDGER_RING_SIZE: usize = 32; // finalized settlement epochs retained in RewardLedger
+pub const OBSERVATION_MIN_INTERVAL: i64 = 300; // minimum seconds between two observations
+pub const DEPEG_CONFIRM_OBSERVATIONS: u8 = 3; // consecutive off-peg observations that trip the stable breaker
+pub const PRICE_SCALE: u128 = 1_000_000_000_000u128; // fixed-point scale of observed prices
+pub const DEFAULT_ABANDONMENT_PERIOD: i64 = 365 * 24 * 3600; // unclaimed positions become sweepable a year after vesting_end
+pub const TREASURY_ROUTE_INTERVAL: i64 = 24 * 3600; // minimum seconds between route_treasury_to_rewards runs
+pub const REWARD_MODE_LP: u8 = 0; // swap reward fees accrue to acc_reward_per_lp, paid from the LP reward vault
+pub const REWARD_MODE_UNDERLYING: u8 = 1; // swap reward fees stay in token A/B, paid from the underlying reward vaults
+pub const MAX_PORTFOLIO_POSITIONS: usize = 32; // vesting positions tracked per Portfolio
+pub const CLAIM_ALL_ACCOUNTS_PER_POSITION: usize = 8; // remaining accounts per position in claim_all_matured
+pub const MAX_ALLOWED_CALLERS: usize = 4; // programs an AllowedCallers list can hold
+pub const TWAP_SLICE_SLOTS: u64 = 1; // slots between two slices of a TwapOrder
+pub const MAX_TWAP_SLICES: u16 = 1_000;
+pub const MAX_FEE_VOTE_OPTIONS: usize = 4; // fee tiers a FeeVote can offer
+pub const FEE_VOTE_QUORUM_BPS: u64 = 2_000; // share of locked LP that must vote for a fee vote to apply
+pub const MAX_PENALTY_WAIVER_SECONDS: i64 = 14 * 24 * 3600; // longest early-unvest penalty waiver a vote can grant
+pub const PENALTY_WAIVER_QUORUM_BPS: u64 = 3_000; // share of locked LP that must approve a penalty waiver
+pub const COUNCIL_SIZE: usize = 5; // members of an EmergencyCouncil
+pub const COUNCIL_THRESHOLD: u32 = 3; // member approvals that execute a council proposal
+pub const COUNCIL_PROPOSAL_TTL: i64 = 3 * 24 * 60 * 60; // seconds a council proposal stays open
+pub const TRADER_REBATE_DEPOSIT_ID: u64 = u64::MAX; // deposit_id of a trader's rebate VestingStake; unreachable by deposits
+pub const POSITION_HOOK_IX: &str = "global:on_position_event"; // sighash preimage of the instruction a pool hook receives
+pub const CLAIM_VOUCHER_DOMAIN: &[u8] = b"vesting_locked_amm:claim_voucher"; // prefix of the message a claim voucher signs
+
+/// Metaplex token-metadata program, used to name LP mints.
+pub mod mpl_token_metadata {
+    use super::*;
+    declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+}
+
+/// `require!(left >= right, error)` that logs which check failed and both values, so a failed
+/// transaction can be diagnosed from the explorer logs alone. Each side is evaluated once.
+macro_rules! require_gte_ctx {
+    ($left:expr, $right:expr, $error:expr $(,)?) => {{
+        let (left, right) = ($left, $right);
+        if left < right {
+            msg!("{}: {} >= {} failed", stringify!($error), stringify!($left), stringify!($right));
+            return Err(error!($error).with_values((left, right)));
+        }
+    }};
+}
+
+/// `require!(left <= right, error)` counterpart of `require_gte_ctx!`.
+macro_rules! require_lte_ctx {
+    ($left:expr, $right:expr, $error:expr $(,)?) => {{
+        let (left, right) = ($left, $right);
+        if left > right {
+            msg!("{}: {} <= {} failed", stringify!($error), stringify!($left), stringify!($right));
+            return Err(error!($error).with_values((left, right)));
+        }
+    }};
+}
+
+/// `require!(left > right, error)` counterpart of `require_gte_ctx!`.
+macro_rules! require_gt_ctx {
+    ($left:expr, $right:expr, $error:expr $(,)?) => {{
+        let (left, right) = ($left, $right);
+        if left <= right {
+            msg!("{}: {} > {} failed", stringify!($error), stringify!($left), stringify!($right));
+            return Err(error!($error).with_values((left, right)));
+        }
+    }};
+}
+
+/// `require!(left < right, error)` counterpart of `require_gte_ctx!`.
+macro_rules! require_lt_ctx {
+    ($left:expr, $right:expr, $error:expr $(,)?) => {{
+        let (left, right) = ($left, $right);
+        if left >= right {
+            msg!("{}: {} < {} failed", stringify!($error), stringify!($left), stringify!($right));
+            return Err(error!($error).with_values((left, right)));
+        }
+    }};
+}
+
+/// `require!(left == right, error)` counterpart of `require_gte_ctx!`.
+macro_rules! require_eq_ctx {
+    ($left:expr, $right:expr, $error:expr $(,)?) => {{
+        let (left, right) = ($left, $right);
+        if left != right {
+            msg!("{}: {} == {} failed", stringify!($error), stringify!($left), stringify!($right));
+            return Err(error!($error).with_values((left, right)));
+        }
+    }};
+}
+
+/// `err!` that logs a formatted context line (expected vs actual, which input) before failing.
+macro_rules! err_ctx {
+    ($error:expr, $($arg:tt)+) => {{
+        msg!($($arg)+);
+        Err(error!($error))
+    }};
+}
+
+/// Signer seeds of a `Pool`, for CPIs where the pool PDA is the token authority. Use inside the
+/// statement that makes the CPI; the seed bytes are temporaries.
+macro_rules! pool_signer {
+    ($pool:expr) => {
+        &[&[SEED_POOL, $pool.lp_mint.as_ref(), &[$pool.bump]]]
+    };
+}
+
+/// Signer seeds of a `VestingStake`, the authority of its vault. Derived from `depositor`, which
+/// stays fixed when the position changes hands.
+macro_rules! vesting_signer {
+    ($stake:expr) => {
+        &[&[
+            SEED_VESTING,
+            $stake.pool.as_ref(),
+            $stake.depositor.as_ref(),
+            &$stake.deposit_id.to_le_bytes(),
+            &[$stake.bump],
+        ]]
+    };
+}
+
+/// Signer seeds of a TWAP, limit or DCA order, the authority of its escrow.
+macro_rules! order_signer {
+    ($seed:expr, $order:expr) => {
+        &[&[$seed, $order.pool.as_ref(), $order.owner.as_ref(), &$order.order_id.to_le_bytes(), &[$order.bump]]]
+    };
+}
 
 #[program]
 pub mod vesting_locked_amm {
     use super::*;
 
+    /// Add an address to the global sanctions blocklist (protocol admin only).
+    pub fn add_to_blocklist(ctx: Context<AddToBlocklist>, address: Pubkey) -> Result<()> {
+        ctx.accounts.blocklist_entry.address = address;
+        emit_cpi!(BlocklistUpdated {
+            address,
+            blocked: true,
+        });
+        Ok(())
+    }
+
+    /// Remove an address from the global blocklist, refunding rent to the admin.
+    pub fn remove_from_blocklist(ctx: Context<RemoveFromBlocklist>) -> Result<()> {
+        emit_cpi!(BlocklistUpdated {
+            address: ctx.accounts.blocklist_entry.address,
+            blocked: false,
+        });
+        Ok(())
+    }
+
+    /// Approve a mint for use as a pool token (protocol admin only). Mints without a freeze
+    /// authority are accepted without a badge.
+    pub fn approve_token_badge(ctx: Context<ApproveTokenBadge>) -> Result<()> {
+        let mint = ctx.accounts.mint.key();
+        ctx.accounts.token_badge.mint = mint;
+        emit_cpi!(TokenBadgeUpdated { mint, approved: true });
+        Ok(())
+    }
+
+    /// Revoke a mint's badge, refunding rent to the admin. Existing pools are unaffected.
+    pub fn revoke_token_badge(ctx: Context<RevokeTokenBadge>) -> Result<()> {
+        emit_cpi!(TokenBadgeUpdated {
+            mint: ctx.accounts.token_badge.mint,
+            approved: false,
+        });
+        Ok(())
+    }
+
+    /// Create the program-wide config holding the bounds every pool must respect. Only the program's
+    /// upgrade authority can call it (it would otherwise go to whoever lands first); it becomes the
+    /// protocol admin.
+    pub fn initialize_protocol_config(
+        ctx: Context<InitializeProtocolConfig>,
+        max_protocol_fee_bps: u16,
+        max_penalty_bps: u16,
+        max_reward_emission_per_second: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.admin = ctx.accounts.admin.key();
+        config.bump = ctx.bumps.protocol_config;
+        config.set_bounds(max_protocol_fee_bps, max_penalty_bps, max_reward_emission_per_second)?;
+        emit_cpi!(ProtocolConfigUpdated {
+            admin: config.admin,
+            max_protocol_fee_bps,
+            max_penalty_bps,
+            max_reward_emission_per_second,
+        });
+        Ok(())
+    }
+
+    /// Update the protocol-wide bounds (admin only). Bounds can never exceed the hard caps.
+    pub fn update_protocol_config(
+        ctx: Context<UpdateProtocolConfig>,
+        max_protocol_fee_bps: u16,
+        max_penalty_bps: u16,
+        max_reward_emission_per_second: u64,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.protocol_config;
+        config.set_bounds(max_protocol_fee_bps, max_penalty_bps, max_reward_emission_per_second)?;
+        emit_cpi!(ProtocolConfigUpdated {
+            admin: config.admin,
+            max_protocol_fee_bps,
+            max_penalty_bps,
+            max_reward_emission_per_second,
+        });
+        Ok(())
+    }
+
+    /// Set the insurance fund's share of protocol income (admin only, 0 disables): early exits route it
+    /// out of each penalty and `route_treasury_to_insurance` moves it out of the swap-fee treasuries.
+    pub fn set_insurance_bps(ctx: Context<UpdateProtocolConfig>, insurance_bps: u16) -> Result<()> {
+        require_lte_ctx!(insurance_bps, 10_000, AmmError::InvalidFeeSplit);
+        ctx.accounts.protocol_config.insurance_bps = insurance_bps;
+        emit_cpi!(InsuranceBpsSet { insurance_bps });
+        Ok(())
+    }
+
+    /// Require a co-signature from the program's upgrade authority or the protocol admin on
+    /// `emergency_withdraw` for pools whose LP supply exceeds `cosign_lp_threshold` (admin only,
+    /// 0 disables). LP mints all use `LP_DECIMALS`, so one threshold sizes every pool alike.
+    pub fn set_cosign_threshold(ctx: Context<UpdateProtocolConfig>, cosign_lp_threshold: u64) -> Result<()> {
+        ctx.accounts.protocol_config.cosign_lp_threshold = cosign_lp_threshold;
+        emit_cpi!(CosignThresholdSet { cosign_lp_threshold });
+        Ok(())
+    }
+
+    /// Create the protocol insurance fund vault for `mint`, held by the protocol config PDA.
+    /// Permissionless; one vault per mint is shared by every pool.
+    pub fn init_insurance_fund(_ctx: Context<InitInsuranceFund>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Permissionless crank moving `insurance_bps` of each pool-held swap-fee treasury account (token A,
+    /// token B) into the insurance fund, at most once per `TREASURY_ROUTE_INTERVAL`. Treasury
+    /// accounts not held by the pool PDA are left alone. Early-unvest penalties are routed at exit, so
+    /// the LP treasury (penalties and bond POL) is not touched.
+    pub fn route_treasury_to_insurance(ctx: Context<RouteTreasuryToInsurance>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let insurance_bps = ctx.accounts.protocol_config.insurance_bps;
+        require!(insurance_bps > 0, AmmError::InsuranceDisabled);
+        require_gte_ctx!(
+            now,
+            ctx.accounts.pool.last_insurance_route_ts.saturating_add(TREASURY_ROUTE_INTERVAL),
+            AmmError::TreasuryRouteTooSoon
+        );
+
+        let amount_a = ctx.accounts.route_to_insurance(
+            &ctx.accounts.treasury_token_account_a,
+            &ctx.accounts.insurance_fund_a,
+            insurance_bps,
+        )?;
+        let amount_b = ctx.accounts.route_to_insurance(
+            &ctx.accounts.treasury_token_account_b,
+            &ctx.accounts.insurance_fund_b,
+            insurance_bps,
+        )?;
+        ctx.accounts.pool.last_insurance_route_ts = now;
+
+        emit_cpi!(TreasuryRoutedToInsurance {
+            pool: ctx.accounts.pool.key(),
+            amount_a,
+            amount_b,
+        });
+        Ok(())
+    }
+
+    /// Seat the insurance council (protocol admin only, once). Payouts from the insurance fund need
+    /// `COUNCIL_THRESHOLD` of its `COUNCIL_SIZE` members, so no single key can move the fund.
+    pub fn init_insurance_council(ctx: Context<InitInsuranceCouncil>, members: [Pubkey; COUNCIL_SIZE]) -> Result<()> {
+        validate_council_members(&members)?;
+        ctx.accounts.insurance_council.members = members;
+        emit_cpi!(InsuranceCouncilSet { members });
+        Ok(())
+    }
+
+    /// Propose paying `amount` from an insurance fund vault to `recipient` for an incident (insurance
+    /// council member only), counting the proposer's approval. `incident_id` ties the payout to its
+    /// write-up and keys the proposal, so an incident is paid at most once.
+    pub fn propose_insurance_payout(
+        ctx: Context<ProposeInsurancePayout>,
+        incident_id: [u8; 32],
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, AmmError::InsufficientInsuranceFund);
+        let index = ctx.accounts.insurance_council.member_index(&ctx.accounts.member.key())?;
+        let payout = &mut ctx.accounts.insurance_payout;
+        payout.incident_id = incident_id;
+        payout.insurance_fund = ctx.accounts.insurance_fund.key();
+        payout.recipient = ctx.accounts.recipient.key();
+        payout.amount = amount;
+        payout.approvals = 1 << index;
+        emit_cpi!(InsurancePayoutProposed {
+            incident_id,
+            mint: ctx.accounts.insurance_fund.mint,
+            recipient: payout.recipient,
+            amount,
+            proposer: ctx.accounts.member.key(),
+        });
+        Ok(())
+    }
+
+    /// Approve an insurance payout (insurance council member only). The approval that reaches
+    /// `COUNCIL_THRESHOLD` pays it; the paid proposal stays open so the incident can't be paid again.
+    pub fn approve_insurance_payout(ctx: Context<ApproveInsurancePayout>, incident_id: [u8; 32]) -> Result<()> {
+        let index = ctx.accounts.insurance_council.member_index(&ctx.accounts.member.key())?;
+        let payout = &mut ctx.accounts.insurance_payout;
+        require!(!payout.paid, AmmError::InsurancePayoutPaid);
+        require!(payout.approvals & (1 << index) == 0, AmmError::CouncilAlreadyApproved);
+        payout.approvals |= 1 << index;
+        let approvals = payout.approvals.count_ones();
+        emit_cpi!(InsurancePayoutApproved {
+            incident_id,
+            member: ctx.accounts.member.key(),
+            approvals,
+        });
+        if approvals < COUNCIL_THRESHOLD {
+            return Ok(());
+        }
+
+        let amount = payout.amount;
+        require_lte_ctx!(amount, ctx.accounts.insurance_fund.amount, AmmError::InsufficientInsuranceFund);
+        payout.paid = true;
+        let config_bump = ctx.accounts.protocol_config.bump;
+        token::transfer(
+            ctx.accounts.transfer_to_recipient_context().with_signer(&[&[SEED_PROTOCOL_CONFIG, &[config_bump]]]),
+            amount,
+        )?;
+        emit_cpi!(InsurancePaid {
+            mint: ctx.accounts.insurance_fund.mint,
+            recipient: ctx.accounts.recipient.key(),
+            amount,
+            incident_id,
+        });
+        Ok(())
+    }
+
+    /// Create the protocol `GaugeController` (protocol admin only), which splits `emission_per_second`
+    /// of `reward_mint` across gauged pools by weight, paid out of its vault (PDA `["gauge_vault"]`);
+    /// anyone funds the vault with a plain token transfer. The signer becomes its admin; hand it to a
+    /// governance program with `set_gauge_admin`.
+    pub fn init_gauge_controller(
+        ctx: Context<InitGaugeController>,
+        emission_per_second: u64,
+        epoch_duration: i64,
+    ) -> Result<()> {
+        require!(epoch_duration > 0, AmmError::InvalidGaugeEpoch);
+        let controller = &mut ctx.accounts.gauge_controller;
+        controller.admin = ctx.accounts.admin.key();
+        controller.emission_per_second = emission_per_second;
+        controller.epoch_duration = epoch_duration;
+        controller.genesis_ts = Clock::get()?.unix_timestamp;
+        controller.reward_mint = ctx.accounts.reward_mint.key();
+        controller.bump = ctx.bumps.gauge_controller;
+        emit_cpi!(GaugeControllerUpdated {
+            admin: controller.admin,
+            emission_per_second,
+            epoch_duration,
+        });
+        Ok(())
+    }
+
+    /// Change the global emission budget (gauge admin only). Pools pick up their new share on their
+    /// next `sync_pool_gauge`.
+    pub fn set_gauge_emission(ctx: Context<GaugeAdmin>, emission_per_second: u64) -> Result<()> {
+        let controller = &mut ctx.accounts.gauge_controller;
+        controller.emission_per_second = emission_per_second;
+        emit_cpi!(GaugeControllerUpdated {
+            admin: controller.admin,
+            emission_per_second,
+            epoch_duration: controller.epoch_duration,
+        });
+        Ok(())
+    }
+
+    /// Hand the gauge controller to a new admin, e.g. a DAO's governance account (gauge admin only).
+    pub fn set_gauge_admin(ctx: Context<GaugeAdmin>, new_admin: Pubkey) -> Result<()> {
+        let controller = &mut ctx.accounts.gauge_controller;
+        controller.admin = new_admin;
+        emit_cpi!(GaugeControllerUpdated {
+            admin: new_admin,
+            emission_per_second: controller.emission_per_second,
+            epoch_duration: controller.epoch_duration,
+        });
+        Ok(())
+    }
+
+    /// Put a pool under the gauge controller with `weight` (gauge admin plus the pool authority). The
+    /// pool must trade the controller's reward mint, which it pays out as its token A or B reward.
+    /// From then on the gauge replaces the pool's own LP emission, which `set_reward_emission` can no
+    /// longer set.
+    pub fn add_pool_gauge(ctx: Context<AddPoolGauge>, weight: u64) -> Result<()> {
+        let controller = &mut ctx.accounts.gauge_controller;
+        let pool = &ctx.accounts.pool;
+        require!(
+            pool.token_a_mint == controller.reward_mint || pool.token_b_mint == controller.reward_mint,
+            AmmError::GaugeRewardMintNotInPool
+        );
+        controller.total_weight = controller.total_weight.checked_add(weight).ok_or(AmmError::NumericOverflow)?;
+        let gauge = &mut ctx.accounts.pool_gauge;
+        gauge.pool = ctx.accounts.pool.key();
+        gauge.weight = weight;
+        // Paid from the current epoch on, not for the epochs before the pool joined
+        gauge.last_epoch = controller.current_epoch(Clock::get()?.unix_timestamp) - 1;
+        ctx.accounts.pool.gauge_enabled = true;
+        emit_cpi!(PoolGaugeWeightSet {
+            pool: gauge.pool,
+            weight,
+            total_weight: controller.total_weight,
+        });
+        Ok(())
+    }
+
+    /// Reweight a gauged pool (gauge admin only). Takes effect on the pool's next sync.
+    pub fn set_pool_gauge_weight(ctx: Context<SetPoolGaugeWeight>, weight: u64) -> Result<()> {
+        let controller = &mut ctx.accounts.gauge_controller;
+        let gauge = &mut ctx.accounts.pool_gauge;
+        controller.total_weight = controller
+            .total_weight
+            .checked_sub(gauge.weight)
+            .ok_or(AmmError::NumericOverflow)?
+            .checked_add(weight)
+            .ok_or(AmmError::NumericOverflow)?;
+        gauge.weight = weight;
+        emit_cpi!(PoolGaugeWeightSet {
+            pool: gauge.pool,
+            weight,
+            total_weight: controller.total_weight,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank, once per gauge epoch per pool: move the pool's weighted share of one
+    /// epoch's gauge budget from the gauge vault into the pool's underlying reward vault for the
+    /// reward mint, credited to the LP locked now like an underlying-token reward fee. The pool's own
+    /// LP emission is checkpointed and stopped. Each call settles the oldest unsynced epoch, so a
+    /// pool that missed epochs catches up one call per epoch. With nothing locked the epoch's share
+    /// stays in the gauge vault; an underfunded gauge vault fails the sync.
+    pub fn sync_pool_gauge(ctx: Context<SyncPoolGauge>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let controller = &ctx.accounts.gauge_controller;
+        let gauge = &mut ctx.accounts.pool_gauge;
+        require_gt_ctx!(controller.current_epoch(now), gauge.last_epoch, AmmError::GaugeAlreadySynced);
+        let epoch = gauge.last_epoch + 1;
+        gauge.last_epoch = epoch;
+        let budget = u64::try_from(u128::from(controller.pool_share(gauge.weight)) * controller.epoch_duration as u128)
+            .map_err(|_| AmmError::NumericOverflow)?;
+
+        let pool = &mut ctx.accounts.pool;
+        update_pool_rewards(pool, now)?;
+        pool.reward_emission_per_second = 0;
+
+        let reward_in_a = pool.token_a_mint == controller.reward_mint;
+        let acc = if reward_in_a { pool.acc_reward_a_per_lp } else { pool.acc_reward_b_per_lp };
+        let credited = (budget > 0 && pool.total_locked_lp > 0)
+            .then(|| credit_accumulator(acc, u128::from(budget) * REWARD_SCALE / u128::from(pool.total_locked_lp)))
+            .flatten();
+        let mut amount = 0;
+        if let Some(next) = credited {
+            require_gte_ctx!(ctx.accounts.gauge_vault.amount, budget, AmmError::GaugeVaultInsufficient);
+            let bump = controller.bump;
+            token::transfer(
+                ctx.accounts
+                    .transfer_to_reward_vault_context()
+                    .with_signer(&[&[SEED_GAUGE_CONTROLLER, &[bump]]]),
+                budget,
+            )?;
+            let pool = &mut ctx.accounts.pool;
+            if reward_in_a {
+                pool.acc_reward_a_per_lp = next;
+            } else {
+                pool.acc_reward_b_per_lp = next;
+            }
+            amount = budget;
+        }
+
+        let gauge = &ctx.accounts.pool_gauge;
+        emit_cpi!(GaugeSynced {
+            pool: ctx.accounts.pool.key(),
+            epoch,
+            weight: gauge.weight,
+            total_weight: ctx.accounts.gauge_controller.total_weight,
+            amount,
+        });
+        Ok(())
+    }
+
     /// Initialize pool and transfer LP-mint authority to the pool PDA.
-    /// Also configures treasury split and reward fee split.
+    /// Also configures treasury split, reward fee split and the early-unvest penalty (within the
+    /// protocol's `max_penalty_bps`).
+    /// Token mints with a freeze authority need a `TokenBadge` from the protocol admin.
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
         protocol_fee_bps: u16,
         treasury_fee_bps: u16,
         reward_fee_bps: u16,
+        early_unvest_penalty_bps: u16,
+        lp_metadata: Option<LpTokenMetadata>,
     ) -> Result<()> {
-        // basic fee split sanity check
+        validate_fees(&ctx.accounts.protocol_config, protocol_fee_bps, treasury_fee_bps, reward_fee_bps)?;
+        require_lte_ctx!(
+            early_unvest_penalty_bps,
+            ctx.accounts.protocol_config.max_penalty_bps,
+            AmmError::InvalidPenalty
+        );
+        require!(ctx.accounts.lp_mint.decimals == LP_DECIMALS, AmmError::InvalidLpDecimals);
+        let lp_mint_key = ctx.accounts.lp_mint.key();
+        require!(ctx.accounts.token_a_mint.key() != ctx.accounts.token_b_mint.key(), AmmError::IdenticalMints);
         require!(
-            treasury_fee_bps
-                .checked_add(reward_fee_bps)
-                .unwrap_or(u16::MAX)
-                <= protocol_fee_bps,
-            AmmError::InvalidFeeSplit
+            ctx.accounts.token_a_mint.key() != lp_mint_key && ctx.accounts.token_b_mint.key() != lp_mint_key,
+            AmmError::LpMintIsPoolToken
         );
+        check_token_badge(&ctx.accounts.token_a_mint, ctx.accounts.token_a_badge.as_ref())?;
+        check_token_badge(&ctx.accounts.token_b_mint, ctx.accounts.token_b_badge.as_ref())?;
 
         let pool = &mut ctx.accounts.pool;
         pool.authority = *ctx.accounts.authority.key;
         pool.token_a_mint = ctx.accounts.token_a_mint.key();
         pool.token_b_mint = ctx.accounts.token_b_mint.key();
         pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.bump = ctx.bumps.pool;
         pool.reserve_a = ctx.accounts.reserve_a.key();
         pool.reserve_b = ctx.accounts.reserve_b.key();
         pool.protocol_fee_bps = protocol_fee_bps;
+        // Whatever isn't routed to treasury or rewards stays in reserves for LPs
+        pool.lp_fee_bps = protocol_fee_bps - treasury_fee_bps - reward_fee_bps;
         pool.treasury = ctx.accounts.treasury.key();
         pool.treasury_fee_bps = treasury_fee_bps;
         pool.reward_fee_bps = reward_fee_bps;
         pool.vesting_nonce = 0;
         pool.paused = false;
         pool.acc_reward_per_lp = 0u128;
+        pool.permissioned = false;
+        pool.keeper = *ctx.accounts.authority.key;
+        pool.abandonment_period = DEFAULT_ABANDONMENT_PERIOD;
+        pool.curve_type = CURVE_CONSTANT_PRODUCT;
+        pool.treasury_a = ctx.accounts.treasury_token_account_a.key();
+        pool.treasury_b = ctx.accounts.treasury_token_account_b.key();
+        pool.reward_emission_per_second = 0;
+        pool.last_reward_update_ts = Clock::get()?.unix_timestamp;
+        pool.penalize_rewards = true;
+        pool.early_unvest_penalty_bps = early_unvest_penalty_bps;
+
+        let stats = &mut ctx.accounts.pool_stats;
+        stats.pool = pool.key();
+        pool.allow_claims_while_paused = true;
 
         // Transfer LP mint authority to the pool PDA.
         // The current authority (ctx.accounts.authority) must be the current mint authority and sign this tx.
@@ -55,63 +600,83 @@ pub mod vesting_locked_amm {
             Some(pool_key),
         )?;
 
-        emit!(PoolInitialized {
+        emit_cpi!(PoolInitialized {
             pool: pool.key(),
             authority: pool.authority,
             treasury: pool.treasury,
+            treasury_a: pool.treasury_a,
+            treasury_b: pool.treasury_b,
         });
 
+        // Optional wallet-facing name/symbol/URI for the LP mint, created with the pool PDA as
+        // mint and update authority
+        if let Some(metadata) = lp_metadata {
+            ctx.accounts.create_lp_metadata(&metadata, ctx.bumps.pool)?;
+        }
+
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.pools = stats.pools.saturating_add(1);
+            stats.updated_ts = Clock::get()?.unix_timestamp;
+        }
+
         Ok(())
     }
 
     /// Deposit tokens A+B and mint LP tokens, but lock them into a vesting PDA until `vesting_seconds` passes.
     /// This instruction program-creates the vesting token account (owned by the vesting PDA) to simplify client UX.
+    /// `tag` is an opaque client label (campaign id, strategy id, ...) stored on the position and echoed in events.
+    /// `deposit_id` seeds the position PDAs and must be at least the caller's `UserVestingIndex.next_nonce`, so a
+    /// client sending several deposits at once gives each its own id instead of racing on a shared counter.
     pub fn deposit_and_vest(
         ctx: Context<DepositAndVest>,
         amount_a: u64,
         amount_b: u64,
         vesting_seconds: i64,
+        tag: [u8; 32],
+        deposit_id: u64,
     ) -> Result<()> {
         // Read immutable bits first (avoid mutable borrow while building CPI contexts)
-        require!(!ctx.accounts.pool.paused, AmmError::Paused);
+        require!(!ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp), AmmError::Paused);
+        require_gte_ctx!(deposit_id, ctx.accounts.user_vesting_index.next_nonce, AmmError::DepositIdTooLow);
 
-        // Enforce vesting window
-        let min_vesting = 30 * 24 * 3600;
-        let max_vesting = 180 * 24 * 3600;
-        require!(
-            vesting_seconds >= min_vesting && vesting_seconds <= max_vesting,
-            AmmError::InvalidVestingPeriod
-        );
+        // Both sides are required; single-sided liquidity is not supported
+        require!(amount_a > 0 || amount_b > 0, AmmError::ZeroDepositAmount);
+        require!(amount_a > 0 && amount_b > 0, AmmError::OneSidedDeposit);
+        check_deposit(
+            &ctx.accounts.pool,
+            (amount_a, amount_b),
+            (&ctx.accounts.reserve_a, &ctx.accounts.reserve_b),
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.allowlist_entry.is_some(),
+            Clock::get()?.unix_timestamp,
+        )?;
 
-        // Defensive checks: require reserve token accounts to be rent-exempt and owned by token program
-        let rent = Rent::get()?;
-        require!(
-            rent.is_exempt(
-                ctx.accounts.reserve_a.to_account_info().lamports(),
-                ctx.accounts.reserve_a.to_account_info().data_len()
-            ),
-            AmmError::NotRentExempt
-        );
-        require!(
-            rent.is_exempt(
-                ctx.accounts.reserve_b.to_account_info().lamports(),
-                ctx.accounts.reserve_b.to_account_info().data_len()
-            ),
-            AmmError::NotRentExempt
-        );
-        require!(
-            ctx.accounts.reserve_a.to_account_info().owner == &token::ID,
-            AmmError::InvalidTokenAccountOwner
-        );
-        require!(
-            ctx.accounts.reserve_b.to_account_info().owner == &token::ID,
-            AmmError::InvalidTokenAccountOwner
-        );
+        // Bring acc_reward_per_lp up to date before the new position snapshots it
+        let lp_supply_before = ctx.accounts.lp_mint.supply;
+        if lp_supply_before == 0 {
+            check_initial_ratio(&ctx.accounts.pool, amount_a, amount_b)?;
+        }
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+
+        // Enforce vesting window
+        require_gte_ctx!(vesting_seconds, MIN_VESTING_SECONDS, AmmError::InvalidVestingPeriod);
+        require_lte_ctx!(vesting_seconds, MAX_VESTING_SECONDS, AmmError::InvalidVestingPeriod);
 
         // Capture some values we will need after CPIs
         let pool_key = ctx.accounts.pool.key();
-        // vesting_stake PDA was created with seeds involving current pool.vesting_nonce; Anchor validated that already.
-        let current_vesting_nonce = ctx.accounts.pool.vesting_nonce;
+
+        #[cfg(feature = "accounting-checks")]
+        let snapshot = accounting::Snapshot::take(
+            vec![ctx.accounts.reserve_a.to_account_info()],
+            vec![ctx.accounts.reserve_b.to_account_info()],
+            Some(ctx.accounts.lp_mint.to_account_info()),
+        );
 
         // Transfer token A and B from user to pool reserves (CPIs)
         token::transfer(ctx.accounts.transfer_a_context(), amount_a)?;
@@ -124,10 +689,15 @@ pub mod vesting_locked_amm {
             ctx.accounts.reserve_a.amount,
             ctx.accounts.reserve_b.amount,
             ctx.accounts.lp_mint.supply,
+            ctx.accounts.token_a_mint.decimals,
+            ctx.accounts.token_b_mint.decimals,
         )?;
+        require_gte_ctx!(lp_minted, ctx.accounts.pool.min_lp_minted, AmmError::DepositTooSmall);
 
         // Mint LP tokens to the vesting token account (owned by vesting PDA)
-        token::mint_to(ctx.accounts.mint_to_vesting_context(), lp_minted)?;
+        token::mint_to(ctx.accounts.mint_to_vesting_context().with_signer(pool_signer!(ctx.accounts.pool)), lp_minted)?;
+        #[cfg(feature = "accounting-checks")]
+        snapshot.assert_moved("deposit_and_vest", amount_a.into(), amount_b.into(), lp_minted.into());
 
         // Now mutate pool & vesting accounts (safe: no active CPI borrows)
         let pool = &mut ctx.accounts.pool;
@@ -135,753 +705,14248 @@ pub mod vesting_locked_amm {
 
         vesting.pool = pool_key;
         vesting.user = ctx.accounts.user.key();
+        vesting.depositor = ctx.accounts.user.key();
+        vesting.bump = ctx.bumps.vesting_stake;
+        vesting.rent_payer = ctx.accounts.rent_payer.key();
+        vesting.tag = tag;
+        vesting.reward_epoch = pool.reward_epoch;
         vesting.amount = lp_minted;
         let clock = Clock::get()?;
         vesting.vesting_end = clock.unix_timestamp + vesting_seconds;
+        vesting.held_since = clock.unix_timestamp;
         vesting.claimed = false;
-        vesting.deposit_id = current_vesting_nonce;
+        vesting.deposit_id = deposit_id;
 
         // Reward accounting snapshot
         vesting.reward_debt = (u128::from(lp_minted) * pool.acc_reward_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_a = (u128::from(lp_minted) * pool.acc_reward_a_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_b = (u128::from(lp_minted) * pool.acc_reward_b_per_lp) / REWARD_SCALE;
 
-        pool.vesting_nonce = pool
-            .vesting_nonce
-            .checked_add(1)
+        ctx.accounts.user_vesting_index.next_nonce = deposit_id.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+        pool.total_locked_lp = pool
+            .total_locked_lp
+            .checked_add(lp_minted)
+            .ok_or(AmmError::NumericOverflow)?;
+
+        if let Some(portfolio) = ctx.accounts.portfolio.as_mut() {
+            portfolio.add_position(vesting.key())?;
+        }
+
+        emit_cpi!(Deposited {
+            pool: pool_key,
+            user: vesting.user,
+            amount: vesting.amount,
+            vesting_end: vesting.vesting_end,
+            tag,
+            deposit_id,
+        });
+
+        invoke_position_hook(
+            &ctx.accounts.pool,
+            ctx.accounts.pool_hook.as_deref().map(|h| &**h),
+            ctx.remaining_accounts,
+            PositionHookPayload {
+                kind: PositionHookKind::Deposit,
+                pool: pool_key,
+                user: ctx.accounts.user.key(),
+                deposit_id,
+                lp_amount: lp_minted,
+                vesting_end: clock.unix_timestamp + vesting_seconds,
+                timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.record_deposit(clock.unix_timestamp);
+        }
+        if let Some(view) = ctx.accounts.pool_view.as_mut() {
+            reload_pool_view(
+                view,
+                &ctx.accounts.pool,
+                &mut ctx.accounts.reserve_a,
+                &mut ctx.accounts.reserve_b,
+                &mut ctx.accounts.lp_mint,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Single-sided entry: sell half of `amount_a` for B through the pool (under `swap`'s rules, caller
+    /// restriction included), then deposit the rest of A with the B received and lock the LP like
+    /// `deposit_and_vest`. `min_lp_out` bounds the whole operation; there is no separate bound on the swap leg.
+    pub fn swap_and_vest(
+        ctx: Context<SwapAndVest>,
+        amount_a: u64,
+        min_lp_out: u64,
+        vesting_seconds: i64,
+        tag: [u8; 32],
+        deposit_id: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(!ctx.accounts.pool.is_paused(clock.unix_timestamp), AmmError::Paused);
+        require_gte_ctx!(deposit_id, ctx.accounts.user_vesting_index.next_nonce, AmmError::DepositIdTooLow);
+        require!(!ctx.accounts.pool.emergency_drip_active, AmmError::EmergencyDripActive);
+        // Needs a price to swap against; the first deposit must bring both tokens
+        require!(ctx.accounts.lp_mint.supply > 0, AmmError::InsufficientLiquidity);
+        require_gte_ctx!(vesting_seconds, MIN_VESTING_SECONDS, AmmError::InvalidVestingPeriod);
+        require_lte_ctx!(vesting_seconds, MAX_VESTING_SECONDS, AmmError::InvalidVestingPeriod);
+        if ctx.accounts.pool.permissioned {
+            require!(ctx.accounts.allowlist_entry.is_some(), AmmError::NotAllowlisted);
+        }
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+        check_swap_caller(
+            &ctx.accounts.pool,
+            ctx.accounts.allowed_callers.as_deref().map(|c| &**c),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+
+        // Swap leg: half of the A goes through the pool, the B lands in the user's account
+        let swapped_a = amount_a / 2;
+        let deposit_a = amount_a - swapped_a;
+        let is_a_to_b = true;
+        let fill =
+            execute_order_swap(ctx.accounts.order_swap(&[]), swapped_a, 0, is_a_to_b, AmmError::SlippageExceeded)?;
+        let amount_b = fill.quote.amount_out;
+        if fill.depeg_tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+        emit_cpi!(FeesAccrued {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            lp_fee: fill.quote.lp_fee,
+            treasury_fee: fill.quote.treasury_fee,
+            reward_fee: fill.quote.reward_fee,
+            acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            acc_reward_a_per_lp_after: ctx.accounts.pool.acc_reward_a_per_lp,
+            acc_reward_b_per_lp_after: ctx.accounts.pool.acc_reward_b_per_lp,
+        });
+        if fill.quote.lp_fee > 0 {
+            let stats = &ctx.accounts.pool_stats;
+            emit_cpi!(LiquidityReinvested {
+                pool: ctx.accounts.pool.key(),
+                fee_in_a: fill.fee_in_a,
+                amount: fill.quote.lp_fee,
+                total_reinvested_a: stats.lp_fees_a,
+                total_reinvested_b: stats.lp_fees_b,
+                reserve_a: ctx.accounts.reserve_a.amount,
+                reserve_b: ctx.accounts.reserve_b.amount,
+                lp_supply: fill.lp_supply,
+            });
+        }
+        emit_cpi!(Swapped {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_in: swapped_a,
+            amount_out: amount_b,
+            is_a_to_b,
+        });
+
+        // Deposit leg, against the post-swap reserves
+        require!(!ctx.accounts.pool.depegged, AmmError::PoolDepegged);
+        require!(deposit_a > 0 && amount_b > 0, AmmError::OneSidedDeposit);
+        require_gte_ctx!(deposit_a, ctx.accounts.pool.min_deposit_a, AmmError::DepositTooSmall);
+        require_gte_ctx!(amount_b, ctx.accounts.pool.min_deposit_b, AmmError::DepositTooSmall);
+        token::transfer(ctx.accounts.transfer_a_context(), deposit_a)?;
+        token::transfer(ctx.accounts.transfer_b_context(), amount_b)?;
+        ctx.accounts.reserve_a.reload()?;
+        ctx.accounts.reserve_b.reload()?;
+        let lp_minted = calculate_lp_mint_amount(
+            deposit_a,
+            amount_b,
+            ctx.accounts.reserve_a.amount,
+            ctx.accounts.reserve_b.amount,
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.token_a_mint.decimals,
+            ctx.accounts.token_b_mint.decimals,
+        )?;
+        require_gte_ctx!(lp_minted, ctx.accounts.pool.min_lp_minted, AmmError::DepositTooSmall);
+        require_gte_ctx!(lp_minted, min_lp_out, AmmError::SlippageExceeded);
+        token::mint_to(ctx.accounts.mint_to_vesting_context().with_signer(pool_signer!(ctx.accounts.pool)), lp_minted)?;
+
+        let pool_key = ctx.accounts.pool.key();
+        let pool = &mut ctx.accounts.pool;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.pool = pool_key;
+        vesting.user = ctx.accounts.user.key();
+        vesting.depositor = ctx.accounts.user.key();
+        vesting.bump = ctx.bumps.vesting_stake;
+        vesting.rent_payer = ctx.accounts.rent_payer.key();
+        vesting.tag = tag;
+        vesting.reward_epoch = pool.reward_epoch;
+        vesting.amount = lp_minted;
+        vesting.vesting_end = clock.unix_timestamp + vesting_seconds;
+        vesting.held_since = clock.unix_timestamp;
+        vesting.claimed = false;
+        vesting.deposit_id = deposit_id;
+        vesting.reward_debt = (u128::from(lp_minted) * pool.acc_reward_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_a = (u128::from(lp_minted) * pool.acc_reward_a_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_b = (u128::from(lp_minted) * pool.acc_reward_b_per_lp) / REWARD_SCALE;
+
+        ctx.accounts.user_vesting_index.next_nonce = deposit_id.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+        pool.total_locked_lp = pool
+            .total_locked_lp
+            .checked_add(lp_minted)
             .ok_or(AmmError::NumericOverflow)?;
 
-        emit!(Deposited {
+        if let Some(portfolio) = ctx.accounts.portfolio.as_mut() {
+            portfolio.add_position(vesting.key())?;
+        }
+
+        emit_cpi!(Deposited {
             pool: pool_key,
             user: vesting.user,
             amount: vesting.amount,
             vesting_end: vesting.vesting_end,
+            tag,
+            deposit_id,
         });
 
+        invoke_position_hook(
+            &ctx.accounts.pool,
+            ctx.accounts.pool_hook.as_deref().map(|h| &**h),
+            ctx.remaining_accounts,
+            PositionHookPayload {
+                kind: PositionHookKind::Deposit,
+                pool: pool_key,
+                user: ctx.accounts.user.key(),
+                deposit_id,
+                lp_amount: lp_minted,
+                vesting_end: clock.unix_timestamp + vesting_seconds,
+                timestamp: clock.unix_timestamp,
+            },
+        )?;
+
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.record_swap(fill.quote.treasury_fee, clock.unix_timestamp);
+            stats.record_deposit(clock.unix_timestamp);
+        }
+        if let Some(view) = ctx.accounts.pool_view.as_mut() {
+            reload_pool_view(
+                view,
+                &ctx.accounts.pool,
+                &mut ctx.accounts.reserve_a,
+                &mut ctx.accounts.reserve_b,
+                &mut ctx.accounts.lp_mint,
+                clock.unix_timestamp,
+            )?;
+        }
+
         Ok(())
     }
 
     /// Claim the vested LP tokens (transfer them from the vesting token account to the user's LP token account)
+    /// A first-time claimer may pass their not-yet-created LP ATA plus the associated token program;
+    /// it is created at the user's expense before the payout.
     pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        // Matured principal stays claimable during a pause unless the pool opted out
+        require!(
+            !ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp) || ctx.accounts.pool.allow_claims_while_paused,
+            AmmError::Paused
+        );
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
         // Read required values immutably
-        require!(!ctx.accounts.pool.paused, AmmError::Paused);
         let vesting_amount = ctx.accounts.vesting_stake.amount;
         let vesting_end = ctx.accounts.vesting_stake.vesting_end;
         let vesting_claimed = ctx.accounts.vesting_stake.claimed;
-        let vesting_reward_debt = ctx.accounts.vesting_stake.reward_debt;
 
         require!(!vesting_claimed, AmmError::AlreadyClaimed);
+        check_pledge(&ctx.accounts.vesting_stake, ctx.accounts.pledgee.as_ref())?;
+        check_not_listed(&ctx.accounts.vesting_stake)?;
         let clock = Clock::get()?;
-        require!(clock.unix_timestamp >= vesting_end, AmmError::VestingNotFinished);
+        require_gte_ctx!(clock.unix_timestamp, vesting_end, AmmError::VestingNotFinished);
+        ctx.accounts.ensure_user_lp_token_account()?;
+
+        let pending_a = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_a_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_a,
+        )?;
+        let pending_b = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_b_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_b,
+        )?;
 
-        // Compute pending reward (in LP-equivalent units using acc_reward_per_lp snapshot)
-        let total_reward_for_stake = (u128::from(vesting_amount) * ctx.accounts.pool.acc_reward_per_lp) / REWARD_SCALE;
-        let pending_reward = total_reward_for_stake.checked_sub(vesting_reward_debt).unwrap_or(0u128);
+        let accounts = ClaimPayout {
+            token_program: ctx.accounts.token_program.to_account_info(),
+            vesting_token_account: &ctx.accounts.vesting_token_account,
+            lp_destination: ctx.accounts.user_lp_token_account.to_account_info(),
+            reward_vault: &ctx.accounts.reward_vault,
+            reward_destination: ctx.accounts.reward_destination_info()?,
+            rent_payer: ctx.accounts.rent_payer.to_account_info(),
+        };
+        let reward_paid = settle_matured_claim(
+            &mut ctx.accounts.pool,
+            &ctx.accounts.vesting_stake,
+            ctx.accounts.reward_epochs.as_deref().map(|e| &**e),
+            accounts,
+            ctx.accounts.global_stats.as_deref_mut().map(|s| &mut **s),
+            ctx.accounts.pool_view.as_deref_mut().map(|v| &mut **v),
+            clock.unix_timestamp,
+        )?;
 
-        // Perform transfers (CPIs) while only immutable borrows in scope
-        token::transfer(ctx.accounts.transfer_from_vesting_context(), vesting_amount)?;
+        let paid_a = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_a.as_ref(),
+            ctx.accounts.user_token_a.as_ref(),
+            pending_a,
+        )?;
+        let paid_b = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_b.as_ref(),
+            ctx.accounts.user_token_b.as_ref(),
+            pending_b,
+        )?;
+        if paid_a > 0 || paid_b > 0 {
+            emit_cpi!(UnderlyingRewardsPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                amount_a: paid_a,
+                amount_b: paid_b,
+            });
+        }
 
-        if pending_reward > 0 {
-            let pending_u64: u64 = pending_reward.try_into().map_err(|_| AmmError::NumericOverflow)?;
-            if ctx.accounts.reward_vault.amount >= pending_u64 {
-                token::transfer(ctx.accounts.transfer_reward_to_user_context(), pending_u64)?;
-            }
+        let vesting_key = ctx.accounts.vesting_stake.key();
+        if let Some(portfolio) = ctx.accounts.portfolio.as_mut() {
+            portfolio.remove_position(&vesting_key);
+        }
+
+        let receipt_period = ctx.accounts.pool.claim_receipt_period;
+        if receipt_period > 0 {
+            let receipt = ctx.accounts.claim_receipt.as_mut().ok_or(AmmError::ClaimReceiptRequired)?;
+            receipt.pool = ctx.accounts.pool.key();
+            receipt.user = ctx.accounts.user.key();
+            receipt.deposit_id = ctx.accounts.vesting_stake.deposit_id;
+            receipt.amount = vesting_amount;
+            receipt.reward_paid = reward_paid;
+            receipt.reward_paid_a = paid_a;
+            receipt.reward_paid_b = paid_b;
+            receipt.claimed_at = clock.unix_timestamp;
+            receipt.closable_at = clock.unix_timestamp.saturating_add(receipt_period);
         }
 
+        // Self-claim: the bounty goes back to the owner
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+
         // Now mutate vesting account (safe)
         let vesting = &mut ctx.accounts.vesting_stake;
         vesting.claimed = true;
 
-        emit!(Claimed {
+        emit_cpi!(Claimed {
             pool: ctx.accounts.pool.key(),
             user: vesting.user,
             amount: vesting.amount,
+            tag: vesting.tag,
         });
 
+        let payload = PositionHookPayload {
+            kind: PositionHookKind::Claim,
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            lp_amount: vesting_amount,
+            vesting_end,
+            timestamp: clock.unix_timestamp,
+        };
+        invoke_position_hook(&ctx.accounts.pool, ctx.accounts.pool_hook.as_deref().map(|h| &**h), ctx.remaining_accounts, payload)?;
+
         Ok(())
     }
 
-    /// Allow early unvest (partial or full) with penalty. Penalty is sent to treasury LP token account.
-    pub fn early_unvest(
-        ctx: Context<EarlyUnvest>,
-        lp_amount: u64,
-        penalty_bps: u16,
-    ) -> Result<()> {
-        require!(!ctx.accounts.pool.paused, AmmError::Paused);
-        require!(penalty_bps <= 10_000, AmmError::InvalidPenalty);
+    /// Pay a position's pending LP reward (with its relock bonus) without claiming the position,
+    /// to the user's registered reward destination or, without a registration, their own LP
+    /// account. Token A/B rewards stay pending until claim. Fails rather than forfeiting the reward
+    /// when the reward vault can't cover it.
+    pub fn harvest_rewards(ctx: Context<HarvestRewards>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !ctx.accounts.pool.is_paused(now) || ctx.accounts.pool.allow_claims_while_paused,
+            AmmError::Paused
+        );
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
 
-        // Read vesting immutable fields first
-        let vesting_amount = ctx.accounts.vesting_stake.amount;
-        let vesting_claimed = ctx.accounts.vesting_stake.claimed;
-        require!(!vesting_claimed, AmmError::AlreadyClaimed);
-        require!(lp_amount <= vesting_amount, AmmError::InsufficientVestedAmount);
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_pledge(vesting, ctx.accounts.pledgee.as_ref())?;
+        check_not_listed(vesting)?;
+        let destination = &ctx.accounts.destination;
+        match ctx.accounts.reward_destination.as_deref() {
+            Some(registration) => {
+                require!(destination.key() == registration.destination, AmmError::InvalidRewardDestination)
+            }
+            None => require!(destination.owner == vesting.user, AmmError::InvalidTokenAccountOwner),
+        }
 
-        let penalty_lp = (u128::from(lp_amount) * u128::from(penalty_bps) / 10_000u128) as u64;
-        let amount_to_user = lp_amount.checked_sub(penalty_lp).ok_or(AmmError::NumericOverflow)?;
+        let pool = &ctx.accounts.pool;
+        let pending = pending_reward_amount(vesting.amount, pool.acc_reward_per_lp, vesting.reward_debt)?;
+        require!(pending > 0, AmmError::NoPendingRewards);
+        let bonus_bps = position_relock_bonus_bps(pool, vesting, ctx.accounts.reward_epochs.as_deref().map(|e| &**e))?;
+        let reward_vault_amount = ctx.accounts.reward_vault.amount;
+        let reward_with_bonus =
+            apply_relock_bonus(bonus_bps, vesting.relock_count, pending, pool.reward_surplus(reward_vault_amount));
+        require_gte_ctx!(reward_vault_amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
 
-        // Transfers: penalty -> treasury, remainder -> user
-        if penalty_lp > 0 {
-            token::transfer(ctx.accounts.transfer_penalty_to_treasury_context(), penalty_lp)?;
-        }
-        if amount_to_user > 0 {
-            token::transfer(ctx.accounts.transfer_from_vesting_context(), amount_to_user)?;
-        }
+        token::transfer(
+            ctx.accounts.transfer_reward_context().with_signer(pool_signer!(ctx.accounts.pool)),
+            reward_with_bonus,
+        )?;
 
-        // Update vesting account
+        let acc_reward_per_lp = ctx.accounts.pool.acc_reward_per_lp;
+        ctx.accounts.pool.release_reward_liability(pending);
         let vesting = &mut ctx.accounts.vesting_stake;
-        vesting.amount = vesting.amount.checked_sub(lp_amount).ok_or(AmmError::NumericOverflow)?;
-        if vesting.amount == 0 {
-            vesting.claimed = true;
-        }
+        vesting.reward_debt = (u128::from(vesting.amount) * acc_reward_per_lp) / REWARD_SCALE;
 
-        emit!(EarlyUnvested {
-            pool: ctx.accounts.pool.key(),
+        emit_cpi!(RewardsHarvested {
+            pool: vesting.pool,
             user: vesting.user,
-            amount_unvested: lp_amount,
-            penalty: penalty_lp,
+            deposit_id: vesting.deposit_id,
+            destination: ctx.accounts.destination.key(),
+            amount: reward_with_bonus,
         });
-
         Ok(())
     }
 
-    /// Burn unlocked LP tokens and withdraw proportional amounts of token A and B from pool reserves.
-    pub fn withdraw_unlocked(ctx: Context<Withdraw>, lp_amount: u64) -> Result<()> {
-        require!(!ctx.accounts.pool.paused, AmmError::Paused);
-
+    /// Claim a matured position and redeem it for token A and B in one step: the vault's LP and
+    /// the position's LP reward are burned at the pool ratio instead of being sent to the user, so
+    /// no LP token account is needed. Follows `claim_vested` for rewards, receipts, hooks and
+    /// `allow_claims_while_paused`; a compromised pool stays redeemable as in `withdraw_unlocked`.
+    pub fn claim_and_withdraw(ctx: Context<ClaimAndWithdraw>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(
+            !pool.is_paused(Clock::get()?.unix_timestamp) || pool.allow_claims_while_paused || pool.compromised,
+            AmmError::Paused
+        );
         let lp_supply = ctx.accounts.lp_mint.supply;
         require!(lp_supply > 0, AmmError::InsufficientLiquidity);
+        let clock = Clock::get()?;
+        update_pool_rewards(&mut ctx.accounts.pool, clock.unix_timestamp)?;
 
-        let amount_a = (u128::from(ctx.accounts.reserve_a.amount)
-            .checked_mul(u128::from(lp_amount))
-            .ok_or(AmmError::NumericOverflow)?
-            / u128::from(lp_supply)) as u64;
+        let vesting_amount = ctx.accounts.vesting_stake.amount;
+        let vesting_end = ctx.accounts.vesting_stake.vesting_end;
+        require!(!ctx.accounts.vesting_stake.claimed, AmmError::AlreadyClaimed);
+        check_pledge(&ctx.accounts.vesting_stake, ctx.accounts.pledgee.as_ref())?;
+        check_not_listed(&ctx.accounts.vesting_stake)?;
+        require_gte_ctx!(clock.unix_timestamp, vesting_end, AmmError::VestingNotFinished);
 
-        let amount_b = (u128::from(ctx.accounts.reserve_b.amount)
-            .checked_mul(u128::from(lp_amount))
-            .ok_or(AmmError::NumericOverflow)?
-            / u128::from(lp_supply)) as u64;
+        let pending = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_per_lp,
+            ctx.accounts.vesting_stake.reward_debt,
+        )?;
+        let pending_a = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_a_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_a,
+        )?;
+        let pending_b = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_b_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_b,
+        )?;
+        let bonus_bps = position_relock_bonus_bps(
+            &ctx.accounts.pool,
+            &ctx.accounts.vesting_stake,
+            ctx.accounts.reward_epochs.as_deref().map(|e| &**e),
+        )?;
+        let reward_with_bonus = apply_relock_bonus(
+            bonus_bps,
+            ctx.accounts.vesting_stake.relock_count,
+            pending,
+            ctx.accounts.pool.reward_surplus(ctx.accounts.reward_vault.amount),
+        );
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+        let reward_paid = reward_with_bonus;
 
-        token::burn(ctx.accounts.burn_lp_context(), lp_amount)?;
-        token::transfer(ctx.accounts.transfer_a_to_user_context(), amount_a)?;
-        token::transfer(ctx.accounts.transfer_b_to_user_context(), amount_b)?;
+        // Principal and LP reward are redeemed together against the pre-burn supply
+        let lp_amount = vesting_amount.checked_add(reward_paid).ok_or(AmmError::NumericOverflow)?;
+        let share = |reserve: u64| -> Result<u64> {
+            Ok((u128::from(reserve)
+                .checked_mul(u128::from(lp_amount))
+                .ok_or(AmmError::NumericOverflow)?
+                / u128::from(lp_supply)) as u64)
+        };
+        let amount_a = share(ctx.accounts.reserve_a.amount)?;
+        let amount_b = share(ctx.accounts.reserve_b.amount)?;
 
-        emit!(Withdrawn {
-            pool: ctx.accounts.pool.key(),
-            user: ctx.accounts.user.key(),
-            lp_amount,
+        token::burn(
+            ctx.accounts.burn_vesting_lp_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            vesting_amount,
+        )?;
+        if reward_paid > 0 {
+            token::burn(
+                ctx.accounts.burn_reward_lp_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_paid,
+            )?;
+        }
+        token::transfer(
+            ctx.accounts.transfer_a_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
             amount_a,
+        )?;
+        token::transfer(
+            ctx.accounts.transfer_b_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
             amount_b,
-        });
+        )?;
 
-        Ok(())
-    }
+        let paid_a = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_a.as_ref(),
+            Some(&ctx.accounts.user_token_a),
+            pending_a,
+        )?;
+        let paid_b = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_b.as_ref(),
+            Some(&ctx.accounts.user_token_b),
+            pending_b,
+        )?;
+        if paid_a > 0 || paid_b > 0 {
+            emit_cpi!(UnderlyingRewardsPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                amount_a: paid_a,
+                amount_b: paid_b,
+            });
+        }
 
-    /// Simple constant-product swap with protocol fee charged (fee goes to the pool reserves).
-    /// A portion of the protocol fee is routed to treasury and a portion to the reward pool (simple model).
-    pub fn swap(
-        ctx: Context<Swap>,
-        amount_in: u64,
-        minimum_amount_out: u64,
-        is_a_to_b: bool,
-        min_slot: Option<u64>,
-    ) -> Result<()> {
-        require!(!ctx.accounts.pool.paused, AmmError::Paused);
+        ctx.accounts.pool.release_reward_liability(pending);
+        ctx.accounts.pool.total_locked_lp = ctx.accounts.pool.total_locked_lp.saturating_sub(vesting_amount);
 
-        if let Some(ms) = min_slot {
-            let clock = Clock::get()?;
-            require!(clock.slot >= ms, AmmError::SlotTooLow);
+        let vesting_key = ctx.accounts.vesting_stake.key();
+        if let Some(portfolio) = ctx.accounts.portfolio.as_mut() {
+            portfolio.remove_position(&vesting_key);
         }
 
-        // Read values immutably
-        let fee_bps = u128::from(ctx.accounts.pool.protocol_fee_bps);
-        let fee_denom = 10_000u128;
-
-        let (reserve_in_amount, reserve_out_amount) = if is_a_to_b {
-            (u128::from(ctx.accounts.reserve_a.amount), u128::from(ctx.accounts.reserve_b.amount))
-        } else {
-            (u128::from(ctx.accounts.reserve_b.amount), u128::from(ctx.accounts.reserve_a.amount))
-        };
+        let receipt_period = ctx.accounts.pool.claim_receipt_period;
+        if receipt_period > 0 {
+            let receipt = ctx.accounts.claim_receipt.as_mut().ok_or(AmmError::ClaimReceiptRequired)?;
+            receipt.pool = ctx.accounts.pool.key();
+            receipt.user = ctx.accounts.user.key();
+            receipt.deposit_id = ctx.accounts.vesting_stake.deposit_id;
+            receipt.amount = vesting_amount;
+            receipt.reward_paid = reward_paid;
+            receipt.reward_paid_a = paid_a;
+            receipt.reward_paid_b = paid_b;
+            receipt.claimed_at = clock.unix_timestamp;
+            receipt.closable_at = clock.unix_timestamp.saturating_add(receipt_period);
+        }
 
-        require!(
-            reserve_in_amount > 0 && reserve_out_amount > 0,
-            AmmError::InsufficientLiquidity
-        );
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.claimed = true;
 
-        let amount_in_u128 = u128::from(amount_in);
-        let amount_in_after_fee = amount_in_u128
-            .checked_mul(fee_denom.checked_sub(fee_bps).ok_or(AmmError::NumericOverflow)?)
-            .ok_or(AmmError::NumericOverflow)?
-            / fee_denom;
+        emit_cpi!(Claimed {
+            pool: vesting.pool,
+            user: vesting.user,
+            amount: vesting_amount,
+            tag: vesting.tag,
+        });
+        emit_cpi!(Withdrawn {
+            pool: vesting.pool,
+            user: vesting.user,
+            lp_amount,
+            amount_a,
+            amount_b,
+        });
 
-        let total_fee = amount_in_u128.checked_sub(amount_in_after_fee).ok_or(AmmError::NumericOverflow)?;
+        let payload = PositionHookPayload {
+            kind: PositionHookKind::Claim,
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            lp_amount: vesting_amount,
+            vesting_end,
+            timestamp: clock.unix_timestamp,
+        };
+        invoke_position_hook(&ctx.accounts.pool, ctx.accounts.pool_hook.as_deref().map(|h| &**h), ctx.remaining_accounts, payload)?;
 
-        let treasury_fee = (total_fee * u128::from(ctx.accounts.pool.treasury_fee_bps))
-            / u128::from(ctx.accounts.pool.protocol_fee_bps.max(1));
-        let reward_fee = (total_fee * u128::from(ctx.accounts.pool.reward_fee_bps))
-            / u128::from(ctx.accounts.pool.protocol_fee_bps.max(1));
-        let _to_reserve_fee = total_fee
-            .checked_sub(treasury_fee)
-            .ok_or(AmmError::NumericOverflow)?
-            .checked_sub(reward_fee)
-            .ok_or(AmmError::NumericOverflow)?;
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.record_position_closed(clock.unix_timestamp);
+        }
 
-        // Compute new acc_reward_per_lp locally (no mutable borrow)
-        let total_locked_lp = ctx.accounts.lp_mint.supply; // naive
-        let mut acc_reward_per_lp_local = ctx.accounts.pool.acc_reward_per_lp;
-        if total_locked_lp > 0 && reward_fee > 0 {
-            acc_reward_per_lp_local = acc_reward_per_lp_local
-                .checked_add((reward_fee * REWARD_SCALE) / u128::from(total_locked_lp))
-                .ok_or(AmmError::NumericOverflow)?;
+        if let Some(view) = ctx.accounts.pool_view.as_mut() {
+            reload_pool_view(
+                view,
+                &ctx.accounts.pool,
+                &mut ctx.accounts.reserve_a,
+                &mut ctx.accounts.reserve_b,
+                &mut ctx.accounts.lp_mint,
+                clock.unix_timestamp,
+            )?;
         }
 
-        // constant-product calc
-        let k = reserve_in_amount.checked_mul(reserve_out_amount).ok_or(AmmError::NumericOverflow)?;
-        let new_reserve_in = reserve_in_amount.checked_add(amount_in_after_fee).ok_or(AmmError::NumericOverflow)?;
-        let new_reserve_out = k.checked_div(new_reserve_in).ok_or(AmmError::NumericOverflow)?;
-        let amount_out_u128 = reserve_out_amount.checked_sub(new_reserve_out).ok_or(AmmError::NumericOverflow)?;
-        let amount_out = amount_out_u128 as u64;
-        require!(amount_out >= minimum_amount_out, AmmError::SlippageExceeded);
+        Ok(())
+    }
 
-        // Do CPIs (transfers)
-        if is_a_to_b {
-            token::transfer(ctx.accounts.transfer_in_a_context(), amount_in)?;
-            token::transfer(ctx.accounts.transfer_out_b_context(), amount_out)?;
-            if treasury_fee > 0 {
-                let t_fee: u64 = treasury_fee.try_into().map_err(|_| AmmError::NumericOverflow)?;
-                token::transfer(ctx.accounts.transfer_treasury_from_reserve_a_context(), t_fee)?;
-            }
-        } else {
-            token::transfer(ctx.accounts.transfer_in_b_context(), amount_in)?;
-            token::transfer(ctx.accounts.transfer_out_a_context(), amount_out)?;
-            if treasury_fee > 0 {
-                let t_fee: u64 = treasury_fee.try_into().map_err(|_| AmmError::NumericOverflow)?;
-                token::transfer(ctx.accounts.transfer_treasury_from_reserve_b_context(), t_fee)?;
-            }
-        }
+    /// Close a `ClaimReceipt` once its retention period is over, refunding the rent to its user.
+    /// Permissionless so stale receipts can be cleaned up by anyone.
+    pub fn close_claim_receipt(ctx: Context<CloseClaimReceipt>) -> Result<()> {
+        require_gte_ctx!(
+            Clock::get()?.unix_timestamp,
+            ctx.accounts.claim_receipt.closable_at,
+            AmmError::ClaimReceiptNotExpired
+        );
+        Ok(())
+    }
 
-        // Now mutate pool.acc_reward_per_lp
-        let pool = &mut ctx.accounts.pool;
-        pool.acc_reward_per_lp = acc_reward_per_lp_local;
+    /// Create the caller's `UserVestingIndex` for `pool`, which hands out the seed nonces of their positions.
+    /// It starts at the pool's legacy global `vesting_nonce`, above every id used before per-user counters.
+    pub fn init_user_vesting_index(ctx: Context<InitUserVestingIndex>) -> Result<()> {
+        let index = &mut ctx.accounts.user_vesting_index;
+        index.pool = ctx.accounts.pool.key();
+        index.user = ctx.accounts.user.key();
+        index.next_nonce = ctx.accounts.pool.vesting_nonce;
+        Ok(())
+    }
+
+    /// Create the pool's (empty) `AllowedCallers` list (authority only). Restriction starts with
+    /// `set_allowed_callers`.
+    pub fn init_allowed_callers(ctx: Context<InitAllowedCallers>) -> Result<()> {
+        let callers = &mut ctx.accounts.allowed_callers;
+        callers.pool = ctx.accounts.pool.key();
+        callers.allow_direct = true;
+        Ok(())
+    }
 
-        emit!(Swapped {
+    /// Replace the programs allowed to invoke `swap` via CPI and turn the restriction on or off
+    /// (authority only). The immediate caller is checked, so a swap reached through two levels of
+    /// CPI is rejected while the restriction is on.
+    pub fn set_allowed_callers(
+        ctx: Context<SetAllowedCallers>,
+        programs: Vec<Pubkey>,
+        allow_direct: bool,
+        restricted: bool,
+    ) -> Result<()> {
+        require_lte_ctx!(programs.len(), MAX_ALLOWED_CALLERS, AmmError::TooManyAllowedCallers);
+        let callers = &mut ctx.accounts.allowed_callers;
+        callers.allow_direct = allow_direct;
+        callers.count = programs.len() as u8;
+        callers.programs = [Pubkey::default(); MAX_ALLOWED_CALLERS];
+        callers.programs[..programs.len()].copy_from_slice(&programs);
+        ctx.accounts.pool.caller_restricted = restricted;
+        emit_cpi!(AllowedCallersSet {
             pool: ctx.accounts.pool.key(),
-            user: ctx.accounts.user.key(),
-            amount_in,
-            amount_out,
-            is_a_to_b,
+            programs,
+            allow_direct,
+            restricted,
         });
+        Ok(())
+    }
 
+    /// Create the caller's `TraderPoints` for `pool`. Swaps only accrue points once it exists.
+    pub fn init_trader_points(ctx: Context<InitTraderPoints>) -> Result<()> {
+        let points = &mut ctx.accounts.trader_points;
+        points.pool = ctx.accounts.pool.key();
+        points.user = ctx.accounts.user.key();
         Ok(())
     }
 
-    pub fn pause(ctx: Context<OnlyAuthority>) -> Result<()> {
+    /// Set how much LP reward a trader gets per 10_000 points redeemed (authority only, 0 disables).
+    pub fn set_points_rate(ctx: Context<OnlyAuthority>, points_rate_bps: u16) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        pool.paused = true;
-        emit!(Paused { pool: pool.key() });
+        pool.points_rate_bps = points_rate_bps;
+        emit_cpi!(PointsRateSet {
+            pool: pool.key(),
+            points_rate_bps,
+        });
         Ok(())
     }
 
-    pub fn unpause(ctx: Context<OnlyAuthority>) -> Result<()> {
+    /// Rebate `trader_rebate_bps` of each swap's LP fee to the trader as LP locked for
+    /// `trader_rebate_seconds` (authority only, 0 bps disables). The fee stays in the reserves and
+    /// LP worth it is minted into the trader's rebate position, so the slice comes out of LP fee
+    /// income rather than the pool's principal.
+    pub fn set_trader_rebate(
+        ctx: Context<OnlyAuthority>,
+        trader_rebate_bps: u16,
+        trader_rebate_seconds: i64,
+    ) -> Result<()> {
+        require_lte_ctx!(trader_rebate_bps, 10_000, AmmError::InvalidTraderRebate);
+        if trader_rebate_bps > 0 {
+            require!(
+                (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&trader_rebate_seconds),
+                AmmError::InvalidVestingPeriod
+            );
+        }
         let pool = &mut ctx.accounts.pool;
-        pool.paused = false;
-        emit!(Unpaused { pool: pool.key() });
+        pool.trader_rebate_bps = trader_rebate_bps;
+        pool.trader_rebate_seconds = trader_rebate_seconds;
+        emit_cpi!(TraderRebateSet {
+            pool: pool.key(),
+            trader_rebate_bps,
+            trader_rebate_seconds,
+        });
         Ok(())
     }
 
-    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>) -> Result<()> {
-        // Transfers while only immutable reads used earlier
-        let reserve_a_bal = ctx.accounts.reserve_a.amount;
-        let reserve_b_bal = ctx.accounts.reserve_b.amount;
-        if reserve_a_bal > 0 {
-            token::transfer(ctx.accounts.transfer_reserve_a_to_treasury_context(), reserve_a_bal)?;
-        }
-        if reserve_b_bal > 0 {
-            token::transfer(ctx.accounts.transfer_reserve_b_to_treasury_context(), reserve_b_bal)?;
+    /// Convert the caller's unredeemed points into LP at `points_rate_bps`, paid from reward-vault
+    /// surplus (above `total_reward_liability`) so stakers' rewards are never touched. When the
+    /// surplus is short only the points it covers are redeemed.
+    pub fn redeem_trader_points(ctx: Context<RedeemTraderPoints>) -> Result<()> {
+        let rate = u128::from(ctx.accounts.pool.points_rate_bps);
+        require!(rate > 0, AmmError::PointsRedemptionDisabled);
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
+        let trader = &ctx.accounts.trader_points;
+        let unredeemed = trader.points.saturating_sub(trader.redeemed_points);
+        let surplus = ctx
+            .accounts
+            .reward_vault
+            .amount
+            .saturating_sub(ctx.accounts.pool.total_reward_liability);
+        let mut reward = (u128::from(unredeemed) * rate / 10_000u128) as u64;
+        let mut points = unredeemed;
+        if reward > surplus {
+            reward = surplus;
+            points = (u128::from(surplus) * 10_000u128 / rate) as u64;
         }
-        emit!(EmergencyWithdrawn { pool: ctx.accounts.pool.key() });
+        require!(reward > 0, AmmError::NoPointsToRedeem);
+
+        token::transfer(
+            ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+            reward,
+        )?;
+        let trader = &mut ctx.accounts.trader_points;
+        trader.redeemed_points = trader.redeemed_points.saturating_add(points);
+
+        emit_cpi!(TraderPointsRedeemed {
+            pool: trader.pool,
+            user: trader.user,
+            points,
+            reward,
+        });
         Ok(())
     }
-}
 
-// ---------------------- Accounts ----------------------
+    /// Create the program's `GlobalStats` account. Permissionless; counting starts from here.
+    pub fn init_global_stats(ctx: Context<InitGlobalStats>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stats = &mut ctx.accounts.global_stats;
+        stats.created_at = now;
+        stats.updated_ts = now;
+        Ok(())
+    }
 
-#[account]
-pub struct Pool {
-    pub authority: Pubkey,
-    pub token_a_mint: Pubkey,
-    pub token_b_mint: Pubkey,
-    pub lp_mint: Pubkey,
-    pub reserve_a: Pubkey,
-    pub reserve_b: Pubkey,
-    pub protocol_fee_bps: u16,
-    pub treasury: Pubkey,
-    pub treasury_fee_bps: u16,
-    pub reward_fee_bps: u16,
-    pub vesting_nonce: u64,
-    pub paused: bool,
-    pub acc_reward_per_lp: u128, // scaled by REWARD_SCALE
-}
+    /// Create the pool's `PoolView` summary account and fill it from current state. Permissionless.
+    pub fn init_pool_view(ctx: Context<InitPoolView>) -> Result<()> {
+        let view = &mut ctx.accounts.pool_view;
+        view.pool = ctx.accounts.pool.key();
+        view.refresh(
+            &ctx.accounts.pool,
+            ctx.accounts.reserve_a.amount,
+            ctx.accounts.reserve_b.amount,
+            ctx.accounts.lp_mint.supply,
+            Clock::get()?.unix_timestamp,
+        )
+    }
 
-#[account]
-pub struct VestingStake {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount: u64,
-    pub vesting_end: i64,
-    pub claimed: bool,
-    pub deposit_id: u64,
-    pub reward_debt: u128,
-}
+    /// Permissionless crank rewriting `PoolView`, for changes made by instructions that don't take it
+    /// (admin setters, emissions accruing over time, ...).
+    pub fn refresh_pool_view(ctx: Context<RefreshPoolView>) -> Result<()> {
+        ctx.accounts.pool_view.refresh(
+            &ctx.accounts.pool,
+            ctx.accounts.reserve_a.amount,
+            ctx.accounts.reserve_b.amount,
+            ctx.accounts.lp_mint.supply,
+            Clock::get()?.unix_timestamp,
+        )
+    }
 
-// ---------------------- Events ----------------------
+    /// Create the caller's `Portfolio`, which indexes their vesting positions across every pool.
+    pub fn init_portfolio(ctx: Context<InitPortfolio>) -> Result<()> {
+        let portfolio = &mut ctx.accounts.portfolio;
+        portfolio.owner = ctx.accounts.user.key();
+        portfolio.positions = Vec::new();
+        Ok(())
+    }
 
-#[event]
-pub struct PoolInitialized {
-    pub pool: Pubkey,
-    pub authority: Pubkey,
-    pub treasury: Pubkey,
-}
-#[event]
-pub struct Deposited {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount: u64,
-    pub vesting_end: i64,
-}
-#[event]
-pub struct Claimed {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount: u64,
-}
-#[event]
-pub struct EarlyUnvested {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount_unvested: u64,
-    pub penalty: u64,
-}
-#[event]
-pub struct Withdrawn {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub lp_amount: u64,
-    pub amount_a: u64,
-    pub amount_b: u64,
-}
-#[event]
-pub struct Swapped {
-    pub pool: Pubkey,
-    pub user: Pubkey,
-    pub amount_in: u64,
-    pub amount_out: u64,
-    pub is_a_to_b: bool,
-}
-#[event]
-pub struct Paused {
-    pub pool: Pubkey,
-}
-#[event]
-pub struct Unpaused {
-    pub pool: Pubkey,
-}
-#[event]
-pub struct EmergencyWithdrawn {
-    pub pool: Pubkey,
-}
+    /// Claim every matured position passed in `remaining_accounts`, across any number of pools.
+    /// Each position takes `CLAIM_ALL_ACCOUNTS_PER_POSITION` accounts, in order:
+    /// pool, lp_mint, vesting_stake, vesting_token_account, user_lp_token_account, reward_vault, rent_payer,
+    /// and the pool's `PoolView` (or this program's id when the pool has none). Each position is settled
+    /// as `claim_vested` settles it.
+    /// Positions that are not claimable yet, pledged, owed underlying-token rewards, opened in an
+    /// earlier reward epoch, or in a pool with a registered hook or claim receipts (which need the
+    /// extra accounts of `claim_vested`) are skipped rather than failing the whole batch, as are
+    /// positions whose reward the reward vault can't cover yet.
+    pub fn claim_all_matured<'info>(ctx: Context<'_, '_, 'info, 'info, ClaimAllMatured<'info>>) -> Result<()> {
+        require!(
+            ctx.remaining_accounts.len().is_multiple_of(CLAIM_ALL_ACCOUNTS_PER_POSITION),
+            AmmError::InvalidVestingAccount
+        );
+        let user = &ctx.accounts.user;
+        let now = Clock::get()?.unix_timestamp;
 
-// ---------------------- Contexts ----------------------
+        let mut claimed_positions: u32 = 0;
+        for chunk in ctx.remaining_accounts.chunks(CLAIM_ALL_ACCOUNTS_PER_POSITION) {
+            let mut pool: Account<'info, Pool> = Account::try_from(&chunk[0])?;
+            let lp_mint: Account<'info, Mint> = Account::try_from(&chunk[1])?;
+            let mut vesting: Account<'info, VestingStake> = Account::try_from(&chunk[2])?;
+            let vesting_token_account: Account<'info, TokenAccount> = Account::try_from(&chunk[3])?;
+            let user_lp_token_account: Account<'info, TokenAccount> = Account::try_from(&chunk[4])?;
+            let reward_vault: Account<'info, TokenAccount> = Account::try_from(&chunk[5])?;
+            let rent_payer = &chunk[6];
+            let mut pool_view: Option<Account<'info, PoolView>> =
+                if chunk[7].key() == ID { None } else { Some(Account::try_from(&chunk[7])?) };
 
-#[derive(Accounts)]
-pub struct InitializePool<'info> {
-    #[account(init, payer = authority, space = 8 + 256, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
-    /// CHECK: token accounts created by client
-    #[account(mut)]
-    pub reserve_a: AccountInfo<'info>,
-    /// CHECK: token accounts created by client
-    #[account(mut)]
-    pub reserve_b: AccountInfo<'info>,
-    /// CHECK: treasury token account (must be a token account for LP tokens for penalty/tax routing)
-    #[account(mut)]
-    pub treasury: AccountInfo<'info>,
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-}
+            require!(
+                pool.lp_mint == lp_mint.key() && vesting.pool == pool.key() && vesting.user == user.key(),
+                AmmError::InvalidVestingAccount
+            );
+            require!(
+                vesting_token_account.owner == vesting.key() && vesting_token_account.mint == pool.lp_mint,
+                AmmError::InvalidVestingAccount
+            );
+            require!(
+                user_lp_token_account.owner == user.key() && user_lp_token_account.mint == pool.lp_mint,
+                AmmError::InvalidTokenAccountOwner
+            );
+            // Same rule as `claim_and_withdraw`: a pool-owned LP account other than the recovery vault
+            // and the treasury
+            require!(
+                reward_vault.mint == pool.lp_mint
+                    && reward_vault.owner == pool.key()
+                    && reward_vault.key() != recovery_vault_address(&pool.key()).0
+                    && reward_vault.key() != pool.treasury,
+                AmmError::InvalidRewardVault
+            );
+            require!(rent_payer.key() == vesting.rent_payer, AmmError::InvalidVestingAccount);
+            // A pool has one view, at its PDA, so matching the pool is enough
+            require!(
+                pool_view.as_ref().is_none_or(|view| view.pool == pool.key()),
+                AmmError::InvalidVestingAccount
+            );
 
-#[derive(Accounts)]
-#[instruction(amount_a: u64, amount_b: u64, vesting_seconds: i64)]
-pub struct DepositAndVest<'info> {
-    #[account(mut, has_one = lp_mint, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
+            if vesting.claimed
+                || now < vesting.vesting_end
+                || vesting.pledgee != Pubkey::default()
+                || vesting.listed
+                || (pool.is_paused(now) && !pool.allow_claims_while_paused)
+                || pool.hook_enabled
+                || pool.claim_receipt_period > 0
+            {
+                continue;
+            }
 
-    #[account(mut, token::mint = token_a_mint)]
-    pub reserve_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint)]
-    pub reserve_b: Account<'info, TokenAccount>,
+            update_pool_rewards(&mut pool, now)?;
+            let pending_a = pending_reward_amount(vesting.amount, pool.acc_reward_a_per_lp, vesting.reward_debt_a)?;
+            let pending_b = pending_reward_amount(vesting.amount, pool.acc_reward_b_per_lp, vesting.reward_debt_b)?;
+            // Positions from an earlier reward epoch need `RewardEpochs` for their bonus rate
+            let stale_epoch = vesting.reward_epoch != 0 && vesting.reward_epoch != pool.reward_epoch;
+            if pending_a > 0 || pending_b > 0 || stale_epoch {
+                pool.exit(&ID)?;
+                continue;
+            }
+            let (_, reward_with_bonus) = relock_reward(&pool, &vesting, None, reward_vault.amount)?;
+            // Left for a later claim, untouched, while the reward vault can't cover it
+            if reward_vault.amount < reward_with_bonus {
+                pool.exit(&ID)?;
+                continue;
+            }
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+            let accounts = ClaimPayout {
+                token_program: ctx.accounts.token_program.to_account_info(),
+                vesting_token_account: &vesting_token_account,
+                lp_destination: user_lp_token_account.to_account_info(),
+                reward_vault: &reward_vault,
+                reward_destination: user_lp_token_account.to_account_info(),
+                rent_payer: rent_payer.clone(),
+            };
+            settle_matured_claim(
+                &mut pool,
+                &vesting,
+                None,
+                accounts,
+                ctx.accounts.global_stats.as_deref_mut().map(|s| &mut **s),
+                pool_view.as_deref_mut(),
+                now,
+            )?;
+            pool.exit(&ID)?;
+            if let Some(view) = pool_view.as_ref() {
+                view.exit(&ID)?;
+            }
 
-    #[account(mut, token::mint = token_a_mint, token::authority = user)]
-    pub user_token_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint, token::authority = user)]
-    pub user_token_b: Account<'info, TokenAccount>,
+            if let Some(portfolio) = ctx.accounts.portfolio.as_mut() {
+                portfolio.remove_position(&vesting.key());
+            }
+            emit_cpi!(Claimed {
+                pool: pool.key(),
+                user: vesting.user,
+                amount: vesting.amount,
+                tag: vesting.tag,
+            });
+            refund_claim_bounty(&mut vesting, &user.to_account_info())?;
+            vesting.close(rent_payer.clone())?;
+            claimed_positions += 1;
+        }
+
+        emit_cpi!(MaturedPositionsClaimed {
+            user: user.key(),
+            positions: claimed_positions,
+        });
+        Ok(())
+    }
+
+    /// Re-lock a matured, unclaimed position for another `vesting_seconds` instead of claiming it.
+    /// LP rewards accrued so far are paid out (with the current streak bonus) and `relock_count`
+    /// grows, raising the bonus on future rewards. Token A/B rewards keep accruing untouched.
+    pub fn relock_vested(ctx: Context<RelockVested>, vesting_seconds: i64) -> Result<()> {
+        require!(
+            (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&vesting_seconds),
+            AmmError::InvalidVestingPeriod
+        );
+        let now = Clock::get()?.unix_timestamp;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_not_listed(vesting)?;
+        check_pledge(vesting, ctx.accounts.pledgee.as_ref())?;
+        require_gte_ctx!(now, vesting.vesting_end, AmmError::VestingNotFinished);
+
+        let (pending, reward_with_bonus) = relock_reward(
+            &ctx.accounts.pool,
+            vesting,
+            ctx.accounts.reward_epochs.as_deref().map(|e| &**e),
+            ctx.accounts.reward_vault.amount,
+        )?;
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+        let mut reward_paid = 0u64;
+        if reward_with_bonus > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_with_bonus,
+            )?;
+            reward_paid = reward_with_bonus;
+        }
+        roll_position(&mut ctx.accounts.pool, &mut ctx.accounts.vesting_stake, pending, vesting_seconds, now);
+
+        let vesting = &ctx.accounts.vesting_stake;
+        emit_cpi!(Relocked {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            vesting_end: vesting.vesting_end,
+            relock_count: vesting.relock_count,
+            reward_paid,
+        });
+        Ok(())
+    }
+
+    /// Opt a position into automatic relocking: once matured, anyone may run `execute_auto_relock`
+    /// to roll it into a new lock of `vesting_seconds`. `None` opts out.
+    pub fn set_auto_relock(ctx: Context<SetAutoRelock>, vesting_seconds: Option<i64>) -> Result<()> {
+        if let Some(seconds) = vesting_seconds {
+            require!(
+                (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&seconds),
+                AmmError::InvalidVestingPeriod
+            );
+        }
+        let vesting = &mut ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        vesting.auto_relock = vesting_seconds;
+        emit_cpi!(AutoRelockSet {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            vesting_seconds,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank for positions with `auto_relock` set: after maturity it does what
+    /// `relock_vested` would, for the configured duration, paying the LP rewards accrued so far
+    /// to the owner's LP account.
+    pub fn execute_auto_relock(ctx: Context<ExecuteAutoRelock>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let vesting = &ctx.accounts.vesting_stake;
+        let vesting_seconds = vesting.auto_relock.ok_or(AmmError::AutoRelockNotSet)?;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_not_listed(vesting)?;
+        // The crank can't bring the pledgee's signature, so pledged positions relock by hand
+        check_pledge(vesting, None)?;
+        require_gte_ctx!(now, vesting.vesting_end, AmmError::VestingNotFinished);
+
+        let (pending, reward_with_bonus) = relock_reward(
+            &ctx.accounts.pool,
+            vesting,
+            ctx.accounts.reward_epochs.as_deref().map(|e| &**e),
+            ctx.accounts.reward_vault.amount,
+        )?;
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+        let mut reward_paid = 0u64;
+        if reward_with_bonus > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_with_bonus,
+            )?;
+            reward_paid = reward_with_bonus;
+        }
+        roll_position(&mut ctx.accounts.pool, &mut ctx.accounts.vesting_stake, pending, vesting_seconds, now);
+
+        let vesting = &ctx.accounts.vesting_stake;
+        emit_cpi!(Relocked {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            vesting_end: vesting.vesting_end,
+            relock_count: vesting.relock_count,
+            reward_paid,
+        });
+        Ok(())
+    }
+
+    /// Allow early unvest (partial or full) with penalty. Penalty is sent to treasury LP token account.
+    ///
+    /// Rewards: the exited share of the position's pending rewards is settled now (pro rata to
+    /// `lp_amount / amount`); the remaining position keeps its share via a proportionally reduced
+    /// `reward_debt`, and the exited LP earns nothing further. When `pool.penalize_rewards` is set the
+    /// same penalty is taken from the settled rewards and routed to the treasury as well. The protocol's
+    /// `insurance_bps` of each treasury-bound penalty goes to the LP mint's insurance fund instead.
+    /// The penalty rate is the pool's `early_unvest_penalty_bps`, never the caller's choice; within
+    /// `pool.penalty_grace_seconds` of `vesting_end` no penalty applies.
+    pub fn early_unvest(ctx: Context<EarlyUnvest>, lp_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp), AmmError::Paused);
+        require!(lp_amount > 0, AmmError::ZeroLpAmount);
+        // An authority-approved hardship request waives the penalty for this position
+        let hardship_approved = ctx
+            .accounts
+            .hardship_request
+            .as_ref()
+            .is_some_and(|request| request.approved);
+        let now = Clock::get()?.unix_timestamp;
+        let waived = ctx.accounts.penalty_waiver.as_ref().is_some_and(|waiver| waiver.active(now));
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+        let settlement = settle_early_exit(
+            &ctx.accounts.pool,
+            &ctx.accounts.protocol_config,
+            &ctx.accounts.vesting_stake,
+            ctx.accounts.pledgee.as_ref(),
+            lp_amount,
+            hardship_approved || waived,
+            now,
+        )?;
+
+        // Transfers: penalty -> treasury (less the lockers' share -> reward vault), remainder -> user
+        let penalty_to_treasury = settlement.penalty_to_treasury();
+        if penalty_to_treasury > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_penalty_to_treasury_context()
+                    .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                penalty_to_treasury,
+            )?;
+        }
+        if settlement.penalty_to_rewards > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_penalty_to_rewards_context()
+                    .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                settlement.penalty_to_rewards,
+            )?;
+            ctx.accounts.pool.distribute_rewards(settlement.penalty_to_rewards, lp_supply)?;
+            emit_cpi!(PenaltyRoutedToRewards {
+                pool: ctx.accounts.pool.key(),
+                amount: settlement.penalty_to_rewards,
+                acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            });
+        }
+        if settlement.amount_to_user > 0 {
+            token::transfer(
+                ctx.accounts.transfer_from_vesting_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                settlement.amount_to_user,
+            )?;
+        }
+
+        // The exited share's entitlement is released below, so an underfunded vault fails the exit
+        require_gte_ctx!(
+            ctx.accounts.reward_vault.amount,
+            settlement.exited_reward,
+            AmmError::RewardVaultInsufficient
+        );
+        let (reward_paid, reward_penalty_paid) = (settlement.reward_to_user(), settlement.reward_penalty);
+        let reward_penalty_to_treasury = settlement.reward_penalty_to_treasury();
+        if reward_penalty_to_treasury > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_penalty_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_penalty_to_treasury,
+            )?;
+        }
+        if reward_paid > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_paid,
+            )?;
+        }
+
+        // The insurance fund's slice of both penalties, taken from what the treasury would receive
+        let penalty_to_insurance = settlement.penalty_to_insurance + settlement.reward_penalty_to_insurance;
+        if penalty_to_insurance > 0 {
+            let insurance_fund = ctx.accounts.insurance_fund.as_deref().ok_or(AmmError::InsuranceFundMissing)?;
+            if settlement.penalty_to_insurance > 0 {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_penalty_to_insurance_context(insurance_fund)
+                        .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                    settlement.penalty_to_insurance,
+                )?;
+            }
+            if settlement.reward_penalty_to_insurance > 0 {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_reward_penalty_to_insurance_context(insurance_fund)
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    settlement.reward_penalty_to_insurance,
+                )?;
+            }
+            emit_cpi!(PenaltyRoutedToInsurance {
+                pool: ctx.accounts.pool.key(),
+                amount: penalty_to_insurance,
+            });
+        }
+
+        // Underlying-token rewards: same split, penalty to the token A/B treasury accounts
+        let paid_a = pay_exited_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_a.as_ref(),
+            ctx.accounts.treasury_token_account_a.as_ref(),
+            ctx.accounts.user_token_a.as_ref(),
+            settlement.exited_reward_a,
+            settlement.reward_penalty_a,
+        )?;
+        let paid_b = pay_exited_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_b.as_ref(),
+            ctx.accounts.treasury_token_account_b.as_ref(),
+            ctx.accounts.user_token_b.as_ref(),
+            settlement.exited_reward_b,
+            settlement.reward_penalty_b,
+        )?;
+        if paid_a > 0 || paid_b > 0 {
+            emit_cpi!(UnderlyingRewardsPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                amount_a: paid_a,
+                amount_b: paid_b,
+            });
+        }
+
+        let fully_exited =
+            book_early_exit(&mut ctx.accounts.pool, &mut ctx.accounts.vesting_stake, lp_amount, &settlement)?;
+        let vesting = &ctx.accounts.vesting_stake;
+
+        emit_cpi!(EarlyUnvested {
+            pool: ctx.accounts.pool.key(),
+            user: vesting.user,
+            amount_unvested: lp_amount,
+            penalty: settlement.penalty_lp,
+            reward_paid,
+            reward_penalty: reward_penalty_paid,
+        });
+
+        let payload = PositionHookPayload {
+            kind: PositionHookKind::EarlyUnvest,
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            lp_amount,
+            vesting_end: vesting.vesting_end,
+            timestamp: now,
+        };
+        invoke_position_hook(&ctx.accounts.pool, ctx.accounts.pool_hook.as_deref().map(|h| &**h), ctx.remaining_accounts, payload)?;
+
+        // An approved hardship request is single-use
+        if hardship_approved {
+            if let Some(request) = ctx.accounts.hardship_request.as_ref() {
+                request.close(ctx.accounts.user.to_account_info())?;
+            }
+        }
+
+        if fully_exited {
+            if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+                stats.record_position_closed(now);
+            }
+        }
+
+        // Fully exited: close the vault and the vesting PDA, refunding rent to its payer (mirrors claim).
+        // The cached vault balance is pre-transfer, so equality means the vault is now empty.
+        if fully_exited && ctx.accounts.vesting_token_account.amount == lp_amount {
+            token::close_account(
+                ctx.accounts.close_vesting_vault_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            )?;
+            refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+            ctx.accounts
+                .vesting_stake
+                .close(ctx.accounts.rent_payer.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    /// `early_unvest`, but the released LP (and the settled LP reward) is burned for token A and B at
+    /// the pool ratio instead of being sent to the user. The penalty still goes to the treasury as LP.
+    pub fn early_unvest_to_tokens(ctx: Context<EarlyUnvestToTokens>, lp_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp), AmmError::Paused);
+        require!(lp_amount > 0, AmmError::ZeroLpAmount);
+        let hardship_approved = ctx
+            .accounts
+            .hardship_request
+            .as_ref()
+            .is_some_and(|request| request.approved);
+        let now = Clock::get()?.unix_timestamp;
+        let waived = ctx.accounts.penalty_waiver.as_ref().is_some_and(|waiver| waiver.active(now));
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+        let settlement = settle_early_exit(
+            &ctx.accounts.pool,
+            &ctx.accounts.protocol_config,
+            &ctx.accounts.vesting_stake,
+            ctx.accounts.pledgee.as_ref(),
+            lp_amount,
+            hardship_approved || waived,
+            now,
+        )?;
+        let amount_to_user = settlement.amount_to_user;
+
+        // The exited share's entitlement is released below, so an underfunded vault fails the exit
+        require_gte_ctx!(
+            ctx.accounts.reward_vault.amount,
+            settlement.exited_reward,
+            AmmError::RewardVaultInsufficient
+        );
+        let (reward_paid, reward_penalty_paid) = (settlement.reward_to_user(), settlement.reward_penalty);
+
+        // Released principal and LP reward are redeemed together against the pre-burn supply
+        let lp_redeemed = amount_to_user.checked_add(reward_paid).ok_or(AmmError::NumericOverflow)?;
+        let share = |reserve: u64| -> Result<u64> {
+            Ok((u128::from(reserve)
+                .checked_mul(u128::from(lp_redeemed))
+                .ok_or(AmmError::NumericOverflow)?
+                / u128::from(lp_supply)) as u64)
+        };
+        let amount_a = share(ctx.accounts.reserve_a.amount)?;
+        let amount_b = share(ctx.accounts.reserve_b.amount)?;
+
+        // Penalty -> treasury, less the lockers' share -> reward vault
+        let penalty_to_treasury = settlement.penalty_to_treasury();
+        if penalty_to_treasury > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_penalty_to_treasury_context()
+                    .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                penalty_to_treasury,
+            )?;
+        }
+        if settlement.penalty_to_rewards > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_penalty_to_rewards_context()
+                    .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                settlement.penalty_to_rewards,
+            )?;
+            ctx.accounts.pool.distribute_rewards(settlement.penalty_to_rewards, lp_supply)?;
+            emit_cpi!(PenaltyRoutedToRewards {
+                pool: ctx.accounts.pool.key(),
+                amount: settlement.penalty_to_rewards,
+                acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            });
+        }
+        if amount_to_user > 0 {
+            token::burn(
+                ctx.accounts.burn_vesting_lp_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                amount_to_user,
+            )?;
+        }
+        let reward_penalty_to_treasury = settlement.reward_penalty_to_treasury();
+        if reward_penalty_to_treasury > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_penalty_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_penalty_to_treasury,
+            )?;
+        }
+
+        // The insurance fund's slice of both penalties, taken from what the treasury would receive
+        let penalty_to_insurance = settlement.penalty_to_insurance + settlement.reward_penalty_to_insurance;
+        if penalty_to_insurance > 0 {
+            let insurance_fund = ctx.accounts.insurance_fund.as_deref().ok_or(AmmError::InsuranceFundMissing)?;
+            if settlement.penalty_to_insurance > 0 {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_penalty_to_insurance_context(insurance_fund)
+                        .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                    settlement.penalty_to_insurance,
+                )?;
+            }
+            if settlement.reward_penalty_to_insurance > 0 {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_reward_penalty_to_insurance_context(insurance_fund)
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    settlement.reward_penalty_to_insurance,
+                )?;
+            }
+            emit_cpi!(PenaltyRoutedToInsurance {
+                pool: ctx.accounts.pool.key(),
+                amount: penalty_to_insurance,
+            });
+        }
+
+        if reward_paid > 0 {
+            token::burn(
+                ctx.accounts.burn_reward_lp_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_paid,
+            )?;
+        }
+        if amount_a > 0 {
+            token::transfer(
+                ctx.accounts.transfer_a_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_a,
+            )?;
+        }
+        if amount_b > 0 {
+            token::transfer(
+                ctx.accounts.transfer_b_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_b,
+            )?;
+        }
+
+        // Underlying-token rewards: same split, penalty to the token A/B treasury accounts
+        let paid_a = pay_exited_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_a.as_ref(),
+            ctx.accounts.treasury_token_account_a.as_ref(),
+            Some(&ctx.accounts.user_token_a),
+            settlement.exited_reward_a,
+            settlement.reward_penalty_a,
+        )?;
+        let paid_b = pay_exited_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_b.as_ref(),
+            ctx.accounts.treasury_token_account_b.as_ref(),
+            Some(&ctx.accounts.user_token_b),
+            settlement.exited_reward_b,
+            settlement.reward_penalty_b,
+        )?;
+        if paid_a > 0 || paid_b > 0 {
+            emit_cpi!(UnderlyingRewardsPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                amount_a: paid_a,
+                amount_b: paid_b,
+            });
+        }
+
+        let fully_exited =
+            book_early_exit(&mut ctx.accounts.pool, &mut ctx.accounts.vesting_stake, lp_amount, &settlement)?;
+        let vesting = &ctx.accounts.vesting_stake;
+
+        emit_cpi!(EarlyUnvested {
+            pool: vesting.pool,
+            user: vesting.user,
+            amount_unvested: lp_amount,
+            penalty: settlement.penalty_lp,
+            reward_paid,
+            reward_penalty: reward_penalty_paid,
+        });
+        emit_cpi!(Withdrawn {
+            pool: vesting.pool,
+            user: vesting.user,
+            lp_amount: lp_redeemed,
+            amount_a,
+            amount_b,
+        });
+
+        let payload = PositionHookPayload {
+            kind: PositionHookKind::EarlyUnvest,
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            lp_amount,
+            vesting_end: vesting.vesting_end,
+            timestamp: now,
+        };
+        invoke_position_hook(&ctx.accounts.pool, ctx.accounts.pool_hook.as_deref().map(|h| &**h), ctx.remaining_accounts, payload)?;
+
+        if hardship_approved {
+            if let Some(request) = ctx.accounts.hardship_request.as_ref() {
+                request.close(ctx.accounts.user.to_account_info())?;
+            }
+        }
+
+        if fully_exited {
+            if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+                stats.record_position_closed(now);
+            }
+        }
+
+        // Fully exited: close the vault and the vesting PDA, refunding rent to its payer
+        if fully_exited && ctx.accounts.vesting_token_account.amount == lp_amount {
+            token::close_account(
+                ctx.accounts.close_vesting_vault_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            )?;
+            refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+            ctx.accounts
+                .vesting_stake
+                .close(ctx.accounts.rent_payer.to_account_info())?;
+        }
+
+        Ok(())
+    }
+
+    /// Permissionless sweep of a position left unclaimed for `abandonment_period` after `vesting_end`.
+    /// The LP moves to the pool recovery vault and a `RecoveryClaim` records what the user is owed.
+    /// The sweeper funds the claim record; the rent of the closed vesting PDA and vault goes back to
+    /// the position's `rent_payer` and any escrowed claim bounty to its owner.
+    pub fn sweep_abandoned(ctx: Context<SweepAbandoned>) -> Result<()> {
+        let abandonment_period = ctx.accounts.pool.abandonment_period;
+        require!(abandonment_period > 0, AmmError::SweepDisabled);
+
+        let vesting_amount = ctx.accounts.vesting_stake.amount;
+        let vesting_end = ctx.accounts.vesting_stake.vesting_end;
+        require!(!ctx.accounts.vesting_stake.claimed, AmmError::AlreadyClaimed);
+        require!(
+            ctx.accounts.vesting_stake.pledgee == Pubkey::default(),
+            AmmError::PositionPledged
+        );
+        check_not_listed(&ctx.accounts.vesting_stake)?;
+
+        let clock = Clock::get()?;
+        let sweepable_at = vesting_end
+            .checked_add(abandonment_period)
+            .ok_or(AmmError::NumericOverflow)?;
+        require_gte_ctx!(clock.unix_timestamp, sweepable_at, AmmError::NotAbandoned);
+        update_pool_rewards(&mut ctx.accounts.pool, clock.unix_timestamp)?;
+
+        // Pending reward is frozen at sweep time and paid on redemption
+        let pending_u64 = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_per_lp,
+            ctx.accounts.vesting_stake.reward_debt,
+        )?;
+        let pending_a = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_a_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_a,
+        )?;
+        let pending_b = pending_reward_amount(
+            vesting_amount,
+            ctx.accounts.pool.acc_reward_b_per_lp,
+            ctx.accounts.vesting_stake.reward_debt_b,
+        )?;
+
+        // Move the whole vault balance so the vault can be closed
+        let vault_balance = ctx.accounts.vesting_token_account.amount;
+        if vault_balance > 0 {
+            token::transfer(
+                ctx.accounts.transfer_to_recovery_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                vault_balance,
+            )?;
+        }
+        token::close_account(
+            ctx.accounts.close_vesting_vault_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+        )?;
+        ctx.accounts.pool.total_locked_lp = ctx.accounts.pool.total_locked_lp.saturating_sub(vesting_amount);
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+
+        let record = &mut ctx.accounts.recovery_claim;
+        record.pool = ctx.accounts.pool.key();
+        record.user = ctx.accounts.vesting_stake.user;
+        record.deposit_id = ctx.accounts.vesting_stake.deposit_id;
+        record.amount = vault_balance;
+        record.pending_reward = pending_u64;
+        record.pending_reward_a = pending_a;
+        record.pending_reward_b = pending_b;
+
+        emit_cpi!(AbandonedSwept {
+            pool: record.pool,
+            user: record.user,
+            deposit_id: record.deposit_id,
+            amount: record.amount,
+            pending_reward: record.pending_reward,
+            sweeper: ctx.accounts.sweeper.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Redeem LP (plus the pending reward frozen at sweep time) from the recovery vault.
+    pub fn redeem_recovered(ctx: Context<RedeemRecovered>) -> Result<()> {
+        let amount = ctx.accounts.recovery_claim.amount;
+        let pending_reward = ctx.accounts.recovery_claim.pending_reward;
+
+        if amount > 0 {
+            token::transfer(
+                ctx.accounts.transfer_from_recovery_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount,
+            )?;
+        }
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, pending_reward, AmmError::RewardVaultInsufficient);
+        if pending_reward > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                pending_reward,
+            )?;
+        }
+        ctx.accounts.pool.release_reward_liability(pending_reward);
+
+        let paid_a = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_a.as_ref(),
+            ctx.accounts.user_token_a.as_ref(),
+            ctx.accounts.recovery_claim.pending_reward_a,
+        )?;
+        let paid_b = transfer_underlying_reward(
+            &ctx.accounts.token_program,
+            &ctx.accounts.pool,
+            ctx.accounts.reward_vault_b.as_ref(),
+            ctx.accounts.user_token_b.as_ref(),
+            ctx.accounts.recovery_claim.pending_reward_b,
+        )?;
+        if paid_a > 0 || paid_b > 0 {
+            emit_cpi!(UnderlyingRewardsPaid {
+                pool: ctx.accounts.pool.key(),
+                user: ctx.accounts.user.key(),
+                amount_a: paid_a,
+                amount_b: paid_b,
+            });
+        }
+
+        emit_cpi!(RecoveredRedeemed {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            deposit_id: ctx.accounts.recovery_claim.deposit_id,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Escape hatch for positions whose normal claim path is broken (bad migration, corrupted
+    /// position state): moves the whole vault balance to the user regardless of `vesting_end` or
+    /// the `claimed` flag. Needs both the pool authority and the position owner (and the pledgee,
+    /// if pledged). Pending rewards are forfeited; the position is left open for the audit trail.
+    /// The transfer is signed by whichever PDA the vault actually belongs to, the position or the
+    /// pool, so a vault migrated under the pool's authority can still be rescued.
+    pub fn rescue_vesting_tokens(ctx: Context<RescueVestingTokens>) -> Result<()> {
+        check_pledge(&ctx.accounts.vesting_stake, ctx.accounts.pledgee.as_ref())?;
+        check_not_listed(&ctx.accounts.vesting_stake)?;
+        let vault_balance = ctx.accounts.vesting_token_account.amount;
+        require!(vault_balance > 0, AmmError::NothingToRescue);
+
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+        let vault_owner = ctx.accounts.vesting_token_account.owner;
+        if vault_owner == ctx.accounts.vesting_stake.key() {
+            let authority = ctx.accounts.vesting_stake.to_account_info();
+            token::transfer(
+                ctx.accounts
+                    .transfer_from_vesting_context(authority)
+                    .with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+                vault_balance,
+            )?;
+        } else if vault_owner == ctx.accounts.pool.key() {
+            let authority = ctx.accounts.pool.to_account_info();
+            token::transfer(
+                ctx.accounts.transfer_from_vesting_context(authority).with_signer(pool_signer!(ctx.accounts.pool)),
+                vault_balance,
+            )?;
+        } else {
+            return err!(AmmError::VaultAuthorityUnknown);
+        }
+
+        let was_claimed = ctx.accounts.vesting_stake.claimed;
+        if !was_claimed {
+            let vesting = &ctx.accounts.vesting_stake;
+            let pending = pending_reward_amount(vesting.amount, ctx.accounts.pool.acc_reward_per_lp, vesting.reward_debt)?;
+            let amount = vesting.amount;
+            ctx.accounts.pool.release_reward_liability(pending);
+            ctx.accounts.pool.total_locked_lp = ctx.accounts.pool.total_locked_lp.saturating_sub(amount);
+            ctx.accounts.vesting_stake.claimed = true;
+        }
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+
+        emit_cpi!(VestingRescued {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            deposit_id: ctx.accounts.vesting_stake.deposit_id,
+            amount: vault_balance,
+            was_claimed,
+            authority: ctx.accounts.authority.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Escrow `lamports` on the position as a bounty for whoever first cranks its claim after
+    /// maturity (`crank_claim`). Claiming it yourself, or exiting early, refunds the bounty.
+    pub fn escrow_claim_bounty(ctx: Context<EscrowClaimBounty>, lamports: u64) -> Result<()> {
+        require!(lamports > 0, AmmError::InvalidClaimBounty);
+        require!(!ctx.accounts.vesting_stake.claimed, AmmError::AlreadyClaimed);
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: ctx.accounts.vesting_stake.to_account_info(),
+                },
+            ),
+            lamports,
+        )?;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.claim_bounty = vesting.claim_bounty.checked_add(lamports).ok_or(AmmError::NumericOverflow)?;
+        emit_cpi!(ClaimBountyEscrowed {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            lamports,
+            claim_bounty: vesting.claim_bounty,
+        });
+        Ok(())
+    }
+
+    /// Permissionless claim of a matured position that carries a bounty: LP and LP rewards go to the
+    /// owner's LP account and the bounty to the cranker. Positions that need owner-supplied accounts
+    /// (pledged, owed token A/B rewards, older reward epoch, hooked pool, claim receipts) or a paused
+    /// pool are refused; their owner claims with `claim_vested`.
+    pub fn crank_claim(ctx: Context<CrankClaim>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let pool = &ctx.accounts.pool;
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(vesting.claim_bounty > 0, AmmError::InvalidClaimBounty);
+        require!(vesting.auto_relock.is_none(), AmmError::AutoRelockEnabled);
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_not_listed(vesting)?;
+        require_gte_ctx!(now, vesting.vesting_end, AmmError::VestingNotFinished);
+        require!(!pool.is_paused(now) || pool.allow_claims_while_paused, AmmError::Paused);
+        let pending_a = pending_reward_amount(vesting.amount, pool.acc_reward_a_per_lp, vesting.reward_debt_a)?;
+        let pending_b = pending_reward_amount(vesting.amount, pool.acc_reward_b_per_lp, vesting.reward_debt_b)?;
+        let stale_epoch = vesting.reward_epoch != 0 && vesting.reward_epoch != pool.reward_epoch;
+        require!(
+            vesting.pledgee == Pubkey::default()
+                && pending_a == 0
+                && pending_b == 0
+                && !stale_epoch
+                && !pool.hook_enabled
+                && pool.claim_receipt_period == 0,
+            AmmError::ClaimNeedsOwner
+        );
+
+        let vesting_amount = vesting.amount;
+        let pending = pending_reward_amount(vesting_amount, pool.acc_reward_per_lp, vesting.reward_debt)?;
+        let surplus = pool.reward_surplus(ctx.accounts.reward_vault.amount);
+        let reward_with_bonus = apply_relock_bonus(pool.relock_bonus_bps, vesting.relock_count, pending, surplus);
+
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+
+        token::transfer(
+            ctx.accounts.transfer_from_vesting_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            vesting_amount,
+        )?;
+        if reward_with_bonus > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_with_bonus,
+            )?;
+        }
+
+        ctx.accounts.pool.release_reward_liability(pending);
+        ctx.accounts.pool.total_locked_lp = ctx.accounts.pool.total_locked_lp.saturating_sub(vesting_amount);
+
+        let bounty = ctx.accounts.vesting_stake.claim_bounty;
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.cranker.to_account_info())?;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.claimed = true;
+
+        emit_cpi!(Claimed {
+            pool: vesting.pool,
+            user: vesting.user,
+            amount: vesting_amount,
+            tag: vesting.tag,
+        });
+        emit_cpi!(ClaimBountyPaid {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            cranker: ctx.accounts.cranker.key(),
+            bounty,
+        });
+        Ok(())
+    }
+
+    /// Relayed claim of a matured position, authorized off-chain: the owner signs
+    /// `claim_voucher_message(vesting_stake, relayer, destination, nonce, expires_at)` with their
+    /// wallet key and the relayer submits it as an ed25519 program instruction placed right before
+    /// this one. LP and LP rewards go to the signed destination and any claim bounty back to the
+    /// owner; the relayer only pays fees and the rent of the `UsedVoucher` receipt (PDA
+    /// `["used_voucher", owner, nonce]`), whose `init` refuses a replayed nonce. Positions needing
+    /// more owner accounts are refused as in `crank_claim`.
+    pub fn claim_vested_with_voucher(ctx: Context<ClaimVestedWithVoucher>, nonce: u64, expires_at: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require_gte_ctx!(expires_at, now, AmmError::VoucherExpired);
+        let message = claim_voucher_message(
+            &ctx.accounts.vesting_stake.key(),
+            &ctx.accounts.relayer.key(),
+            &ctx.accounts.destination.key(),
+            nonce,
+            expires_at,
+        );
+        verify_ed25519_voucher(&ctx.accounts.instructions_sysvar, &ctx.accounts.vesting_stake.user, &message)?;
+        let used_voucher = &mut ctx.accounts.used_voucher;
+        used_voucher.user = ctx.accounts.vesting_stake.user;
+        used_voucher.nonce = nonce;
+
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let pool = &ctx.accounts.pool;
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_not_listed(vesting)?;
+        require_gte_ctx!(now, vesting.vesting_end, AmmError::VestingNotFinished);
+        require!(!pool.is_paused(now) || pool.allow_claims_while_paused, AmmError::Paused);
+        let pending_a = pending_reward_amount(vesting.amount, pool.acc_reward_a_per_lp, vesting.reward_debt_a)?;
+        let pending_b = pending_reward_amount(vesting.amount, pool.acc_reward_b_per_lp, vesting.reward_debt_b)?;
+        let stale_epoch = vesting.reward_epoch != 0 && vesting.reward_epoch != pool.reward_epoch;
+        require!(
+            vesting.pledgee == Pubkey::default()
+                && pending_a == 0
+                && pending_b == 0
+                && !stale_epoch
+                && !pool.hook_enabled
+                && pool.claim_receipt_period == 0,
+            AmmError::ClaimNeedsOwner
+        );
+
+        let vesting_amount = vesting.amount;
+        let pending = pending_reward_amount(vesting_amount, pool.acc_reward_per_lp, vesting.reward_debt)?;
+        let surplus = pool.reward_surplus(ctx.accounts.reward_vault.amount);
+        let reward_with_bonus = apply_relock_bonus(pool.relock_bonus_bps, vesting.relock_count, pending, surplus);
+
+        require_gte_ctx!(ctx.accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+
+        token::transfer(
+            ctx.accounts.transfer_from_vesting_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            vesting_amount,
+        )?;
+        if reward_with_bonus > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reward_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reward_with_bonus,
+            )?;
+        }
+
+        ctx.accounts.pool.release_reward_liability(pending);
+        ctx.accounts.pool.total_locked_lp = ctx.accounts.pool.total_locked_lp.saturating_sub(vesting_amount);
+
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.claimed = true;
+
+        emit_cpi!(Claimed {
+            pool: vesting.pool,
+            user: vesting.user,
+            amount: vesting_amount,
+            tag: vesting.tag,
+        });
+        emit_cpi!(VoucherClaimRelayed {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            relayer: ctx.accounts.relayer.key(),
+            nonce,
+        });
+        Ok(())
+    }
+
+    /// Register the address notification services should alert when the position matures.
+    pub fn set_notify_address(ctx: Context<SetNotifyAddress>, notify_address: Pubkey) -> Result<()> {
+        let registration = &mut ctx.accounts.notify_registration;
+        registration.vesting_stake = ctx.accounts.vesting_stake.key();
+        registration.notify_address = notify_address;
+        Ok(())
+    }
+
+    /// Drop the position's notify address, refunding the rent to the user.
+    pub fn clear_notify_address(_ctx: Context<ClearNotifyAddress>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Register where the user's LP rewards from this pool go (`harvest_rewards`, `claim_vested`):
+    /// any unfrozen LP token account not owned by the pool, e.g. a cold wallet or a charity.
+    pub fn set_reward_destination(ctx: Context<SetRewardDestination>) -> Result<()> {
+        check_reward_destination(&ctx.accounts.pool, &ctx.accounts.destination)?;
+        let registration = &mut ctx.accounts.reward_destination;
+        registration.pool = ctx.accounts.pool.key();
+        registration.user = ctx.accounts.user.key();
+        registration.destination = ctx.accounts.destination.key();
+        emit_cpi!(RewardDestinationSet {
+            pool: registration.pool,
+            user: registration.user,
+            destination: registration.destination,
+        });
+        Ok(())
+    }
+
+    /// Point an existing registration at a new destination, validated as at registration.
+    pub fn update_reward_destination(ctx: Context<UpdateRewardDestination>) -> Result<()> {
+        check_reward_destination(&ctx.accounts.pool, &ctx.accounts.destination)?;
+        let registration = &mut ctx.accounts.reward_destination;
+        registration.destination = ctx.accounts.destination.key();
+        emit_cpi!(RewardDestinationSet {
+            pool: registration.pool,
+            user: registration.user,
+            destination: registration.destination,
+        });
+        Ok(())
+    }
+
+    /// Drop the registration, so rewards go to the user's LP account again; rent back to the user.
+    pub fn clear_reward_destination(_ctx: Context<ClearRewardDestination>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Permissionless crank flagging a position as matured once `vesting_end` has passed, emitting
+    /// `VestingMatured` (with the registered notify address, if any) as a trigger for bots.
+    pub fn mark_matured(ctx: Context<MarkMatured>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        require!(!vesting.matured, AmmError::AlreadyMatured);
+        require_gte_ctx!(Clock::get()?.unix_timestamp, vesting.vesting_end, AmmError::VestingNotFinished);
+        vesting.matured = true;
+        emit_cpi!(VestingMatured {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            vesting_end: vesting.vesting_end,
+            notify_address: ctx
+                .accounts
+                .notify_registration
+                .as_ref()
+                .map(|registration| registration.notify_address),
+        });
+        Ok(())
+    }
+
+    /// Ask the pool authority for a penalty-free early unlock of one position, with a reason code.
+    pub fn request_hardship_unlock(ctx: Context<RequestHardshipUnlock>, reason_code: u16) -> Result<()> {
+        require!(!ctx.accounts.vesting_stake.claimed, AmmError::AlreadyClaimed);
+        let request = &mut ctx.accounts.hardship_request;
+        request.vesting_stake = ctx.accounts.vesting_stake.key();
+        request.user = ctx.accounts.user.key();
+        request.reason_code = reason_code;
+        request.approved = false;
+        request.requested_at = Clock::get()?.unix_timestamp;
+        emit_cpi!(HardshipRequested {
+            pool: ctx.accounts.vesting_stake.pool,
+            vesting_stake: request.vesting_stake,
+            user: request.user,
+            reason_code,
+        });
+        Ok(())
+    }
+
+    /// Approve a pending hardship request (authority only). The next `early_unvest` of that
+    /// position that passes the request runs with zero penalty and consumes it.
+    pub fn approve_hardship_unlock(ctx: Context<ApproveHardshipUnlock>) -> Result<()> {
+        let request = &mut ctx.accounts.hardship_request;
+        request.approved = true;
+        emit_cpi!(HardshipApproved {
+            pool: ctx.accounts.pool.key(),
+            vesting_stake: request.vesting_stake,
+            user: request.user,
+            reason_code: request.reason_code,
+        });
+        Ok(())
+    }
+
+    /// Withdraw a hardship request, refunding its rent to the user.
+    pub fn cancel_hardship_request(_ctx: Context<CancelHardshipRequest>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Register a lender's claim on a vesting position. Both the owner and the pledgee sign; while
+    /// pledged, `claim_vested` and `early_unvest` require the pledgee's co-signature and the
+    /// position cannot be swept.
+    pub fn pledge_vesting(ctx: Context<PledgeVesting>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        require!(vesting.pledgee == Pubkey::default(), AmmError::PositionPledged);
+        check_not_listed(vesting)?;
+        vesting.pledgee = ctx.accounts.pledgee.key();
+        emit_cpi!(VestingPledged {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            pledgee: vesting.pledgee,
+        });
+        Ok(())
+    }
+
+    /// Release a pledge. Only the current pledgee can sign this.
+    pub fn release_pledge(ctx: Context<ReleasePledge>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.pledgee = Pubkey::default();
+        emit_cpi!(PledgeReleased {
+            pool: vesting.pool,
+            user: vesting.user,
+            deposit_id: vesting.deposit_id,
+            pledgee: ctx.accounts.pledgee.key(),
+        });
+        Ok(())
+    }
+
+    /// Offer a vesting position for sale at `price` units of `quote_mint`. The position stays in
+    /// its vault; only ownership (`user`) moves on settlement.
+    pub fn list_vesting_for_sale(ctx: Context<ListVestingForSale>, price: u64, quote_mint: Pubkey) -> Result<()> {
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        require!(vesting.pledgee == Pubkey::default(), AmmError::PositionPledged);
+        require!(price > 0, AmmError::InvalidListing);
+
+        let listing = &mut ctx.accounts.listing;
+        listing.vesting_stake = vesting.key();
+        listing.seller = ctx.accounts.user.key();
+        listing.quote_mint = quote_mint;
+        listing.price = price;
+        listing.amount = vesting.amount;
+
+        emit_cpi!(VestingListed {
+            pool: vesting.pool,
+            vesting_stake: listing.vesting_stake,
+            seller: listing.seller,
+            quote_mint,
+            price,
+        });
+        ctx.accounts.vesting_stake.listed = true;
+        Ok(())
+    }
+
+    /// Create the pool's TWAP observation buffer. Anyone may pay for it.
+    pub fn init_price_observations(ctx: Context<InitPriceObservations>) -> Result<()> {
+        let observations = &mut ctx.accounts.price_observations;
+        observations.pool = ctx.accounts.pool.key();
+        observations.latest_index = 0;
+        observations.last_update_ts = 0;
+        observations.price_a_cumulative = 0;
+        observations.price_b_cumulative = 0;
+        observations.entries = [Observation::default(); OBSERVATION_RING_SIZE];
+        Ok(())
+    }
+
+    /// Permissionless crank appending an observation when none was written for
+    /// `OBSERVATION_MIN_INTERVAL`, so quiet pools keep a fresh TWAP. If the pool holds its own
+    /// token A treasury account, the caller is paid `oracle_poke_reward` from it.
+    pub fn poke_oracle(ctx: Context<PokeOracle>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let (reserve_a, reserve_b) = (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount);
+        let (written, tripped) =
+            observe_price(&mut ctx.accounts.pool, &mut ctx.accounts.price_observations, reserve_a, reserve_b, now)?;
+        require!(written, AmmError::ObservationTooRecent);
+        if tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+
+        let reward = ctx.accounts.pool.oracle_poke_reward;
+        let mut reward_paid = 0u64;
+        if reward > 0 {
+            if let Some(poker_token_a) = ctx.accounts.poker_token_a.as_ref() {
+                let treasury = &ctx.accounts.treasury_token_account_a;
+                if treasury.owner == ctx.accounts.pool.key() && treasury.amount >= reward {
+                    let cpi_accounts = Transfer {
+                        from: treasury.to_account_info(),
+                        to: poker_token_a.to_account_info(),
+                        authority: ctx.accounts.pool.to_account_info(),
+                    };
+                    token::transfer(
+                        CpiContext::new_with_signer(
+                            ctx.accounts.token_program.to_account_info(),
+                            cpi_accounts,
+                            pool_signer!(ctx.accounts.pool),
+                        ),
+                        reward,
+                    )?;
+                    reward_paid = reward;
+                }
+            }
+        }
+
+        let latest = ctx.accounts.price_observations.latest();
+        emit_cpi!(OraclePoked {
+            pool: ctx.accounts.pool.key(),
+            poker: ctx.accounts.poker.key(),
+            timestamp: latest.timestamp,
+            price_a_cumulative: latest.price_a_cumulative,
+            price_b_cumulative: latest.price_b_cumulative,
+            reward_paid,
+        });
+        Ok(())
+    }
+
+    /// Post the reference price (A in B, scaled by `PRICE_SCALE`) the first deposit is checked
+    /// against, with its tolerance. Callable by the authority or the keeper; a zero price disables the check.
+    pub fn set_oracle_price(ctx: Context<OnlyOperator>, oracle_price: u128, oracle_tolerance_bps: u16) -> Result<()> {
+        require_lte_ctx!(oracle_tolerance_bps, 10_000, AmmError::InvalidFeeSplit);
+        let pool = &mut ctx.accounts.pool;
+        pool.oracle_price = oracle_price;
+        pool.oracle_tolerance_bps = oracle_tolerance_bps;
+        emit_cpi!(OraclePriceSet {
+            pool: pool.key(),
+            oracle_price,
+            oracle_tolerance_bps,
+        });
+        Ok(())
+    }
+
+    /// Set the LP reward bonus earned per consecutive relock (total bonus capped at `MAX_RELOCK_BONUS_BPS`).
+    pub fn set_relock_bonus(ctx: Context<SetRelockBonus>, relock_bonus_bps: u16) -> Result<()> {
+        require_lte_ctx!(u64::from(relock_bonus_bps), MAX_RELOCK_BONUS_BPS, AmmError::FeeTooHigh);
+        let pool = &mut ctx.accounts.pool;
+        pool.relock_bonus_bps = relock_bonus_bps;
+        start_reward_epoch(
+            pool,
+            ctx.accounts.reward_epochs.as_deref_mut().map(|e| &mut **e),
+            Clock::get()?.unix_timestamp,
+        )?;
+        emit_cpi!(RelockBonusSet {
+            pool: pool.key(),
+            relock_bonus_bps,
+        });
+        Ok(())
+    }
+
+    /// Configure the stable-pool de-peg breaker: trips when the internal price stays more than
+    /// `depeg_threshold_bps` from 1:1 (or `oracle_price`) for `DEPEG_CONFIRM_OBSERVATIONS`
+    /// consecutive observations. Zero disables it.
+    pub fn set_depeg_guard(ctx: Context<OnlyAuthority>, depeg_threshold_bps: u16, depeg_blocks_swaps: bool) -> Result<()> {
+        require_lte_ctx!(depeg_threshold_bps, 10_000, AmmError::InvalidDepegThreshold);
+        let pool = &mut ctx.accounts.pool;
+        pool.depeg_threshold_bps = depeg_threshold_bps;
+        pool.depeg_blocks_swaps = depeg_blocks_swaps;
+        emit_cpi!(DepegGuardSet {
+            pool: pool.key(),
+            depeg_threshold_bps,
+            depeg_blocks_swaps,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank for quiet pools: record an observation (a no-op within
+    /// `OBSERVATION_MIN_INTERVAL` of the last one) and count it toward the de-peg breaker.
+    pub fn check_depeg(ctx: Context<CheckDepeg>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let (reserve_a, reserve_b) = (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount);
+        let (_, tripped) =
+            observe_price(&mut ctx.accounts.pool, &mut ctx.accounts.price_observations, reserve_a, reserve_b, now)?;
+        if tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+        Ok(())
+    }
+
+    /// Reopen a pool after a de-peg has been reviewed.
+    pub fn clear_depeg(ctx: Context<OnlyAuthority>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.depegged = false;
+        pool.depeg_strikes = 0;
+        emit_cpi!(DepegCleared { pool: pool.key() });
+        Ok(())
+    }
+
+    /// Reduced fee for swaps that move the pool price toward the oracle price (or the TWAP when no
+    /// oracle is set). Must be below `protocol_fee_bps` to have any effect; zero disables it.
+    pub fn set_rebalance_fee(ctx: Context<OnlyAuthority>, rebalance_fee_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require_lte_ctx!(rebalance_fee_bps, pool.protocol_fee_bps, AmmError::InvalidFeeSplit);
+        pool.rebalance_fee_bps = rebalance_fee_bps;
+        emit_cpi!(RebalanceFeeSet {
+            pool: pool.key(),
+            rebalance_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Schedule a promotional swap fee (authority only): between `fee_holiday_start` and
+    /// `fee_holiday_end` swaps pay `fee_holiday_bps` instead of `protocol_fee_bps`, then the normal
+    /// fee resumes on its own. The rate can only be lowered; pass zeros to cancel.
+    pub fn set_fee_holiday(
+        ctx: Context<OnlyAuthority>,
+        fee_holiday_bps: u16,
+        fee_holiday_start: i64,
+        fee_holiday_end: i64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let cancel = fee_holiday_start == 0 && fee_holiday_end == 0;
+        if !cancel {
+            require_gt_ctx!(fee_holiday_end, fee_holiday_start, AmmError::InvalidFeeHoliday);
+            require_gt_ctx!(fee_holiday_end, Clock::get()?.unix_timestamp, AmmError::InvalidFeeHoliday);
+            require_lte_ctx!(fee_holiday_bps, pool.protocol_fee_bps, AmmError::InvalidFeeHoliday);
+        }
+        pool.fee_holiday_bps = fee_holiday_bps;
+        pool.fee_holiday_start = fee_holiday_start;
+        pool.fee_holiday_end = fee_holiday_end;
+        emit_cpi!(FeeHolidaySet {
+            pool: pool.key(),
+            fee_holiday_bps,
+            fee_holiday_start,
+            fee_holiday_end,
+        });
+        Ok(())
+    }
+
+    /// How long `claim_vested` receipts stay open (authority only). While non-zero, claims must
+    /// create a `ClaimReceipt`; zero turns receipts off.
+    pub fn set_claim_receipt_period(ctx: Context<OnlyAuthority>, claim_receipt_period: i64) -> Result<()> {
+        require!(
+            (0..=MAX_CLAIM_RECEIPT_PERIOD).contains(&claim_receipt_period),
+            AmmError::InvalidClaimReceiptPeriod
+        );
+        let pool = &mut ctx.accounts.pool;
+        pool.claim_receipt_period = claim_receipt_period;
+        emit_cpi!(ClaimReceiptPeriodSet {
+            pool: pool.key(),
+            claim_receipt_period,
+        });
+        Ok(())
+    }
+
+    /// Charge the swap fee on the output side instead of the input (some integrators quote that way).
+    pub fn set_fee_on_output(ctx: Context<OnlyAuthority>, fee_on_output: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.fee_on_output = fee_on_output;
+        emit_cpi!(FeeSideSet {
+            pool: pool.key(),
+            fee_on_output,
+        });
+        Ok(())
+    }
+
+    /// Set the token A incentive paid per successful `poke_oracle` (0 disables it).
+    pub fn set_oracle_poke_reward(ctx: Context<OnlyAuthority>, oracle_poke_reward: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.oracle_poke_reward = oracle_poke_reward;
+        emit_cpi!(OraclePokeRewardSet {
+            pool: pool.key(),
+            oracle_poke_reward,
+        });
+        Ok(())
+    }
+
+    /// Withdraw a listing, unfreezing the position. Rent returns to the seller.
+    pub fn cancel_vesting_listing(ctx: Context<CancelVestingListing>) -> Result<()> {
+        ctx.accounts.vesting_stake.listed = false;
+        emit_cpi!(VestingListingCancelled {
+            vesting_stake: ctx.accounts.listing.vesting_stake,
+            seller: ctx.accounts.seller.key(),
+        });
+        Ok(())
+    }
+
+    /// Buy a listed position. The buyer pays the seller in the quote mint (minus `pool.sale_fee_bps`,
+    /// which goes to the treasury owner's quote account) and becomes the position's `user`.
+    /// `expected_price` guards against the listing being replaced between quote and purchase.
+    pub fn buy_vesting_position(ctx: Context<BuyVestingPosition>, expected_price: u64) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+        let vesting = &ctx.accounts.vesting_stake;
+        require_eq_ctx!(listing.price, expected_price, AmmError::InvalidListing);
+        require!(!vesting.claimed, AmmError::InvalidListing);
+        require_eq_ctx!(vesting.amount, listing.amount, AmmError::InvalidListing);
+        require!(vesting.user == listing.seller, AmmError::InvalidListing);
+        require!(vesting.pledgee == Pubkey::default(), AmmError::PositionPledged);
+
+        let price = listing.price;
+        let sale_fee = (u128::from(price) * u128::from(ctx.accounts.pool.sale_fee_bps) / 10_000u128) as u64;
+        let to_seller = price.checked_sub(sale_fee).ok_or(AmmError::NumericOverflow)?;
+
+        if sale_fee > 0 {
+            token::transfer(ctx.accounts.transfer_sale_fee_context()?, sale_fee)?;
+        }
+        token::transfer(ctx.accounts.transfer_to_seller_context(), to_seller)?;
+
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.user = ctx.accounts.buyer.key();
+        vesting.listed = false;
+        vesting.held_since = Clock::get()?.unix_timestamp;
+
+        emit_cpi!(VestingSold {
+            pool: vesting.pool,
+            vesting_stake: vesting.key(),
+            seller: ctx.accounts.seller.key(),
+            buyer: vesting.user,
+            quote_mint: ctx.accounts.quote_mint.key(),
+            price,
+            sale_fee,
+        });
+        Ok(())
+    }
+
+    /// Set the protocol cut of secondary-market position sales (capped like swap fees).
+    pub fn set_sale_fee(ctx: Context<OnlyAuthority>, sale_fee_bps: u16) -> Result<()> {
+        require_lte_ctx!(sale_fee_bps, HARD_MAX_PROTOCOL_FEE_BPS, AmmError::FeeTooHigh);
+        let pool = &mut ctx.accounts.pool;
+        pool.sale_fee_bps = sale_fee_bps;
+        emit_cpi!(SaleFeeSet {
+            pool: pool.key(),
+            sale_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Create the user's voter-weight record for the pool's governance realm (spl-governance addin layout).
+    pub fn create_voter_weight_record(ctx: Context<CreateVoterWeightRecord>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(pool.governance_realm != Pubkey::default(), AmmError::GovernanceNotConfigured);
+        let record = &mut ctx.accounts.voter_weight_record;
+        record.realm = pool.governance_realm;
+        record.governing_token_mint = pool.governance_mint;
+        record.governing_token_owner = ctx.accounts.user.key();
+        record.voter_weight = 0;
+        record.voter_weight_expiry = Some(0);
+        record.weight_action = None;
+        record.weight_action_target = None;
+        Ok(())
+    }
+
+    /// Recompute the user's voting weight from their locked positions, passed as remaining accounts.
+    /// Weight is each position's `weighted_amount` and is only valid for the current slot, so
+    /// governance clients call this in the same transaction as the vote.
+    pub fn update_voter_weight_record(ctx: Context<UpdateVoterWeightRecord>) -> Result<()> {
+        let pool_key = ctx.accounts.pool.key();
+        let user_key = ctx.accounts.user.key();
+        let clock = Clock::get()?;
+
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut voter_weight: u64 = 0;
+        for info in ctx.remaining_accounts.iter() {
+            require!(info.owner == &ID, AmmError::InvalidVestingAccount);
+            require!(!seen.contains(info.key), AmmError::DuplicateVestingAccount);
+            seen.push(*info.key);
+
+            let data = info.try_borrow_data()?;
+            let stake = VestingStake::try_deserialize(&mut &data[..])?;
+            require!(stake.pool == pool_key && stake.user == user_key, AmmError::InvalidVestingAccount);
+            if stake.claimed {
+                continue;
+            }
+            voter_weight = voter_weight
+                .checked_add(stake.weighted_amount(clock.unix_timestamp))
+                .ok_or(AmmError::NumericOverflow)?;
+        }
+
+        let record = &mut ctx.accounts.voter_weight_record;
+        record.voter_weight = voter_weight;
+        record.voter_weight_expiry = Some(clock.slot);
+        record.weight_action = None;
+        record.weight_action_target = None;
+
+        emit_cpi!(VoterWeightUpdated {
+            pool: pool_key,
+            user: user_key,
+            voter_weight,
+            positions: seen.len() as u32,
+        });
+        Ok(())
+    }
+
+    /// Open a fee vote (authority only): locked positions pick one of `options` as the next
+    /// `protocol_fee_bps` until `voting_end`. Each option must pass the protocol fee bounds with the
+    /// pool's current treasury/reward split. One round runs at a time, and the LP locked now is the
+    /// quorum base.
+    pub fn open_fee_vote(ctx: Context<OpenFeeVote>, options: Vec<u16>, voting_end: i64) -> Result<()> {
+        require!(!options.is_empty(), AmmError::InvalidFeeVoteOptions);
+        require_lte_ctx!(options.len(), MAX_FEE_VOTE_OPTIONS, AmmError::InvalidFeeVoteOptions);
+        let now = Clock::get()?.unix_timestamp;
+        require_gt_ctx!(voting_end, now, AmmError::InvalidDeadline);
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.fee_vote_open, AmmError::FeeVoteOpen);
+        for option in options.iter() {
+            validate_fees(&ctx.accounts.protocol_config, *option, pool.treasury_fee_bps, pool.reward_fee_bps)?;
+        }
+
+        let fee_vote = &mut ctx.accounts.fee_vote;
+        fee_vote.pool = pool.key();
+        fee_vote.round = pool.fee_vote_round;
+        fee_vote.opened_at = now;
+        fee_vote.voting_end = voting_end;
+        fee_vote.option_count = options.len() as u8;
+        fee_vote.options[..options.len()].copy_from_slice(&options);
+        fee_vote.total_locked_lp = pool.total_locked_lp;
+        pool.fee_vote_round = pool.fee_vote_round.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+        pool.fee_vote_open = true;
+
+        emit_cpi!(FeeVoteOpened {
+            pool: fee_vote.pool,
+            round: fee_vote.round,
+            options,
+            voting_end,
+        });
+        Ok(())
+    }
+
+    /// Vote with one still-locked position held since before the round opened, weighted by its
+    /// `weighted_amount` at opening, so weight bought or locked during the vote doesn't count. Each
+    /// position votes once per round.
+    pub fn cast_fee_vote(ctx: Context<CastFeeVote>, option: u8) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let fee_vote = &mut ctx.accounts.fee_vote;
+        require_lt_ctx!(now, fee_vote.voting_end, AmmError::FeeVoteClosed);
+        require_lt_ctx!(option, fee_vote.option_count, AmmError::InvalidFeeVoteOptions);
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::PositionNotLocked);
+        require_lt_ctx!(now, vesting.vesting_end, AmmError::PositionNotLocked);
+        require_lt_ctx!(vesting.held_since, fee_vote.opened_at, AmmError::PositionTooNew);
+
+        let weight = vesting.weighted_amount(fee_vote.opened_at);
+        fee_vote.weights[option as usize] = fee_vote.weights[option as usize]
+            .checked_add(weight)
+            .ok_or(AmmError::NumericOverflow)?;
+        fee_vote.turnout_lp = fee_vote.turnout_lp.checked_add(vesting.amount).ok_or(AmmError::NumericOverflow)?;
+
+        let record = &mut ctx.accounts.fee_vote_record;
+        record.fee_vote = fee_vote.key();
+        record.vesting_stake = vesting.key();
+        record.option = option;
+        record.weight = weight;
+
+        emit_cpi!(FeeVoteCast {
+            pool: fee_vote.pool,
+            round: fee_vote.round,
+            vesting_stake: vesting.key(),
+            user: vesting.user,
+            option,
+            weight,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank closing a fee vote after `voting_end` and applying the option with the
+    /// most weight (the earlier option on a tie). Fees stay unchanged unless positions holding
+    /// `FEE_VOTE_QUORUM_BPS` of the LP locked at opening voted.
+    pub fn finalize_fee_vote(ctx: Context<FinalizeFeeVote>) -> Result<()> {
+        let fee_vote = &mut ctx.accounts.fee_vote;
+        require_gte_ctx!(Clock::get()?.unix_timestamp, fee_vote.voting_end, AmmError::FeeVoteOpen);
+        require!(!fee_vote.finalized, AmmError::FeeVoteFinalized);
+        fee_vote.finalized = true;
+        ctx.accounts.pool.fee_vote_open = false;
+        let quorum = u128::from(fee_vote.total_locked_lp) * u128::from(FEE_VOTE_QUORUM_BPS) / 10_000;
+        let quorum_met = fee_vote.turnout_lp > 0 && u128::from(fee_vote.turnout_lp) >= quorum;
+
+        let (winner, weight) = fee_vote.weights[..fee_vote.option_count as usize]
+            .iter()
+            .enumerate()
+            .fold((0usize, 0u64), |best, (i, w)| if *w > best.1 { (i, *w) } else { best });
+        let round = fee_vote.round;
+        let winning_fee_bps = (quorum_met && weight > 0).then_some(fee_vote.options[winner]);
+
+        if let Some(protocol_fee_bps) = winning_fee_bps {
+            let pool = &mut ctx.accounts.pool;
+            validate_fees(&ctx.accounts.protocol_config, protocol_fee_bps, pool.treasury_fee_bps, pool.reward_fee_bps)?;
+            pool.protocol_fee_bps = protocol_fee_bps;
+            pool.lp_fee_bps = protocol_fee_bps - pool.treasury_fee_bps - pool.reward_fee_bps;
+            emit_cpi!(FeesUpdated {
+                pool: pool.key(),
+                protocol_fee_bps,
+                lp_fee_bps: pool.lp_fee_bps,
+                treasury_fee_bps: pool.treasury_fee_bps,
+                reward_fee_bps: pool.reward_fee_bps,
+            });
+        }
+
+        emit_cpi!(FeeVoteFinalized {
+            pool: ctx.accounts.pool.key(),
+            round,
+            winning_fee_bps,
+            weight,
+        });
+        Ok(())
+    }
+
+    /// Declare an emergency and put a temporary early-unvest penalty waiver to the pool's locked LPs
+    /// (authority only). Still-locked positions vote until `voting_end` with their locked LP; the
+    /// waiver passes with `PENALTY_WAIVER_QUORUM_BPS` of the LP locked at opening approving and more
+    /// approving than rejecting weight. One vote per pool at a time.
+    pub fn open_penalty_waiver_vote(
+        ctx: Context<OpenPenaltyWaiverVote>,
+        reason_code: u16,
+        waiver_seconds: i64,
+        voting_end: i64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require_gt_ctx!(voting_end, now, AmmError::InvalidDeadline);
+        require_gt_ctx!(waiver_seconds, 0, AmmError::InvalidWaiverDuration);
+        require_lte_ctx!(waiver_seconds, MAX_PENALTY_WAIVER_SECONDS, AmmError::InvalidWaiverDuration);
+        let vote = &mut ctx.accounts.penalty_waiver;
+        vote.pool = ctx.accounts.pool.key();
+        vote.reason_code = reason_code;
+        vote.opened_at = now;
+        vote.voting_end = voting_end;
+        vote.waiver_seconds = waiver_seconds;
+        vote.total_locked_lp = ctx.accounts.pool.total_locked_lp;
+        vote.approve_weight = 0;
+        vote.reject_weight = 0;
+        vote.finalized = false;
+        vote.waiver_until = 0;
+
+        emit_cpi!(PenaltyWaiverVoteOpened {
+            pool: vote.pool,
+            reason_code,
+            waiver_seconds,
+            voting_end,
+            total_locked_lp: vote.total_locked_lp,
+        });
+        Ok(())
+    }
+
+    /// Vote on the open penalty waiver with one still-locked position, weighted by its locked LP.
+    /// Each position votes once per waiver vote.
+    pub fn cast_penalty_waiver_vote(ctx: Context<CastPenaltyWaiverVote>, approve: bool) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let vote = &mut ctx.accounts.penalty_waiver;
+        require_lt_ctx!(now, vote.voting_end, AmmError::WaiverVoteClosed);
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::PositionNotLocked);
+        require_lt_ctx!(now, vesting.vesting_end, AmmError::PositionNotLocked);
+
+        let weight = vesting.amount;
+        let tally = if approve { &mut vote.approve_weight } else { &mut vote.reject_weight };
+        *tally = tally.checked_add(weight).ok_or(AmmError::NumericOverflow)?;
+
+        let ballot = &mut ctx.accounts.ballot;
+        ballot.penalty_waiver = vote.key();
+        ballot.vesting_stake = vesting.key();
+        ballot.approve = approve;
+        ballot.weight = weight;
+
+        emit_cpi!(PenaltyWaiverVoteCast {
+            pool: vote.pool,
+            vesting_stake: vesting.key(),
+            user: vesting.user,
+            approve,
+            weight,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank closing the waiver vote after `voting_end`. If it passed, early unvests
+    /// that pass the vote account pay no penalty for `waiver_seconds` from now.
+    pub fn finalize_penalty_waiver_vote(ctx: Context<FinalizePenaltyWaiverVote>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let vote = &mut ctx.accounts.penalty_waiver;
+        require_gte_ctx!(now, vote.voting_end, AmmError::WaiverVoteOpen);
+        require!(!vote.finalized, AmmError::WaiverVoteFinalized);
+        vote.finalized = true;
+
+        let quorum = u128::from(vote.total_locked_lp) * u128::from(PENALTY_WAIVER_QUORUM_BPS) / 10_000;
+        let passed = vote.approve_weight > vote.reject_weight && u128::from(vote.approve_weight) >= quorum;
+        if passed {
+            vote.waiver_until = now + vote.waiver_seconds;
+        }
+
+        emit_cpi!(PenaltyWaiverVoteFinalized {
+            pool: vote.pool,
+            passed,
+            approve_weight: vote.approve_weight,
+            reject_weight: vote.reject_weight,
+            waiver_until: vote.waiver_until,
+        });
+        Ok(())
+    }
+
+    /// Close a finalized waiver vote once any waiver it granted has run out, refunding the rent to
+    /// the pool authority and making room for the next vote. Permissionless.
+    pub fn close_penalty_waiver_vote(ctx: Context<ClosePenaltyWaiverVote>) -> Result<()> {
+        let vote = &ctx.accounts.penalty_waiver;
+        require!(vote.finalized, AmmError::WaiverVoteOpen);
+        require!(!vote.active(Clock::get()?.unix_timestamp), AmmError::WaiverActive);
+        Ok(())
+    }
+
+    /// Start tracking reward epochs (authority only). From here on every change to
+    /// `relock_bonus_bps` opens a new epoch in `RewardEpochs`, and positions keep the relock bonus
+    /// rate of the epoch they were opened or relocked in.
+    pub fn init_reward_epochs(ctx: Context<InitRewardEpochs>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        update_pool_rewards(pool, now)?;
+        pool.reward_epoch = 1;
+        let epochs = &mut ctx.accounts.reward_epochs;
+        epochs.pool = pool.key();
+        epochs.entries = [RewardEpoch::default(); REWARD_EPOCH_RING_SIZE];
+        epochs.record(pool, now);
+        Ok(())
+    }
+
+    /// Switch the pool's emissions to epoch settlement (authority only, one way). Emissions accrued so
+    /// far are folded in as usual; from then on `acc_reward_per_lp` only moves for emissions when
+    /// `finalize_reward_epoch` closes an epoch of `SNAPSHOT_EPOCH_SECONDS`, in one step per epoch
+    /// recorded in the `RewardLedger`. Swap reward fees still credit the accumulator as they arrive.
+    pub fn enable_epoch_rewards(ctx: Context<EnableEpochRewards>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.epoch_rewards, AmmError::EpochRewardsEnabled);
+        update_pool_rewards(pool, now)?;
+        pool.epoch_rewards = true;
+        let ledger = &mut ctx.accounts.reward_ledger;
+        ledger.pool = pool.key();
+        ledger.start_epoch = (now / SNAPSHOT_EPOCH_SECONDS) as u64;
+        ledger.last_finalized_epoch = ledger.start_epoch.saturating_sub(1);
+        ledger.entries = [FinalizedEpoch::default(); REWARD_LEDGER_RING_SIZE];
+        emit_cpi!(EpochRewardsEnabled {
+            pool: pool.key(),
+            start_epoch: ledger.start_epoch,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank closing the next unfinalized epoch once it has ended: its emission (at
+    /// the rate in force now, for the part of the epoch not yet accounted) is split over the LP
+    /// locked at finalization and added to `acc_reward_per_lp` in one step. Epochs are finalized in
+    /// order, one per call; with nothing locked the epoch's emission is not distributed.
+    pub fn finalize_reward_epoch(ctx: Context<FinalizeRewardEpoch>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let ledger = &ctx.accounts.reward_ledger;
+        let epoch_index = ledger.last_finalized_epoch + 1;
+        let epoch_start = epoch_index as i64 * SNAPSHOT_EPOCH_SECONDS;
+        let epoch_end = epoch_start + SNAPSHOT_EPOCH_SECONDS;
+        require_gte_ctx!(now, epoch_end, AmmError::EpochNotEnded);
+
+        let pool = &mut ctx.accounts.pool;
+        let seconds = epoch_end - pool.last_reward_update_ts.max(epoch_start);
+        let emitted = u64::try_from(
+            u128::from(seconds.max(0) as u64) * u128::from(pool.reward_emission_per_second),
+        )
+        .map_err(|_| AmmError::NumericOverflow)?;
+        let total_locked_lp = pool.total_locked_lp;
+        let mut reward_per_lp = if total_locked_lp > 0 {
+            u128::from(emitted) * REWARD_SCALE / u128::from(total_locked_lp)
+        } else {
+            0
+        };
+        match credit_accumulator(pool.acc_reward_per_lp, reward_per_lp) {
+            Some(acc) if reward_per_lp > 0 => {
+                pool.acc_reward_per_lp = acc;
+                pool.add_reward_liability(emitted);
+            }
+            _ => reward_per_lp = 0,
+        }
+        pool.last_reward_update_ts = epoch_end;
+
+        let entry = FinalizedEpoch {
+            epoch_index,
+            emitted: if reward_per_lp > 0 { emitted } else { 0 },
+            total_locked_lp,
+            reward_per_lp,
+            acc_reward_per_lp: pool.acc_reward_per_lp,
+            finalized_at: now,
+        };
+        let ledger = &mut ctx.accounts.reward_ledger;
+        ledger.entries[epoch_index as usize % REWARD_LEDGER_RING_SIZE] = entry;
+        ledger.last_finalized_epoch = epoch_index;
+        emit_cpi!(RewardEpochFinalized {
+            pool: pool.key(),
+            epoch_index,
+            emitted: entry.emitted,
+            total_locked_lp,
+            reward_per_lp,
+            acc_reward_per_lp: entry.acc_reward_per_lp,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank lowering `acc_reward_per_lp` (and the token A/B accumulators) once any
+    /// of them reaches `ACC_REWARD_REBASE_THRESHOLD`, so a long-lived pool never hits the cap where
+    /// reward credits are skipped. Every open position of the pool must be passed as a writable
+    /// remaining account: their LP must add up to `total_locked_lp`. Each accumulator drops by the
+    /// lowest entry point among those positions and every debt is re-expressed against the new
+    /// value, leaving each position's pending reward unchanged. A position that has not settled
+    /// for a long time therefore limits how far the accumulator can come down. Snapshot and ledger
+    /// entries recorded earlier keep the old scale.
+    pub fn rebase_reward_accumulator(ctx: Context<RebaseRewardAccumulator>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        update_pool_rewards(pool, now)?;
+        let acc = [pool.acc_reward_per_lp, pool.acc_reward_a_per_lp, pool.acc_reward_b_per_lp];
+        require!(
+            acc.iter().any(|a| *a >= ACC_REWARD_REBASE_THRESHOLD),
+            AmmError::RebaseNotNeeded
+        );
+        let pool_key = pool.key();
+
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut stakes: Vec<VestingStake> = Vec::with_capacity(ctx.remaining_accounts.len());
+        let mut locked_lp: u64 = 0;
+        let mut base = acc;
+        for info in ctx.remaining_accounts.iter() {
+            require!(info.owner == &ID && info.is_writable, AmmError::InvalidVestingAccount);
+            require!(!seen.contains(info.key), AmmError::DuplicateVestingAccount);
+            seen.push(*info.key);
+
+            let stake = VestingStake::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            require!(stake.pool == pool_key, AmmError::InvalidVestingAccount);
+            if !stake.claimed && stake.amount > 0 {
+                locked_lp = locked_lp.checked_add(stake.amount).ok_or(AmmError::NumericOverflow)?;
+                let debts = [stake.reward_debt, stake.reward_debt_a, stake.reward_debt_b];
+                for (b, debt) in base.iter_mut().zip(debts) {
+                    let entry = debt.checked_mul(REWARD_SCALE).ok_or(AmmError::NumericOverflow)?
+                        / u128::from(stake.amount);
+                    *b = (*b).min(entry);
+                }
+            }
+            stakes.push(stake);
+        }
+        require_eq_ctx!(locked_lp, pool.total_locked_lp, AmmError::RebaseIncomplete);
+
+        for (info, mut stake) in ctx.remaining_accounts.iter().zip(stakes) {
+            if stake.claimed {
+                continue;
+            }
+            stake.reward_debt = rebased_debt(stake.amount, stake.reward_debt, acc[0], base[0])?;
+            stake.reward_debt_a = rebased_debt(stake.amount, stake.reward_debt_a, acc[1], base[1])?;
+            stake.reward_debt_b = rebased_debt(stake.amount, stake.reward_debt_b, acc[2], base[2])?;
+            stake.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+        }
+        pool.acc_reward_per_lp = acc[0] - base[0];
+        pool.acc_reward_a_per_lp = acc[1] - base[1];
+        pool.acc_reward_b_per_lp = acc[2] - base[2];
+
+        emit_cpi!(RewardAccumulatorRebased {
+            pool: pool_key,
+            base: base[0],
+            base_a: base[1],
+            base_b: base[2],
+            acc_reward_per_lp: pool.acc_reward_per_lp,
+            positions: seen.len() as u32,
+        });
+        Ok(())
+    }
+
+    /// Create the pool's rolling epoch snapshot buffer. Anyone may pay for it.
+    pub fn init_epoch_snapshots(ctx: Context<InitEpochSnapshots>) -> Result<()> {
+        let snapshots = &mut ctx.accounts.epoch_snapshot;
+        snapshots.pool = ctx.accounts.pool.key();
+        snapshots.entries = [SnapshotEntry::default(); SNAPSHOT_RING_SIZE];
+        Ok(())
+    }
+
+    /// Permissionless crank recording total locked LP and `acc_reward_per_lp` for the current epoch,
+    /// so off-chain reward programs can verify per-epoch participation from a single account.
+    pub fn snapshot_locked_balances(ctx: Context<SnapshotLockedBalances>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let epoch_index = (now / SNAPSHOT_EPOCH_SECONDS) as u64;
+        let slot = (epoch_index % SNAPSHOT_RING_SIZE as u64) as usize;
+        let snapshots = &mut ctx.accounts.epoch_snapshot;
+        require!(
+            snapshots.entries[slot].timestamp == 0 || snapshots.entries[slot].epoch_index != epoch_index,
+            AmmError::EpochAlreadySnapshotted
+        );
+
+        let entry = SnapshotEntry {
+            epoch_index,
+            total_locked_lp: ctx.accounts.pool.total_locked_lp,
+            acc_reward_per_lp: ctx.accounts.pool.acc_reward_per_lp,
+            timestamp: now,
+        };
+        snapshots.entries[slot] = entry;
+        snapshots.latest_epoch = epoch_index;
+
+        emit_cpi!(EpochSnapshotted {
+            pool: ctx.accounts.pool.key(),
+            epoch_index,
+            total_locked_lp: entry.total_locked_lp,
+            acc_reward_per_lp: entry.acc_reward_per_lp,
+            timestamp: now,
+        });
+        Ok(())
+    }
+
+    /// Burn unlocked LP tokens and withdraw proportional amounts of token A and B from pool reserves.
+    /// The signer may be the LP owner or an approved SPL delegate; proceeds go to the owner.
+    pub fn withdraw_unlocked(ctx: Context<Withdraw>, lp_amount: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp) || ctx.accounts.pool.compromised, AmmError::Paused);
+        require!(lp_amount > 0, AmmError::ZeroLpAmount);
+        ctx.accounts.check_lp_authority(lp_amount)?;
+
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_supply > 0, AmmError::InsufficientLiquidity);
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
+        let amount_a = (u128::from(ctx.accounts.reserve_a.amount)
+            .checked_mul(u128::from(lp_amount))
+            .ok_or(AmmError::NumericOverflow)?
+            / u128::from(lp_supply)) as u64;
+
+        let amount_b = (u128::from(ctx.accounts.reserve_b.amount)
+            .checked_mul(u128::from(lp_amount))
+            .ok_or(AmmError::NumericOverflow)?
+            / u128::from(lp_supply)) as u64;
+
+        #[cfg(feature = "accounting-checks")]
+        let snapshot = accounting::Snapshot::take(
+            vec![ctx.accounts.reserve_a.to_account_info()],
+            vec![ctx.accounts.reserve_b.to_account_info()],
+            Some(ctx.accounts.lp_mint.to_account_info()),
+        );
+        token::burn(ctx.accounts.burn_lp_context(), lp_amount)?;
+        token::transfer(
+            ctx.accounts.transfer_a_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+            amount_a,
+        )?;
+        token::transfer(
+            ctx.accounts.transfer_b_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+            amount_b,
+        )?;
+        #[cfg(feature = "accounting-checks")]
+        snapshot.assert_moved(
+            "withdraw_unlocked",
+            -i128::from(amount_a),
+            -i128::from(amount_b),
+            -i128::from(lp_amount),
+        );
+
+        emit_cpi!(Withdrawn {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            lp_amount,
+            amount_a,
+            amount_b,
+        });
+
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.withdrawals = stats.withdrawals.saturating_add(1);
+            stats.updated_ts = Clock::get()?.unix_timestamp;
+        }
+        if let Some(view) = ctx.accounts.pool_view.as_mut() {
+            reload_pool_view(
+                view,
+                &ctx.accounts.pool,
+                &mut ctx.accounts.reserve_a,
+                &mut ctx.accounts.reserve_b,
+                &mut ctx.accounts.lp_mint,
+                Clock::get()?.unix_timestamp,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Simple constant-product swap with protocol fee charged (fee goes to the pool reserves).
+    /// A portion of the protocol fee is routed to treasury and a portion to the reward pool (simple model).
+    pub fn swap(
+        ctx: Context<Swap>,
+        amount_in: u64,
+        minimum_amount_out: u64,
+        is_a_to_b: bool,
+        min_slot: Option<u64>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.pool.is_paused(Clock::get()?.unix_timestamp), AmmError::Paused);
+        require!(!ctx.accounts.pool.direction_paused(is_a_to_b), AmmError::SwapDirectionPaused);
+        require!(
+            !(ctx.accounts.pool.depegged && ctx.accounts.pool.depeg_blocks_swaps),
+            AmmError::PoolDepegged
+        );
+        require!(amount_in > 0, AmmError::ZeroSwapAmount);
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+        check_swap_caller(
+            &ctx.accounts.pool,
+            ctx.accounts.allowed_callers.as_deref().map(|c| &**c),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+
+        let clock = Clock::get()?;
+        if let Some(ms) = min_slot {
+            require_gte_ctx!(clock.slot, ms, AmmError::SlotTooLow);
+        }
+
+        // Bootstrap phase: only registered, unexpired market makers may trade
+        if ctx.accounts.pool.swap_allowlist_active(clock.unix_timestamp) {
+            let entry = ctx
+                .accounts
+                .market_maker_entry
+                .as_ref()
+                .ok_or(AmmError::NotMarketMaker)?;
+            require_lt_ctx!(clock.unix_timestamp, entry.expires_at, AmmError::NotMarketMaker);
+        }
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        update_pool_rewards(&mut ctx.accounts.pool, clock.unix_timestamp)?;
+
+        // Price against a reserve snapshot taken before any transfer
+        let (reserve_in_before, reserve_out_before) = if is_a_to_b {
+            (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount)
+        } else {
+            (ctx.accounts.reserve_b.amount, ctx.accounts.reserve_a.amount)
+        };
+
+        let quote = compute_trade_quote(
+            &ctx.accounts.pool,
+            Some(&ctx.accounts.price_observations),
+            amount_in,
+            ctx.accounts.reserve_a.amount,
+            ctx.accounts.reserve_b.amount,
+            is_a_to_b,
+            clock.unix_timestamp,
+        )?;
+        let amount_out = quote.amount_out;
+        let treasury_fee = quote.treasury_fee;
+        let fee_in_a = ctx.accounts.pool.fee_in_token_a(is_a_to_b);
+
+        // Accumulate the pre-swap price into the TWAP buffer and the de-peg breaker. A trade that
+        // trips the breaker still settles, but every later deposit (and swap, if configured) is refused
+        let (reserve_a_before, reserve_b_before) = (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount);
+        let (_, trip_depeg) = observe_price(
+            &mut ctx.accounts.pool,
+            &mut ctx.accounts.price_observations,
+            reserve_a_before,
+            reserve_b_before,
+            clock.unix_timestamp,
+        )?;
+        // Compute new reward accumulators locally (no mutable borrow)
+        let total_locked_lp = ctx.accounts.lp_mint.supply; // naive
+        let (acc_reward_per_lp_local, acc_reward_a_per_lp_local, acc_reward_b_per_lp_local, underlying_reward_fee) =
+            accrue_swap_reward_fee(&ctx.accounts.pool, &quote, fee_in_a, total_locked_lp)?;
+
+        require_gte_ctx!(amount_out, minimum_amount_out, AmmError::SlippageExceeded);
+
+        #[cfg(feature = "accounting-checks")]
+        let snapshot = ctx.accounts.accounting_snapshot();
+
+        // Do CPIs (transfers): input in, treasury/reward fees out of the fee-side reserve, then the
+        // output. The input always lands before any reserve is debited.
+        if is_a_to_b {
+            token::transfer(ctx.accounts.transfer_in_a_context(), amount_in)?;
+        } else {
+            token::transfer(ctx.accounts.transfer_in_b_context(), amount_in)?;
+        }
+        if treasury_fee > 0 {
+            ctx.accounts.create_treasury_ata_if_missing(fee_in_a)?;
+            if fee_in_a {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_treasury_from_reserve_a_context()
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    treasury_fee,
+                )?;
+            } else {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_treasury_from_reserve_b_context()
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    treasury_fee,
+                )?;
+            }
+        }
+        if underlying_reward_fee > 0 {
+            if fee_in_a {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_reward_fee_from_reserve_a_context()?
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    underlying_reward_fee,
+                )?;
+            } else {
+                token::transfer(
+                    ctx.accounts
+                        .transfer_reward_fee_from_reserve_b_context()?
+                        .with_signer(pool_signer!(ctx.accounts.pool)),
+                    underlying_reward_fee,
+                )?;
+            }
+        }
+        if is_a_to_b {
+            token::transfer(
+                ctx.accounts.transfer_out_b_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_out,
+            )?;
+        } else {
+            token::transfer(
+                ctx.accounts.transfer_out_a_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_out,
+            )?;
+        }
+
+        #[cfg(feature = "accounting-checks")]
+        {
+            let (a, b) = accounting::trade_deltas(is_a_to_b, amount_in, amount_out);
+            snapshot.assert_moved("swap", a, b, 0);
+        }
+
+        // Re-read actual balances and make sure the swap never shrank k
+        ctx.accounts.reserve_a.reload()?;
+        ctx.accounts.reserve_b.reload()?;
+        let (reserve_in_after, reserve_out_after) = if is_a_to_b {
+            (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount)
+        } else {
+            (ctx.accounts.reserve_b.amount, ctx.accounts.reserve_a.amount)
+        };
+        check_invariant(
+            &ctx.accounts.pool,
+            clock.unix_timestamp,
+            reserve_in_before,
+            reserve_out_before,
+            reserve_in_after,
+            reserve_out_after,
+        )?;
+
+        // Now mutate pool.acc_reward_per_lp
+        let pool = &mut ctx.accounts.pool;
+        if trip_depeg {
+            emit_cpi!(DepegDetected { pool: pool.key() });
+        }
+        let acc_reward_per_lp_before = pool.acc_reward_per_lp;
+        pool.acc_reward_per_lp = acc_reward_per_lp_local;
+        pool.acc_reward_a_per_lp = acc_reward_a_per_lp_local;
+        pool.acc_reward_b_per_lp = acc_reward_b_per_lp_local;
+        pool.book_accumulator_liability(acc_reward_per_lp_before);
+
+        ctx.accounts.pool_stats.record_swap(&quote, is_a_to_b, fee_in_a, clock.unix_timestamp);
+        if quote.lp_fee > 0 {
+            let stats = &ctx.accounts.pool_stats;
+            emit_cpi!(LiquidityReinvested {
+                pool: ctx.accounts.pool.key(),
+                fee_in_a,
+                amount: quote.lp_fee,
+                total_reinvested_a: stats.lp_fees_a,
+                total_reinvested_b: stats.lp_fees_b,
+                reserve_a: ctx.accounts.reserve_a.amount,
+                reserve_b: ctx.accounts.reserve_b.amount,
+                lp_supply,
+            });
+        }
+
+        emit_cpi!(FeesAccrued {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            lp_fee: quote.lp_fee,
+            treasury_fee: quote.treasury_fee,
+            reward_fee: quote.reward_fee,
+            acc_reward_per_lp_after: acc_reward_per_lp_local,
+            acc_reward_a_per_lp_after: acc_reward_a_per_lp_local,
+            acc_reward_b_per_lp_after: acc_reward_b_per_lp_local,
+        });
+
+        emit_cpi!(Swapped {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            amount_in,
+            amount_out,
+            is_a_to_b,
+        });
+
+        // Trader rebate: LP worth a slice of the LP fee (valued against half the LP supply's
+        // fee-side reserve), locked in the trader's rebate position
+        let rebate_fee =
+            (u128::from(quote.lp_fee) * u128::from(ctx.accounts.pool.trader_rebate_bps) / 10_000u128) as u64;
+        let fee_reserve_before = if fee_in_a == is_a_to_b { reserve_in_before } else { reserve_out_before };
+        if rebate_fee > 0 && lp_supply > 0 && fee_reserve_before > 0 {
+            let rebate_lp =
+                (u128::from(rebate_fee) * u128::from(lp_supply) / (2 * u128::from(fee_reserve_before))) as u64;
+            if rebate_lp > 0 {
+                if let Some(vesting_end) = ctx.accounts.credit_trader_rebate(rebate_lp, clock.unix_timestamp)? {
+                    emit_cpi!(TraderRebateAccrued {
+                        pool: ctx.accounts.pool.key(),
+                        user: ctx.accounts.user.key(),
+                        fee_in_a,
+                        fee_amount: rebate_fee,
+                        lp_amount: rebate_lp,
+                        vesting_end,
+                    });
+                }
+            }
+        }
+
+        if let Some(stats) = ctx.accounts.global_stats.as_mut() {
+            stats.record_swap(treasury_fee, clock.unix_timestamp);
+        }
+        if let Some(trader) = ctx.accounts.trader_points.as_mut() {
+            let (volume_a, volume_b) = if is_a_to_b { (amount_in, amount_out) } else { (amount_out, amount_in) };
+            trader.volume_a = trader.volume_a.saturating_add(u128::from(volume_a));
+            trader.volume_b = trader.volume_b.saturating_add(u128::from(volume_b));
+            if lp_supply > 0 && reserve_in_before > 0 {
+                let points = u128::from(amount_in) * u128::from(lp_supply) / (2 * u128::from(reserve_in_before));
+                trader.points = trader.points.saturating_add(points.try_into().unwrap_or(u64::MAX));
+            }
+        }
+
+        if let Some(view) = ctx.accounts.pool_view.as_mut() {
+            reload_pool_view(
+                view,
+                &ctx.accounts.pool,
+                &mut ctx.accounts.reserve_a,
+                &mut ctx.accounts.reserve_b,
+                &mut ctx.accounts.lp_mint,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Sell `amount_in_total` over `num_slices` equal slices instead of one trade. The input is
+    /// escrowed in a `TwapOrder` (PDA `["twap_order", pool, user, order_id]`) and anyone may run
+    /// `execute_twap_slice` once per `TWAP_SLICE_SLOTS`. Each slice must return its pro-rata share of
+    /// `min_out_total` and the last one whatever is still missing. On `caller_restricted` pools the
+    /// order must be placed through an allowed caller, as `swap` would be.
+    pub fn swap_twap(
+        ctx: Context<SwapTwap>,
+        amount_in_total: u64,
+        num_slices: u16,
+        min_out_total: u64,
+        is_a_to_b: bool,
+        order_id: u64,
+    ) -> Result<()> {
+        require_gt_ctx!(num_slices, 0, AmmError::InvalidTwapOrder);
+        require_lte_ctx!(num_slices, MAX_TWAP_SLICES, AmmError::InvalidTwapOrder);
+        require_gte_ctx!(amount_in_total, u64::from(num_slices), AmmError::InvalidTwapOrder);
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+        check_swap_caller(
+            &ctx.accounts.pool,
+            ctx.accounts.allowed_callers.as_deref().map(|c| &**c),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+        token::transfer(ctx.accounts.transfer_to_escrow_context(), amount_in_total)?;
+
+        let order = &mut ctx.accounts.twap_order;
+        order.pool = ctx.accounts.pool.key();
+        order.owner = ctx.accounts.user.key();
+        order.destination = ctx.accounts.destination.key();
+        order.order_id = order_id;
+        order.bump = ctx.bumps.twap_order;
+        order.is_a_to_b = is_a_to_b;
+        order.amount_in_total = amount_in_total;
+        order.amount_in_remaining = amount_in_total;
+        order.min_out_total = min_out_total;
+        order.num_slices = num_slices;
+        emit_cpi!(TwapOrderCreated {
+            pool: order.pool,
+            owner: order.owner,
+            order_id,
+            amount_in_total,
+            num_slices,
+            min_out_total,
+            is_a_to_b,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank executing the next slice of a `TwapOrder` against the pool, with the
+    /// same pause, de-peg, fee and invariant rules as `swap`; the caller restriction was applied when
+    /// the order was placed. The last slice closes the order and its escrow, refunding rent to the owner.
+    pub fn execute_twap_slice(ctx: Context<ExecuteTwapSlice>) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        let order = (*ctx.accounts.twap_order).clone(); // owned copy, so the fill can sign with its seeds
+        let is_a_to_b = order.is_a_to_b;
+        if order.slices_done > 0 {
+            require_gte_ctx!(slot, order.last_slot.saturating_add(TWAP_SLICE_SLOTS), AmmError::TwapSliceTooSoon);
+        }
+
+        let last_slice = order.slices_done + 1 == order.num_slices;
+        let amount_in = if last_slice {
+            order.amount_in_remaining
+        } else {
+            order.amount_in_remaining / u64::from(order.num_slices - order.slices_done)
+        };
+        let minimum_amount_out = if last_slice {
+            order.min_out_total.saturating_sub(order.amount_out_filled)
+        } else {
+            (u128::from(order.min_out_total) * u128::from(amount_in) / u128::from(order.amount_in_total)) as u64
+        };
+
+        let fill = execute_order_swap(
+            ctx.accounts.order_swap(order_signer!(SEED_TWAP_ORDER, order)),
+            amount_in,
+            u128::from(minimum_amount_out),
+            is_a_to_b,
+            AmmError::SlippageExceeded,
+        )?;
+        let amount_out = fill.quote.amount_out;
+        if fill.depeg_tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+        emit_cpi!(FeesAccrued {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            lp_fee: fill.quote.lp_fee,
+            treasury_fee: fill.quote.treasury_fee,
+            reward_fee: fill.quote.reward_fee,
+            acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            acc_reward_a_per_lp_after: ctx.accounts.pool.acc_reward_a_per_lp,
+            acc_reward_b_per_lp_after: ctx.accounts.pool.acc_reward_b_per_lp,
+        });
+        if fill.quote.lp_fee > 0 {
+            let stats = &ctx.accounts.pool_stats;
+            emit_cpi!(LiquidityReinvested {
+                pool: ctx.accounts.pool.key(),
+                fee_in_a: fill.fee_in_a,
+                amount: fill.quote.lp_fee,
+                total_reinvested_a: stats.lp_fees_a,
+                total_reinvested_b: stats.lp_fees_b,
+                reserve_a: ctx.accounts.reserve_a.amount,
+                reserve_b: ctx.accounts.reserve_b.amount,
+                lp_supply: fill.lp_supply,
+            });
+        }
+        emit_cpi!(Swapped {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.twap_order.owner,
+            amount_in,
+            amount_out,
+            is_a_to_b,
+        });
+
+        let order = &mut ctx.accounts.twap_order;
+        order.amount_in_remaining -= amount_in;
+        order.amount_out_filled = order.amount_out_filled.checked_add(amount_out).ok_or(AmmError::NumericOverflow)?;
+        order.slices_done += 1;
+        order.last_slot = slot;
+        emit_cpi!(TwapSliceExecuted {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            slice: order.slices_done,
+            amount_in,
+            amount_out,
+            amount_in_remaining: order.amount_in_remaining,
+            amount_out_filled: order.amount_out_filled,
+        });
+
+        if last_slice {
+            token::close_account(
+                ctx.accounts
+                    .close_escrow_context()
+                    .with_signer(order_signer!(SEED_TWAP_ORDER, ctx.accounts.twap_order)),
+            )?;
+            ctx.accounts.twap_order.close(ctx.accounts.owner.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    /// Cancel a TWAP order (owner only): unsold input goes back to `owner_token_in`, output already
+    /// filled stays with the owner, and the order and escrow are closed.
+    pub fn cancel_twap_order(ctx: Context<CancelTwapOrder>) -> Result<()> {
+        let remaining = ctx.accounts.twap_escrow.amount;
+        if remaining > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_escrow_to_owner_context()
+                    .with_signer(order_signer!(SEED_TWAP_ORDER, ctx.accounts.twap_order)),
+                remaining,
+            )?;
+        }
+        token::close_account(
+            ctx.accounts.close_escrow_context().with_signer(order_signer!(SEED_TWAP_ORDER, ctx.accounts.twap_order)),
+        )?;
+        let order = &ctx.accounts.twap_order;
+        emit_cpi!(TwapOrderCancelled {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            amount_in_refunded: remaining,
+            amount_out_filled: order.amount_out_filled,
+        });
+        Ok(())
+    }
+
+    /// Escrow `amount_in` as a limit order that any cranker may fill against the pool once it pays at
+    /// least `min_price` (output per input, scaled by `PRICE_SCALE`) after fees, until `expiry`.
+    /// `tip_lamports` sit on the order and go to whoever fills it. On `caller_restricted` pools the
+    /// order must be placed through an allowed caller, as `swap` would be.
+    pub fn place_limit_order(
+        ctx: Context<PlaceLimitOrder>,
+        amount_in: u64,
+        min_price: u128,
+        expiry: i64,
+        is_a_to_b: bool,
+        order_id: u64,
+        tip_lamports: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0 && min_price > 0, AmmError::InvalidLimitOrder);
+        require_gt_ctx!(expiry, Clock::get()?.unix_timestamp, AmmError::InvalidDeadline);
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+        check_swap_caller(
+            &ctx.accounts.pool,
+            ctx.accounts.allowed_callers.as_deref().map(|c| &**c),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+        token::transfer(ctx.accounts.transfer_to_escrow_context(), amount_in)?;
+        if tip_lamports > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.user.to_account_info(),
+                        to: ctx.accounts.limit_order.to_account_info(),
+                    },
+                ),
+                tip_lamports,
+            )?;
+        }
+
+        let order = &mut ctx.accounts.limit_order;
+        order.pool = ctx.accounts.pool.key();
+        order.owner = ctx.accounts.user.key();
+        order.destination = ctx.accounts.destination.key();
+        order.order_id = order_id;
+        order.bump = ctx.bumps.limit_order;
+        order.is_a_to_b = is_a_to_b;
+        order.amount_in = amount_in;
+        order.min_price = min_price;
+        order.expiry = expiry;
+        order.tip_lamports = tip_lamports;
+        emit_cpi!(LimitOrderPlaced {
+            pool: order.pool,
+            owner: order.owner,
+            order_id,
+            amount_in,
+            min_price,
+            expiry,
+            is_a_to_b,
+            tip_lamports,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank filling a limit order in full once the pool pays its limit price, with
+    /// the same pause, de-peg, fee and invariant rules as `swap`; the caller restriction was applied
+    /// when the order was placed. The cranker takes the tip; the order and escrow close to the owner.
+    pub fn fill_limit_order(ctx: Context<FillLimitOrder>) -> Result<()> {
+        let order = (*ctx.accounts.limit_order).clone(); // owned copy, so the fill can sign with its seeds
+        require_lt_ctx!(Clock::get()?.unix_timestamp, order.expiry, AmmError::LimitOrderExpired);
+        let is_a_to_b = order.is_a_to_b;
+        let amount_in = order.amount_in;
+        let limit_out = order
+            .min_price
+            .checked_mul(u128::from(amount_in))
+            .ok_or(AmmError::NumericOverflow)?
+            .div_ceil(PRICE_SCALE);
+
+        let fill = execute_order_swap(
+            ctx.accounts.order_swap(order_signer!(SEED_LIMIT_ORDER, order)),
+            amount_in,
+            limit_out,
+            is_a_to_b,
+            AmmError::LimitPriceNotReached,
+        )?;
+        let amount_out = fill.quote.amount_out;
+        if fill.depeg_tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+        emit_cpi!(FeesAccrued {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            lp_fee: fill.quote.lp_fee,
+            treasury_fee: fill.quote.treasury_fee,
+            reward_fee: fill.quote.reward_fee,
+            acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            acc_reward_a_per_lp_after: ctx.accounts.pool.acc_reward_a_per_lp,
+            acc_reward_b_per_lp_after: ctx.accounts.pool.acc_reward_b_per_lp,
+        });
+        if fill.quote.lp_fee > 0 {
+            let stats = &ctx.accounts.pool_stats;
+            emit_cpi!(LiquidityReinvested {
+                pool: ctx.accounts.pool.key(),
+                fee_in_a: fill.fee_in_a,
+                amount: fill.quote.lp_fee,
+                total_reinvested_a: stats.lp_fees_a,
+                total_reinvested_b: stats.lp_fees_b,
+                reserve_a: ctx.accounts.reserve_a.amount,
+                reserve_b: ctx.accounts.reserve_b.amount,
+                lp_supply: fill.lp_supply,
+            });
+        }
+        emit_cpi!(Swapped {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.limit_order.owner,
+            amount_in,
+            amount_out,
+            is_a_to_b,
+        });
+        // Tip to the cranker, then everything else on the order and escrow back to the owner
+        let tip = ctx.accounts.limit_order.tip_lamports;
+        token::close_account(
+            ctx.accounts.close_escrow_context().with_signer(order_signer!(SEED_LIMIT_ORDER, ctx.accounts.limit_order)),
+        )?;
+        if tip > 0 {
+            let order_info = ctx.accounts.limit_order.to_account_info();
+            let cranker_info = ctx.accounts.cranker.to_account_info();
+            **order_info.try_borrow_mut_lamports()? =
+                order_info.lamports().checked_sub(tip).ok_or(AmmError::NumericOverflow)?;
+            **cranker_info.try_borrow_mut_lamports()? =
+                cranker_info.lamports().checked_add(tip).ok_or(AmmError::NumericOverflow)?;
+        }
+        let order = &ctx.accounts.limit_order;
+        emit_cpi!(LimitOrderFilled {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            amount_in,
+            amount_out,
+            cranker: ctx.accounts.cranker.key(),
+            tip_lamports: tip,
+        });
+        ctx.accounts.limit_order.close(ctx.accounts.owner.to_account_info())?;
+        Ok(())
+    }
+
+    /// Cancel a limit order (owner only), returning the escrowed input and the tip.
+    pub fn cancel_limit_order(ctx: Context<CancelLimitOrder>) -> Result<()> {
+        let amount = ctx.accounts.limit_escrow.amount;
+        if amount > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_escrow_to_owner_context()
+                    .with_signer(order_signer!(SEED_LIMIT_ORDER, ctx.accounts.limit_order)),
+                amount,
+            )?;
+        }
+        token::close_account(
+            ctx.accounts.close_escrow_context().with_signer(order_signer!(SEED_LIMIT_ORDER, ctx.accounts.limit_order)),
+        )?;
+        let order = &ctx.accounts.limit_order;
+        emit_cpi!(LimitOrderCancelled {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            amount_in_refunded: amount,
+        });
+        Ok(())
+    }
+
+    /// Escrow `total` input for a recurring buy of `amount_per_interval` every `interval` seconds.
+    /// Fills are run by anyone via `execute_dca_fill`; each must pay at least `min_price` (output
+    /// per input after fees, `PRICE_SCALE`; 0 = any price). On `caller_restricted` pools the order
+    /// must be created through an allowed caller, as `swap` would be.
+    pub fn create_dca_order(
+        ctx: Context<CreateDcaOrder>,
+        amount_per_interval: u64,
+        interval: i64,
+        total: u64,
+        is_a_to_b: bool,
+        order_id: u64,
+        min_price: u128,
+    ) -> Result<()> {
+        require!(amount_per_interval > 0 && interval > 0, AmmError::InvalidDcaOrder);
+        require_gte_ctx!(total, amount_per_interval, AmmError::InvalidDcaOrder);
+        check_not_blocked(
+            &ctx.accounts.pool,
+            ctx.accounts.blocklist_entry.as_ref(),
+            ctx.accounts.user.key(),
+        )?;
+        check_swap_caller(
+            &ctx.accounts.pool,
+            ctx.accounts.allowed_callers.as_deref().map(|c| &**c),
+            ctx.accounts.instructions_sysvar.as_ref(),
+        )?;
+        token::transfer(ctx.accounts.transfer_to_escrow_context(), total)?;
+
+        let order = &mut ctx.accounts.dca_order;
+        order.pool = ctx.accounts.pool.key();
+        order.owner = ctx.accounts.user.key();
+        order.destination = ctx.accounts.destination.key();
+        order.order_id = order_id;
+        order.bump = ctx.bumps.dca_order;
+        order.is_a_to_b = is_a_to_b;
+        order.amount_per_interval = amount_per_interval;
+        order.interval = interval;
+        order.total = total;
+        order.min_price = min_price;
+        order.next_fill_ts = Clock::get()?.unix_timestamp;
+        emit_cpi!(DcaOrderCreated {
+            pool: order.pool,
+            owner: order.owner,
+            order_id,
+            amount_per_interval,
+            interval,
+            total,
+            is_a_to_b,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank buying the next `amount_per_interval` (or what is left) of a DCA order
+    /// once `next_fill_ts` is reached, under `swap`'s rules; the caller restriction was applied when the
+    /// order was created. The fill that spends the last input closes the order and its escrow to the owner.
+    pub fn execute_dca_fill(ctx: Context<ExecuteDcaFill>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let order = (*ctx.accounts.dca_order).clone(); // owned copy, so the fill can sign with its seeds
+        require_gte_ctx!(now, order.next_fill_ts, AmmError::DcaFillTooSoon);
+        let is_a_to_b = order.is_a_to_b;
+        let amount_in = order.amount_per_interval.min(order.total - order.spent);
+        let min_amount_out = (order.min_price * u128::from(amount_in)).div_ceil(PRICE_SCALE);
+
+        let fill = execute_order_swap(
+            ctx.accounts.order_swap(order_signer!(SEED_DCA_ORDER, order)),
+            amount_in,
+            min_amount_out,
+            is_a_to_b,
+            AmmError::SlippageExceeded,
+        )?;
+        let amount_out = fill.quote.amount_out;
+        if fill.depeg_tripped {
+            emit_cpi!(DepegDetected { pool: ctx.accounts.pool.key() });
+        }
+        emit_cpi!(FeesAccrued {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            lp_fee: fill.quote.lp_fee,
+            treasury_fee: fill.quote.treasury_fee,
+            reward_fee: fill.quote.reward_fee,
+            acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+            acc_reward_a_per_lp_after: ctx.accounts.pool.acc_reward_a_per_lp,
+            acc_reward_b_per_lp_after: ctx.accounts.pool.acc_reward_b_per_lp,
+        });
+        if fill.quote.lp_fee > 0 {
+            let stats = &ctx.accounts.pool_stats;
+            emit_cpi!(LiquidityReinvested {
+                pool: ctx.accounts.pool.key(),
+                fee_in_a: fill.fee_in_a,
+                amount: fill.quote.lp_fee,
+                total_reinvested_a: stats.lp_fees_a,
+                total_reinvested_b: stats.lp_fees_b,
+                reserve_a: ctx.accounts.reserve_a.amount,
+                reserve_b: ctx.accounts.reserve_b.amount,
+                lp_supply: fill.lp_supply,
+            });
+        }
+        emit_cpi!(Swapped {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.dca_order.owner,
+            amount_in,
+            amount_out,
+            is_a_to_b,
+        });
+
+        let order = &mut ctx.accounts.dca_order;
+        order.spent += amount_in;
+        order.received = order.received.checked_add(amount_out).ok_or(AmmError::NumericOverflow)?;
+        order.fills += 1;
+        order.next_fill_ts = now.checked_add(order.interval).ok_or(AmmError::NumericOverflow)?;
+        let done = order.spent == order.total;
+        emit_cpi!(DcaFilled {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            fill: order.fills,
+            amount_in,
+            amount_out,
+            spent: order.spent,
+            received: order.received,
+        });
+
+        if done {
+            token::close_account(
+                ctx.accounts.close_escrow_context().with_signer(order_signer!(SEED_DCA_ORDER, ctx.accounts.dca_order)),
+            )?;
+            ctx.accounts.dca_order.close(ctx.accounts.owner.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    /// Stop a DCA order (owner only), refunding the unspent input and closing the order.
+    pub fn cancel_dca_order(ctx: Context<CancelDcaOrder>) -> Result<()> {
+        let amount = ctx.accounts.dca_escrow.amount;
+        if amount > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_escrow_to_owner_context()
+                    .with_signer(order_signer!(SEED_DCA_ORDER, ctx.accounts.dca_order)),
+                amount,
+            )?;
+        }
+        token::close_account(
+            ctx.accounts.close_escrow_context().with_signer(order_signer!(SEED_DCA_ORDER, ctx.accounts.dca_order)),
+        )?;
+        let order = &ctx.accounts.dca_order;
+        emit_cpi!(DcaOrderCancelled {
+            pool: order.pool,
+            owner: order.owner,
+            order_id: order.order_id,
+            amount_in_refunded: amount,
+            received: order.received,
+        });
+        Ok(())
+    }
+
+    /// Stream `total` from a pool-held treasury account (`pool.treasury`, `treasury_a` or
+    /// `treasury_b`) to `recipient`, vesting linearly from `start_ts` to `end_ts` with nothing
+    /// claimable before `cliff_ts` (authority only). The funds move into the stream's escrow now.
+    pub fn create_treasury_stream(
+        ctx: Context<CreateTreasuryStream>,
+        stream_id: u64,
+        recipient: Pubkey,
+        total: u64,
+        start_ts: i64,
+        cliff_ts: i64,
+        end_ts: i64,
+    ) -> Result<()> {
+        require_gt_ctx!(total, 0, AmmError::InvalidStream);
+        require_lt_ctx!(start_ts, end_ts, AmmError::InvalidStream);
+        require_gte_ctx!(cliff_ts, start_ts, AmmError::InvalidStream);
+        require_lte_ctx!(cliff_ts, end_ts, AmmError::InvalidStream);
+        token::transfer(ctx.accounts.transfer_to_escrow_context().with_signer(pool_signer!(ctx.accounts.pool)), total)?;
+
+        let stream = &mut ctx.accounts.treasury_stream;
+        stream.pool = ctx.accounts.pool.key();
+        stream.recipient = recipient;
+        stream.mint = ctx.accounts.treasury_source.mint;
+        stream.source = ctx.accounts.treasury_source.key();
+        stream.stream_id = stream_id;
+        stream.bump = ctx.bumps.treasury_stream;
+        stream.total = total;
+        stream.start_ts = start_ts;
+        stream.cliff_ts = cliff_ts;
+        stream.end_ts = end_ts;
+        emit_cpi!(TreasuryStreamCreated {
+            pool: stream.pool,
+            stream: stream.key(),
+            recipient,
+            mint: stream.mint,
+            total,
+            start_ts,
+            cliff_ts,
+            end_ts,
+        });
+        Ok(())
+    }
+
+    /// Pay the recipient everything vested and not yet claimed. The stream and its escrow are
+    /// closed (rent to the recipient) once fully paid.
+    pub fn claim_stream(ctx: Context<ClaimStream>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stream = &ctx.accounts.treasury_stream;
+        let amount = stream.vested(now).saturating_sub(stream.claimed);
+        require!(amount > 0, AmmError::NothingToRedeem);
+        let (pool_key, stream_id, bump) = (stream.pool, stream.stream_id.to_le_bytes(), stream.bump);
+        let stream_signer: &[&[&[u8]]] = &[&[SEED_TREASURY_STREAM, pool_key.as_ref(), &stream_id, &[bump]]];
+        token::transfer(ctx.accounts.transfer_to_recipient_context().with_signer(stream_signer), amount)?;
+
+        let stream = &mut ctx.accounts.treasury_stream;
+        stream.claimed += amount;
+        let remaining = stream.total - stream.claimed;
+        emit_cpi!(StreamClaimed {
+            stream: stream.key(),
+            recipient: stream.recipient,
+            amount,
+            remaining,
+        });
+        if remaining == 0 {
+            token::close_account(ctx.accounts.close_escrow_context().with_signer(stream_signer))?;
+            ctx.accounts.treasury_stream.close(ctx.accounts.recipient.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    /// Stop a stream (authority only): the recipient gets what has vested so far, the rest goes
+    /// back to the treasury account it came from, and the stream is closed.
+    pub fn cancel_treasury_stream(ctx: Context<CancelTreasuryStream>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let stream = &ctx.accounts.treasury_stream;
+        let to_recipient = stream.vested(now).saturating_sub(stream.claimed);
+        let returned = stream.total - stream.claimed - to_recipient;
+        let stream_id = stream.stream_id.to_le_bytes();
+        let stream_signer: &[&[&[u8]]] = &[&[SEED_TREASURY_STREAM, stream.pool.as_ref(), &stream_id, &[stream.bump]]];
+        if to_recipient > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_from_escrow_context(ctx.accounts.recipient_token_account.to_account_info())
+                    .with_signer(stream_signer),
+                to_recipient,
+            )?;
+        }
+        if returned > 0 {
+            token::transfer(
+                ctx.accounts
+                    .transfer_from_escrow_context(ctx.accounts.treasury_source.to_account_info())
+                    .with_signer(stream_signer),
+                returned,
+            )?;
+        }
+        token::close_account(ctx.accounts.close_escrow_context().with_signer(stream_signer))?;
+        emit_cpi!(TreasuryStreamCancelled {
+            stream: ctx.accounts.treasury_stream.key(),
+            recipient: ctx.accounts.treasury_stream.recipient,
+            paid_to_recipient: to_recipient,
+            returned,
+        });
+        Ok(())
+    }
+
+    /// Open the pool's protocol-owned-liquidity bond market (pool authority). Users sell token A/B
+    /// or LP to the protocol with `bond` for `payout_mint` tokens from the market's vault, priced at
+    /// `payout_per_lp` (PRICE_SCALE) plus `discount_bps`, vesting linearly over `vesting_seconds`.
+    /// The market starts inactive with no capacity; see `fund_bond_market` and `set_bond_terms`.
+    pub fn create_bond_market(
+        ctx: Context<CreateBondMarket>,
+        payout_per_lp: u128,
+        discount_bps: u16,
+        vesting_seconds: i64,
+    ) -> Result<()> {
+        check_bond_terms(payout_per_lp, discount_bps, vesting_seconds)?;
+        let market = &mut ctx.accounts.bond_market;
+        market.pool = ctx.accounts.pool.key();
+        market.bump = ctx.bumps.bond_market;
+        market.payout_mint = ctx.accounts.payout_mint.key();
+        market.payout_per_lp = payout_per_lp;
+        market.discount_bps = discount_bps;
+        market.vesting_seconds = vesting_seconds;
+        emit_cpi!(BondMarketCreated {
+            pool: market.pool,
+            bond_market: market.key(),
+            payout_mint: market.payout_mint,
+        });
+        Ok(())
+    }
+
+    /// Reprice or (de)activate the bond market (pool authority). Applies to new bonds only.
+    pub fn set_bond_terms(
+        ctx: Context<SetBondTerms>,
+        payout_per_lp: u128,
+        discount_bps: u16,
+        vesting_seconds: i64,
+        active: bool,
+    ) -> Result<()> {
+        check_bond_terms(payout_per_lp, discount_bps, vesting_seconds)?;
+        let market = &mut ctx.accounts.bond_market;
+        market.payout_per_lp = payout_per_lp;
+        market.discount_bps = discount_bps;
+        market.vesting_seconds = vesting_seconds;
+        market.active = active;
+        emit_cpi!(BondTermsSet {
+            bond_market: market.key(),
+            payout_per_lp,
+            discount_bps,
+            vesting_seconds,
+            active,
+        });
+        Ok(())
+    }
+
+    /// Add payout tokens to the bond vault; the amount becomes bondable capacity. Permissionless,
+    /// so a project can fund it straight from its own treasury.
+    pub fn fund_bond_market(ctx: Context<FundBondMarket>, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::InvalidBondTerms);
+        token::transfer(ctx.accounts.transfer_to_vault_context(), amount)?;
+        let market = &mut ctx.accounts.bond_market;
+        market.capacity = market.capacity.checked_add(amount).ok_or(AmmError::NumericOverflow)?;
+        emit_cpi!(BondMarketFunded {
+            bond_market: market.key(),
+            funder: ctx.accounts.funder.key(),
+            amount,
+            capacity: market.capacity,
+        });
+        Ok(())
+    }
+
+    /// Sell `amount_a`/`amount_b` (deposited into the reserves at the pool ratio; both or neither)
+    /// and/or `lp_amount` LP to the protocol. Everything acquired ends up as LP in `pool.treasury`,
+    /// and the seller gets a `Bond` (`["bond", bond_market, user, bond_id]`) for its payout,
+    /// vesting linearly from now. Input beyond the pool ratio stays with the seller.
+    pub fn bond(
+        ctx: Context<BondTokens>,
+        amount_a: u64,
+        amount_b: u64,
+        lp_amount: u64,
+        min_payout: u64,
+        bond_id: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &ctx.accounts.pool;
+        let market = &ctx.accounts.bond_market;
+        require!(market.active, AmmError::BondMarketInactive);
+        require!(!pool.is_paused(now), AmmError::Paused);
+        if (amount_a > 0) != (amount_b > 0) {
+            return err_ctx!(
+                AmmError::OneSidedDeposit,
+                "bond pays {} token A and {} token B; both or neither must be set",
+                amount_a,
+                amount_b
+            );
+        }
+        if amount_a == 0 && lp_amount == 0 {
+            return err_ctx!(AmmError::ZeroDepositAmount, "bond pays no tokens and {} LP", lp_amount);
+        }
+        check_not_blocked(pool, ctx.accounts.blocklist_entry.as_ref(), ctx.accounts.user.key())?;
+
+        let mut lp_minted = 0u64;
+        let (mut deposit_a, mut deposit_b) = (0u64, 0u64);
+        if amount_a > 0 {
+            let lp_supply = ctx.accounts.lp_mint.supply;
+            check_deposit(
+                pool,
+                (amount_a, amount_b),
+                (&ctx.accounts.reserve_a, &ctx.accounts.reserve_b),
+                lp_supply,
+                ctx.accounts.allowlist_entry.is_some(),
+                now,
+            )?;
+            let (reserve_a, reserve_b) = (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount);
+            require!(lp_supply > 0 && reserve_a > 0 && reserve_b > 0, AmmError::InsufficientLiquidity);
+            let share = |amount: u64, numerator: u64, denominator: u64| -> Result<u64> {
+                u64::try_from(u128::from(amount) * u128::from(numerator) / u128::from(denominator))
+                    .map_err(|_| error!(AmmError::NumericOverflow))
+            };
+            lp_minted = share(amount_a, lp_supply, reserve_a)?.min(share(amount_b, lp_supply, reserve_b)?);
+            require_gt_ctx!(lp_minted, 0, AmmError::DepositTooSmall);
+            // Round the deposit up so the minted LP never exceeds what was paid in
+            deposit_a = share(lp_minted, reserve_a, lp_supply)?.saturating_add(1).min(amount_a);
+            deposit_b = share(lp_minted, reserve_b, lp_supply)?.saturating_add(1).min(amount_b);
+        }
+        let lp_acquired = lp_minted.checked_add(lp_amount).ok_or(AmmError::NumericOverflow)?;
+        let payout = u64::try_from(
+            u128::from(lp_acquired)
+                .checked_mul(market.payout_per_lp)
+                .ok_or(AmmError::NumericOverflow)?
+                / PRICE_SCALE
+                * u128::from(10_000 + u32::from(market.discount_bps))
+                / 10_000u128,
+        )
+        .map_err(|_| AmmError::NumericOverflow)?;
+        require_gt_ctx!(payout, 0, AmmError::DepositTooSmall);
+        require_gte_ctx!(payout, min_payout, AmmError::SlippageExceeded);
+        require_lte_ctx!(payout, market.capacity, AmmError::BondCapacityExceeded);
+
+        if lp_minted > 0 {
+            // The LP supply grows, so bring acc_reward_per_lp up to date first
+            update_pool_rewards(&mut ctx.accounts.pool, now)?;
+            token::transfer(ctx.accounts.transfer_a_context(), deposit_a)?;
+            token::transfer(ctx.accounts.transfer_b_context(), deposit_b)?;
+            token::mint_to(
+                ctx.accounts.mint_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                lp_minted,
+            )?;
+        }
+        if lp_amount > 0 {
+            let user_lp = ctx.accounts.user_lp_token_account.as_ref().ok_or(AmmError::ZeroLpAmount)?;
+            let cpi_accounts = Transfer {
+                from: user_lp.to_account_info(),
+                to: ctx.accounts.treasury_lp_account.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            token::transfer(CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts), lp_amount)?;
+        }
+
+        let vesting_end = now + ctx.accounts.bond_market.vesting_seconds;
+        let market = &mut ctx.accounts.bond_market;
+        market.capacity -= payout;
+        market.total_lp_acquired = market.total_lp_acquired.saturating_add(lp_acquired);
+        market.total_payout = market.total_payout.saturating_add(payout);
+        let bond = &mut ctx.accounts.bond;
+        bond.bond_market = market.key();
+        bond.owner = ctx.accounts.user.key();
+        bond.bond_id = bond_id;
+        bond.payout = payout;
+        bond.vesting_start = now;
+        bond.vesting_end = vesting_end;
+
+        emit_cpi!(Bonded {
+            bond_market: bond.bond_market,
+            owner: bond.owner,
+            bond_id,
+            amount_a: deposit_a,
+            amount_b: deposit_b,
+            lp_amount,
+            lp_acquired,
+            payout,
+            vesting_end,
+        });
+        Ok(())
+    }
+
+    /// Pay out the vested, unredeemed part of a bond to its owner. The bond is closed (rent to the
+    /// owner) once fully redeemed.
+    pub fn redeem_bond(ctx: Context<RedeemBond>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let bond = &ctx.accounts.bond;
+        let amount = bond.vested(now).saturating_sub(bond.redeemed);
+        require!(amount > 0, AmmError::NothingToRedeem);
+        let market = &ctx.accounts.bond_market;
+        token::transfer(
+            ctx.accounts
+                .transfer_payout_context()
+                .with_signer(&[&[SEED_BOND_MARKET, market.pool.as_ref(), &[market.bump]]]),
+            amount,
+        )?;
+
+        let bond = &mut ctx.accounts.bond;
+        bond.redeemed += amount;
+        let remaining = bond.payout - bond.redeemed;
+        emit_cpi!(BondRedeemed {
+            bond_market: bond.bond_market,
+            owner: bond.owner,
+            bond_id: bond.bond_id,
+            amount,
+            remaining,
+        });
+        if remaining == 0 {
+            ctx.accounts.bond.close(ctx.accounts.owner.to_account_info())?;
+        }
+        Ok(())
+    }
+
+    /// Permissionless crank swapping the whole non-asset treasury balance through the pool into
+    /// `treasury_asset_mint`. The source treasury account must be held by the pool PDA. Output is
+    /// bounded by the caller's `minimum_amount_out` and by `max_conversion_impact_bps` against the
+    /// pre-trade spot price; the full swap fee stays in reserves.
+    pub fn convert_treasury_fees(ctx: Context<ConvertTreasuryFees>, minimum_amount_out: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(!pool.is_paused(Clock::get()?.unix_timestamp), AmmError::Paused);
+        require!(pool.treasury_asset_mint != Pubkey::default(), AmmError::InvalidTreasuryAsset);
+
+        // Converting into B means spending the token A treasury balance, and vice versa
+        let is_a_to_b = pool.treasury_asset_mint == pool.token_b_mint;
+        require!(!pool.direction_paused(is_a_to_b), AmmError::SwapDirectionPaused);
+        let (source, reserve_in, reserve_out) = if is_a_to_b {
+            (&ctx.accounts.treasury_token_account_a, &ctx.accounts.reserve_a, &ctx.accounts.reserve_b)
+        } else {
+            (&ctx.accounts.treasury_token_account_b, &ctx.accounts.reserve_b, &ctx.accounts.reserve_a)
+        };
+        require!(source.owner == pool.key(), AmmError::InvalidTokenAccountOwner);
+        let amount_in = source.amount;
+        require!(amount_in > 0, AmmError::ZeroSwapAmount);
+
+        let reserve_in_before = reserve_in.amount;
+        let reserve_out_before = reserve_out.amount;
+        let now = Clock::get()?.unix_timestamp;
+        let quote = compute_swap_quote(pool, amount_in, reserve_in_before, reserve_out_before, now)?;
+        let amount_out = quote.amount_out;
+        require_gte_ctx!(amount_out, minimum_amount_out, AmmError::SlippageExceeded);
+
+        let spot_out = u128::from(amount_in)
+            .checked_mul(u128::from(reserve_out_before))
+            .ok_or(AmmError::NumericOverflow)?
+            / u128::from(reserve_in_before);
+        let min_out = spot_out * u128::from(10_000 - pool.max_conversion_impact_bps) / 10_000u128;
+        require_gte_ctx!(u128::from(amount_out), min_out, AmmError::SlippageExceeded);
+
+        if is_a_to_b {
+            token::transfer(
+                ctx.accounts.transfer_treasury_a_to_reserve_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_in,
+            )?;
+            token::transfer(
+                ctx.accounts.transfer_reserve_b_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_out,
+            )?;
+        } else {
+            token::transfer(
+                ctx.accounts.transfer_treasury_b_to_reserve_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_in,
+            )?;
+            token::transfer(
+                ctx.accounts.transfer_reserve_a_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_out,
+            )?;
+        }
+
+        ctx.accounts.reserve_a.reload()?;
+        ctx.accounts.reserve_b.reload()?;
+        let (reserve_in_after, reserve_out_after) = if is_a_to_b {
+            (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount)
+        } else {
+            (ctx.accounts.reserve_b.amount, ctx.accounts.reserve_a.amount)
+        };
+        check_invariant(
+            &ctx.accounts.pool,
+            now,
+            reserve_in_before,
+            reserve_out_before,
+            reserve_in_after,
+            reserve_out_after,
+        )?;
+
+        emit_cpi!(TreasuryFeesConverted {
+            pool: ctx.accounts.pool.key(),
+            is_a_to_b,
+            amount_in,
+            amount_out,
+            cranker: ctx.accounts.cranker.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Configure the asset `convert_treasury_fees` consolidates into (token A or B mint; default
+    /// pubkey disables conversion) and the maximum price impact a conversion may take.
+    pub fn set_treasury_asset(
+        ctx: Context<OnlyAuthority>,
+        treasury_asset_mint: Pubkey,
+        max_conversion_impact_bps: u16,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            treasury_asset_mint == Pubkey::default()
+                || treasury_asset_mint == pool.token_a_mint
+                || treasury_asset_mint == pool.token_b_mint,
+            AmmError::InvalidTreasuryAsset
+        );
+        require_lte_ctx!(max_conversion_impact_bps, 10_000, AmmError::InvalidTreasuryAsset);
+        pool.treasury_asset_mint = treasury_asset_mint;
+        pool.max_conversion_impact_bps = max_conversion_impact_bps;
+        emit_cpi!(TreasuryAssetSet {
+            pool: pool.key(),
+            treasury_asset_mint,
+            max_conversion_impact_bps,
+        });
+        Ok(())
+    }
+
+    /// Pre-announce a fee change, emission change or unpause taking effect at `activation_ts`.
+    /// Bounds are checked now and again at execution, against the protocol config of the day.
+    pub fn schedule_action(
+        ctx: Context<ScheduleAction>,
+        action: ScheduledActionKind,
+        activation_ts: i64,
+    ) -> Result<()> {
+        require_gt_ctx!(activation_ts, Clock::get()?.unix_timestamp, AmmError::InvalidDeadline);
+        validate_scheduled_action(&ctx.accounts.protocol_config, &action)?;
+
+        let pool = &mut ctx.accounts.pool;
+        let scheduled = &mut ctx.accounts.scheduled_action;
+        scheduled.pool = pool.key();
+        scheduled.creator = ctx.accounts.authority.key();
+        scheduled.id = pool.scheduled_action_nonce;
+        scheduled.activation_ts = activation_ts;
+        scheduled.action = action;
+        pool.scheduled_action_nonce = pool
+            .scheduled_action_nonce
+            .checked_add(1)
+            .ok_or(AmmError::NumericOverflow)?;
+
+        emit_cpi!(ActionScheduled {
+            pool: scheduled.pool,
+            id: scheduled.id,
+            activation_ts,
+            action,
+        });
+        Ok(())
+    }
+
+    /// Drop a scheduled action before it runs (authority only).
+    pub fn cancel_scheduled_action(ctx: Context<CancelScheduledAction>) -> Result<()> {
+        emit_cpi!(ScheduledActionCancelled {
+            pool: ctx.accounts.pool.key(),
+            id: ctx.accounts.scheduled_action.id,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank applying a scheduled action once its activation time has passed.
+    pub fn execute_scheduled_action(ctx: Context<ExecuteScheduledAction>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let scheduled = &ctx.accounts.scheduled_action;
+        require_gte_ctx!(now, scheduled.activation_ts, AmmError::ScheduledActionNotReady);
+        let action = scheduled.action;
+        let id = scheduled.id;
+        validate_scheduled_action(&ctx.accounts.protocol_config, &action)?;
+
+        let pool = &mut ctx.accounts.pool;
+        match action {
+            ScheduledActionKind::FeeChange {
+                protocol_fee_bps,
+                treasury_fee_bps,
+                reward_fee_bps,
+            } => {
+                pool.protocol_fee_bps = protocol_fee_bps;
+                pool.treasury_fee_bps = treasury_fee_bps;
+                pool.reward_fee_bps = reward_fee_bps;
+                pool.lp_fee_bps = protocol_fee_bps - treasury_fee_bps - reward_fee_bps;
+                emit_cpi!(FeesUpdated {
+                    pool: pool.key(),
+                    protocol_fee_bps,
+                    lp_fee_bps: pool.lp_fee_bps,
+                    treasury_fee_bps,
+                    reward_fee_bps,
+                });
+            }
+            ScheduledActionKind::EmissionChange { reward_emission_per_second } => {
+                require!(!pool.gauge_enabled, AmmError::EmissionSetByGauge);
+                update_pool_rewards(pool, now)?;
+                pool.reward_emission_per_second = reward_emission_per_second;
+                emit_cpi!(RewardEmissionSet {
+                    pool: pool.key(),
+                    reward_emission_per_second,
+                });
+            }
+            ScheduledActionKind::Unpause => {
+                pool.paused = false;
+                pool.pause_expires_at = 0;
+                emit_cpi!(Unpaused { pool: pool.key() });
+            }
+        }
+
+        emit_cpi!(ScheduledActionExecuted {
+            pool: pool.key(),
+            id,
+            executor: ctx.accounts.executor.key(),
+        });
+        Ok(())
+    }
+
+    /// Pause trading. Callable by the authority or the keeper.
+    pub fn pause(ctx: Context<OnlyOperator>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = true;
+        pool.pause_expires_at = 0;
+        emit_cpi!(Paused { pool: pool.key() });
+        Ok(())
+    }
+
+    /// Pause for `duration` seconds (authority or keeper). The pause lifts on its own once
+    /// `pause_expires_at` passes unless renewed by calling this again, so a lost key cannot keep
+    /// matured positions frozen.
+    pub fn pause_for(ctx: Context<OnlyOperator>, duration: i64) -> Result<()> {
+        require!(duration > 0, AmmError::InvalidDeadline);
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = true;
+        pool.pause_expires_at = Clock::get()?
+            .unix_timestamp
+            .checked_add(duration)
+            .ok_or(AmmError::NumericOverflow)?;
+        emit_cpi!(Paused { pool: pool.key() });
+        emit_cpi!(PauseExpirySet {
+            pool: pool.key(),
+            pause_expires_at: pool.pause_expires_at,
+        });
+        Ok(())
+    }
+
+    /// Unpause trading. Callable by the authority or the keeper.
+    pub fn unpause(ctx: Context<OnlyOperator>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.paused = false;
+        pool.pause_expires_at = 0;
+        emit_cpi!(Unpaused { pool: pool.key() });
+        Ok(())
+    }
+
+    /// Halt swaps in one direction only (authority or keeper), e.g. while one asset is being dumped
+    /// into the pool, leaving the healthy direction, deposits and claims open.
+    pub fn set_swap_direction_paused(ctx: Context<OnlyOperator>, a_to_b_paused: bool, b_to_a_paused: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.a_to_b_paused = a_to_b_paused;
+        pool.b_to_a_paused = b_to_a_paused;
+        emit_cpi!(SwapDirectionPauseSet {
+            pool: pool.key(),
+            a_to_b_paused,
+            b_to_a_paused,
+        });
+        Ok(())
+    }
+
+    /// Rotate the treasury token accounts (LP, token A, token B). Each is validated against the pool mints.
+    pub fn set_treasury_accounts(ctx: Context<SetTreasuryAccounts>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.treasury = ctx.accounts.treasury.key();
+        pool.treasury_a = ctx.accounts.treasury_token_account_a.key();
+        pool.treasury_b = ctx.accounts.treasury_token_account_b.key();
+        pool.treasury_owner = Pubkey::default();
+        emit_cpi!(TreasuryAccountsSet {
+            pool: pool.key(),
+            treasury: pool.treasury,
+            treasury_a: pool.treasury_a,
+            treasury_b: pool.treasury_b,
+        });
+        Ok(())
+    }
+
+    /// Send token A/B treasury fees to `owner`'s associated token accounts (authority only). The ATAs
+    /// need not exist yet: `swap` creates a missing one, at the trader's expense, the first time it
+    /// pays a fee into it. Anyone (e.g. the keeper) may also pre-create them with the ATA program.
+    pub fn set_treasury_owner(ctx: Context<OnlyAuthority>, owner: Pubkey) -> Result<()> {
+        require!(owner != Pubkey::default(), AmmError::InvalidTreasuryAccount);
+        let pool = &mut ctx.accounts.pool;
+        pool.treasury_owner = owner;
+        pool.treasury_a = get_associated_token_address(&owner, &pool.token_a_mint);
+        pool.treasury_b = get_associated_token_address(&owner, &pool.token_b_mint);
+        emit_cpi!(TreasuryAccountsSet {
+            pool: pool.key(),
+            treasury: pool.treasury,
+            treasury_a: pool.treasury_a,
+            treasury_b: pool.treasury_b,
+        });
+        Ok(())
+    }
+
+    /// Switch an empty pool between the constant-product and stable curves. `amp` seeds the
+    /// stable curve's amplification coefficient (ignored for constant product).
+    pub fn set_curve_type(ctx: Context<SetCurveType>, curve_type: u8, amp: u32) -> Result<()> {
+        require_eq_ctx!(ctx.accounts.lp_mint.supply, 0, AmmError::ReservesAlreadyFunded);
+        require!(
+            curve_type == CURVE_CONSTANT_PRODUCT || curve_type == CURVE_STABLE,
+            AmmError::InvalidCurveType
+        );
+        if curve_type == CURVE_STABLE {
+            require!(
+                ctx.accounts.token_a_mint.decimals == ctx.accounts.token_b_mint.decimals,
+                AmmError::InvalidCurveType
+            );
+            require!((1..=MAX_AMP).contains(&amp), AmmError::InvalidAmp);
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        pool.curve_type = curve_type;
+        pool.initial_amp = amp;
+        pool.target_amp = amp;
+        pool.ramp_start_ts = now;
+        pool.ramp_end_ts = now;
+        emit_cpi!(CurveTypeSet {
+            pool: pool.key(),
+            curve_type,
+            amp,
+        });
+        Ok(())
+    }
+
+    /// Start moving a stable pool's amplification coefficient linearly to `target_amp` by
+    /// `ramp_end_ts`, so locked LPs never see the invariant jump in a single block.
+    pub fn ramp_amp(ctx: Context<OnlyAuthority>, target_amp: u32, ramp_end_ts: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.curve_type == CURVE_STABLE, AmmError::InvalidCurveType);
+        require!((1..=MAX_AMP).contains(&target_amp), AmmError::InvalidAmp);
+        require_gte_ctx!(now, pool.ramp_start_ts.saturating_add(MIN_RAMP_DURATION), AmmError::InvalidRamp);
+        require_gte_ctx!(ramp_end_ts, now.saturating_add(MIN_RAMP_DURATION), AmmError::InvalidRamp);
+        let current = pool.current_amp(now);
+        let target = u64::from(target_amp);
+        let max_change = u64::from(MAX_AMP_CHANGE);
+        require_lte_ctx!(target, current.saturating_mul(max_change), AmmError::InvalidAmp);
+        require_gte_ctx!(target.saturating_mul(max_change), current, AmmError::InvalidAmp);
+        pool.initial_amp = current as u32;
+        pool.target_amp = target_amp;
+        pool.ramp_start_ts = now;
+        pool.ramp_end_ts = ramp_end_ts;
+        emit_cpi!(AmpRampStarted {
+            pool: pool.key(),
+            initial_amp: pool.initial_amp,
+            target_amp,
+            ramp_start_ts: now,
+            ramp_end_ts,
+        });
+        Ok(())
+    }
+
+    /// Freeze the amplification coefficient at its current interpolated value.
+    pub fn stop_ramp(ctx: Context<OnlyAuthority>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.curve_type == CURVE_STABLE, AmmError::InvalidCurveType);
+        let amp = pool.current_amp(now) as u32;
+        pool.initial_amp = amp;
+        pool.target_amp = amp;
+        pool.ramp_start_ts = now;
+        pool.ramp_end_ts = now;
+        emit_cpi!(AmpRampStopped {
+            pool: pool.key(),
+            amp,
+        });
+        Ok(())
+    }
+
+    /// Create the pool-owned LP vault that holds swept (abandoned) positions. Anyone may pay for it.
+    pub fn init_recovery_vault(_ctx: Context<InitRecoveryVault>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Set how long after `vesting_end` an unclaimed position becomes sweepable. Zero disables sweeping.
+    pub fn set_abandonment_period(ctx: Context<OnlyAuthority>, abandonment_period: i64) -> Result<()> {
+        require_gte_ctx!(abandonment_period, 0, AmmError::InvalidAbandonmentPeriod);
+        let pool = &mut ctx.accounts.pool;
+        pool.abandonment_period = abandonment_period;
+        emit_cpi!(AbandonmentPeriodSet {
+            pool: pool.key(),
+            abandonment_period,
+        });
+        Ok(())
+    }
+
+    /// Set the pool's early-unvest penalty (authority only), within the protocol's `max_penalty_bps`.
+    pub fn set_early_unvest_penalty(ctx: Context<SetEarlyUnvestPenalty>, penalty_bps: u16) -> Result<()> {
+        require_lte_ctx!(
+            penalty_bps,
+            ctx.accounts.protocol_config.max_penalty_bps,
+            AmmError::InvalidPenalty
+        );
+        let pool = &mut ctx.accounts.pool;
+        pool.early_unvest_penalty_bps = penalty_bps;
+        emit_cpi!(EarlyUnvestPenaltySet {
+            pool: pool.key(),
+            penalty_bps,
+        });
+        Ok(())
+    }
+
+    /// Set how long before `vesting_end` early unvests stop paying a penalty (authority only), so a
+    /// user who exits hours before maturity isn't charged the full rate. Zero disables the window.
+    pub fn set_penalty_grace(ctx: Context<OnlyAuthority>, penalty_grace_seconds: i64) -> Result<()> {
+        require!(
+            (0..=MAX_PENALTY_GRACE_SECONDS).contains(&penalty_grace_seconds),
+            AmmError::InvalidPenaltyGrace
+        );
+        let pool = &mut ctx.accounts.pool;
+        pool.penalty_grace_seconds = penalty_grace_seconds;
+        emit_cpi!(PenaltyGraceSet {
+            pool: pool.key(),
+            penalty_grace_seconds,
+        });
+        Ok(())
+    }
+
+    /// Set the per-second reward emission (in reward-vault LP units). Callable by the authority or keeper.
+    /// Rewards are checkpointed at the old rate first. The vault must be funded separately.
+    pub fn set_reward_emission(ctx: Context<UpdateEmission>, reward_emission_per_second: u64) -> Result<()> {
+        require!(!ctx.accounts.pool.gauge_enabled, AmmError::EmissionSetByGauge);
+        require_lte_ctx!(
+            reward_emission_per_second,
+            ctx.accounts.protocol_config.max_reward_emission_per_second,
+            AmmError::EmissionTooHigh
+        );
+        let pool = &mut ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        update_pool_rewards(pool, now)?;
+        pool.reward_emission_per_second = reward_emission_per_second;
+        emit_cpi!(RewardEmissionSet {
+            pool: pool.key(),
+            reward_emission_per_second,
+        });
+        Ok(())
+    }
+
+    /// Change the swap fee and its split (authority only), within the protocol config bounds.
+    /// With `dry_run` the change is validated and previewed via `FeesUpdatePreview` but not applied.
+    pub fn update_fees(
+        ctx: Context<UpdateFees>,
+        protocol_fee_bps: u16,
+        treasury_fee_bps: u16,
+        reward_fee_bps: u16,
+        dry_run: bool,
+    ) -> Result<()> {
+        validate_fees(&ctx.accounts.protocol_config, protocol_fee_bps, treasury_fee_bps, reward_fee_bps)?;
+        if dry_run {
+            let pool = &ctx.accounts.pool;
+            emit_cpi!(FeesUpdatePreview {
+                pool: pool.key(),
+                current_protocol_fee_bps: pool.protocol_fee_bps,
+                current_treasury_fee_bps: pool.treasury_fee_bps,
+                current_reward_fee_bps: pool.reward_fee_bps,
+                protocol_fee_bps,
+                lp_fee_bps: protocol_fee_bps - treasury_fee_bps - reward_fee_bps,
+                treasury_fee_bps,
+                reward_fee_bps,
+            });
+            return Ok(());
+        }
+        let pool = &mut ctx.accounts.pool;
+        pool.protocol_fee_bps = protocol_fee_bps;
+        pool.treasury_fee_bps = treasury_fee_bps;
+        pool.reward_fee_bps = reward_fee_bps;
+        pool.lp_fee_bps = protocol_fee_bps - treasury_fee_bps - reward_fee_bps;
+        emit_cpi!(FeesUpdated {
+            pool: pool.key(),
+            protocol_fee_bps,
+            lp_fee_bps: pool.lp_fee_bps,
+            treasury_fee_bps,
+            reward_fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Point the pool at an SPL Governance realm whose voters use locked LP as voting weight.
+    pub fn set_governance_realm(
+        ctx: Context<OnlyAuthority>,
+        realm: Pubkey,
+        governing_token_mint: Pubkey,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.governance_realm = realm;
+        pool.governance_mint = governing_token_mint;
+        emit_cpi!(GovernanceRealmSet {
+            pool: pool.key(),
+            realm,
+            governing_token_mint,
+        });
+        Ok(())
+    }
+
+    /// Hand pool authority to a new key, e.g. a governance PDA so fee and emission changes go through votes.
+    pub fn set_pool_authority(ctx: Context<OnlyAuthority>, new_authority: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let old_authority = pool.authority;
+        pool.authority = new_authority;
+        emit_cpi!(PoolAuthoritySet {
+            pool: pool.key(),
+            old_authority,
+            new_authority,
+        });
+        Ok(())
+    }
+
+    /// Sweep reward-vault balance above `total_reward_liability` to the treasury LP account (authority only).
+    /// User-owed rewards can never be swept.
+    pub fn reconcile_reward_vault(ctx: Context<ReconcileRewardVault>) -> Result<()> {
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
+        let vault_balance = ctx.accounts.reward_vault.amount;
+        let liability = ctx.accounts.pool.total_reward_liability;
+        let surplus = vault_balance.saturating_sub(liability);
+        if surplus > 0 {
+            token::transfer(
+                ctx.accounts.transfer_surplus_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                surplus,
+            )?;
+        }
+
+        emit_cpi!(RewardVaultReconciled {
+            pool: ctx.accounts.pool.key(),
+            vault_balance,
+            liability,
+            swept: surplus,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank moving `treasury_to_rewards_bps` of the pool-held LP treasury into the
+    /// reward vault, at most once per `TREASURY_ROUTE_INTERVAL`. The amount is distributed through
+    /// `acc_reward_per_lp` like a swap reward fee, so it is owed to stakers rather than vault surplus.
+    pub fn route_treasury_to_rewards(ctx: Context<RouteTreasuryToRewards>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        update_pool_rewards(&mut ctx.accounts.pool, now)?;
+
+        let pool = &ctx.accounts.pool;
+        require!(pool.treasury_to_rewards_bps > 0, AmmError::TreasuryRoutingDisabled);
+        require_gte_ctx!(
+            now,
+            pool.last_treasury_route_ts.saturating_add(TREASURY_ROUTE_INTERVAL),
+            AmmError::TreasuryRouteTooSoon
+        );
+        require!(
+            ctx.accounts.treasury_lp_account.owner == pool.key(),
+            AmmError::InvalidTokenAccountOwner
+        );
+
+        let amount = (u128::from(ctx.accounts.treasury_lp_account.amount)
+            * u128::from(pool.treasury_to_rewards_bps)
+            / 10_000u128) as u64;
+        if amount > 0 && lp_supply > 0 {
+            token::transfer(
+                ctx.accounts.transfer_treasury_to_rewards_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount,
+            )?;
+            ctx.accounts.pool.distribute_rewards(amount, lp_supply)?;
+        }
+        ctx.accounts.pool.last_treasury_route_ts = now;
+
+        emit_cpi!(TreasuryRoutedToRewards {
+            pool: ctx.accounts.pool.key(),
+            amount,
+            acc_reward_per_lp_after: ctx.accounts.pool.acc_reward_per_lp,
+        });
+        Ok(())
+    }
+
+    /// Move protocol-owned liquidity between two pools of the same pair in one transaction (authority
+    /// of both): burn `lp_amount` of the source pool's treasury LP, send the underlying A/B straight
+    /// into the target's reserves and lock the LP minted there in a `VestingStake` for the authority,
+    /// like `deposit_and_vest`. Whatever does not fit the target's ratio goes to the target's token
+    /// treasuries. Reverts unless at least `min_lp_out` is minted.
+    pub fn rebalance_treasury_lp<'info>(
+        ctx: Context<'_, '_, '_, 'info, RebalanceTreasuryLp<'info>>,
+        lp_amount: u64,
+        min_lp_out: u64,
+        vesting_seconds: i64,
+        deposit_id: u64,
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(lp_amount > 0, AmmError::ZeroLpAmount);
+        require!(
+            (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&vesting_seconds),
+            AmmError::InvalidVestingPeriod
+        );
+        require_gte_ctx!(deposit_id, ctx.accounts.user_vesting_index.next_nonce, AmmError::DepositIdTooLow);
+        let (source, target) = (&ctx.accounts.source_pool, &ctx.accounts.target_pool);
+        require!(source.key() != target.key(), AmmError::PoolPairMismatch);
+        require!(
+            source.token_a_mint == target.token_a_mint && source.token_b_mint == target.token_b_mint,
+            AmmError::PoolPairMismatch
+        );
+        require!(!target.is_paused(now), AmmError::Paused);
+        require!(!target.depegged, AmmError::PoolDepegged);
+        require!(!target.emergency_drip_active, AmmError::EmergencyDripActive);
+
+        let source_supply = ctx.accounts.source_lp_mint.supply;
+        let target_supply = ctx.accounts.target_lp_mint.supply;
+        require!(source_supply > 0 && target_supply > 0, AmmError::InsufficientLiquidity);
+        update_pool_rewards(&mut ctx.accounts.source_pool, now)?;
+        update_pool_rewards(&mut ctx.accounts.target_pool, now)?;
+
+        // Source side: pro-rata share of the burned LP
+        let share = |reserve: u64, lp: u64, supply: u64| -> Result<u64> {
+            u64::try_from(u128::from(reserve) * u128::from(lp) / u128::from(supply))
+                .map_err(|_| error!(AmmError::NumericOverflow))
+        };
+        let amount_a = share(ctx.accounts.source_reserve_a.amount, lp_amount, source_supply)?;
+        let amount_b = share(ctx.accounts.source_reserve_b.amount, lp_amount, source_supply)?;
+
+        // Target side: mint against the limiting token, deposit only what that LP is worth
+        let (target_a, target_b) = (ctx.accounts.target_reserve_a.amount, ctx.accounts.target_reserve_b.amount);
+        require!(target_a > 0 && target_b > 0, AmmError::InsufficientLiquidity);
+        let lp_minted = share(amount_a, target_supply, target_a)?.min(share(amount_b, target_supply, target_b)?);
+        require_gte_ctx!(lp_minted, min_lp_out.max(1), AmmError::SlippageExceeded);
+        let deposit_a = share(target_a, lp_minted, target_supply)?.min(amount_a);
+        let deposit_b = share(target_b, lp_minted, target_supply)?.min(amount_b);
+        let (leftover_a, leftover_b) = (amount_a - deposit_a, amount_b - deposit_b);
+
+        let accounts = &ctx.accounts;
+        let source_signer: &[&[&[u8]]] = pool_signer!(accounts.source_pool);
+        token::burn(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Burn {
+                    mint: accounts.source_lp_mint.to_account_info(),
+                    from: accounts.source_treasury_lp.to_account_info(),
+                    authority: accounts.source_pool.to_account_info(),
+                },
+                source_signer,
+            ),
+            lp_amount,
+        )?;
+        let source_pool_info = accounts.source_pool.to_account_info();
+        let move_tokens = |from: AccountInfo<'info>, to: AccountInfo<'info>, amount: u64| -> Result<()> {
+            if amount == 0 {
+                return Ok(());
+            }
+            let cpi_accounts = Transfer {
+                from,
+                to,
+                authority: source_pool_info.clone(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(accounts.token_program.to_account_info(), cpi_accounts, source_signer),
+                amount,
+            )
+        };
+        let (source_a, source_b) = (accounts.source_reserve_a.to_account_info(), accounts.source_reserve_b.to_account_info());
+        move_tokens(source_a.clone(), accounts.target_reserve_a.to_account_info(), deposit_a)?;
+        move_tokens(source_b.clone(), accounts.target_reserve_b.to_account_info(), deposit_b)?;
+        move_tokens(source_a, accounts.target_treasury_a.to_account_info(), leftover_a)?;
+        move_tokens(source_b, accounts.target_treasury_b.to_account_info(), leftover_b)?;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: accounts.target_lp_mint.to_account_info(),
+                    to: accounts.vesting_token_account.to_account_info(),
+                    authority: accounts.target_pool.to_account_info(), // pool PDA is mint authority
+                },
+                pool_signer!(accounts.target_pool),
+            ),
+            lp_minted,
+        )?;
+
+        let target = &mut ctx.accounts.target_pool;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.pool = target.key();
+        vesting.user = ctx.accounts.authority.key();
+        vesting.depositor = ctx.accounts.authority.key();
+        vesting.bump = ctx.bumps.vesting_stake;
+        vesting.rent_payer = ctx.accounts.authority.key();
+        vesting.reward_epoch = target.reward_epoch;
+        vesting.amount = lp_minted;
+        vesting.vesting_end = now + vesting_seconds;
+        vesting.held_since = now;
+        vesting.deposit_id = deposit_id;
+        vesting.reward_debt = (u128::from(lp_minted) * target.acc_reward_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_a = (u128::from(lp_minted) * target.acc_reward_a_per_lp) / REWARD_SCALE;
+        vesting.reward_debt_b = (u128::from(lp_minted) * target.acc_reward_b_per_lp) / REWARD_SCALE;
+        target.total_locked_lp = target
+            .total_locked_lp
+            .checked_add(lp_minted)
+            .ok_or(AmmError::NumericOverflow)?;
+        ctx.accounts.user_vesting_index.next_nonce = deposit_id.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+
+        emit_cpi!(TreasuryLpRebalanced {
+            source_pool: ctx.accounts.source_pool.key(),
+            target_pool: ctx.accounts.target_pool.key(),
+            lp_burned: lp_amount,
+            amount_a: deposit_a,
+            amount_b: deposit_b,
+            leftover_a,
+            leftover_b,
+            lp_minted,
+            deposit_id,
+            vesting_end: now + vesting_seconds,
+        });
+        Ok(())
+    }
+
+    /// Set the share of every early-unvest LP penalty paid into the reward vault instead of the
+    /// treasury (authority only), so lockers who stay earn from those who leave. 0 disables it.
+    pub fn set_penalty_to_rewards_bps(ctx: Context<OnlyAuthority>, penalty_to_rewards_bps: u16) -> Result<()> {
+        require_lte_ctx!(penalty_to_rewards_bps, 10_000, AmmError::InvalidFeeSplit);
+        let pool = &mut ctx.accounts.pool;
+        pool.penalty_to_rewards_bps = penalty_to_rewards_bps;
+        emit_cpi!(PenaltyToRewardsSet {
+            pool: pool.key(),
+            penalty_to_rewards_bps,
+        });
+        Ok(())
+    }
+
+    /// Set the share of the LP treasury `route_treasury_to_rewards` moves per run (0 disables it).
+    pub fn set_treasury_to_rewards_bps(ctx: Context<OnlyAuthority>, treasury_to_rewards_bps: u16) -> Result<()> {
+        require_lte_ctx!(treasury_to_rewards_bps, 10_000, AmmError::InvalidFeeSplit);
+        let pool = &mut ctx.accounts.pool;
+        pool.treasury_to_rewards_bps = treasury_to_rewards_bps;
+        emit_cpi!(TreasuryToRewardsSet {
+            pool: pool.key(),
+            treasury_to_rewards_bps,
+        });
+        Ok(())
+    }
+
+    /// Set per-deposit minimums (token A, token B, and LP minted) to keep dust positions out.
+    pub fn set_min_deposit(
+        ctx: Context<OnlyAuthority>,
+        min_deposit_a: u64,
+        min_deposit_b: u64,
+        min_lp_minted: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.min_deposit_a = min_deposit_a;
+        pool.min_deposit_b = min_deposit_b;
+        pool.min_lp_minted = min_lp_minted;
+        emit_cpi!(MinDepositSet {
+            pool: pool.key(),
+            min_deposit_a,
+            min_deposit_b,
+            min_lp_minted,
+        });
+        Ok(())
+    }
+
+    /// Choose whether matured positions can still be claimed while the pool is paused.
+    pub fn set_allow_claims_while_paused(ctx: Context<OnlyAuthority>, allow: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.allow_claims_while_paused = allow;
+        emit_cpi!(ClaimsWhilePausedSet {
+            pool: pool.key(),
+            allow,
+        });
+        Ok(())
+    }
+
+    /// Choose whether the early-unvest penalty also applies to the rewards settled on exit.
+    pub fn set_penalize_rewards(ctx: Context<OnlyAuthority>, penalize_rewards: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.penalize_rewards = penalize_rewards;
+        emit_cpi!(PenalizeRewardsSet {
+            pool: pool.key(),
+            penalize_rewards,
+        });
+        Ok(())
+    }
+
+    /// Create the pool-owned token A and token B vaults used by `REWARD_MODE_UNDERLYING`.
+    pub fn init_underlying_reward_vaults(_ctx: Context<InitUnderlyingRewardVaults>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Choose whether swap reward fees accrue as LP (`REWARD_MODE_LP`) or stay in token A/B
+    /// (`REWARD_MODE_UNDERLYING`). Already-accrued rewards keep paying in their original denomination.
+    pub fn set_reward_mode(ctx: Context<OnlyAuthority>, reward_mode: u8) -> Result<()> {
+        require!(
+            reward_mode == REWARD_MODE_LP || reward_mode == REWARD_MODE_UNDERLYING,
+            AmmError::InvalidRewardMode
+        );
+        let pool = &mut ctx.accounts.pool;
+        pool.reward_mode = reward_mode;
+        emit_cpi!(RewardModeSet {
+            pool: pool.key(),
+            reward_mode,
+        });
+        Ok(())
+    }
+
+    /// Set the keeper key allowed to run operational instructions (pause, cranks, emission updates, oracle pokes).
+    /// The keeper cannot change fees or trigger emergency withdraw.
+    pub fn set_keeper(ctx: Context<OnlyAuthority>, keeper: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.keeper = keeper;
+        emit_cpi!(KeeperSet {
+            pool: pool.key(),
+            keeper,
+        });
+        Ok(())
+    }
+
+    /// Read-only quote for routers: prices `amount_in` against current reserves with the same math as `swap`
+    /// and returns the serialized `SwapQuote` as return data. Safe to simulate.
+    /// Pass the pool's `price_observations` to get the rebalance fee the same way `swap` would.
+    pub fn quote(ctx: Context<QuotePool>, amount_in: u64, is_a_to_b: bool) -> Result<SwapQuote> {
+        require!(amount_in > 0, AmmError::ZeroSwapAmount);
+        compute_trade_quote(
+            &ctx.accounts.pool,
+            ctx.accounts.price_observations.as_deref().map(|o| &**o),
+            amount_in,
+            ctx.accounts.reserve_a.amount,
+            ctx.accounts.reserve_b.amount,
+            is_a_to_b,
+            Clock::get()?.unix_timestamp,
+        )
+    }
+
+    /// Packed pool view (mints, reserves, fees, curve) for aggregators, returned as return data.
+    pub fn get_amm_info(ctx: Context<QuotePool>) -> Result<AmmInfo> {
+        let pool = &ctx.accounts.pool;
+        Ok(AmmInfo {
+            pool: pool.key(),
+            curve_type: pool.curve_type,
+            token_a_mint: pool.token_a_mint,
+            token_b_mint: pool.token_b_mint,
+            lp_mint: pool.lp_mint,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            reserve_a_amount: ctx.accounts.reserve_a.amount,
+            reserve_b_amount: ctx.accounts.reserve_b.amount,
+            lp_supply: ctx.accounts.lp_mint.supply,
+            protocol_fee_bps: pool.protocol_fee_bps,
+            lp_fee_bps: pool.lp_fee_bps,
+            treasury_fee_bps: pool.treasury_fee_bps,
+            reward_fee_bps: pool.reward_fee_bps,
+            paused: pool.is_paused(Clock::get()?.unix_timestamp),
+        })
+    }
+
+    /// Pool configuration (fees, protocol bounds, curve, flags, vault addresses) as `PoolConfig`
+    /// return data. Read-only and permissionless.
+    pub fn get_pool_config(ctx: Context<GetPoolConfig>) -> Result<PoolConfig> {
+        let pool = &ctx.accounts.pool;
+        let config = &ctx.accounts.protocol_config;
+        let now = Clock::get()?.unix_timestamp;
+        let pool_key = pool.key();
+        Ok(PoolConfig {
+            pool: pool_key,
+            authority: pool.authority,
+            keeper: pool.keeper,
+            protocol_fee_bps: pool.protocol_fee_bps,
+            swap_fee_bps: pool.swap_fee_bps(now),
+            lp_fee_bps: pool.lp_fee_bps,
+            treasury_fee_bps: pool.treasury_fee_bps,
+            reward_fee_bps: pool.reward_fee_bps,
+            rebalance_fee_bps: pool.rebalance_fee_bps,
+            fee_on_output: pool.fee_on_output,
+            sale_fee_bps: pool.sale_fee_bps,
+            max_protocol_fee_bps: config.max_protocol_fee_bps,
+            max_penalty_bps: config.max_penalty_bps,
+            max_reward_emission_per_second: config.max_reward_emission_per_second,
+            curve_type: pool.curve_type,
+            amp: if pool.curve_type == CURVE_STABLE { pool.current_amp(now) } else { 0 },
+            paused: pool.is_paused(now),
+            a_to_b_paused: pool.a_to_b_paused,
+            b_to_a_paused: pool.b_to_a_paused,
+            depegged: pool.depegged,
+            allow_claims_while_paused: pool.allow_claims_while_paused,
+            permissioned: pool.permissioned,
+            compliance_mode: pool.compliance_mode,
+            swap_allowlist_active: pool.swap_allowlist_active(now),
+            reward_mode: pool.reward_mode,
+            reserve_a: pool.reserve_a,
+            reserve_b: pool.reserve_b,
+            treasury: pool.treasury,
+            treasury_a: pool.treasury_a,
+            treasury_b: pool.treasury_b,
+            recovery_vault: recovery_vault_address(&pool_key).0,
+            underlying_reward_vault_a: underlying_reward_vault_address(&pool_key, &pool.token_a_mint).0,
+            underlying_reward_vault_b: underlying_reward_vault_address(&pool_key, &pool.token_b_mint).0,
+        })
+    }
+
+    /// Manipulation-resistant LP price for lending markets, returned as `FairLpPrice` return data.
+    /// Uses the fair-reserve formula: with `k = reserve_a * reserve_b` and `p` the TWAP of A in B,
+    /// the pool is valued at `2 * sqrt(k * p)` in B, so a flash-loan skew of the reserves (which
+    /// keeps `k`) cannot inflate it. Constant-product pools only; needs a TWAP that spans time.
+    /// Pass a `vesting_stake` to also get that position's value. Read-only and safe to simulate.
+    pub fn get_fair_lp_price(ctx: Context<GetFairLpPrice>) -> Result<FairLpPrice> {
+        let pool = &ctx.accounts.pool;
+        require!(pool.curve_type == CURVE_CONSTANT_PRODUCT, AmmError::FairPriceUnsupportedCurve);
+        let twap_price_a = ctx.accounts.price_observations.twap_price_a().ok_or(AmmError::TwapUnavailable)?;
+        let (fair_reserve_a, fair_reserve_b) =
+            fair_reserves(ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount, twap_price_a)?;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_supply > 0, AmmError::InsufficientLiquidity);
+        let per_lp = |fair_reserve: u128, lp: u64| -> Result<u128> {
+            Ok(fair_reserve
+                .checked_mul(2 * u128::from(lp))
+                .ok_or(AmmError::NumericOverflow)?
+                / u128::from(lp_supply))
+        };
+        let position_lp = ctx.accounts.vesting_stake.as_ref().map_or(0, |stake| stake.amount);
+        Ok(FairLpPrice {
+            pool: pool.key(),
+            twap_price_a,
+            fair_reserve_a: u64::try_from(fair_reserve_a).map_err(|_| AmmError::NumericOverflow)?,
+            fair_reserve_b: u64::try_from(fair_reserve_b).map_err(|_| AmmError::NumericOverflow)?,
+            lp_supply,
+            lp_price_in_a: per_lp(fair_reserve_a.checked_mul(PRICE_SCALE).ok_or(AmmError::NumericOverflow)?, 1)?,
+            lp_price_in_b: per_lp(fair_reserve_b.checked_mul(PRICE_SCALE).ok_or(AmmError::NumericOverflow)?, 1)?,
+            position_lp,
+            position_value_in_a: per_lp(fair_reserve_a, position_lp)?,
+            position_value_in_b: per_lp(fair_reserve_b, position_lp)?,
+        })
+    }
+
+    /// Portfolio view of one position as `PositionValue` return data: its LP valued at current
+    /// reserves, pending rewards with emissions projected to now (before any relock bonus), time to
+    /// maturity, and what `early_unvest` of the whole position would cost at the pool's penalty rate
+    /// (zero once matured, inside the grace window, or with an approved hardship request). Read-only
+    /// and safe to simulate.
+    pub fn get_position_value(ctx: Context<GetPositionValue>) -> Result<PositionValue> {
+        let now = Clock::get()?.unix_timestamp;
+        let stake = &ctx.accounts.vesting_stake;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let mut pool = (*ctx.accounts.pool).clone();
+        update_pool_rewards(&mut pool, now)?;
+
+        let share = |amount: u64, reserve: u64| -> Result<u64> {
+            if lp_supply == 0 {
+                return Ok(0);
+            }
+            u64::try_from(u128::from(amount) * u128::from(reserve) / u128::from(lp_supply))
+                .map_err(|_| error!(AmmError::NumericOverflow))
+        };
+        let (reserve_a, reserve_b) = (ctx.accounts.reserve_a.amount, ctx.accounts.reserve_b.amount);
+        let lp_amount = if stake.claimed { 0 } else { stake.amount };
+
+        let matured = now >= stake.vesting_end;
+        let in_grace = pool.penalty_grace_seconds > 0
+            && now >= stake.vesting_end.saturating_sub(pool.penalty_grace_seconds);
+        let hardship_approved = ctx.accounts.hardship_request.as_ref().is_some_and(|request| request.approved);
+        let waived = ctx.accounts.penalty_waiver.as_ref().is_some_and(|waiver| waiver.active(now));
+        let penalty_bps = if matured || in_grace || hardship_approved || waived {
+            0
+        } else {
+            pool.early_unvest_penalty(&ctx.accounts.protocol_config)
+        };
+        let penalty_lp = (u128::from(lp_amount) * u128::from(penalty_bps) / 10_000u128) as u64;
+
+        Ok(PositionValue {
+            pool: ctx.accounts.pool.key(),
+            vesting_stake: stake.key(),
+            user: stake.user,
+            lp_amount,
+            amount_a: share(lp_amount, reserve_a)?,
+            amount_b: share(lp_amount, reserve_b)?,
+            pending_reward: pending_reward_amount(lp_amount, pool.acc_reward_per_lp, stake.reward_debt)?,
+            pending_reward_a: pending_reward_amount(lp_amount, pool.acc_reward_a_per_lp, stake.reward_debt_a)?,
+            pending_reward_b: pending_reward_amount(lp_amount, pool.acc_reward_b_per_lp, stake.reward_debt_b)?,
+            vesting_end: stake.vesting_end,
+            seconds_to_maturity: stake.vesting_end.saturating_sub(now).max(0),
+            pledged: stake.pledgee != Pubkey::default(),
+            exit_penalty_bps: penalty_bps,
+            exit_penalty_lp: penalty_lp,
+            exit_penalty_a: share(penalty_lp, reserve_a)?,
+            exit_penalty_b: share(penalty_lp, reserve_b)?,
+        })
+    }
+
+    /// Start the bootstrap phase: until `until`, only registered market makers can swap.
+    pub fn start_swap_allowlist(ctx: Context<OnlyAuthority>, until: i64) -> Result<()> {
+        require_gt_ctx!(until, Clock::get()?.unix_timestamp, AmmError::InvalidDeadline);
+        let pool = &mut ctx.accounts.pool;
+        pool.swap_allowlist_enabled = true;
+        pool.swap_allowlist_until = until;
+        emit_cpi!(SwapAllowlistStarted {
+            pool: pool.key(),
+            until,
+        });
+        Ok(())
+    }
+
+    /// End the bootstrap phase. Anyone may call once the deadline has passed; the authority any time.
+    pub fn end_swap_allowlist(ctx: Context<EndSwapAllowlist>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let pool = &mut ctx.accounts.pool;
+        require!(
+            now >= pool.swap_allowlist_until || ctx.accounts.caller.key() == pool.authority,
+            AmmError::SwapAllowlistStillActive
+        );
+        pool.swap_allowlist_enabled = false;
+        emit_cpi!(SwapAllowlistEnded { pool: pool.key() });
+        Ok(())
+    }
+
+    /// Cap the share of the output reserve a single trade may take (authority only), e.g. 1_000 = 10%,
+    /// until `until` (0 = no end). Applies to every trade path on top of any price-impact bound;
+    /// `max_reserve_out_bps = 0` removes the cap.
+    pub fn set_reserve_utilization_cap(
+        ctx: Context<OnlyAuthority>,
+        max_reserve_out_bps: u16,
+        until: i64,
+    ) -> Result<()> {
+        require_lte_ctx!(max_reserve_out_bps, 10_000, AmmError::InvalidReserveCap);
+        let pool = &mut ctx.accounts.pool;
+        pool.max_reserve_out_bps = max_reserve_out_bps;
+        pool.reserve_cap_until = until;
+        emit_cpi!(ReserveUtilizationCapSet {
+            pool: pool.key(),
+            max_reserve_out_bps,
+            until,
+        });
+        Ok(())
+    }
+
+    /// Register a market maker allowed to swap during bootstrap until `expires_at`.
+    pub fn add_market_maker(ctx: Context<AddMarketMaker>, trader: Pubkey, expires_at: i64) -> Result<()> {
+        let entry = &mut ctx.accounts.market_maker_entry;
+        entry.pool = ctx.accounts.pool.key();
+        entry.trader = trader;
+        entry.expires_at = expires_at;
+        emit_cpi!(MarketMakerUpdated {
+            pool: entry.pool,
+            trader,
+            expires_at,
+        });
+        Ok(())
+    }
+
+    /// Remove a market maker, refunding the entry rent to the authority.
+    pub fn remove_market_maker(ctx: Context<RemoveMarketMaker>) -> Result<()> {
+        emit_cpi!(MarketMakerUpdated {
+            pool: ctx.accounts.pool.key(),
+            trader: ctx.accounts.market_maker_entry.trader,
+            expires_at: 0,
+        });
+        Ok(())
+    }
+
+    /// Opt the pool into compliance mode: deposits and swaps are checked against the global blocklist.
+    pub fn set_compliance_mode(ctx: Context<OnlyAuthority>, compliance_mode: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.compliance_mode = compliance_mode;
+        emit_cpi!(ComplianceModeSet {
+            pool: pool.key(),
+            compliance_mode,
+        });
+        Ok(())
+    }
+
+    /// Toggle permissioned mode. When enabled, `deposit_and_vest` requires an allowlist entry for the depositor.
+    /// Swaps stay public either way.
+    pub fn set_permissioned(ctx: Context<OnlyAuthority>, permissioned: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.permissioned = permissioned;
+        emit_cpi!(PermissionedSet {
+            pool: pool.key(),
+            permissioned,
+        });
+        Ok(())
+    }
+
+    /// Add a user to the pool's depositor allowlist (authority only).
+    pub fn add_to_allowlist(ctx: Context<AddToAllowlist>, user: Pubkey) -> Result<()> {
+        let entry = &mut ctx.accounts.allowlist_entry;
+        entry.pool = ctx.accounts.pool.key();
+        entry.user = user;
+        emit_cpi!(AllowlistUpdated {
+            pool: entry.pool,
+            user,
+            allowed: true,
+        });
+        Ok(())
+    }
+
+    /// Remove a user from the pool's depositor allowlist, refunding the entry rent to the authority.
+    pub fn remove_from_allowlist(ctx: Context<RemoveFromAllowlist>) -> Result<()> {
+        emit_cpi!(AllowlistUpdated {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.allowlist_entry.user,
+            allowed: false,
+        });
+        Ok(())
+    }
+
+    /// Drain both reserves to the treasury. With `dry_run` only the amounts that would move are
+    /// emitted (`EmergencyWithdrawPreview`), so multisig signers can check them first.
+    /// Above the protocol's `cosign_lp_threshold` the upgrade authority or protocol admin must co-sign.
+    /// See `start_emergency_drip` for the gradual alternative.
+    pub fn emergency_withdraw(ctx: Context<EmergencyWithdraw>, dry_run: bool) -> Result<()> {
+        // Transfers while only immutable reads used earlier
+        let reserve_a_bal = ctx.accounts.reserve_a.amount;
+        let reserve_b_bal = ctx.accounts.reserve_b.amount;
+        if dry_run {
+            emit_cpi!(EmergencyWithdrawPreview {
+                pool: ctx.accounts.pool.key(),
+                amount_a: reserve_a_bal,
+                amount_b: reserve_b_bal,
+                treasury_a: ctx.accounts.treasury_token_account_a.key(),
+                treasury_b: ctx.accounts.treasury_token_account_b.key(),
+            });
+            return Ok(());
+        }
+        check_privileged_cosigner(
+            &ctx.accounts.protocol_config,
+            ctx.accounts.lp_mint.supply,
+            ctx.accounts.program_data.as_deref(),
+            ctx.accounts.cosigner.as_ref(),
+        )?;
+        if reserve_a_bal > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reserve_a_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reserve_a_bal,
+            )?;
+        }
+        if reserve_b_bal > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reserve_b_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                reserve_b_bal,
+            )?;
+        }
+        emit_cpi!(EmergencyWithdrawn { pool: ctx.accounts.pool.key() });
+        Ok(())
+    }
+
+    /// Start streaming both reserves to the treasury over `drip_slots` slots instead of draining
+    /// them at once. Deposits are blocked while the drip runs; withdrawals and claims are not.
+    pub fn start_emergency_drip(ctx: Context<StartEmergencyDrip>, drip_slots: u64) -> Result<()> {
+        require!(drip_slots > 0, AmmError::InvalidDripSlots);
+        let slot = Clock::get()?.slot;
+        let drip = &mut ctx.accounts.emergency_drip;
+        drip.pool = ctx.accounts.pool.key();
+        drip.start_slot = slot;
+        drip.last_slot = slot;
+        drip.end_slot = slot.checked_add(drip_slots).ok_or(AmmError::NumericOverflow)?;
+        drip.released_a = 0;
+        drip.released_b = 0;
+        ctx.accounts.pool.emergency_drip_active = true;
+        emit_cpi!(EmergencyDripStarted {
+            pool: drip.pool,
+            start_slot: drip.start_slot,
+            end_slot: drip.end_slot,
+        });
+        Ok(())
+    }
+
+    /// Move the portion of the reserves due since the last crank to the treasury (authority or keeper).
+    pub fn crank_emergency_drip(ctx: Context<CrankEmergencyDrip>) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        let drip = &ctx.accounts.emergency_drip;
+        require_gt_ctx!(slot, drip.last_slot, AmmError::DripNotDue);
+        let elapsed = u128::from(slot.min(drip.end_slot) - drip.last_slot);
+        let remaining = u128::from(drip.end_slot - drip.last_slot).max(1);
+        let due = |balance: u64| -> u64 {
+            if slot >= drip.end_slot {
+                balance
+            } else {
+                (u128::from(balance) * elapsed / remaining) as u64
+            }
+        };
+        let amount_a = due(ctx.accounts.reserve_a.amount);
+        let amount_b = due(ctx.accounts.reserve_b.amount);
+
+        if amount_a > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reserve_a_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_a,
+            )?;
+        }
+        if amount_b > 0 {
+            token::transfer(
+                ctx.accounts.transfer_reserve_b_to_treasury_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_b,
+            )?;
+        }
+
+        let drip = &mut ctx.accounts.emergency_drip;
+        drip.last_slot = slot.min(drip.end_slot);
+        drip.released_a = drip.released_a.saturating_add(amount_a);
+        drip.released_b = drip.released_b.saturating_add(amount_b);
+        emit_cpi!(EmergencyDripped {
+            pool: drip.pool,
+            amount_a,
+            amount_b,
+            released_a: drip.released_a,
+            released_b: drip.released_b,
+            slot,
+        });
+        Ok(())
+    }
+
+    /// Stop a running drip (authority only) and reopen deposits. Already released funds stay with the treasury.
+    pub fn cancel_emergency_drip(ctx: Context<CancelEmergencyDrip>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.emergency_drip_active = false;
+        emit_cpi!(EmergencyDripCancelled {
+            pool: pool.key(),
+            released_a: ctx.accounts.emergency_drip.released_a,
+            released_b: ctx.accounts.emergency_drip.released_b,
+        });
+        Ok(())
+    }
+
+    /// Register an external program that deposit, claim and early-unvest CPI into with the position's
+    /// data (authority only). `accounts_hash` commits to the accounts the hook takes, see
+    /// `hook_accounts_hash`; callers pass the hook program and then those accounts as remaining accounts.
+    pub fn register_pool_hook(ctx: Context<RegisterPoolHook>, program_id: Pubkey, accounts_hash: [u8; 32]) -> Result<()> {
+        require!(program_id != ID && program_id != token::ID, AmmError::InvalidPoolHook);
+        let hook = &mut ctx.accounts.pool_hook;
+        hook.pool = ctx.accounts.pool.key();
+        hook.program_id = program_id;
+        hook.accounts_hash = accounts_hash;
+        ctx.accounts.pool.hook_enabled = true;
+        emit_cpi!(PoolHookRegistered {
+            pool: hook.pool,
+            program_id,
+            accounts_hash,
+        });
+        Ok(())
+    }
+
+    /// Unregister the pool hook (authority only), closing its account.
+    pub fn remove_pool_hook(ctx: Context<RemovePoolHook>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.hook_enabled = false;
+        emit_cpi!(PoolHookRemoved {
+            pool: pool.key(),
+            program_id: ctx.accounts.pool_hook.program_id,
+        });
+        Ok(())
+    }
+
+    /// Flag (or clear) the pool as compromised (authority only). While set, `emergency_exit` lets
+    /// locked positions leave early and `withdraw_unlocked` works even when paused.
+    pub fn set_compromised(ctx: Context<OnlyAuthority>, compromised: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.compromised = compromised;
+        emit_cpi!(PoolCompromisedSet {
+            pool: pool.key(),
+            compromised,
+        });
+        Ok(())
+    }
+
+    /// Permissionless crank flagging the pool compromised when its books no longer hold: LP is
+    /// outstanding but a reserve is empty, or more LP is recorded as locked than exists.
+    pub fn check_pool_health(ctx: Context<CheckPoolHealth>) -> Result<()> {
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        let broken = (lp_supply > 0 && (ctx.accounts.reserve_a.amount == 0 || ctx.accounts.reserve_b.amount == 0))
+            || ctx.accounts.pool.total_locked_lp > lp_supply;
+        let pool = &mut ctx.accounts.pool;
+        if broken && !pool.compromised {
+            pool.compromised = true;
+            emit_cpi!(PoolCompromisedSet {
+                pool: pool.key(),
+                compromised: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Leave a compromised pool with a still-locked position: the vault's LP is burned and the
+    /// proportional token A/B goes to the owner, ignoring the lock and any pause. Pending rewards
+    /// are forfeited; the emptied vault and the position close to the position's rent payer.
+    pub fn emergency_exit(ctx: Context<EmergencyExit>) -> Result<()> {
+        require!(ctx.accounts.pool.compromised, AmmError::PoolNotCompromised);
+        require!(!ctx.accounts.vesting_stake.claimed, AmmError::AlreadyClaimed);
+        check_pledge(&ctx.accounts.vesting_stake, ctx.accounts.pledgee.as_ref())?;
+        check_not_listed(&ctx.accounts.vesting_stake)?;
+        update_pool_rewards(&mut ctx.accounts.pool, Clock::get()?.unix_timestamp)?;
+
+        let lp_amount = ctx.accounts.vesting_token_account.amount;
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_amount > 0 && lp_supply > 0, AmmError::ZeroLpAmount);
+        let share = |reserve: u64| -> Result<u64> {
+            Ok((u128::from(reserve)
+                .checked_mul(u128::from(lp_amount))
+                .ok_or(AmmError::NumericOverflow)?
+                / u128::from(lp_supply)) as u64)
+        };
+        let amount_a = share(ctx.accounts.reserve_a.amount)?;
+        let amount_b = share(ctx.accounts.reserve_b.amount)?;
+
+        token::burn(
+            ctx.accounts.burn_vesting_lp_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+            lp_amount,
+        )?;
+        // The whole vault balance was burned, so the vault closes with the position
+        token::close_account(
+            ctx.accounts.close_vesting_vault_context().with_signer(vesting_signer!(ctx.accounts.vesting_stake)),
+        )?;
+        if amount_a > 0 {
+            token::transfer(
+                ctx.accounts.transfer_a_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_a,
+            )?;
+        }
+        if amount_b > 0 {
+            token::transfer(
+                ctx.accounts.transfer_b_to_user_context().with_signer(pool_signer!(ctx.accounts.pool)),
+                amount_b,
+            )?;
+        }
+
+        let vesting = &ctx.accounts.vesting_stake;
+        let pending = pending_reward_amount(vesting.amount, ctx.accounts.pool.acc_reward_per_lp, vesting.reward_debt)?;
+        let vesting_amount = vesting.amount;
+        let deposit_id = vesting.deposit_id;
+        let pool = &mut ctx.accounts.pool;
+        pool.release_reward_liability(pending);
+        pool.total_locked_lp = pool.total_locked_lp.saturating_sub(vesting_amount);
+        ctx.accounts.vesting_stake.claimed = true;
+        refund_claim_bounty(&mut ctx.accounts.vesting_stake, &ctx.accounts.user.to_account_info())?;
+
+        emit_cpi!(EmergencyExited {
+            pool: ctx.accounts.pool.key(),
+            user: ctx.accounts.user.key(),
+            deposit_id,
+            lp_amount,
+            amount_a,
+            amount_b,
+        });
+        Ok(())
+    }
+
+    /// Seat a 3-of-5 emergency council for the pool (authority only). The council can pause the
+    /// pool, flag it compromised, hand authority to a new key and rotate its own members without
+    /// the authority key, so losing that key does not strand the pool.
+    pub fn init_emergency_council(ctx: Context<InitEmergencyCouncil>, members: [Pubkey; COUNCIL_SIZE]) -> Result<()> {
+        validate_council_members(&members)?;
+        let council = &mut ctx.accounts.emergency_council;
+        council.pool = ctx.accounts.pool.key();
+        council.members = members;
+        emit_cpi!(EmergencyCouncilSet {
+            pool: council.pool,
+            members,
+        });
+        Ok(())
+    }
+
+    /// Open a council proposal in its own PDA (member only, who pays its rent), counting the
+    /// proposer's approval. Any number can be open at once; each expires after `COUNCIL_PROPOSAL_TTL`.
+    pub fn council_propose(ctx: Context<CouncilPropose>, action: CouncilAction) -> Result<()> {
+        let council = &mut ctx.accounts.emergency_council;
+        let index = council.member_index(&ctx.accounts.member.key())?;
+        if let CouncilAction::RotateMember { old_member, new_member } = action {
+            let mut members = council.members;
+            replace_council_member(&mut members, old_member, new_member)?;
+        }
+        let proposal_id = council.proposal_count;
+        council.proposal_count = proposal_id.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+        let proposal = &mut ctx.accounts.council_proposal;
+        proposal.council = council.key();
+        proposal.proposal_id = proposal_id;
+        proposal.generation = council.generation;
+        proposal.action = action;
+        proposal.approvals = 1 << index;
+        proposal.proposed_at = Clock::get()?.unix_timestamp;
+        emit_cpi!(CouncilActionProposed {
+            pool: council.pool,
+            proposal_id,
+            proposer: ctx.accounts.member.key(),
+            action,
+        });
+        Ok(())
+    }
+
+    /// Approve a council proposal (member only). The approval that reaches `COUNCIL_THRESHOLD`
+    /// executes it. Proposals opened before the last membership change can no longer pass.
+    pub fn council_approve(ctx: Context<CouncilApprove>, proposal_id: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let council = &ctx.accounts.emergency_council;
+        let index = council.member_index(&ctx.accounts.member.key())?;
+        let proposal = &mut ctx.accounts.council_proposal;
+        require!(!proposal.executed, AmmError::CouncilProposalNotFound);
+        require!(proposal.generation == council.generation, AmmError::CouncilProposalStale);
+        require_lt_ctx!(
+            now,
+            proposal.proposed_at.saturating_add(COUNCIL_PROPOSAL_TTL),
+            AmmError::CouncilProposalExpired
+        );
+        require!(proposal.approvals & (1 << index) == 0, AmmError::CouncilAlreadyApproved);
+        proposal.approvals |= 1 << index;
+        let approvals = proposal.approvals.count_ones();
+        emit_cpi!(CouncilActionApproved {
+            pool: council.pool,
+            proposal_id,
+            member: ctx.accounts.member.key(),
+            approvals,
+        });
+        if approvals < COUNCIL_THRESHOLD {
+            return Ok(());
+        }
+
+        proposal.executed = true;
+        let action = proposal.action;
+        let pool = &mut ctx.accounts.pool;
+        match action {
+            CouncilAction::Pause => {
+                pool.paused = true;
+                pool.pause_expires_at = 0;
+                emit_cpi!(Paused { pool: pool.key() });
+            }
+            CouncilAction::FlagCompromised => {
+                pool.compromised = true;
+                emit_cpi!(PoolCompromisedSet {
+                    pool: pool.key(),
+                    compromised: true,
+                });
+            }
+            CouncilAction::SetAuthority { new_authority } => {
+                let old_authority = pool.authority;
+                pool.authority = new_authority;
+                emit_cpi!(PoolAuthoritySet {
+                    pool: pool.key(),
+                    old_authority,
+                    new_authority,
+                });
+            }
+            CouncilAction::RotateMember { old_member, new_member } => {
+                let council = &mut ctx.accounts.emergency_council;
+                council.replace_member(old_member, new_member)?;
+                emit_cpi!(EmergencyCouncilSet {
+                    pool: council.pool,
+                    members: council.members,
+                });
+            }
+        }
+        emit_cpi!(CouncilActionExecuted {
+            pool: ctx.accounts.pool.key(),
+            proposal_id,
+            action,
+        });
+        Ok(())
+    }
+
+    /// Create a multi-asset stable pool over 3..=`MAX_MULTI_RESERVES` same-decimal tokens.
+    /// Remaining accounts are `n × [mint, reserve]`; each reserve must already be a token account
+    /// of its mint owned by the multi-pool PDA. The LP mint's authority moves to the pool.
+    /// Mints with a freeze authority are rejected; badges are not consulted here.
+    pub fn initialize_multi_pool<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitializeMultiPool<'info>>,
+        amp: u32,
+        fee_bps: u16,
+    ) -> Result<()> {
+        let n = ctx.remaining_accounts.len() / 2;
+        require!(
+            ctx.remaining_accounts.len().is_multiple_of(2) && (MIN_MULTI_RESERVES..=MAX_MULTI_RESERVES).contains(&n),
+            AmmError::InvalidReserveCount
+        );
+        require!((1..=MAX_AMP).contains(&amp), AmmError::InvalidAmp);
+        require_lte_ctx!(fee_bps, ctx.accounts.protocol_config.max_protocol_fee_bps, AmmError::FeeTooHigh);
+        require!(ctx.accounts.lp_mint.decimals == LP_DECIMALS, AmmError::InvalidLpDecimals);
+
+        let pool_key = ctx.accounts.multi_pool.key();
+        let mut mints = [Pubkey::default(); MAX_MULTI_RESERVES];
+        let mut reserves = [Pubkey::default(); MAX_MULTI_RESERVES];
+        let mut decimals = None;
+        for (k, chunk) in ctx.remaining_accounts.chunks(2).enumerate() {
+            let mint: Account<'info, Mint> = Account::try_from(&chunk[0])?;
+            let reserve: Account<'info, TokenAccount> = Account::try_from(&chunk[1])?;
+            require!(
+                reserve.mint == mint.key() && reserve.owner == pool_key,
+                AmmError::InvalidTokenAccountOwner
+            );
+            check_token_badge(&mint, None)?;
+            require!(!mints[..k].contains(&mint.key()), AmmError::InvalidMultiPoolAccounts);
+            require!(*decimals.get_or_insert(mint.decimals) == mint.decimals, AmmError::InvalidCurveType);
+            mints[k] = mint.key();
+            reserves[k] = reserve.key();
+        }
+
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        multi_pool.authority = ctx.accounts.authority.key();
+        multi_pool.lp_mint = ctx.accounts.lp_mint.key();
+        multi_pool.bump = ctx.bumps.multi_pool;
+        multi_pool.n_reserves = n as u8;
+        multi_pool.mints = mints;
+        multi_pool.reserves = reserves;
+        multi_pool.amp = amp;
+        multi_pool.fee_bps = fee_bps;
+        multi_pool.decimals = decimals.unwrap_or_default();
+
+        let cpi_accounts = SetAuthority {
+            account_or_mint: ctx.accounts.lp_mint.to_account_info().clone(),
+            current_authority: ctx.accounts.authority.to_account_info().clone(),
+        };
+        token::set_authority(
+            CpiContext::new(ctx.accounts.token_program.to_account_info().clone(), cpi_accounts),
+            SplAuthorityType::MintTokens,
+            Some(pool_key),
+        )?;
+
+        emit_cpi!(MultiPoolInitialized {
+            pool: pool_key,
+            authority: multi_pool.authority,
+            n_reserves: multi_pool.n_reserves,
+            amp,
+            fee_bps,
+        });
+        Ok(())
+    }
+
+    /// Deposit any mix of a multi-asset pool's tokens and lock the minted LP in a vesting position.
+    /// `amounts[k]` goes to reserve k; remaining accounts are `n × [user_token_account, reserve]`.
+    /// Imbalanced deposits pay the pool fee on their deviation from the current ratios.
+    /// `deposit_id` is any id the user hasn't used in this pool: the position PDAs are seeded by
+    /// user and id, so one user's ids can never block another's.
+    pub fn deposit_multi_and_vest<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DepositMultiAndVest<'info>>,
+        amounts: Vec<u64>,
+        vesting_seconds: i64,
+        min_lp_minted: u64,
+        deposit_id: u64,
+    ) -> Result<()> {
+        require!(!ctx.accounts.multi_pool.paused, AmmError::Paused);
+        require!(
+            (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&vesting_seconds),
+            AmmError::InvalidVestingPeriod
+        );
+        require_eq_ctx!(
+            amounts.len(),
+            usize::from(ctx.accounts.multi_pool.n_reserves),
+            AmmError::InvalidMultiPoolAccounts
+        );
+        require!(amounts.iter().any(|a| *a > 0), AmmError::ZeroDepositAmount);
+        let accounts = load_multi_accounts(
+            &ctx.accounts.multi_pool,
+            ctx.remaining_accounts,
+            &ctx.accounts.user.key(),
+        )?;
+
+        let old_balances: Vec<u128> = accounts.iter().map(|(_, reserve)| u128::from(reserve.amount)).collect();
+        let new_balances: Vec<u128> = old_balances
+            .iter()
+            .zip(&amounts)
+            .map(|(balance, amount)| balance + u128::from(*amount))
+            .collect();
+        let lp_minted = calculate_multi_lp_mint_amount(
+            &ctx.accounts.multi_pool,
+            &old_balances,
+            &new_balances,
+            ctx.accounts.lp_mint.supply,
+        )?;
+        require!(lp_minted > 0, AmmError::SlippageExceeded);
+        require_gte_ctx!(lp_minted, min_lp_minted, AmmError::SlippageExceeded);
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        for ((user_token, reserve), amount) in accounts.iter().zip(&amounts) {
+            if *amount > 0 {
+                token::transfer(
+                    CpiContext::new(
+                        token_program.clone(),
+                        Transfer {
+                            from: user_token.to_account_info(),
+                            to: reserve.to_account_info(),
+                            authority: ctx.accounts.user.to_account_info(),
+                        },
+                    ),
+                    *amount,
+                )?;
+            }
+        }
+        let multi_pool = &ctx.accounts.multi_pool;
+        token::mint_to(
+            CpiContext::new_with_signer(
+                token_program,
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.vesting_token_account.to_account_info(),
+                    authority: multi_pool.to_account_info(),
+                },
+                &[&[SEED_MULTI_POOL, multi_pool.lp_mint.as_ref(), &[multi_pool.bump]]],
+            ),
+            lp_minted,
+        )?;
+
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.pool = multi_pool.key();
+        vesting.user = ctx.accounts.user.key();
+        vesting.depositor = ctx.accounts.user.key();
+        vesting.bump = ctx.bumps.vesting_stake;
+        vesting.rent_payer = ctx.accounts.user.key();
+        vesting.amount = lp_minted;
+        vesting.held_since = Clock::get()?.unix_timestamp;
+        vesting.vesting_end = vesting.held_since + vesting_seconds;
+        vesting.claimed = false;
+        vesting.deposit_id = deposit_id;
+
+        multi_pool.total_locked_lp = multi_pool
+            .total_locked_lp
+            .checked_add(lp_minted)
+            .ok_or(AmmError::NumericOverflow)?;
+
+        emit_cpi!(MultiDeposited {
+            pool: multi_pool.key(),
+            user: vesting.user,
+            amounts,
+            lp_minted,
+            vesting_end: vesting.vesting_end,
+            deposit_id,
+        });
+        Ok(())
+    }
+
+    /// Release a matured multi-asset pool position's LP to the user and close its vault.
+    pub fn claim_multi_vested(ctx: Context<ClaimMultiVested>) -> Result<()> {
+        let vesting = &ctx.accounts.vesting_stake;
+        require!(!vesting.claimed, AmmError::AlreadyClaimed);
+        check_not_listed(vesting)?;
+        require_gte_ctx!(Clock::get()?.unix_timestamp, vesting.vesting_end, AmmError::VestingNotFinished);
+
+        // Move the whole vault balance so the vault can be closed
+        let amount = vesting.amount;
+        let vault_balance = ctx.accounts.vesting_token_account.amount;
+        let token_program = ctx.accounts.token_program.to_account_info();
+        if vault_balance > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.clone(),
+                    Transfer {
+                        from: ctx.accounts.vesting_token_account.to_account_info(),
+                        to: ctx.accounts.user_lp_token_account.to_account_info(),
+                        authority: ctx.accounts.vesting_stake.to_account_info(),
+                    },
+                    vesting_signer!(ctx.accounts.vesting_stake),
+                ),
+                vault_balance,
+            )?;
+        }
+        token::close_account(CpiContext::new_with_signer(
+            token_program,
+            CloseAccount {
+                account: ctx.accounts.vesting_token_account.to_account_info(),
+                destination: ctx.accounts.user.to_account_info(),
+                authority: ctx.accounts.vesting_stake.to_account_info(),
+            },
+            vesting_signer!(ctx.accounts.vesting_stake),
+        ))?;
+
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        multi_pool.total_locked_lp = multi_pool.total_locked_lp.saturating_sub(amount);
+        let vesting = &mut ctx.accounts.vesting_stake;
+        vesting.claimed = true;
+
+        emit_cpi!(Claimed {
+            pool: multi_pool.key(),
+            user: vesting.user,
+            amount,
+            tag: vesting.tag,
+        });
+        Ok(())
+    }
+
+    /// Halt multi-pool deposits and swaps (multi-pool authority). Claims and withdrawals stay open.
+    pub fn pause_multi_pool(ctx: Context<OnlyMultiPoolAuthority>) -> Result<()> {
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        multi_pool.paused = true;
+        emit_cpi!(Paused { pool: multi_pool.key() });
+        Ok(())
+    }
+
+    /// Resume multi-pool deposits and swaps (multi-pool authority).
+    pub fn unpause_multi_pool(ctx: Context<OnlyMultiPoolAuthority>) -> Result<()> {
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        multi_pool.paused = false;
+        emit_cpi!(Unpaused { pool: multi_pool.key() });
+        Ok(())
+    }
+
+    /// Hand multi-pool authority to a new key, as `set_pool_authority` does for pairs.
+    pub fn set_multi_pool_authority(ctx: Context<OnlyMultiPoolAuthority>, new_authority: Pubkey) -> Result<()> {
+        let multi_pool = &mut ctx.accounts.multi_pool;
+        let old_authority = multi_pool.authority;
+        multi_pool.authority = new_authority;
+        emit_cpi!(PoolAuthoritySet {
+            pool: multi_pool.key(),
+            old_authority,
+            new_authority,
+        });
+        Ok(())
+    }
+
+    /// Burn unlocked multi-pool LP for a pro-rata share of every reserve.
+    /// Remaining accounts are `n × [user_token_account, reserve]`.
+    pub fn withdraw_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawMulti<'info>>,
+        lp_amount: u64,
+        min_amounts: Vec<u64>,
+    ) -> Result<()> {
+        require!(lp_amount > 0, AmmError::ZeroLpAmount);
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_supply > 0, AmmError::InsufficientLiquidity);
+        require_eq_ctx!(
+            min_amounts.len(),
+            usize::from(ctx.accounts.multi_pool.n_reserves),
+            AmmError::InvalidMultiPoolAccounts
+        );
+        let accounts = load_multi_accounts(
+            &ctx.accounts.multi_pool,
+            ctx.remaining_accounts,
+            &ctx.accounts.user.key(),
+        )?;
+
+        let mut amounts = Vec::with_capacity(accounts.len());
+        for ((_, reserve), min_amount) in accounts.iter().zip(&min_amounts) {
+            let amount = u128::from(reserve.amount)
+                .checked_mul(u128::from(lp_amount))
+                .ok_or(AmmError::NumericOverflow)?
+                / u128::from(lp_supply);
+            let amount = u64::try_from(amount).map_err(|_| AmmError::NumericOverflow)?;
+            require_gte_ctx!(amount, *min_amount, AmmError::SlippageExceeded);
+            amounts.push(amount);
+        }
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        token::burn(
+            CpiContext::new(
+                token_program.clone(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.user_lp_token_account.to_account_info(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+        for ((user_token, reserve), amount) in accounts.iter().zip(&amounts) {
+            if *amount > 0 {
+                token::transfer(
+                    CpiContext::new_with_signer(
+                        token_program.clone(),
+                        Transfer {
+                            from: reserve.to_account_info(),
+                            to: user_token.to_account_info(),
+                            authority: ctx.accounts.multi_pool.to_account_info(),
+                        },
+                        &[&[SEED_MULTI_POOL, ctx.accounts.lp_mint.key().as_ref(), &[ctx.accounts.multi_pool.bump]]],
+                    ),
+                    *amount,
+                )?;
+            }
+        }
+
+        emit_cpi!(MultiWithdrawn {
+            pool: ctx.accounts.multi_pool.key(),
+            user: ctx.accounts.user.key(),
+            lp_amount,
+            amounts,
+        });
+        Ok(())
+    }
+
+    /// Swap `amount_in` of reserve `i`'s token for reserve `j`'s on a multi-asset pool. All `n`
+    /// reserves are passed in order as remaining accounts. The fee is withheld from the output
+    /// and stays in the pool for LPs.
+    pub fn swap_multi<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SwapMulti<'info>>,
+        i: u8,
+        j: u8,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<()> {
+        let multi_pool = &ctx.accounts.multi_pool;
+        require!(!multi_pool.paused, AmmError::Paused);
+        require!(amount_in > 0, AmmError::ZeroSwapAmount);
+        let n = usize::from(multi_pool.n_reserves);
+        let (i, j) = (usize::from(i), usize::from(j));
+        require!(
+            i < n && j < n && i != j && ctx.remaining_accounts.len() == n,
+            AmmError::InvalidMultiPoolAccounts
+        );
+        require!(
+            ctx.accounts.user_source.mint == multi_pool.mints[i]
+                && ctx.accounts.user_destination.mint == multi_pool.mints[j],
+            AmmError::InvalidTokenAccountOwner
+        );
+
+        let balances_before = load_multi_balances(multi_pool, ctx.remaining_accounts)?;
+        let (amount_out, fee) = compute_multi_swap(multi_pool, &balances_before, i, j, amount_in)?;
+        require_gte_ctx!(amount_out, minimum_amount_out, AmmError::SlippageExceeded);
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        token::transfer(
+            CpiContext::new(
+                token_program.clone(),
+                Transfer {
+                    from: ctx.accounts.user_source.to_account_info(),
+                    to: ctx.remaining_accounts[i].clone(),
+                    authority: ctx.accounts.user.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program,
+                Transfer {
+                    from: ctx.remaining_accounts[j].clone(),
+                    to: ctx.accounts.user_destination.to_account_info(),
+                    authority: multi_pool.to_account_info(),
+                },
+                &[&[SEED_MULTI_POOL, multi_pool.lp_mint.as_ref(), &[multi_pool.bump]]],
+            ),
+            amount_out,
+        )?;
+
+        // Re-read the reserves and make sure D never shrank
+        let amp = u64::from(multi_pool.amp);
+        let balances_after = load_multi_balances(multi_pool, ctx.remaining_accounts)?;
+        require_gte_ctx!(
+            stable_invariant(amp, &balances_after)?,
+            stable_invariant(amp, &balances_before)?,
+            AmmError::InvariantViolated
+        );
+
+        emit_cpi!(MultiSwapped {
+            pool: multi_pool.key(),
+            user: ctx.accounts.user.key(),
+            i: i as u8,
+            j: j as u8,
+            amount_in,
+            amount_out,
+            fee,
+        });
+        Ok(())
+    }
+}
+
+// ---------------------- Accounts ----------------------
+
+#[account]
+pub struct Pool {
+    pub authority: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub reserve_a: Pubkey,
+    pub reserve_b: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub treasury: Pubkey,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+    pub vesting_nonce: u64, // legacy global deposit counter; frozen, new positions use UserVestingIndex
+    pub paused: bool,
+    pub acc_reward_per_lp: u128, // scaled by REWARD_SCALE
+    pub permissioned: bool,      // deposits gated by AllowlistEntry PDAs
+    pub keeper: Pubkey,          // operational role (pause, cranks), no fee or emergency powers
+    pub abandonment_period: i64, // seconds after vesting_end before a position can be swept; 0 = disabled
+    pub curve_type: u8,          // CURVE_* constant
+    pub treasury_a: Pubkey,      // token A account receiving treasury swap fees
+    pub treasury_b: Pubkey,      // token B account receiving treasury swap fees
+    pub reward_emission_per_second: u64,
+    pub last_reward_update_ts: i64, // last time emissions were folded into acc_reward_per_lp
+    pub penalize_rewards: bool,  // early-unvest penalty also applies to settled rewards
+    pub lp_fee_bps: u16,         // share of protocol_fee_bps left in reserves for LPs
+    pub allow_claims_while_paused: bool, // matured positions stay claimable during a pause
+    pub total_reward_liability: u64, // rewards accrued to positions but not yet settled
+    pub governance_realm: Pubkey, // SPL Governance realm using locked LP as voter weight; default = none
+    pub governance_mint: Pubkey,  // governing token mint registered for this pool in the realm
+    pub total_locked_lp: u64,     // LP currently held in vesting vaults
+    pub min_deposit_a: u64,
+    pub min_deposit_b: u64,
+    pub min_lp_minted: u64,       // smallest position deposit_and_vest will create
+    pub compliance_mode: bool,    // deposits and swaps checked against the global blocklist
+    pub swap_allowlist_enabled: bool, // bootstrap phase: swaps limited to MarketMakerEntry holders
+    pub swap_allowlist_until: i64,    // bootstrap deadline; gating lapses automatically after it
+    pub reward_mode: u8,              // REWARD_MODE_* constant
+    pub acc_reward_a_per_lp: u128,    // token A rewards per locked LP, scaled by REWARD_SCALE
+    pub acc_reward_b_per_lp: u128,    // token B rewards per locked LP, scaled by REWARD_SCALE
+    pub treasury_asset_mint: Pubkey,  // mint convert_treasury_fees consolidates into; default = disabled
+    pub max_conversion_impact_bps: u16, // max shortfall of a conversion vs. pre-trade spot price
+    pub sale_fee_bps: u16,            // protocol cut of secondary-market position sales, to treasury
+    pub scheduled_action_nonce: u64,  // id of the next ScheduledAction
+    pub oracle_poke_reward: u64,      // token A paid from a pool-held treasury_a per successful poke_oracle
+    pub relock_bonus_bps: u16,        // LP reward bonus per consecutive relock, capped at MAX_RELOCK_BONUS_BPS
+    pub oracle_price: u128,           // reference price of A in B, scaled by PRICE_SCALE; 0 = no oracle
+    pub oracle_tolerance_bps: u16,    // allowed deviation of the first deposit's ratio from oracle_price
+    pub treasury_to_rewards_bps: u16, // share of the pool-held LP treasury routed to rewards per run
+    pub last_treasury_route_ts: i64,
+    pub initial_amp: u32,             // stable curve: amp at ramp_start_ts
+    pub target_amp: u32,              // stable curve: amp reached at ramp_end_ts
+    pub ramp_start_ts: i64,
+    pub ramp_end_ts: i64,
+    pub fee_on_output: bool,          // swap fee withheld from the output token instead of the input
+    pub depeg_threshold_bps: u16,     // stable curve: internal price deviation that trips the breaker; 0 = off
+    pub depeg_blocks_swaps: bool,     // a tripped breaker also halts swaps, not just deposits
+    pub depegged: bool,               // breaker tripped; cleared only by the authority
+    pub rebalance_fee_bps: u16,       // reduced fee for swaps moving the price toward oracle/TWAP; 0 = off
+    pub reward_epoch: u32,            // bumped on every relock bonus change; 0 = RewardEpochs not tracked
+    pub penalty_grace_seconds: i64,   // early_unvest is penalty-free this close to vesting_end; 0 = off
+    pub fee_holiday_start: i64,       // promotional swap fee window [start, end); 0/0 = none
+    pub fee_holiday_end: i64,
+    pub fee_holiday_bps: u16,         // fee charged instead of protocol_fee_bps during the window
+    pub claim_receipt_period: i64,    // seconds a ClaimReceipt stays open after claim_vested; 0 = no receipts
+    pub a_to_b_paused: bool,          // halts A->B swaps only; deposits, claims and B->A keep working
+    pub b_to_a_paused: bool,          // halts B->A swaps only
+    pub emergency_drip_active: bool,  // reserves are streaming to treasury (EmergencyDrip); deposits blocked
+    pub compromised: bool,            // pool failing: locked positions may exit early via emergency_exit
+    pub hook_enabled: bool,           // a PoolHook is registered; deposit/claim/early_unvest CPI into it
+    pub last_insurance_route_ts: i64, // last route_treasury_to_insurance run
+    pub points_rate_bps: u16,         // LP reward paid per 10_000 TraderPoints redeemed; 0 disables redemption
+    pub caller_restricted: bool,      // swap only from the programs in AllowedCallers
+    pub fee_vote_round: u32,          // seeds the next FeeVote
+    pub penalty_to_rewards_bps: u16,  // share of early-unvest LP penalties paid to lockers via the reward vault
+    pub pause_expires_at: i64,        // a pause set by pause_for lifts on its own at this time; 0 = no expiry
+    pub gauge_enabled: bool,          // reward emission is set by the GaugeController via sync_pool_gauge
+    pub max_reserve_out_bps: u16,     // most of the output reserve one trade may take; 0 = uncapped
+    pub reserve_cap_until: i64,       // max_reserve_out_bps stops applying at this time; 0 = no end
+    pub treasury_owner: Pubkey,       // treasury_a/b are this owner's ATAs, created by swap when missing; default = unset
+    pub trader_rebate_bps: u16,       // share of each swap's LP fee rebated to the trader as locked LP; 0 = off
+    pub trader_rebate_seconds: i64,   // a rebate top-up keeps the trader's rebate position locked this long
+    pub epoch_rewards: bool,          // emissions reach acc_reward_per_lp only via finalize_reward_epoch
+    pub bump: u8,                     // signs as reserve/vault owner and LP mint authority
+    pub early_unvest_penalty_bps: u16, // early_unvest penalty; applied capped at protocol max_penalty_bps
+    pub depeg_strikes: u8,            // consecutive observations off peg, see DEPEG_CONFIRM_OBSERVATIONS
+    pub fee_vote_open: bool,          // a FeeVote is running; the next opens after it is finalized
+}
+
+impl Pool {
+    /// Whether the pool is paused at `now`, treating a pause past `pause_expires_at` as lifted.
+    pub fn is_paused(&self, now: i64) -> bool {
+        self.paused && (self.pause_expires_at == 0 || now < self.pause_expires_at)
+    }
+
+    /// Whether swaps in this direction are halted by `set_swap_direction_paused`.
+    pub fn direction_paused(&self, is_a_to_b: bool) -> bool {
+        if is_a_to_b {
+            self.a_to_b_paused
+        } else {
+            self.b_to_a_paused
+        }
+    }
+
+    /// Swap fee in force at `now`: the fee-holiday rate inside its window, `protocol_fee_bps` otherwise.
+    pub fn swap_fee_bps(&self, now: i64) -> u16 {
+        if now >= self.fee_holiday_start && now < self.fee_holiday_end {
+            self.fee_holiday_bps.min(self.protocol_fee_bps)
+        } else {
+            self.protocol_fee_bps
+        }
+    }
+
+    /// Token the swap fee is charged in: the input, or the output with `fee_on_output`.
+    pub fn fee_in_token_a(&self, is_a_to_b: bool) -> bool {
+        is_a_to_b != self.fee_on_output
+    }
+
+    /// Amplification coefficient at `now`, linearly interpolated across an active ramp.
+    pub fn current_amp(&self, now: i64) -> u64 {
+        let target = u64::from(self.target_amp);
+        if now >= self.ramp_end_ts || self.ramp_end_ts <= self.ramp_start_ts {
+            return target;
+        }
+        let initial = i128::from(self.initial_amp);
+        let elapsed = i128::from(now.max(self.ramp_start_ts) - self.ramp_start_ts);
+        let duration = i128::from(self.ramp_end_ts - self.ramp_start_ts);
+        let amp = initial + (i128::from(target) - initial) * elapsed / duration;
+        amp as u64
+    }
+
+    /// Early-unvest penalty in force: the pool's rate, capped by the protocol bound in case that was
+    /// lowered after the rate was set.
+    fn early_unvest_penalty(&self, config: &ProtocolConfig) -> u16 {
+        self.early_unvest_penalty_bps.min(config.max_penalty_bps)
+    }
+
+    fn swap_allowlist_active(&self, now: i64) -> bool {
+        self.swap_allowlist_enabled && now < self.swap_allowlist_until
+    }
+
+    fn reserve_cap_active(&self, now: i64) -> bool {
+        self.max_reserve_out_bps > 0 && (self.reserve_cap_until == 0 || now < self.reserve_cap_until)
+    }
+
+    fn add_reward_liability(&mut self, amount: u64) {
+        self.total_reward_liability = self.total_reward_liability.saturating_add(amount);
+    }
+
+    /// Book what a swap's reward-fee credit made claimable: the rise in `acc_reward_per_lp` since
+    /// `acc_before` over the locked LP, in the reward vault's LP units. The fee itself is token A or
+    /// B and can't be booked as is.
+    fn book_accumulator_liability(&mut self, acc_before: u128) {
+        let delta = self.acc_reward_per_lp.saturating_sub(acc_before);
+        let owed = delta.saturating_mul(u128::from(self.total_locked_lp)) / REWARD_SCALE;
+        self.add_reward_liability(u64::try_from(owed).unwrap_or(u64::MAX));
+    }
+
+    fn release_reward_liability(&mut self, amount: u64) {
+        self.total_reward_liability = self.total_reward_liability.saturating_sub(amount);
+    }
+
+    /// Reward-vault balance not owed to any position, the only source for relock bonuses.
+    fn reward_surplus(&self, vault_balance: u64) -> u64 {
+        vault_balance.saturating_sub(self.total_reward_liability)
+    }
+
+    /// Spread `amount` LP just paid into the reward vault over every LP and book it as owed.
+    fn distribute_rewards(&mut self, amount: u64, lp_supply: u64) -> Result<()> {
+        if amount == 0 || lp_supply == 0 {
+            return Ok(());
+        }
+        let delta = u128::from(amount) * REWARD_SCALE / u128::from(lp_supply);
+        self.acc_reward_per_lp =
+            credit_accumulator(self.acc_reward_per_lp, delta).ok_or(AmmError::RewardAccumulatorCapped)?;
+        self.add_reward_liability(amount);
+        Ok(())
+    }
+}
+
+#[account]
+pub struct VestingStake {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub vesting_end: i64,
+    pub claimed: bool,
+    pub deposit_id: u64,
+    pub reward_debt: u128,
+    pub reward_debt_a: u128,
+    pub reward_debt_b: u128,
+    pub pledgee: Pubkey, // lender whose co-signature claim/early-unvest require; default = unpledged
+    pub depositor: Pubkey, // original depositor, kept for PDA seeds after `user` changes hands
+    pub relock_count: u32, // consecutive relocks after maturity; drives the reward bonus
+    pub rent_payer: Pubkey, // funded the vesting PDA and vault; receives their rent on close
+    pub tag: [u8; 32],      // opaque client label set at deposit (campaign, strategy, ...)
+    pub reward_epoch: u32,  // pool.reward_epoch at deposit / last relock; picks the relock bonus rate
+    pub matured: bool,      // set by the mark_matured crank once vesting_end passed; cleared on relock
+    pub claim_bounty: u64,  // lamports escrowed on this PDA for whoever cranks the claim (crank_claim)
+    pub auto_relock: Option<i64>, // relock duration execute_auto_relock rolls into at maturity; None = off
+    pub bump: u8,           // signs for the position's vault
+    pub listed: bool,       // a VestingListing is open; claim, exit and relock wait for its sale or cancel
+    pub held_since: i64,    // deposit, relock, LP top-up or sale; fee votes count positions held before opening
+}
+
+impl VestingStake {
+    /// Governance weight: 1x the locked LP at maturity, scaling linearly up to 2x for a
+    /// position with the maximum lock still remaining.
+    pub fn weighted_amount(&self, now: i64) -> u64 {
+        let remaining = (self.vesting_end - now).clamp(0, MAX_VESTING_SECONDS);
+        let bonus = u128::from(self.amount) * remaining as u128 / MAX_VESTING_SECONDS as u128;
+        self.amount.saturating_add(bonus as u64)
+    }
+}
+
+/// spl-governance voter-weight addin record. Field order and the Anchor discriminator
+/// (`account:VoterWeightRecord`) match `spl_governance_addin_api::voter_weight::VoterWeightRecord`.
+#[account]
+pub struct VoterWeightRecord {
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+    pub governing_token_owner: Pubkey,
+    pub voter_weight: u64,
+    pub voter_weight_expiry: Option<u64>,
+    pub weight_action: Option<VoterWeightAction>,
+    pub weight_action_target: Option<Pubkey>,
+    pub reserved: [u8; 8],
+}
+
+/// Mirrors `spl_governance_addin_api::voter_weight::VoterWeightAction`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VoterWeightAction {
+    CastVote,
+    CommentProposal,
+    CreateGovernance,
+    CreateProposal,
+    SignOffProposal,
+}
+
+/// One round of LP fee voting. `weights[i]` is the weighted LP behind `options[i]`, each position
+/// weighted as of `opened_at`.
+#[account]
+pub struct FeeVote {
+    pub pool: Pubkey,
+    pub round: u32,
+    pub voting_end: i64,
+    pub options: [u16; MAX_FEE_VOTE_OPTIONS], // candidate protocol_fee_bps values
+    pub option_count: u8,
+    pub weights: [u64; MAX_FEE_VOTE_OPTIONS],
+    pub finalized: bool,
+    pub opened_at: i64,       // positions held since before this vote
+    pub total_locked_lp: u64, // pool.total_locked_lp at opening; the quorum base
+    pub turnout_lp: u64,      // locked LP of the positions that voted
+}
+
+/// Emergency vote on waiving early-unvest penalties pool-wide; once passed, the waiver runs
+/// until `waiver_until`.
+#[account]
+pub struct PenaltyWaiverVote {
+    pub pool: Pubkey,
+    pub reason_code: u16,
+    pub opened_at: i64, // seeds this vote's ballots, so a reopened vote starts from fresh ballots
+    pub voting_end: i64,
+    pub waiver_seconds: i64,
+    pub total_locked_lp: u64, // pool.total_locked_lp at opening; the quorum base
+    pub approve_weight: u64,
+    pub reject_weight: u64,
+    pub finalized: bool,
+    pub waiver_until: i64, // 0 unless the vote passed
+}
+
+impl PenaltyWaiverVote {
+    pub fn active(&self, now: i64) -> bool {
+        self.finalized && now < self.waiver_until
+    }
+}
+
+/// A position's ballot in a `PenaltyWaiverVote`; its PDA existing is what stops a second vote.
+#[account]
+pub struct PenaltyWaiverBallot {
+    pub penalty_waiver: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub approve: bool,
+    pub weight: u64,
+}
+
+/// A position's ballot in a `FeeVote`; its PDA existing is what stops a second vote.
+#[account]
+pub struct FeeVoteRecord {
+    pub fee_vote: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub option: u8,
+    pub weight: u64,
+}
+
+/// Relock bonus rate per pool epoch. A new epoch starts whenever `relock_bonus_bps` changes;
+/// entry `epoch % REWARD_EPOCH_RING_SIZE` holds it.
+#[account]
+pub struct RewardEpochs {
+    pub pool: Pubkey,
+    pub entries: [RewardEpoch; REWARD_EPOCH_RING_SIZE],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct RewardEpoch {
+    pub epoch: u32,
+    pub start_ts: i64,
+    pub relock_bonus_bps: u16,
+}
+
+impl RewardEpochs {
+    fn record(&mut self, pool: &Pool, now: i64) {
+        self.entries[pool.reward_epoch as usize % REWARD_EPOCH_RING_SIZE] = RewardEpoch {
+            epoch: pool.reward_epoch,
+            start_ts: now,
+            relock_bonus_bps: pool.relock_bonus_bps,
+        };
+    }
+
+    pub fn get(&self, epoch: u32) -> Option<RewardEpoch> {
+        let entry = self.entries[epoch as usize % REWARD_EPOCH_RING_SIZE];
+        (entry.epoch == epoch && entry.start_ts != 0).then_some(entry)
+    }
+}
+
+/// Finalized emission epochs of a pool in epoch-settlement mode; entry
+/// `epoch_index % REWARD_LEDGER_RING_SIZE` holds one, so payouts can be audited epoch by epoch.
+#[account]
+pub struct RewardLedger {
+    pub pool: Pubkey,
+    pub start_epoch: u64, // first epoch settled this way (partial: from enable_epoch_rewards)
+    pub last_finalized_epoch: u64,
+    pub entries: [FinalizedEpoch; REWARD_LEDGER_RING_SIZE],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct FinalizedEpoch {
+    pub epoch_index: u64,
+    pub emitted: u64, // reward distributed for the epoch
+    pub total_locked_lp: u64,
+    pub reward_per_lp: u128, // added to acc_reward_per_lp, scaled by REWARD_SCALE
+    pub acc_reward_per_lp: u128, // accumulator after this epoch
+    pub finalized_at: i64,
+}
+
+/// Rolling per-pool epoch snapshots; entry `epoch_index % SNAPSHOT_RING_SIZE` is overwritten each epoch.
+#[account]
+pub struct EpochSnapshot {
+    pub pool: Pubkey,
+    pub latest_epoch: u64,
+    pub entries: [SnapshotEntry; SNAPSHOT_RING_SIZE],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct SnapshotEntry {
+    pub epoch_index: u64,
+    pub total_locked_lp: u64,
+    pub acc_reward_per_lp: u128,
+    pub timestamp: i64,
+}
+
+/// Rolling time-weighted price accumulator. The running sums of price * seconds advance on every
+/// swap, at the price that held since the previous one; each entry snapshots them, so the TWAP
+/// between two entries is `(cumulative_2 - cumulative_1) / (timestamp_2 - timestamp_1)`.
+#[account]
+pub struct PriceObservations {
+    pub pool: Pubkey,
+    pub latest_index: u16,
+    pub last_update_ts: i64,         // when the running sums were last advanced
+    pub price_a_cumulative: u128,    // running sum, token A priced in B, scaled by PRICE_SCALE, wrapping
+    pub price_b_cumulative: u128,    // running sum, token B priced in A
+    pub entries: [Observation; OBSERVATION_RING_SIZE],
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct Observation {
+    pub timestamp: i64,
+    pub price_a_cumulative: u128, // token A priced in B, scaled by PRICE_SCALE, wrapping
+    pub price_b_cumulative: u128, // token B priced in A, scaled by PRICE_SCALE, wrapping
+}
+
+impl PriceObservations {
+    pub fn latest(&self) -> Observation {
+        self.entries[self.latest_index as usize]
+    }
+
+    /// Time-weighted price of A in B (PRICE_SCALE) across the whole buffer, once it spans time.
+    pub fn twap_price_a(&self) -> Option<u128> {
+        let latest = self.latest();
+        let next = self.entries[(self.latest_index as usize + 1) % OBSERVATION_RING_SIZE];
+        let oldest = if next.timestamp != 0 { next } else { self.entries[0] };
+        let elapsed = latest.timestamp.checked_sub(oldest.timestamp).filter(|e| *e > 0)?;
+        Some(latest.price_a_cumulative.wrapping_sub(oldest.price_a_cumulative) / elapsed as u128)
+    }
+
+    /// Advance the running sums to `now` at the price of `reserve_a`/`reserve_b`, which must be
+    /// the reserves in effect since the last call (callers pass them before moving any tokens).
+    /// Every swap path calls this, so each interval is priced at the price that actually held.
+    /// A ring entry is written at most once per `OBSERVATION_MIN_INTERVAL`; returns whether one was.
+    fn record(&mut self, reserve_a: u64, reserve_b: u64, now: i64) -> bool {
+        if self.last_update_ts != 0 && now > self.last_update_ts && reserve_a > 0 && reserve_b > 0 {
+            let elapsed = (now - self.last_update_ts) as u128;
+            let price_a = u128::from(reserve_b) * PRICE_SCALE / u128::from(reserve_a);
+            let price_b = u128::from(reserve_a) * PRICE_SCALE / u128::from(reserve_b);
+            self.price_a_cumulative = self.price_a_cumulative.wrapping_add(price_a.wrapping_mul(elapsed));
+            self.price_b_cumulative = self.price_b_cumulative.wrapping_add(price_b.wrapping_mul(elapsed));
+        }
+        self.last_update_ts = self.last_update_ts.max(now);
+
+        let last = self.latest();
+        if last.timestamp != 0 && now - last.timestamp < OBSERVATION_MIN_INTERVAL {
+            return false;
+        }
+        let next = Observation {
+            timestamp: now,
+            price_a_cumulative: self.price_a_cumulative,
+            price_b_cumulative: self.price_b_cumulative,
+        };
+        let index = if last.timestamp == 0 {
+            self.latest_index as usize
+        } else {
+            (self.latest_index as usize + 1) % OBSERVATION_RING_SIZE
+        };
+        self.entries[index] = next;
+        self.latest_index = index as u16;
+        true
+    }
+}
+
+/// Program-wide bounds, managed by the protocol admin, that every pool is checked against.
+#[account]
+pub struct ProtocolConfig {
+    pub admin: Pubkey,
+    pub max_protocol_fee_bps: u16,
+    pub max_penalty_bps: u16,
+    pub max_reward_emission_per_second: u64,
+    pub insurance_bps: u16, // share of pool-held treasuries route_treasury_to_insurance moves per run
+    pub cosign_lp_threshold: u64, // LP supply above which emergency_withdraw needs a co-signer; 0 = off
+    pub bump: u8, // signs for the insurance fund
+}
+
+impl ProtocolConfig {
+    fn set_bounds(
+        &mut self,
+        max_protocol_fee_bps: u16,
+        max_penalty_bps: u16,
+        max_reward_emission_per_second: u64,
+    ) -> Result<()> {
+        require_lte_ctx!(max_protocol_fee_bps, HARD_MAX_PROTOCOL_FEE_BPS, AmmError::FeeTooHigh);
+        require_lte_ctx!(max_penalty_bps, HARD_MAX_PENALTY_BPS, AmmError::InvalidPenalty);
+        self.max_protocol_fee_bps = max_protocol_fee_bps;
+        self.max_penalty_bps = max_penalty_bps;
+        self.max_reward_emission_per_second = max_reward_emission_per_second;
+        Ok(())
+    }
+}
+
+/// Protocol-wide emission budget (PDA `["gauge_controller"]`) split across pools by `PoolGauge` weight.
+/// Gauge epochs are `epoch_duration` long, counted from `genesis_ts`, starting at 1.
+#[account]
+pub struct GaugeController {
+    pub admin: Pubkey, // protocol admin at creation; may be handed to a governance program
+    pub emission_per_second: u64, // budget shared by every gauged pool, in reward_mint units
+    pub total_weight: u64,
+    pub epoch_duration: i64,
+    pub genesis_ts: i64,
+    pub reward_mint: Pubkey, // paid to every gauged pool from the gauge vault
+    pub bump: u8,            // signs for the gauge vault
+}
+
+impl GaugeController {
+    fn current_epoch(&self, now: i64) -> u32 {
+        u32::try_from((now - self.genesis_ts) / self.epoch_duration)
+            .unwrap_or(u32::MAX - 1)
+            .saturating_add(1)
+    }
+
+    fn pool_share(&self, weight: u64) -> u64 {
+        if self.total_weight == 0 {
+            return 0;
+        }
+        // weight <= total_weight, so the share fits in u64
+        (u128::from(self.emission_per_second) * u128::from(weight) / u128::from(self.total_weight)) as u64
+    }
+}
+
+/// A pool's weight in the `GaugeController` (PDA `["pool_gauge", pool]`).
+#[account]
+pub struct PoolGauge {
+    pub pool: Pubkey,
+    pub weight: u64,
+    pub last_epoch: u32, // last gauge epoch paid; starts at the epoch before the pool joined
+}
+
+/// Cumulative per-pool fee accounting, in token A / token B units.
+/// `lp_fees_*` is the portion of swap fees left in reserves, i.e. what LPs earned. It is the
+/// pool's reinvested-liquidity counter: reserve growth beyond it (and beyond deposits, withdrawals
+/// and trades) comes from donations or rounding.
+#[account]
+pub struct PoolStats {
+    pub pool: Pubkey,
+    pub swap_count: u64,
+    pub volume_a_in: u64,
+    pub volume_b_in: u64,
+    pub lp_fees_a: u64,
+    pub lp_fees_b: u64,
+    pub treasury_fees_a: u64,
+    pub treasury_fees_b: u64,
+    pub reward_fees_a: u64,
+    pub reward_fees_b: u64,
+    pub daily: [StatsBucket; STATS_DAY_BUCKETS], // bucket `day_index % STATS_DAY_BUCKETS` holds that UTC day
+}
+
+/// One day of swap activity, in token A / token B units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
+pub struct StatsBucket {
+    pub day_index: u64,
+    pub volume_a_in: u64,
+    pub volume_b_in: u64,
+    pub lp_fees_a: u64,
+    pub lp_fees_b: u64,
+    pub reward_fees_a: u64,
+    pub reward_fees_b: u64,
+}
+
+impl StatsBucket {
+    fn accumulate(&mut self, other: &StatsBucket) {
+        self.volume_a_in = self.volume_a_in.saturating_add(other.volume_a_in);
+        self.volume_b_in = self.volume_b_in.saturating_add(other.volume_b_in);
+        self.lp_fees_a = self.lp_fees_a.saturating_add(other.lp_fees_a);
+        self.lp_fees_b = self.lp_fees_b.saturating_add(other.lp_fees_b);
+        self.reward_fees_a = self.reward_fees_a.saturating_add(other.reward_fees_a);
+        self.reward_fees_b = self.reward_fees_b.saturating_add(other.reward_fees_b);
+    }
+}
+
+impl PoolStats {
+    /// Totals over the last `days` UTC days including today (1 = 24h bucket, 7 = weekly),
+    /// for APR estimates straight from account state.
+    pub fn window_totals(&self, now: i64, days: u64) -> StatsBucket {
+        let today = (now / SNAPSHOT_EPOCH_SECONDS) as u64;
+        let days = days.min(STATS_DAY_BUCKETS as u64);
+        let mut totals = StatsBucket {
+            day_index: today,
+            ..StatsBucket::default()
+        };
+        for bucket in self.daily.iter() {
+            if bucket.day_index <= today && today - bucket.day_index < days && bucket.day_index != 0 {
+                totals.accumulate(bucket);
+            }
+        }
+        totals
+    }
+
+    fn record_swap(&mut self, quote: &SwapQuote, is_a_to_b: bool, fee_in_a: bool, now: i64) {
+        let day_index = (now / SNAPSHOT_EPOCH_SECONDS) as u64;
+        let bucket = &mut self.daily[(day_index % STATS_DAY_BUCKETS as u64) as usize];
+        if bucket.day_index != day_index {
+            *bucket = StatsBucket {
+                day_index,
+                ..StatsBucket::default()
+            };
+        }
+        let today = StatsBucket {
+            day_index,
+            volume_a_in: if is_a_to_b { quote.amount_in } else { 0 },
+            volume_b_in: if is_a_to_b { 0 } else { quote.amount_in },
+            lp_fees_a: if fee_in_a { quote.lp_fee } else { 0 },
+            lp_fees_b: if fee_in_a { 0 } else { quote.lp_fee },
+            reward_fees_a: if fee_in_a { quote.reward_fee } else { 0 },
+            reward_fees_b: if fee_in_a { 0 } else { quote.reward_fee },
+        };
+        bucket.accumulate(&today);
+
+        self.swap_count = self.swap_count.saturating_add(1);
+        let volume = if is_a_to_b { &mut self.volume_a_in } else { &mut self.volume_b_in };
+        *volume = volume.saturating_add(quote.amount_in);
+        let (lp_fees, treasury_fees, reward_fees) = if fee_in_a {
+            (&mut self.lp_fees_a, &mut self.treasury_fees_a, &mut self.reward_fees_a)
+        } else {
+            (&mut self.lp_fees_b, &mut self.treasury_fees_b, &mut self.reward_fees_b)
+        };
+        *lp_fees = lp_fees.saturating_add(quote.lp_fee);
+        *treasury_fees = treasury_fees.saturating_add(quote.treasury_fee);
+        *reward_fees = reward_fees.saturating_add(quote.reward_fee);
+    }
+}
+
+/// Program-wide activity totals for dashboards, one PDA for the whole program. Instructions
+/// that take it as an optional account bump it incrementally, so it covers what passed through
+/// them since `init_global_stats`. Treasury fees are in different tokens per pool, so only the
+/// swaps that routed one are counted; per-token amounts stay in each pool's `PoolStats`.
+#[account]
+pub struct GlobalStats {
+    pub pools: u64,
+    pub deposits: u64,               // deposit_and_vest / swap_and_vest calls
+    pub withdrawals: u64,            // withdraw_unlocked calls
+    pub swaps: u64,
+    pub open_positions: u64,         // positions opened minus positions claimed or fully exited
+    pub treasury_fee_swaps: u64,     // swaps that routed a treasury fee
+    pub created_at: i64,
+    pub updated_ts: i64,
+}
+
+impl GlobalStats {
+    fn record_deposit(&mut self, now: i64) {
+        self.deposits = self.deposits.saturating_add(1);
+        self.open_positions = self.open_positions.saturating_add(1);
+        self.updated_ts = now;
+    }
+
+    fn record_position_closed(&mut self, now: i64) {
+        self.open_positions = self.open_positions.saturating_sub(1);
+        self.updated_ts = now;
+    }
+
+    fn record_swap(&mut self, treasury_fee: u64, now: i64) {
+        self.swaps = self.swaps.saturating_add(1);
+        if treasury_fee > 0 {
+            self.treasury_fee_swaps = self.treasury_fee_swaps.saturating_add(1);
+        }
+        self.updated_ts = now;
+    }
+}
+
+/// Compact read-only summary of a pool, so light clients and other programs can read one small
+/// account instead of pool + both reserves + LP mint. Rewritten by the instructions that move
+/// reserves or locked LP when it is passed, and by the `refresh_pool_view` crank.
+#[account]
+pub struct PoolView {
+    pub pool: Pubkey,
+    pub curve_type: u8,
+    pub amp: u64, // current amplification on the stable curve, 0 for constant product
+    pub reserve_a_amount: u64,
+    pub reserve_b_amount: u64,
+    pub lp_supply: u64,
+    pub protocol_fee_bps: u16,
+    pub lp_fee_bps: u16,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+    pub spot_price_a: u128, // PRICE_SCALE, before fees; 0 while a reserve is empty
+    pub total_locked_lp: u64,
+    pub acc_reward_per_lp: u128,
+    pub paused: bool,
+    pub updated_ts: i64,
+}
+
+impl PoolView {
+    /// Copy the pool-side fields only; for instructions that don't touch the reserves.
+    fn sync_pool(&mut self, pool: &Pool, now: i64) {
+        self.curve_type = pool.curve_type;
+        self.amp = if pool.curve_type == CURVE_STABLE { pool.current_amp(now) } else { 0 };
+        self.protocol_fee_bps = pool.protocol_fee_bps;
+        self.lp_fee_bps = pool.lp_fee_bps;
+        self.treasury_fee_bps = pool.treasury_fee_bps;
+        self.reward_fee_bps = pool.reward_fee_bps;
+        self.total_locked_lp = pool.total_locked_lp;
+        self.acc_reward_per_lp = pool.acc_reward_per_lp;
+        self.paused = pool.is_paused(now);
+        self.updated_ts = now;
+    }
+
+    fn refresh(&mut self, pool: &Pool, reserve_a: u64, reserve_b: u64, lp_supply: u64, now: i64) -> Result<()> {
+        self.sync_pool(pool, now);
+        self.reserve_a_amount = reserve_a;
+        self.reserve_b_amount = reserve_b;
+        self.lp_supply = lp_supply;
+        self.spot_price_a = if reserve_a > 0 && reserve_b > 0 {
+            spot_price_a(pool, reserve_a, reserve_b, now)?
+        } else {
+            0
+        };
+        Ok(())
+    }
+}
+
+/// Global sanctions blocklist record. Existence of the PDA means the address is blocked.
+#[account]
+pub struct BlocklistEntry {
+    pub address: Pubkey,
+}
+
+/// Admin approval of a mint as a pool token. Existence of the PDA means the mint is approved.
+#[account]
+pub struct TokenBadge {
+    pub mint: Pubkey,
+}
+
+/// Market maker allowed to swap during the pool's bootstrap phase.
+#[account]
+pub struct MarketMakerEntry {
+    pub pool: Pubkey,
+    pub trader: Pubkey,
+    pub expires_at: i64,
+}
+
+/// Per-user allow record for permissioned pools. Existence of the PDA is the permission.
+#[account]
+pub struct AllowlistEntry {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+}
+
+/// Where a position's owner wants maturity notices sent, picked up by bots from `VestingMatured`.
+/// Kept beside the position since `VestingStake` has no room for it.
+#[account]
+pub struct NotifyRegistration {
+    pub vesting_stake: Pubkey,
+    pub notify_address: Pubkey,
+}
+
+/// Receipt of a redeemed claim voucher; its PDA is derived from the owner and the voucher nonce,
+/// so a voucher cannot be relayed twice even onto a position re-created at the same address.
+#[account]
+pub struct UsedVoucher {
+    pub user: Pubkey,
+    pub nonce: u64,
+}
+
+/// Token account receiving a user's LP rewards from one pool instead of their LP account.
+#[account]
+pub struct RewardDestination {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub destination: Pubkey,
+}
+
+/// A user's request for a penalty-free early unlock of one position; its PDA is derived from the position.
+#[account]
+pub struct HardshipRequest {
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub reason_code: u16,
+    pub approved: bool,
+    pub requested_at: i64,
+}
+
+/// Per-user index of vesting positions across all pools, maintained on deposit and claim.
+#[account]
+pub struct Portfolio {
+    pub owner: Pubkey,
+    pub positions: Vec<Pubkey>, // VestingStake addresses, at most MAX_PORTFOLIO_POSITIONS
+}
+
+impl Portfolio {
+    fn add_position(&mut self, vesting_stake: Pubkey) -> Result<()> {
+        require_lt_ctx!(self.positions.len(), MAX_PORTFOLIO_POSITIONS, AmmError::PortfolioFull);
+        self.positions.push(vesting_stake);
+        Ok(())
+    }
+
+    fn remove_position(&mut self, vesting_stake: &Pubkey) {
+        self.positions.retain(|p| p != vesting_stake);
+    }
+}
+
+/// A pre-announced parameter change that anyone can execute once `activation_ts` has passed.
+#[account]
+pub struct ScheduledAction {
+    pub pool: Pubkey,
+    pub creator: Pubkey, // receives the rent back on execution or cancellation
+    pub id: u64,
+    pub activation_ts: i64,
+    pub action: ScheduledActionKind,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduledActionKind {
+    FeeChange {
+        protocol_fee_bps: u16,
+        treasury_fee_bps: u16,
+        reward_fee_bps: u16,
+    },
+    EmissionChange {
+        reward_emission_per_second: u64,
+    },
+    Unpause,
+}
+
+/// Escrow record for a vesting position offered for sale. Its PDA is derived from the position.
+#[account]
+pub struct VestingListing {
+    pub vesting_stake: Pubkey,
+    pub seller: Pubkey,
+    pub quote_mint: Pubkey,
+    pub price: u64,
+    pub amount: u64, // position size at listing time; a sale fails if it changed
+}
+
+/// Per-user, per-pool counter for vesting PDA seeds, so deposits don't all write one pool-wide nonce.
+#[account]
+pub struct UserVestingIndex {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub next_nonce: u64, // lowest deposit_id the user's next position may take
+}
+
+/// Programs allowed to CPI into `swap` while `pool.caller_restricted` is set, e.g. a router that
+/// enforces KYC or MEV protection.
+#[account]
+pub struct AllowedCallers {
+    pub pool: Pubkey,
+    pub allow_direct: bool, // top-level swap instructions (no CPI) are accepted too
+    pub count: u8,
+    pub programs: [Pubkey; MAX_ALLOWED_CALLERS],
+}
+
+/// Per-user, per-pool swap volume, created by the trader and updated by every swap that passes it.
+/// A large swap split into `num_slices` slices run by `execute_twap_slice`. The unsold input sits in
+/// the `["twap_escrow", twap_order]` token account; output goes straight to `destination`.
+#[account]
+pub struct TwapOrder {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub destination: Pubkey, // owner's token account for the output token
+    pub order_id: u64,
+    pub is_a_to_b: bool,
+    pub amount_in_total: u64,
+    pub amount_in_remaining: u64,
+    pub min_out_total: u64,
+    pub amount_out_filled: u64,
+    pub num_slices: u16,
+    pub slices_done: u16,
+    pub last_slot: u64, // slot of the latest slice
+    pub bump: u8,       // signs for the escrow
+}
+
+/// A resting order selling `amount_in` (held in the `["limit_escrow", limit_order]` token account)
+/// at no less than `min_price`, filled in full by `fill_limit_order`.
+#[account]
+pub struct LimitOrder {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub destination: Pubkey, // owner's token account for the output token
+    pub order_id: u64,
+    pub is_a_to_b: bool,
+    pub amount_in: u64,
+    pub min_price: u128, // output per input after fees, scaled by PRICE_SCALE
+    pub expiry: i64,
+    pub tip_lamports: u64, // held on this PDA on top of rent, paid to the filler
+    pub bump: u8,          // signs for the escrow
+}
+
+/// Recurring buy: `amount_per_interval` of the input (held in the `["dca_escrow", dca_order]` token
+/// account) is sold every `interval` seconds until `total` is spent.
+#[account]
+pub struct DcaOrder {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub destination: Pubkey, // owner's token account for the output token
+    pub order_id: u64,
+    pub is_a_to_b: bool,
+    pub amount_per_interval: u64,
+    pub interval: i64,
+    pub total: u64,
+    pub spent: u64,
+    pub received: u64,
+    pub min_price: u128, // per-fill floor, output per input scaled by PRICE_SCALE; 0 = none
+    pub next_fill_ts: i64,
+    pub fills: u32,
+    pub bump: u8, // signs for the escrow
+}
+
+/// Protocol-owned-liquidity bond market of a pool. `payout_mint` tokens sit in the
+/// `["bond_vault", bond_market]` account; `capacity` is the part not yet promised to bonds.
+#[account]
+pub struct BondMarket {
+    pub pool: Pubkey,
+    pub payout_mint: Pubkey,
+    pub payout_per_lp: u128, // payout tokens per LP before the discount, scaled by PRICE_SCALE
+    pub discount_bps: u16,   // bonus paid on top of payout_per_lp
+    pub vesting_seconds: i64,
+    pub capacity: u64,
+    pub total_lp_acquired: u64,
+    pub total_payout: u64,
+    pub active: bool,
+    pub bump: u8, // signs for the payout vault
+}
+
+/// One seller's bond: `payout` vests linearly from `vesting_start` to `vesting_end`.
+#[account]
+pub struct Bond {
+    pub bond_market: Pubkey,
+    pub owner: Pubkey,
+    pub bond_id: u64,
+    pub payout: u64,
+    pub redeemed: u64,
+    pub vesting_start: i64,
+    pub vesting_end: i64,
+}
+
+impl Bond {
+    /// Payout vested by `now`.
+    pub fn vested(&self, now: i64) -> u64 {
+        linear_vested(self.payout, self.vesting_start, self.vesting_end, now)
+    }
+}
+
+/// Outbound vesting from a pool-held treasury account (grants, contributor pay): `total` sits in
+/// the `["stream_escrow", treasury_stream]` account and vests linearly from `start_ts` to `end_ts`,
+/// with nothing claimable before `cliff_ts`.
+#[account]
+pub struct TreasuryStream {
+    pub pool: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub source: Pubkey, // treasury account the stream was funded from; unvested funds return here on cancel
+    pub stream_id: u64,
+    pub total: u64,
+    pub claimed: u64,
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+    pub bump: u8, // signs for the escrow
+}
+
+impl TreasuryStream {
+    /// Amount vested by `now`, honouring the cliff.
+    pub fn vested(&self, now: i64) -> u64 {
+        if now < self.cliff_ts {
+            return 0;
+        }
+        linear_vested(self.total, self.start_ts, self.end_ts, now)
+    }
+}
+
+/// `points` values each trade at the LP it was worth (`amount_in` against half the LP supply's
+/// input reserve), so volume in either token earns comparable points.
+#[account]
+pub struct TraderPoints {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub volume_a: u128, // token A moved through swaps, in or out
+    pub volume_b: u128,
+    pub points: u64,
+    pub redeemed_points: u64,
+}
+
+/// Payout record written by `claim_vested`, kept after the `VestingStake` is closed so the user and
+/// off-chain systems can verify it. One per position, so a claim can never be recorded twice.
+#[account]
+pub struct ClaimReceipt {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub amount: u64,         // LP returned from the vesting vault
+    pub reward_paid: u64,    // LP rewards paid, including the relock bonus
+    pub reward_paid_a: u64,  // underlying token A rewards paid
+    pub reward_paid_b: u64,  // underlying token B rewards paid
+    pub claimed_at: i64,
+    pub closable_at: i64,    // close_claim_receipt refunds the rent to `user` from here on
+}
+
+/// Gradual emergency withdrawal: each crank moves the share of what is left in the reserves
+/// proportional to the slots elapsed since the last crank, so the reserves are empty at `end_slot`
+/// and LPs can watch (and exit) along the way.
+#[account]
+pub struct EmergencyDrip {
+    pub pool: Pubkey,
+    pub start_slot: u64,
+    pub last_slot: u64,
+    pub end_slot: u64,
+    pub released_a: u64, // token A moved to treasury so far
+    pub released_b: u64,
+}
+
+/// External program registered on a pool, called by deposit, claim and early-unvest.
+#[account]
+pub struct PoolHook {
+    pub pool: Pubkey,
+    pub program_id: Pubkey,
+    pub accounts_hash: [u8; 32], // hook_accounts_hash of the accounts passed after the hook program
+}
+
+/// Five keys that can act for the pool in an emergency, three at a time. Membership changes only
+/// through the council's own `RotateMember` proposals.
+#[account]
+pub struct EmergencyCouncil {
+    pub pool: Pubkey,
+    pub members: [Pubkey; COUNCIL_SIZE],
+    pub proposal_count: u64, // id of the next proposal
+    pub generation: u32,     // bumped on every membership change; older proposals go stale
+}
+
+impl EmergencyCouncil {
+    pub fn member_index(&self, key: &Pubkey) -> Result<usize> {
+        self.members
+            .iter()
+            .position(|m| m == key)
+            .ok_or_else(|| error!(AmmError::NotCouncilMember))
+    }
+
+    /// Replace `old_member` and stale every open proposal, whose approvals no longer line up.
+    pub fn replace_member(&mut self, old_member: Pubkey, new_member: Pubkey) -> Result<()> {
+        replace_council_member(&mut self.members, old_member, new_member)?;
+        self.generation = self.generation.wrapping_add(1);
+        Ok(())
+    }
+}
+
+/// One emergency council proposal; `approvals` is a bitmask over the council's `members`.
+#[account]
+pub struct CouncilProposal {
+    pub council: Pubkey,
+    pub proposal_id: u64,
+    pub generation: u32, // council generation the approvals were counted against
+    pub action: CouncilAction,
+    pub approvals: u8,
+    pub proposed_at: i64,
+    pub executed: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CouncilAction {
+    Pause,
+    FlagCompromised,
+    SetAuthority { new_authority: Pubkey },
+    RotateMember { old_member: Pubkey, new_member: Pubkey },
+}
+
+/// Protocol-wide keys that approve insurance fund payouts, `COUNCIL_THRESHOLD` at a time.
+#[account]
+pub struct InsuranceCouncil {
+    pub members: [Pubkey; COUNCIL_SIZE],
+}
+
+impl InsuranceCouncil {
+    pub fn member_index(&self, key: &Pubkey) -> Result<usize> {
+        self.members
+            .iter()
+            .position(|m| m == key)
+            .ok_or_else(|| error!(AmmError::NotCouncilMember))
+    }
+}
+
+/// One incident's insurance payout; `approvals` is a bitmask over the insurance council's members.
+#[account]
+pub struct InsurancePayout {
+    pub incident_id: [u8; 32],
+    pub insurance_fund: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub approvals: u8,
+    pub paid: bool,
+}
+
+/// What a user is owed after their abandoned position was swept into the recovery vault.
+#[account]
+pub struct RecoveryClaim {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub amount: u64,
+    pub pending_reward: u64,
+    pub pending_reward_a: u64,
+    pub pending_reward_b: u64,
+}
+
+/// Stable pool over 3..=`MAX_MULTI_RESERVES` tokens. Slots past `n_reserves` stay default.
+/// Positions are regular `VestingStake` accounts whose `pool` is the multi-pool.
+#[account]
+pub struct MultiPool {
+    pub authority: Pubkey,
+    pub lp_mint: Pubkey,
+    pub n_reserves: u8,
+    pub mints: [Pubkey; MAX_MULTI_RESERVES],
+    pub reserves: [Pubkey; MAX_MULTI_RESERVES],
+    pub amp: u32,
+    pub fee_bps: u16, // charged on swap output and on the imbalanced part of deposits; stays with LPs
+    pub paused: bool,
+    pub vesting_nonce: u64, // unused; positions are keyed by user and deposit_id
+    pub total_locked_lp: u64,
+    pub decimals: u8, // shared decimals of the pooled mints
+    pub bump: u8,     // signs as reserve owner and LP mint authority
+}
+
+// ---------------------- Events ----------------------
+
+#[event]
+pub struct PoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub treasury: Pubkey,
+    pub treasury_a: Pubkey,
+    pub treasury_b: Pubkey,
+}
+#[event]
+pub struct Deposited {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub vesting_end: i64,
+    pub tag: [u8; 32],
+    pub deposit_id: u64,
+}
+#[event]
+pub struct Claimed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount: u64,
+    pub tag: [u8; 32],
+}
+#[event]
+pub struct EarlyUnvested {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_unvested: u64,
+    pub penalty: u64,
+    pub reward_paid: u64,
+    pub reward_penalty: u64,
+}
+#[event]
+pub struct Withdrawn {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub lp_amount: u64,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+#[event]
+pub struct TwapOrderCreated {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in_total: u64,
+    pub num_slices: u16,
+    pub min_out_total: u64,
+    pub is_a_to_b: bool,
+}
+#[event]
+pub struct TwapSliceExecuted {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub slice: u16, // 1-based
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub amount_in_remaining: u64,
+    pub amount_out_filled: u64,
+}
+#[event]
+pub struct TwapOrderCancelled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in_refunded: u64,
+    pub amount_out_filled: u64,
+}
+#[event]
+pub struct LimitOrderPlaced {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in: u64,
+    pub min_price: u128,
+    pub expiry: i64,
+    pub is_a_to_b: bool,
+    pub tip_lamports: u64,
+}
+#[event]
+pub struct LimitOrderFilled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub cranker: Pubkey,
+    pub tip_lamports: u64,
+}
+#[event]
+pub struct LimitOrderCancelled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in_refunded: u64,
+}
+#[event]
+pub struct DcaOrderCreated {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_per_interval: u64,
+    pub interval: i64,
+    pub total: u64,
+    pub is_a_to_b: bool,
+}
+#[event]
+pub struct DcaFilled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub fill: u32, // 1-based
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub spent: u64,
+    pub received: u64,
+}
+#[event]
+pub struct DcaOrderCancelled {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub order_id: u64,
+    pub amount_in_refunded: u64,
+    pub received: u64,
+}
+#[event]
+pub struct TreasuryStreamCreated {
+    pub pool: Pubkey,
+    pub stream: Pubkey,
+    pub recipient: Pubkey,
+    pub mint: Pubkey,
+    pub total: u64,
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+}
+#[event]
+pub struct StreamClaimed {
+    pub stream: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+#[event]
+pub struct TreasuryStreamCancelled {
+    pub stream: Pubkey,
+    pub recipient: Pubkey,
+    pub paid_to_recipient: u64,
+    pub returned: u64, // unvested amount sent back to the source treasury account
+}
+#[event]
+pub struct BondMarketCreated {
+    pub pool: Pubkey,
+    pub bond_market: Pubkey,
+    pub payout_mint: Pubkey,
+}
+#[event]
+pub struct BondTermsSet {
+    pub bond_market: Pubkey,
+    pub payout_per_lp: u128,
+    pub discount_bps: u16,
+    pub vesting_seconds: i64,
+    pub active: bool,
+}
+#[event]
+pub struct BondMarketFunded {
+    pub bond_market: Pubkey,
+    pub funder: Pubkey,
+    pub amount: u64,
+    pub capacity: u64,
+}
+#[event]
+pub struct Bonded {
+    pub bond_market: Pubkey,
+    pub owner: Pubkey,
+    pub bond_id: u64,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub lp_amount: u64,
+    pub lp_acquired: u64, // LP added to the pool treasury, minted plus transferred
+    pub payout: u64,
+    pub vesting_end: i64,
+}
+#[event]
+pub struct BondRedeemed {
+    pub bond_market: Pubkey,
+    pub owner: Pubkey,
+    pub bond_id: u64,
+    pub amount: u64,
+    pub remaining: u64,
+}
+#[event]
+pub struct Swapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub is_a_to_b: bool,
+}
+/// Per-swap fee routing, in units of the input token. `acc_reward_per_lp_after` lets reward
+/// auditors follow the accumulator exactly without recomputing the math.
+#[event]
+pub struct FeesAccrued {
+    pub pool: Pubkey,
+    pub is_a_to_b: bool,
+    pub lp_fee: u64,
+    pub treasury_fee: u64,
+    pub reward_fee: u64,
+    pub acc_reward_per_lp_after: u128,
+    pub acc_reward_a_per_lp_after: u128,
+    pub acc_reward_b_per_lp_after: u128,
+}
+/// The LP fee of one swap, left in reserves as liquidity owned pro rata by every LP (vested or
+/// not). Running totals and the post-swap reserves and supply let indexers separate fee-driven
+/// share-value growth from donations and rounding.
+#[event]
+pub struct LiquidityReinvested {
+    pub pool: Pubkey,
+    pub fee_in_a: bool,
+    pub amount: u64,
+    pub total_reinvested_a: u64,
+    pub total_reinvested_b: u64,
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    pub lp_supply: u64,
+}
+#[event]
+pub struct Paused {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct Unpaused {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct PauseExpirySet {
+    pub pool: Pubkey,
+    pub pause_expires_at: i64,
+}
+#[event]
+pub struct EmergencyWithdrawn {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct EmergencyDripStarted {
+    pub pool: Pubkey,
+    pub start_slot: u64,
+    pub end_slot: u64,
+}
+#[event]
+pub struct EmergencyDripped {
+    pub pool: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub released_a: u64,
+    pub released_b: u64,
+    pub slot: u64,
+}
+#[event]
+pub struct EmergencyDripCancelled {
+    pub pool: Pubkey,
+    pub released_a: u64,
+    pub released_b: u64,
+}
+#[event]
+pub struct AllowedCallersSet {
+    pub pool: Pubkey,
+    pub programs: Vec<Pubkey>,
+    pub allow_direct: bool,
+    pub restricted: bool,
+}
+#[event]
+pub struct ClaimBountyEscrowed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub lamports: u64,
+    pub claim_bounty: u64,
+}
+#[event]
+pub struct VoucherClaimRelayed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub relayer: Pubkey,
+    pub nonce: u64,
+}
+#[event]
+pub struct ClaimBountyPaid {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub cranker: Pubkey,
+    pub bounty: u64,
+}
+#[event]
+pub struct RewardDestinationSet {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub destination: Pubkey,
+}
+#[event]
+pub struct RewardsHarvested {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub destination: Pubkey,
+    pub amount: u64,
+}
+#[event]
+pub struct VestingMatured {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub vesting_end: i64,
+    pub notify_address: Option<Pubkey>,
+}
+#[event]
+pub struct PointsRateSet {
+    pub pool: Pubkey,
+    pub points_rate_bps: u16,
+}
+#[event]
+pub struct TreasuryLpRebalanced {
+    pub source_pool: Pubkey,
+    pub target_pool: Pubkey,
+    pub lp_burned: u64,
+    pub amount_a: u64, // deposited into the target
+    pub amount_b: u64,
+    pub leftover_a: u64, // sent to the target's token treasuries
+    pub leftover_b: u64,
+    pub lp_minted: u64,
+    pub deposit_id: u64,
+    pub vesting_end: i64,
+}
+#[event]
+pub struct TraderRebateSet {
+    pub pool: Pubkey,
+    pub trader_rebate_bps: u16,
+    pub trader_rebate_seconds: i64,
+}
+#[event]
+pub struct TraderRebateAccrued {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub fee_in_a: bool,
+    pub fee_amount: u64, // LP fee rebated, in the fee-side token
+    pub lp_amount: u64,  // LP minted into the rebate position
+    pub vesting_end: i64,
+}
+#[event]
+pub struct TraderPointsRedeemed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub points: u64,
+    pub reward: u64,
+}
+#[event]
+pub struct PoolHookRegistered {
+    pub pool: Pubkey,
+    pub program_id: Pubkey,
+    pub accounts_hash: [u8; 32],
+}
+#[event]
+pub struct PoolHookRemoved {
+    pub pool: Pubkey,
+    pub program_id: Pubkey,
+}
+#[event]
+pub struct PoolCompromisedSet {
+    pub pool: Pubkey,
+    pub compromised: bool,
+}
+#[event]
+pub struct EmergencyCouncilSet {
+    pub pool: Pubkey,
+    pub members: [Pubkey; COUNCIL_SIZE],
+}
+#[event]
+pub struct CouncilActionProposed {
+    pub pool: Pubkey,
+    pub proposal_id: u64,
+    pub proposer: Pubkey,
+    pub action: CouncilAction,
+}
+#[event]
+pub struct CouncilActionApproved {
+    pub pool: Pubkey,
+    pub proposal_id: u64,
+    pub member: Pubkey,
+    pub approvals: u32,
+}
+#[event]
+pub struct CouncilActionExecuted {
+    pub pool: Pubkey,
+    pub proposal_id: u64,
+    pub action: CouncilAction,
+}
+#[event]
+pub struct EmergencyExited {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub lp_amount: u64,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+#[event]
+pub struct EmergencyWithdrawPreview {
+    pub pool: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub treasury_a: Pubkey,
+    pub treasury_b: Pubkey,
+}
+#[event]
+pub struct FeesUpdatePreview {
+    pub pool: Pubkey,
+    pub current_protocol_fee_bps: u16,
+    pub current_treasury_fee_bps: u16,
+    pub current_reward_fee_bps: u16,
+    pub protocol_fee_bps: u16,
+    pub lp_fee_bps: u16,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+}
+#[event]
+pub struct TreasuryAccountsSet {
+    pub pool: Pubkey,
+    pub treasury: Pubkey,
+    pub treasury_a: Pubkey,
+    pub treasury_b: Pubkey,
+}
+#[event]
+pub struct AbandonedSwept {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub amount: u64,
+    pub pending_reward: u64,
+    pub sweeper: Pubkey,
+}
+#[event]
+pub struct VestingRescued {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub amount: u64,
+    pub was_claimed: bool, // position was already marked claimed, so no pool accounting changed
+    pub authority: Pubkey,
+}
+#[event]
+pub struct RecoveredRedeemed {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub amount: u64,
+}
+#[event]
+pub struct AbandonmentPeriodSet {
+    pub pool: Pubkey,
+    pub abandonment_period: i64,
+}
+#[event]
+pub struct RewardEmissionSet {
+    pub pool: Pubkey,
+    pub reward_emission_per_second: u64,
+}
+#[event]
+pub struct EpochRewardsEnabled {
+    pub pool: Pubkey,
+    pub start_epoch: u64,
+}
+#[event]
+pub struct RewardEpochFinalized {
+    pub pool: Pubkey,
+    pub epoch_index: u64,
+    pub emitted: u64,
+    pub total_locked_lp: u64,
+    pub reward_per_lp: u128,
+    pub acc_reward_per_lp: u128,
+}
+#[event]
+pub struct RewardAccumulatorRebased {
+    pub pool: Pubkey,
+    pub base: u128, // subtracted from acc_reward_per_lp
+    pub base_a: u128,
+    pub base_b: u128,
+    pub acc_reward_per_lp: u128,
+    pub positions: u32,
+}
+#[event]
+pub struct EpochSnapshotted {
+    pub pool: Pubkey,
+    pub epoch_index: u64,
+    pub total_locked_lp: u64,
+    pub acc_reward_per_lp: u128,
+    pub timestamp: i64,
+}
+#[event]
+pub struct GovernanceRealmSet {
+    pub pool: Pubkey,
+    pub realm: Pubkey,
+    pub governing_token_mint: Pubkey,
+}
+#[event]
+pub struct PoolAuthoritySet {
+    pub pool: Pubkey,
+    pub old_authority: Pubkey,
+    pub new_authority: Pubkey,
+}
+#[event]
+pub struct FeeVoteOpened {
+    pub pool: Pubkey,
+    pub round: u32,
+    pub options: Vec<u16>,
+    pub voting_end: i64,
+}
+#[event]
+pub struct FeeVoteCast {
+    pub pool: Pubkey,
+    pub round: u32,
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub option: u8,
+    pub weight: u64,
+}
+#[event]
+pub struct FeeVoteFinalized {
+    pub pool: Pubkey,
+    pub round: u32,
+    pub winning_fee_bps: Option<u16>, // None when no weight was cast
+    pub weight: u64,
+}
+#[event]
+pub struct PenaltyWaiverVoteOpened {
+    pub pool: Pubkey,
+    pub reason_code: u16,
+    pub waiver_seconds: i64,
+    pub voting_end: i64,
+    pub total_locked_lp: u64,
+}
+#[event]
+pub struct PenaltyWaiverVoteCast {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub approve: bool,
+    pub weight: u64,
+}
+#[event]
+pub struct PenaltyWaiverVoteFinalized {
+    pub pool: Pubkey,
+    pub passed: bool,
+    pub approve_weight: u64,
+    pub reject_weight: u64,
+    pub waiver_until: i64,
+}
+#[event]
+pub struct VoterWeightUpdated {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub voter_weight: u64,
+    pub positions: u32,
+}
+#[event]
+pub struct RewardVaultReconciled {
+    pub pool: Pubkey,
+    pub vault_balance: u64,
+    pub liability: u64,
+    pub swept: u64,
+}
+#[event]
+pub struct MinDepositSet {
+    pub pool: Pubkey,
+    pub min_deposit_a: u64,
+    pub min_deposit_b: u64,
+    pub min_lp_minted: u64,
+}
+#[event]
+pub struct ClaimsWhilePausedSet {
+    pub pool: Pubkey,
+    pub allow: bool,
+}
+#[event]
+pub struct PenalizeRewardsSet {
+    pub pool: Pubkey,
+    pub penalize_rewards: bool,
+}
+#[event]
+pub struct ProtocolConfigUpdated {
+    pub admin: Pubkey,
+    pub max_protocol_fee_bps: u16,
+    pub max_penalty_bps: u16,
+    pub max_reward_emission_per_second: u64,
+}
+#[event]
+pub struct CosignThresholdSet {
+    pub cosign_lp_threshold: u64,
+}
+#[event]
+pub struct GaugeControllerUpdated {
+    pub admin: Pubkey,
+    pub emission_per_second: u64,
+    pub epoch_duration: i64,
+}
+#[event]
+pub struct PoolGaugeWeightSet {
+    pub pool: Pubkey,
+    pub weight: u64,
+    pub total_weight: u64,
+}
+#[event]
+pub struct GaugeSynced {
+    pub pool: Pubkey,
+    pub epoch: u32,
+    pub weight: u64,
+    pub total_weight: u64,
+    pub amount: u64, // reward_mint moved into the pool's reward vault
+}
+#[event]
+pub struct InsuranceBpsSet {
+    pub insurance_bps: u16,
+}
+#[event]
+pub struct TreasuryRoutedToInsurance {
+    pub pool: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+#[event]
+pub struct PenaltyRoutedToInsurance {
+    pub pool: Pubkey,
+    pub amount: u64,
+}
+#[event]
+pub struct InsuranceCouncilSet {
+    pub members: [Pubkey; COUNCIL_SIZE],
+}
+#[event]
+pub struct InsurancePayoutProposed {
+    pub incident_id: [u8; 32],
+    pub mint: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub proposer: Pubkey,
+}
+#[event]
+pub struct InsurancePayoutApproved {
+    pub incident_id: [u8; 32],
+    pub member: Pubkey,
+    pub approvals: u32,
+}
+#[event]
+pub struct InsurancePaid {
+    pub mint: Pubkey,
+    pub recipient: Pubkey,
+    pub amount: u64,
+    pub incident_id: [u8; 32],
+}
+#[event]
+pub struct FeesUpdated {
+    pub pool: Pubkey,
+    pub protocol_fee_bps: u16,
+    pub lp_fee_bps: u16,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+}
+#[event]
+pub struct KeeperSet {
+    pub pool: Pubkey,
+    pub keeper: Pubkey,
+}
+#[event]
+pub struct BlocklistUpdated {
+    pub address: Pubkey,
+    pub blocked: bool,
+}
+#[event]
+pub struct TokenBadgeUpdated {
+    pub mint: Pubkey,
+    pub approved: bool,
+}
+#[event]
+pub struct ComplianceModeSet {
+    pub pool: Pubkey,
+    pub compliance_mode: bool,
+}
+#[event]
+pub struct BlockedAddressRejected {
+    pub pool: Pubkey,
+    pub address: Pubkey,
+}
+#[event]
+pub struct ReserveUtilizationCapSet {
+    pub pool: Pubkey,
+    pub max_reserve_out_bps: u16,
+    pub until: i64,
+}
+#[event]
+pub struct SwapAllowlistStarted {
+    pub pool: Pubkey,
+    pub until: i64,
+}
+#[event]
+pub struct SwapAllowlistEnded {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct MarketMakerUpdated {
+    pub pool: Pubkey,
+    pub trader: Pubkey,
+    pub expires_at: i64,
+}
+#[event]
+pub struct PermissionedSet {
+    pub pool: Pubkey,
+    pub permissioned: bool,
+}
+#[event]
+pub struct AllowlistUpdated {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub allowed: bool,
+}
+#[event]
+pub struct RewardModeSet {
+    pub pool: Pubkey,
+    pub reward_mode: u8,
+}
+#[event]
+pub struct UnderlyingRewardsPaid {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amount_a: u64,
+    pub amount_b: u64,
+}
+#[event]
+pub struct TreasuryAssetSet {
+    pub pool: Pubkey,
+    pub treasury_asset_mint: Pubkey,
+    pub max_conversion_impact_bps: u16,
+}
+#[event]
+pub struct TreasuryFeesConverted {
+    pub pool: Pubkey,
+    pub is_a_to_b: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub cranker: Pubkey,
+}
+#[event]
+pub struct HardshipRequested {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub reason_code: u16,
+}
+#[event]
+pub struct HardshipApproved {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub reason_code: u16,
+}
+#[event]
+pub struct VestingPledged {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub pledgee: Pubkey,
+}
+#[event]
+pub struct PledgeReleased {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub pledgee: Pubkey,
+}
+#[event]
+pub struct TreasuryRoutedToRewards {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub acc_reward_per_lp_after: u128,
+}
+#[event]
+pub struct TreasuryToRewardsSet {
+    pub pool: Pubkey,
+    pub treasury_to_rewards_bps: u16,
+}
+#[event]
+pub struct PenaltyRoutedToRewards {
+    pub pool: Pubkey,
+    pub amount: u64,
+    pub acc_reward_per_lp_after: u128,
+}
+#[event]
+pub struct PenaltyToRewardsSet {
+    pub pool: Pubkey,
+    pub penalty_to_rewards_bps: u16,
+}
+#[event]
+pub struct CurveTypeSet {
+    pub pool: Pubkey,
+    pub curve_type: u8,
+    pub amp: u32,
+}
+#[event]
+pub struct AmpRampStarted {
+    pub pool: Pubkey,
+    pub initial_amp: u32,
+    pub target_amp: u32,
+    pub ramp_start_ts: i64,
+    pub ramp_end_ts: i64,
+}
+#[event]
+pub struct AmpRampStopped {
+    pub pool: Pubkey,
+    pub amp: u32,
+}
+#[event]
+pub struct FeeSideSet {
+    pub pool: Pubkey,
+    pub fee_on_output: bool,
+}
+#[event]
+pub struct MultiPoolInitialized {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub n_reserves: u8,
+    pub amp: u32,
+    pub fee_bps: u16,
+}
+#[event]
+pub struct MultiDeposited {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub amounts: Vec<u64>,
+    pub lp_minted: u64,
+    pub vesting_end: i64,
+    pub deposit_id: u64,
+}
+#[event]
+pub struct MultiWithdrawn {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub lp_amount: u64,
+    pub amounts: Vec<u64>,
+}
+#[event]
+pub struct MultiSwapped {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub i: u8,
+    pub j: u8,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+}
+#[event]
+pub struct DepegGuardSet {
+    pub pool: Pubkey,
+    pub depeg_threshold_bps: u16,
+    pub depeg_blocks_swaps: bool,
+}
+#[event]
+pub struct DepegDetected {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct DepegCleared {
+    pub pool: Pubkey,
+}
+#[event]
+pub struct RebalanceFeeSet {
+    pub pool: Pubkey,
+    pub rebalance_fee_bps: u16,
+}
+#[event]
+pub struct EarlyUnvestPenaltySet {
+    pub pool: Pubkey,
+    pub penalty_bps: u16,
+}
+#[event]
+pub struct PenaltyGraceSet {
+    pub pool: Pubkey,
+    pub penalty_grace_seconds: i64,
+}
+#[event]
+pub struct FeeHolidaySet {
+    pub pool: Pubkey,
+    pub fee_holiday_bps: u16,
+    pub fee_holiday_start: i64,
+    pub fee_holiday_end: i64,
+}
+#[event]
+pub struct ClaimReceiptPeriodSet {
+    pub pool: Pubkey,
+    pub claim_receipt_period: i64,
+}
+#[event]
+pub struct SwapDirectionPauseSet {
+    pub pool: Pubkey,
+    pub a_to_b_paused: bool,
+    pub b_to_a_paused: bool,
+}
+#[event]
+pub struct OraclePriceSet {
+    pub pool: Pubkey,
+    pub oracle_price: u128,
+    pub oracle_tolerance_bps: u16,
+}
+#[event]
+pub struct Relocked {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub vesting_end: i64,
+    pub relock_count: u32,
+    pub reward_paid: u64,
+}
+#[event]
+pub struct AutoRelockSet {
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub vesting_seconds: Option<i64>,
+}
+#[event]
+pub struct RelockBonusSet {
+    pub pool: Pubkey,
+    pub relock_bonus_bps: u16,
+}
+#[event]
+pub struct OraclePoked {
+    pub pool: Pubkey,
+    pub poker: Pubkey,
+    pub timestamp: i64,
+    pub price_a_cumulative: u128,
+    pub price_b_cumulative: u128,
+    pub reward_paid: u64,
+}
+#[event]
+pub struct OraclePokeRewardSet {
+    pub pool: Pubkey,
+    pub oracle_poke_reward: u64,
+}
+#[event]
+pub struct MaturedPositionsClaimed {
+    pub user: Pubkey,
+    pub positions: u32,
+}
+#[event]
+pub struct ActionScheduled {
+    pub pool: Pubkey,
+    pub id: u64,
+    pub activation_ts: i64,
+    pub action: ScheduledActionKind,
+}
+#[event]
+pub struct ScheduledActionCancelled {
+    pub pool: Pubkey,
+    pub id: u64,
+}
+#[event]
+pub struct ScheduledActionExecuted {
+    pub pool: Pubkey,
+    pub id: u64,
+    pub executor: Pubkey,
+}
+#[event]
+pub struct VestingListed {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub seller: Pubkey,
+    pub quote_mint: Pubkey,
+    pub price: u64,
+}
+#[event]
+pub struct VestingListingCancelled {
+    pub vesting_stake: Pubkey,
+    pub seller: Pubkey,
+}
+#[event]
+pub struct VestingSold {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub quote_mint: Pubkey,
+    pub price: u64,
+    pub sale_fee: u64,
+}
+#[event]
+pub struct SaleFeeSet {
+    pub pool: Pubkey,
+    pub sale_fee_bps: u16,
+}
+
+// ---------------------- Types ----------------------
+
+/// Wallet-facing LP token metadata (e.g. "VLA-SOL/USDC LP") written through Metaplex at pool creation.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct LpTokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+}
+
+/// Result of pricing a swap against a reserve snapshot. Shared by `swap` and `quote` so both agree.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Default)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub total_fee: u64,
+    pub lp_fee: u64,
+    pub treasury_fee: u64,
+    pub reward_fee: u64,
+}
+
+/// Position lifecycle step reported to a pool hook.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionHookKind {
+    Deposit,
+    Claim,
+    EarlyUnvest,
+}
+
+/// Argument of the `on_position_event` instruction a pool hook receives. `lp_amount` is the LP
+/// locked (deposit), released (claim) or taken out early (early unvest).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PositionHookPayload {
+    pub kind: PositionHookKind,
+    pub pool: Pubkey,
+    pub user: Pubkey,
+    pub deposit_id: u64,
+    pub lp_amount: u64,
+    pub vesting_end: i64,
+    pub timestamp: i64,
+}
+
+/// Everything a router needs to price this pool, in one fixed layout.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AmmInfo {
+    pub pool: Pubkey,
+    pub curve_type: u8,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub reserve_a: Pubkey,
+    pub reserve_b: Pubkey,
+    pub reserve_a_amount: u64,
+    pub reserve_b_amount: u64,
+    pub lp_supply: u64,
+    pub protocol_fee_bps: u16,
+    pub lp_fee_bps: u16,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+    pub paused: bool,
+}
+
+/// One position's exposure for portfolio UIs. Token amounts are at current reserves; the exit
+/// fields project a full `early_unvest` now.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PositionValue {
+    pub pool: Pubkey,
+    pub vesting_stake: Pubkey,
+    pub user: Pubkey,
+    pub lp_amount: u64, // 0 once claimed
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub pending_reward: u64, // LP rewards, before relock bonus
+    pub pending_reward_a: u64,
+    pub pending_reward_b: u64,
+    pub vesting_end: i64,
+    pub seconds_to_maturity: i64, // 0 once matured
+    pub pledged: bool,
+    pub exit_penalty_bps: u16,
+    pub exit_penalty_lp: u64,
+    pub exit_penalty_a: u64,
+    pub exit_penalty_b: u64,
+}
+
+/// Fair (TWAP-based) LP valuation for lenders. Prices are per whole LP token (raw units),
+/// scaled by `PRICE_SCALE`; position values are in raw token units.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct FairLpPrice {
+    pub pool: Pubkey,
+    pub twap_price_a: u128, // TWAP of A in B, PRICE_SCALE
+    pub fair_reserve_a: u64, // sqrt(k / p)
+    pub fair_reserve_b: u64, // sqrt(k * p)
+    pub lp_supply: u64,
+    pub lp_price_in_a: u128,
+    pub lp_price_in_b: u128,
+    pub position_lp: u64, // 0 when no vesting_stake was passed
+    pub position_value_in_a: u128,
+    pub position_value_in_b: u128,
+}
+
+/// Pool configuration for CPI integrators, so they don't depend on the `Pool` account layout.
+/// Vault addresses are derived PDAs; they may not be initialized yet.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PoolConfig {
+    pub pool: Pubkey,
+    pub authority: Pubkey,
+    pub keeper: Pubkey,
+    // Fees
+    pub protocol_fee_bps: u16,
+    pub swap_fee_bps: u16, // fee charged right now (fee holiday aware)
+    pub lp_fee_bps: u16,
+    pub treasury_fee_bps: u16,
+    pub reward_fee_bps: u16,
+    pub rebalance_fee_bps: u16,
+    pub fee_on_output: bool,
+    pub sale_fee_bps: u16,
+    // Protocol-wide bounds
+    pub max_protocol_fee_bps: u16,
+    pub max_penalty_bps: u16,
+    pub max_reward_emission_per_second: u64,
+    // Curve
+    pub curve_type: u8,
+    pub amp: u64, // current amplification on the stable curve, 0 for constant product
+    // Flags
+    pub paused: bool,
+    pub a_to_b_paused: bool,
+    pub b_to_a_paused: bool,
+    pub depegged: bool,
+    pub allow_claims_while_paused: bool,
+    pub permissioned: bool,
+    pub compliance_mode: bool,
+    pub swap_allowlist_active: bool,
+    pub reward_mode: u8,
+    // Vaults
+    pub reserve_a: Pubkey,
+    pub reserve_b: Pubkey,
+    pub treasury: Pubkey,
+    pub treasury_a: Pubkey,
+    pub treasury_b: Pubkey,
+    pub recovery_vault: Pubkey,
+    pub underlying_reward_vault_a: Pubkey,
+    pub underlying_reward_vault_b: Pubkey,
+}
+
+// ---------------------- Contexts ----------------------
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct AddToBlocklist<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(init, payer = admin, space = 8 + 32, seeds = [SEED_BLOCKLIST, address.as_ref()], bump)]
+    pub blocklist_entry: Account<'info, BlocklistEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveFromBlocklist<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [SEED_BLOCKLIST, blocklist_entry.address.as_ref()],
+        bump
+    )]
+    pub blocklist_entry: Account<'info, BlocklistEntry>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApproveTokenBadge<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub mint: Account<'info, Mint>,
+    #[account(init, payer = admin, space = 8 + 32, seeds = [SEED_TOKEN_BADGE, mint.key().as_ref()], bump)]
+    pub token_badge: Account<'info, TokenBadge>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RevokeTokenBadge<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        close = admin,
+        seeds = [SEED_TOKEN_BADGE, token_badge.mint.as_ref()],
+        bump
+    )]
+    pub token_badge: Account<'info, TokenBadge>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeProtocolConfig<'info> {
+    #[account(init, payer = admin, space = 8 + 64, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    /// This program's upgradeable-loader program data; its upgrade authority must be `admin`
+    #[account(
+        seeds = [crate::ID.as_ref()],
+        bump,
+        seeds::program = bpf_loader_upgradeable::ID,
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ AmmError::Unauthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateProtocolConfig<'info> {
+    #[account(mut, has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub admin: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(init, payer = authority, space = 8 + 768, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 112 + 56 * STATS_DAY_BUCKETS,
+        seeds = [SEED_POOL_STATS, pool.key().as_ref()],
+        bump
+    )]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    /// Empty reserve token accounts owned by the pool PDA (created by the client beforehand)
+    #[account(
+        mut,
+        token::mint = token_a_mint,
+        token::authority = pool,
+        constraint = reserve_a.amount == 0 @ AmmError::ReserveNotEmpty
+    )]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = token_b_mint,
+        token::authority = pool,
+        constraint = reserve_b.amount == 0 @ AmmError::ReserveNotEmpty,
+        constraint = reserve_b.key() != reserve_a.key() @ AmmError::ReservesNotDistinct
+    )]
+    pub reserve_b: Account<'info, TokenAccount>,
+    /// Treasury LP token account for penalty/tax routing
+    #[account(mut, token::mint = lp_mint)]
+    pub treasury: Account<'info, TokenAccount>,
+    /// Treasury token accounts receiving the treasury share of swap fees
+    #[account(token::mint = token_a_mint)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    #[account(token::mint = token_b_mint)]
+    pub treasury_token_account_b: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// CHECK: Metaplex metadata PDA of the LP mint (`lp_metadata_address`); required only
+    /// when `lp_metadata` is passed
+    #[account(mut)]
+    pub lp_metadata_account: Option<UncheckedAccount<'info>>,
+    /// CHECK: Metaplex token-metadata program
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: Option<UncheckedAccount<'info>>,
+
+    /// Admin approval of each token mint; required when the mint has a freeze authority
+    #[account(seeds = [SEED_TOKEN_BADGE, token_a_mint.key().as_ref()], bump)]
+    pub token_a_badge: Option<Account<'info, TokenBadge>>,
+    #[account(seeds = [SEED_TOKEN_BADGE, token_b_mint.key().as_ref()], bump)]
+    pub token_b_badge: Option<Account<'info, TokenBadge>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> InitializePool<'info> {
+    /// `CreateMetadataAccountV3` CPI for the LP mint, signed by the pool PDA (its mint authority).
+    fn create_lp_metadata(&self, metadata: &LpTokenMetadata, pool_bump: u8) -> Result<()> {
+        require_lte_ctx!(metadata.name.len(), MAX_LP_NAME_LEN, AmmError::InvalidLpMetadata);
+        require_lte_ctx!(metadata.symbol.len(), MAX_LP_SYMBOL_LEN, AmmError::InvalidLpMetadata);
+        require_lte_ctx!(metadata.uri.len(), MAX_LP_URI_LEN, AmmError::InvalidLpMetadata);
+        let metadata_account = self.lp_metadata_account.as_ref().ok_or(AmmError::InvalidLpMetadata)?;
+        let metadata_program = self.token_metadata_program.as_ref().ok_or(AmmError::InvalidLpMetadata)?;
+        require!(
+            metadata_account.key() == lp_metadata_address(&self.lp_mint.key()).0,
+            AmmError::InvalidLpMetadata
+        );
+
+        // Borsh layout of CreateMetadataAccountV3: DataV2 without creators, collection or uses,
+        // mutable, no collection details
+        let mut data = vec![33u8];
+        metadata.serialize(&mut data)?;
+        0u16.serialize(&mut data)?; // seller_fee_basis_points
+        data.extend_from_slice(&[0, 0, 0]); // creators, collection, uses: None
+        data.push(1); // is_mutable
+        data.push(0); // collection_details: None
+
+        let pool_info = self.pool.to_account_info();
+        let ix = Instruction {
+            program_id: mpl_token_metadata::ID,
+            accounts: vec![
+                AccountMeta::new(metadata_account.key(), false),
+                AccountMeta::new_readonly(self.lp_mint.key(), false),
+                AccountMeta::new_readonly(pool_info.key(), true),
+                AccountMeta::new(self.authority.key(), true),
+                AccountMeta::new_readonly(pool_info.key(), true),
+                AccountMeta::new_readonly(self.system_program.key(), false),
+            ],
+            data,
+        };
+        let lp_mint_key = self.lp_mint.key();
+        invoke_signed(
+            &ix,
+            &[
+                metadata_account.to_account_info(),
+                self.lp_mint.to_account_info(),
+                pool_info.clone(),
+                self.authority.to_account_info(),
+                pool_info,
+                self.system_program.to_account_info(),
+                metadata_program.to_account_info(),
+            ],
+            &[&[SEED_POOL, lp_mint_key.as_ref(), &[pool_bump]]],
+        )?;
+        Ok(())
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_a: u64, amount_b: u64, vesting_seconds: i64, tag: [u8; 32], deposit_id: u64)]
+pub struct DepositAndVest<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, token::mint = token_a_mint)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint)]
+    pub reserve_b: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut, token::mint = token_a_mint, token::authority = user)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint, token::authority = user)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    /// Hands out this user's deposit ids; see `init_user_vesting_index`
+    #[account(
+        mut,
+        seeds = [SEED_USER_VESTING_INDEX, pool.key().as_ref(), user.key().as_ref()],
+        bump,
+        has_one = pool,
+        has_one = user
+    )]
+    pub user_vesting_index: Account<'info, UserVestingIndex>,
 
     /// Vesting PDA (unique per deposit)
     #[account(
         init,
-        payer = user,
+        payer = rent_payer,
+        space = 8 + 304,
+        seeds = [SEED_VESTING, pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Vesting token account to hold LP tokens. Program creates it and sets authority to the vesting PDA.
+    #[account(
+        init,
+        payer = rent_payer,
+        token::mint = lp_mint,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    /// Reward vault (optional) where reward LP tokens are stored for distribution
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    /// Allowlist record for the depositor; only required when the pool is permissioned
+    #[account(seeds = [SEED_ALLOWLIST, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub allowlist_entry: Option<Account<'info, AllowlistEntry>>,
+
+    /// CHECK: blocklist PDA for the depositor; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+
+    /// Optional position index; the new position is appended when passed
+    #[account(mut, seeds = [SEED_PORTFOLIO, user.key().as_ref()], bump)]
+    pub portfolio: Option<Account<'info, Portfolio>>,
+
+    /// Pays the vesting PDA and vault rent (the user, or a sponsoring dApp) and gets it back on close
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> DepositAndVest<'info> {
+    fn transfer_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_a.to_account_info().clone(),
+            to: self.reserve_a.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_b.to_account_info().clone(),
+            to: self.reserve_b.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn mint_to_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+        let cpi_accounts = MintTo {
+            mint: self.lp_mint.to_account_info().clone(),
+            to: self.vesting_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(), // pool PDA is mint authority
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_a: u64, min_lp_out: u64, vesting_seconds: i64, tag: [u8; 32], deposit_id: u64)]
+pub struct SwapAndVest<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+    #[account(address = pool.token_a_mint)]
+    pub token_a_mint: Box<Account<'info, Mint>>,
+    #[account(address = pool.token_b_mint)]
+    pub token_b_mint: Box<Account<'info, Mint>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = token_a_mint, token::authority = user)]
+    pub user_token_a: Box<Account<'info, TokenAccount>>,
+    /// Receives the swap leg's B, which the deposit leg then takes back in full
+    #[account(mut, token::mint = token_b_mint, token::authority = user)]
+    pub user_token_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [SEED_USER_VESTING_INDEX, pool.key().as_ref(), user.key().as_ref()],
+        bump,
+        has_one = pool,
+        has_one = user
+    )]
+    pub user_vesting_index: Box<Account<'info, UserVestingIndex>>,
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + 304,
+        seeds = [SEED_VESTING, pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Box<Account<'info, VestingStake>>,
+    #[account(
+        init,
+        payer = rent_payer,
+        token::mint = lp_mint,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// Treasury token accounts (where treasury fees land); must match the ones stored on the pool
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_POOL_STATS, pool.key().as_ref()], bump)]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    /// Underlying reward vaults; required in `REWARD_MODE_UNDERLYING`
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// TWAP observation buffer; every swap advances it (see `init_price_observations`)
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+
+    /// Allowlist record for the depositor; only required when the pool is permissioned
+    #[account(seeds = [SEED_ALLOWLIST, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub allowlist_entry: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    /// CHECK: blocklist PDA for the depositor; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    /// Caller allowlist and the instructions sysvar; required while `pool.caller_restricted`
+    #[account(has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Option<Box<Account<'info, AllowedCallers>>>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// Optional position index; the new position is appended when passed
+    #[account(mut, seeds = [SEED_PORTFOLIO, user.key().as_ref()], bump)]
+    pub portfolio: Option<Box<Account<'info, Portfolio>>>,
+
+    /// Pays the vesting PDA and vault rent (the user, or a sponsoring dApp) and gets it back on close
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> SwapAndVest<'info> {
+    fn order_swap<'a>(&'a mut self, escrow_signer: &'a [&'a [&'a [u8]]]) -> OrderSwapAccounts<'a, 'info> {
+        OrderSwapAccounts {
+            pool: &mut self.pool,
+            lp_mint: &self.lp_mint,
+            reserve_a: &mut self.reserve_a,
+            reserve_b: &mut self.reserve_b,
+            treasury_token_account_a: &self.treasury_token_account_a,
+            treasury_token_account_b: &self.treasury_token_account_b,
+            pool_stats: &mut self.pool_stats,
+            reward_vault_a: self.reward_vault_a.as_deref(),
+            reward_vault_b: self.reward_vault_b.as_deref(),
+            price_observations: &mut self.price_observations,
+            escrow: self.user_token_a.to_account_info(),
+            escrow_authority: self.user.to_account_info(),
+            escrow_signer,
+            destination: self.user_token_b.to_account_info(),
+            token_program: &self.token_program,
+        }
+    }
+    fn transfer_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_a.to_account_info().clone(),
+            to: self.reserve_a.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_b.to_account_info().clone(),
+            to: self.reserve_b.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn mint_to_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+        let cpi_accounts = MintTo {
+            mint: self.lp_mint.to_account_info().clone(),
+            to: self.vesting_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(), // pool PDA is mint authority
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimVested<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, close = rent_payer, has_one = pool, has_one = user, has_one = rent_payer)]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Vesting token account owned by vesting PDA
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: destination LP token account of the user, or their LP ATA still to be created; checked
+    /// in `ensure_user_lp_token_account`
+    #[account(mut)]
+    pub user_lp_token_account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Reward vault where reward LPs are held
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Underlying-reward accounts; required only when the position has token A/B rewards pending
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Option<Account<'info, TokenAccount>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// Optional position index; the claimed position is removed when passed
+    #[account(mut, seeds = [SEED_PORTFOLIO, user.key().as_ref()], bump)]
+    pub portfolio: Option<Account<'info, Portfolio>>,
+
+    /// CHECK: rent refund destination, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Needed for positions opened in an earlier reward epoch
+    #[account(has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+
+    /// Payout receipt, paid for by the user; required while `pool.claim_receipt_period` is set
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 120,
+        seeds = [SEED_CLAIM_RECEIPT, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub claim_receipt: Option<Box<Account<'info, ClaimReceipt>>>,
+    pub system_program: Option<Program<'info, System>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    /// Required (with `system_program`) when the user's LP ATA has to be created
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+
+    /// The user's reward destination registration; when passed, LP rewards go to its account
+    #[account(has_one = user, seeds = [SEED_REWARD_DESTINATION, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub reward_destination: Option<Box<Account<'info, RewardDestination>>>,
+    /// The registered destination token account; required with `reward_destination`
+    #[account(mut)]
+    pub reward_destination_account: Option<Box<Account<'info, TokenAccount>>>,
+}
+
+impl<'info> ClaimVested<'info> {
+    /// Create the user's LP ATA if `user_lp_token_account` is that ATA and does not exist yet, then
+    /// check the destination is an LP token account owned by the user.
+    fn ensure_user_lp_token_account(&self) -> Result<()> {
+        let destination = self.user_lp_token_account.to_account_info();
+        if destination.data_is_empty() {
+            require!(
+                destination.key() == get_associated_token_address(&self.user.key(), &self.lp_mint.key()),
+                AmmError::InvalidLpDestination
+            );
+            let associated_token_program =
+                self.associated_token_program.as_ref().ok_or(AmmError::AtaProgramsRequired)?;
+            let system_program = self.system_program.as_ref().ok_or(AmmError::AtaProgramsRequired)?;
+            let cpi_accounts = associated_token::Create {
+                payer: self.user.to_account_info(),
+                associated_token: destination.clone(),
+                authority: self.user.to_account_info(),
+                mint: self.lp_mint.to_account_info(),
+                system_program: system_program.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+            };
+            associated_token::create(CpiContext::new(associated_token_program.to_account_info(), cpi_accounts))?;
+        }
+        require!(destination.owner == &token::ID, AmmError::InvalidLpDestination);
+        let account = TokenAccount::try_deserialize(&mut &destination.try_borrow_data()?[..])?;
+        require!(
+            account.mint == self.lp_mint.key() && account.owner == self.user.key(),
+            AmmError::InvalidLpDestination
+        );
+        Ok(())
+    }
+
+    /// LP rewards go to the registered reward destination when the registration is passed,
+    /// otherwise to the user's LP account.
+    fn reward_destination_info(&self) -> Result<AccountInfo<'info>> {
+        match self.reward_destination.as_deref() {
+            Some(registration) => {
+                let destination = self
+                    .reward_destination_account
+                    .as_ref()
+                    .ok_or(AmmError::RewardDestinationMissing)?;
+                require!(destination.key() == registration.destination, AmmError::InvalidRewardDestination);
+                Ok(destination.to_account_info())
+            }
+            None => Ok(self.user_lp_token_account.to_account_info()),
+        }
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimAndWithdraw<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, close = rent_payer, has_one = pool, has_one = user, has_one = rent_payer)]
+    pub vesting_stake: Box<Account<'info, VestingStake>>,
+
+    /// Vesting token account owned by vesting PDA; its LP is burned
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    /// Reward vault where reward LPs are held; the position's reward is burned from it. Must not be
+    /// the recovery vault or a pool-held treasury, whose LP the pool also owns but owes elsewhere
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = pool,
+        constraint = reward_vault.key() != recovery_vault_address(&pool.key()).0 @ AmmError::InvalidRewardVault,
+        constraint = reward_vault.key() != pool.treasury @ AmmError::InvalidRewardVault
+    )]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Underlying-reward vaults; required only when the position has token A/B rewards pending
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// Optional position index; the claimed position is removed when passed
+    #[account(mut, seeds = [SEED_PORTFOLIO, user.key().as_ref()], bump)]
+    pub portfolio: Option<Box<Account<'info, Portfolio>>>,
+
+    /// CHECK: rent refund destination, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Needed for positions opened in an earlier reward epoch
+    #[account(has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+
+    /// Payout receipt, paid for by the user; required while `pool.claim_receipt_period` is set
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 120,
+        seeds = [SEED_CLAIM_RECEIPT, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub claim_receipt: Option<Box<Account<'info, ClaimReceipt>>>,
+    pub system_program: Option<Program<'info, System>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> ClaimAndWithdraw<'info> {
+    fn burn_vesting_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.vesting_token_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn burn_reward_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.reward_vault.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_a_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.user_token_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_b_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.user_token_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RelockVested<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, has_one = pool, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    /// Needed for positions opened in an earlier reward epoch
+    #[account(has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+}
+
+impl<'info> RelockVested<'info> {
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetAutoRelock<'info> {
+    #[account(mut, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteAutoRelock<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, has_one = pool)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    /// The position owner's LP account; receives the LP rewards settled by the relock
+    #[account(mut, token::mint = lp_mint, token::authority = vesting_stake.user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    /// Needed for positions opened in an earlier reward epoch
+    #[account(has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+}
+
+impl<'info> ExecuteAutoRelock<'info> {
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct CloseClaimReceipt<'info> {
+    #[account(mut, close = user, has_one = user)]
+    pub claim_receipt: Account<'info, ClaimReceipt>,
+    /// CHECK: rent refund destination, checked against `claim_receipt.user`
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitUserVestingIndex<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 32 + 8,
+        seeds = [SEED_USER_VESTING_INDEX, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub user_vesting_index: Account<'info, UserVestingIndex>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitAllowedCallers<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 40 + 32 * MAX_ALLOWED_CALLERS,
+        seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()],
+        bump
+    )]
+    pub allowed_callers: Account<'info, AllowedCallers>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetAllowedCallers<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Account<'info, AllowedCallers>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitTraderPoints<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 112,
+        seeds = [SEED_TRADER_POINTS, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub trader_points: Account<'info, TraderPoints>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RedeemTraderPoints<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = user,
+        seeds = [SEED_TRADER_POINTS, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub trader_points: Account<'info, TraderPoints>,
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    /// Must not be the recovery vault, which also holds pool-owned LP that is owed to users
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = pool,
+        constraint = reward_vault.key() != recovery_vault_address(&pool.key()).0 @ AmmError::InvalidRewardVault
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RedeemTraderPoints<'info> {
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitGlobalStats<'info> {
+    #[account(init, payer = payer, space = 8 + 64, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Account<'info, GlobalStats>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitPoolView<'info> {
+    #[account(has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(init, payer = payer, space = 8 + 128, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Account<'info, PoolView>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefreshPoolView<'info> {
+    #[account(has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Account<'info, PoolView>,
+}
+
+#[derive(Accounts)]
+pub struct InitPortfolio<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 4 + 32 * MAX_PORTFOLIO_POSITIONS,
+        seeds = [SEED_PORTFOLIO, user.key().as_ref()],
+        bump
+    )]
+    pub portfolio: Account<'info, Portfolio>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Positions are passed as remaining accounts; see `claim_all_matured`.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimAllMatured<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, seeds = [SEED_PORTFOLIO, user.key().as_ref()], bump)]
+    pub portfolio: Option<Account<'info, Portfolio>>,
+    pub token_program: Program<'info, Token>,
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EarlyUnvest<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, has_one = pool, has_one = user, has_one = rent_payer)]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Vesting token account owned by vesting PDA
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    /// user's LP account
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+
+    /// treasury LP token account to receive penalties
+    #[account(mut, token::mint = lp_mint, address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Reward vault paying the rewards settled on exit
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Underlying-reward accounts; required only when the position has token A/B rewards pending
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_a_mint, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Option<Account<'info, TokenAccount>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// CHECK: rent refund destination on full exit, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Approved hardship request waiving the penalty; consumed when used
+    #[account(mut, has_one = vesting_stake, seeds = [SEED_HARDSHIP, vesting_stake.key().as_ref()], bump)]
+    pub hardship_request: Option<Account<'info, HardshipRequest>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+
+    /// Passed penalty waiver vote; while it runs the early-unvest penalty is waived
+    #[account(has_one = pool, seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()], bump)]
+    pub penalty_waiver: Option<Box<Account<'info, PenaltyWaiverVote>>>,
+
+    /// Insurance fund for the LP mint; required while `protocol_config.insurance_bps` is set
+    #[account(mut, seeds = [SEED_INSURANCE_FUND, lp_mint.key().as_ref()], bump)]
+    pub insurance_fund: Option<Box<Account<'info, TokenAccount>>>,
+}
+
+impl<'info> EarlyUnvest<'info> {
+    fn transfer_penalty_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_penalty_to_rewards_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.reward_vault.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_from_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_reward_penalty_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_penalty_to_insurance_context(
+        &self,
+        insurance_fund: &Account<'info, TokenAccount>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info(),
+            to: insurance_fund.to_account_info(),
+            authority: self.vesting_stake.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn transfer_reward_penalty_to_insurance_context(
+        &self,
+        insurance_fund: &Account<'info, TokenAccount>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info(),
+            to: insurance_fund.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn close_vesting_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vesting_token_account.to_account_info().clone(),
+            destination: self.rent_payer.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EarlyUnvestToTokens<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut)]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, has_one = pool, has_one = user, has_one = rent_payer)]
+    pub vesting_stake: Box<Account<'info, VestingStake>>,
+
+    /// Vesting token account owned by vesting PDA
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Box<Account<'info, TokenAccount>>,
+
+    /// treasury LP token account to receive penalties
+    #[account(mut, token::mint = lp_mint, address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    /// Reward vault paying the rewards settled on exit; the user's share is burned from it
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Underlying-reward accounts; required only when the position has token A/B rewards pending
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_a_mint, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Option<Account<'info, TokenAccount>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// CHECK: rent refund destination on full exit, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Approved hardship request waiving the penalty; consumed when used
+    #[account(mut, has_one = vesting_stake, seeds = [SEED_HARDSHIP, vesting_stake.key().as_ref()], bump)]
+    pub hardship_request: Option<Account<'info, HardshipRequest>>,
+
+    /// Required while `pool.hook_enabled`
+    #[account(has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Option<Box<Account<'info, PoolHook>>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+
+    /// Passed penalty waiver vote; while it runs the early-unvest penalty is waived
+    #[account(has_one = pool, seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()], bump)]
+    pub penalty_waiver: Option<Box<Account<'info, PenaltyWaiverVote>>>,
+
+    /// Insurance fund for the LP mint; required while `protocol_config.insurance_bps` is set
+    #[account(mut, seeds = [SEED_INSURANCE_FUND, lp_mint.key().as_ref()], bump)]
+    pub insurance_fund: Option<Box<Account<'info, TokenAccount>>>,
+}
+
+impl<'info> EarlyUnvestToTokens<'info> {
+    fn transfer_penalty_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_penalty_to_rewards_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.reward_vault.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn burn_vesting_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.vesting_token_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn burn_reward_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.reward_vault.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_reward_penalty_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_penalty_to_insurance_context(
+        &self,
+        insurance_fund: &Account<'info, TokenAccount>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info(),
+            to: insurance_fund.to_account_info(),
+            authority: self.vesting_stake.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn transfer_reward_penalty_to_insurance_context(
+        &self,
+        insurance_fund: &Account<'info, TokenAccount>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info(),
+            to: insurance_fund.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+
+    fn transfer_a_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.user_token_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_b_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.user_token_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn close_vesting_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vesting_token_account.to_account_info().clone(),
+            destination: self.rent_payer.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SweepAbandoned<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        close = rent_payer,
+        has_one = pool,
+        has_one = user,
+        has_one = rent_payer,
+        seeds = [SEED_VESTING, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    #[account(
+        mut,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = lp_mint, seeds = [SEED_RECOVERY_VAULT, pool.key().as_ref()], bump)]
+    pub recovery_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = sweeper,
+        space = 8 + 112,
+        seeds = [SEED_RECOVERY, pool.key().as_ref(), vesting_stake.user.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub recovery_claim: Account<'info, RecoveryClaim>,
+
+    /// CHECK: position owner, refunded any escrowed claim bounty; checked against `vesting_stake.user`
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    /// CHECK: receives the rent of the closed position and vault, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub sweeper: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SweepAbandoned<'info> {
+    fn transfer_to_recovery_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.recovery_vault.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_vesting_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vesting_token_account.to_account_info().clone(),
+            destination: self.rent_payer.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RedeemRecovered<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, close = user, has_one = pool, has_one = user)]
+    pub recovery_claim: Account<'info, RecoveryClaim>,
+
+    #[account(mut, token::mint = lp_mint, seeds = [SEED_RECOVERY_VAULT, pool.key().as_ref()], bump)]
+    pub recovery_vault: Account<'info, TokenAccount>,
+
+    /// destination LP token account of the user
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// Reward vault where reward LPs are held
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Underlying-reward accounts; required only when the position has token A/B rewards pending
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Option<Account<'info, TokenAccount>>,
+}
+
+impl<'info> RedeemRecovered<'info> {
+    fn transfer_from_recovery_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.recovery_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RescueVestingTokens<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = user,
+        seeds = [SEED_VESTING, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Located by seeds only; its token authority is what may be broken
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+    /// Receives any escrowed claim bounty
+    #[account(mut)]
+    pub user: Signer<'info>,
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RescueVestingTokens<'info> {
+    /// Vault-to-user transfer signed by `authority`, the vault's current owner.
+    fn transfer_from_vesting_context(
+        &self,
+        authority: AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority,
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RequestHardshipUnlock<'info> {
+    #[account(has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 80,
+        seeds = [SEED_HARDSHIP, vesting_stake.key().as_ref()],
+        bump
+    )]
+    pub hardship_request: Account<'info, HardshipRequest>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EscrowClaimBounty<'info> {
+    #[account(mut, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CrankClaim<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, close = rent_payer, has_one = pool, has_one = rent_payer)]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Vesting token account owned by vesting PDA
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    /// The position owner's LP account
+    #[account(mut, token::mint = lp_mint, token::authority = vesting_stake.user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+
+    /// Reward vault where reward LPs are held
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    /// Receives the bounty
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+
+    /// CHECK: rent refund destination, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CrankClaim<'info> {
+    fn transfer_from_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(nonce: u64)]
+pub struct ClaimVestedWithVoucher<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, close = rent_payer, has_one = pool, has_one = rent_payer, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+
+    /// Vesting token account owned by vesting PDA
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    /// CHECK: the position owner and voucher signer, checked against `vesting_stake.user`; receives any claim bounty
+    #[account(mut)]
+    pub user: UncheckedAccount<'info>,
+
+    /// LP account named in the voucher; receives the LP and LP rewards
+    #[account(mut, token::mint = lp_mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// Reward vault where reward LPs are held
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = relayer,
+        space = 8 + 40,
+        seeds = [SEED_USED_VOUCHER, user.key().as_ref(), &nonce.to_le_bytes()],
+        bump
+    )]
+    pub used_voucher: Account<'info, UsedVoucher>,
+
+    /// The relayer named in the voucher; pays the transaction fees and the receipt's rent
+    #[account(mut)]
+    pub relayer: Signer<'info>,
+
+    /// CHECK: rent refund destination, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// CHECK: instructions sysvar, read for the ed25519 voucher instruction
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> ClaimVestedWithVoucher<'info> {
+    fn transfer_from_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.vesting_token_account.to_account_info(),
+            to: self.destination.to_account_info(),
+            authority: self.vesting_stake.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info(),
+            to: self.destination.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetNotifyAddress<'info> {
+    #[account(has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 64,
+        seeds = [SEED_NOTIFY, vesting_stake.key().as_ref()],
+        bump
+    )]
+    pub notify_registration: Account<'info, NotifyRegistration>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClearNotifyAddress<'info> {
+    #[account(has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(mut, close = user, has_one = vesting_stake, seeds = [SEED_NOTIFY, vesting_stake.key().as_ref()], bump)]
+    pub notify_registration: Account<'info, NotifyRegistration>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetRewardDestination<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(token::mint = pool.lp_mint)]
+    pub destination: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 96,
+        seeds = [SEED_REWARD_DESTINATION, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub reward_destination: Account<'info, RewardDestination>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateRewardDestination<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(token::mint = pool.lp_mint)]
+    pub destination: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        has_one = pool,
+        has_one = user,
+        seeds = [SEED_REWARD_DESTINATION, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub reward_destination: Account<'info, RewardDestination>,
+    pub user: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClearRewardDestination<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        close = user,
+        has_one = pool,
+        has_one = user,
+        seeds = [SEED_REWARD_DESTINATION, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub reward_destination: Account<'info, RewardDestination>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct HarvestRewards<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(mut, has_one = pool, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    pub user: Signer<'info>,
+
+    /// Reward vault where reward LPs are held
+    #[account(mut, token::mint = lp_mint)]
+    pub reward_vault: Account<'info, TokenAccount>,
+
+    /// The registered reward destination, or one of the user's own LP accounts without a registration
+    #[account(mut, token::mint = lp_mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// The user's reward destination registration; required to pay anywhere but the user's own account
+    #[account(
+        has_one = pool,
+        has_one = user,
+        seeds = [SEED_REWARD_DESTINATION, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub reward_destination: Option<Box<Account<'info, RewardDestination>>>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// Needed for positions opened in an earlier reward epoch
+    #[account(has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> HarvestRewards<'info> {
+    fn transfer_reward_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info(),
+            to: self.destination.to_account_info(),
+            authority: self.pool.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct MarkMatured<'info> {
+    #[account(mut)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(has_one = vesting_stake, seeds = [SEED_NOTIFY, vesting_stake.key().as_ref()], bump)]
+    pub notify_registration: Option<Account<'info, NotifyRegistration>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ApproveHardshipUnlock<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(has_one = pool)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(mut, has_one = vesting_stake, seeds = [SEED_HARDSHIP, vesting_stake.key().as_ref()], bump)]
+    pub hardship_request: Account<'info, HardshipRequest>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelHardshipRequest<'info> {
+    #[account(mut, close = user, has_one = user)]
+    pub hardship_request: Account<'info, HardshipRequest>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct PledgeVesting<'info> {
+    #[account(mut, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    pub user: Signer<'info>,
+    pub pledgee: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ReleasePledge<'info> {
+    #[account(mut, has_one = pledgee @ AmmError::NotPledgee)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    pub pledgee: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ListVestingForSale<'info> {
+    #[account(mut, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 112,
+        seeds = [SEED_LISTING, vesting_stake.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, VestingListing>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelVestingListing<'info> {
+    #[account(mut, close = seller, has_one = seller, has_one = vesting_stake)]
+    pub listing: Account<'info, VestingListing>,
+    #[account(mut)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct BuyVestingPosition<'info> {
+    #[account(address = vesting_stake.pool)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        mut,
+        close = seller,
+        has_one = vesting_stake,
+        has_one = seller,
+        has_one = quote_mint,
+        seeds = [SEED_LISTING, vesting_stake.key().as_ref()],
+        bump
+    )]
+    pub listing: Account<'info, VestingListing>,
+    pub quote_mint: Account<'info, Mint>,
+
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+    #[account(mut, token::mint = quote_mint, token::authority = seller)]
+    pub seller_quote_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(mut, token::mint = quote_mint, token::authority = buyer)]
+    pub buyer_quote_account: Account<'info, TokenAccount>,
+
+    /// Pool treasury LP account; its owner receives the sale fee
+    #[account(address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Account<'info, TokenAccount>,
+    /// Required when `pool.sale_fee_bps > 0`
+    #[account(mut, token::mint = quote_mint, token::authority = treasury_lp_account.owner)]
+    pub treasury_quote_account: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> BuyVestingPosition<'info> {
+    fn transfer_to_seller_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.buyer_quote_account.to_account_info().clone(),
+            to: self.seller_quote_account.to_account_info().clone(),
+            authority: self.buyer.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_sale_fee_context(&self) -> Result<CpiContext<'_, '_, '_, 'info, Transfer<'info>>> {
+        let treasury = self.treasury_quote_account.as_ref().ok_or(AmmError::InvalidTreasuryAccount)?;
+        let cpi_accounts = Transfer {
+            from: self.buyer_quote_account.to_account_info().clone(),
+            to: treasury.to_account_info().clone(),
+            authority: self.buyer.to_account_info().clone(),
+        };
+        Ok(CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts))
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateVoterWeightRecord<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 164,
+        seeds = [SEED_VOTER_WEIGHT, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub voter_weight_record: Account<'info, VoterWeightRecord>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateVoterWeightRecord<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        mut,
+        seeds = [SEED_VOTER_WEIGHT, pool.key().as_ref(), user.key().as_ref()],
+        bump,
+        constraint = voter_weight_record.realm == pool.governance_realm @ AmmError::GovernanceNotConfigured
+    )]
+    pub voter_weight_record: Account<'info, VoterWeightRecord>,
+    pub user: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct OpenFeeVote<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 128,
+        seeds = [SEED_FEE_VOTE, pool.key().as_ref(), &pool.fee_vote_round.to_le_bytes()],
+        bump
+    )]
+    pub fee_vote: Account<'info, FeeVote>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CastFeeVote<'info> {
+    #[account(mut, has_one = pool)]
+    pub fee_vote: Account<'info, FeeVote>,
+    pub pool: Account<'info, Pool>,
+    #[account(has_one = pool, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 80,
+        seeds = [SEED_FEE_VOTE_RECORD, fee_vote.key().as_ref(), vesting_stake.key().as_ref()],
+        bump
+    )]
+    pub fee_vote_record: Account<'info, FeeVoteRecord>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct OpenPenaltyWaiverVote<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 96,
+        seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()],
+        bump
+    )]
+    pub penalty_waiver: Account<'info, PenaltyWaiverVote>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CastPenaltyWaiverVote<'info> {
+    #[account(mut, has_one = pool, seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()], bump)]
+    pub penalty_waiver: Account<'info, PenaltyWaiverVote>,
+    pub pool: Account<'info, Pool>,
+    #[account(has_one = pool, has_one = user)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 80,
+        seeds = [
+            SEED_PENALTY_WAIVER_BALLOT,
+            penalty_waiver.key().as_ref(),
+            vesting_stake.key().as_ref(),
+            &penalty_waiver.opened_at.to_le_bytes()
+        ],
+        bump
+    )]
+    pub ballot: Account<'info, PenaltyWaiverBallot>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FinalizePenaltyWaiverVote<'info> {
+    #[account(mut, seeds = [SEED_PENALTY_WAIVER, penalty_waiver.pool.as_ref()], bump)]
+    pub penalty_waiver: Account<'info, PenaltyWaiverVote>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePenaltyWaiverVote<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, close = authority, has_one = pool, seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()], bump)]
+    pub penalty_waiver: Account<'info, PenaltyWaiverVote>,
+    /// CHECK: rent destination, checked against `pool.authority`
+    #[account(mut)]
+    pub authority: UncheckedAccount<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FinalizeFeeVote<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool)]
+    pub fee_vote: Account<'info, FeeVote>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[derive(Accounts)]
+pub struct InitRewardEpochs<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 32 + 14 * REWARD_EPOCH_RING_SIZE,
+        seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()],
+        bump
+    )]
+    pub reward_epochs: Box<Account<'info, RewardEpochs>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EnableEpochRewards<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 48 + 64 * REWARD_LEDGER_RING_SIZE,
+        seeds = [SEED_REWARD_LEDGER, pool.key().as_ref()],
+        bump
+    )]
+    pub reward_ledger: Box<Account<'info, RewardLedger>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FinalizeRewardEpoch<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool, seeds = [SEED_REWARD_LEDGER, pool.key().as_ref()], bump)]
+    pub reward_ledger: Box<Account<'info, RewardLedger>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RebaseRewardAccumulator<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct InitEpochSnapshots<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 40 + 40 * SNAPSHOT_RING_SIZE,
+        seeds = [SEED_EPOCH_SNAPSHOT, pool.key().as_ref()],
+        bump
+    )]
+    pub epoch_snapshot: Box<Account<'info, EpochSnapshot>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitPriceObservations<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 74 + 40 * OBSERVATION_RING_SIZE,
+        seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()],
+        bump
+    )]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct PokeOracle<'info> {
+    #[account(mut, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    pub poker: Signer<'info>,
+    /// Destination for the poke incentive; omit to forgo it
+    #[account(mut, token::mint = pool.token_a_mint)]
+    pub poker_token_a: Option<Account<'info, TokenAccount>>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SnapshotLockedBalances<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, has_one = pool, seeds = [SEED_EPOCH_SNAPSHOT, pool.key().as_ref()], bump)]
+    pub epoch_snapshot: Box<Account<'info, EpochSnapshot>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub reserve_b: Account<'info, TokenAccount>,
+
+    /// LP account owner, or an SPL delegate approved on the LP account (e.g. a vault strategy)
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = lp_mint)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    /// Proceeds always go to the LP account owner, even when a delegate signs
+    #[account(mut, token::mint = token_a_mint, token::authority = user_lp_token_account.owner)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint, token::authority = user_lp_token_account.owner)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> Withdraw<'info> {
+    /// The signer must own the LP account or hold a delegate approval covering `lp_amount`.
+    fn check_lp_authority(&self, lp_amount: u64) -> Result<()> {
+        let lp_account = &self.user_lp_token_account;
+        let signer = self.user.key();
+        let is_delegate = lp_account.delegate == COption::Some(signer) && lp_account.delegated_amount >= lp_amount;
+        require!(lp_account.owner == signer || is_delegate, AmmError::Unauthorized);
+        Ok(())
+    }
+
+    fn burn_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.user_lp_token_account.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_a_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.user_token_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+
+    fn transfer_b_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.user_token_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+/// Account order is part of the router interface and must stay stable:
+/// pool, lp_mint, reserve_a, reserve_b, user, user_token_a, user_token_b,
+/// treasury_token_account_a, treasury_token_account_b, token_program, token_a_mint, token_b_mint, pool_stats,
+/// blocklist_entry, market_maker_entry, reward_vault_a, reward_vault_b, price_observations, pool_view,
+/// trader_points, allowed_callers, instructions_sysvar, treasury_owner, associated_token_program,
+/// system_program, trader_rebate_stake, trader_rebate_vault, global_stats, event_authority, program.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = token_a_mint)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint)]
+    pub reserve_b: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = token_a_mint, token::authority = user)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint, token::authority = user)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    /// CHECK: token A treasury (where treasury fees land); must match the one stored on the pool,
+    /// whose mint was checked when it was set
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: UncheckedAccount<'info>,
+    /// CHECK: token B treasury, as above
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+
+    #[account(mut, seeds = [SEED_POOL_STATS, pool.key().as_ref()], bump)]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    /// CHECK: blocklist PDA for the trader; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    /// Market maker record for the trader; only required during the bootstrap phase
+    #[account(seeds = [SEED_MARKET_MAKER, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub market_maker_entry: Option<Account<'info, MarketMakerEntry>>,
+
+    /// Underlying reward vaults; required in `REWARD_MODE_UNDERLYING`
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), token_a_mint.key().as_ref()], bump)]
+    pub reward_vault_a: Option<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), token_b_mint.key().as_ref()], bump)]
+    pub reward_vault_b: Option<Account<'info, TokenAccount>>,
+
+    /// TWAP observation buffer; every swap advances it (see `init_price_observations`)
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+
+    /// Pool summary; refreshed when passed
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_VIEW, pool.key().as_ref()], bump)]
+    pub pool_view: Option<Box<Account<'info, PoolView>>>,
+
+    /// Trader's volume and points; accrued when passed
+    #[account(mut, has_one = pool, seeds = [SEED_TRADER_POINTS, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub trader_points: Option<Box<Account<'info, TraderPoints>>>,
+
+    /// Caller allowlist and the instructions sysvar; required while `pool.caller_restricted`
+    #[account(has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Option<Box<Account<'info, AllowedCallers>>>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// CHECK: `pool.treasury_owner`; with the two programs below, required when the fee-side
+    /// treasury ATA has to be created
+    #[account(address = pool.treasury_owner @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_owner: Option<UncheckedAccount<'info>>,
+    pub associated_token_program: Option<Program<'info, AssociatedToken>>,
+    pub system_program: Option<Program<'info, System>>,
+
+    /// CHECK: the trader's rebate `VestingStake`; created by the swap when empty (needs
+    /// `system_program`). Rebates accrue only when it and its vault are passed.
+    #[account(
+        mut,
+        seeds = [SEED_VESTING, pool.key().as_ref(), user.key().as_ref(), &TRADER_REBATE_DEPOSIT_ID.to_le_bytes()],
+        bump
+    )]
+    pub trader_rebate_stake: Option<UncheckedAccount<'info>>,
+    /// CHECK: the rebate position's LP vault, created alongside it
+    #[account(
+        mut,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), user.key().as_ref(), &TRADER_REBATE_DEPOSIT_ID.to_le_bytes()],
+        bump
+    )]
+    pub trader_rebate_vault: Option<UncheckedAccount<'info>>,
+
+    /// Program-wide totals; updated when passed
+    #[account(mut, seeds = [SEED_GLOBAL_STATS], bump)]
+    pub global_stats: Option<Box<Account<'info, GlobalStats>>>,
+}
+
+impl<'info> Swap<'info> {
+    #[cfg(feature = "accounting-checks")]
+    fn accounting_snapshot(&self) -> accounting::Snapshot<'info> {
+        let side = |reserve: &Account<'info, TokenAccount>,
+                    treasury: &UncheckedAccount<'info>,
+                    vault: &Option<Account<'info, TokenAccount>>| {
+            let mut side = vec![reserve.to_account_info(), treasury.to_account_info()];
+            side.extend(vault.as_ref().map(|v| v.to_account_info()));
+            side
+        };
+        accounting::Snapshot::take(
+            side(&self.reserve_a, &self.treasury_token_account_a, &self.reward_vault_a),
+            side(&self.reserve_b, &self.treasury_token_account_b, &self.reward_vault_b),
+            None,
+        )
+    }
+
+    /// Create the fee-side treasury ATA, paid by the trader, when the pool pays treasury fees to
+    /// `treasury_owner`'s ATAs and that one does not exist yet.
+    fn create_treasury_ata_if_missing(&self, fee_in_a: bool) -> Result<()> {
+        let (treasury, mint) = if fee_in_a {
+            (self.treasury_token_account_a.to_account_info(), self.token_a_mint.to_account_info())
+        } else {
+            (self.treasury_token_account_b.to_account_info(), self.token_b_mint.to_account_info())
+        };
+        if self.pool.treasury_owner == Pubkey::default() || !treasury.data_is_empty() {
+            return Ok(());
+        }
+        let owner = self.treasury_owner.as_ref().ok_or(AmmError::TreasuryAtaAccountsMissing)?;
+        let associated_token_program =
+            self.associated_token_program.as_ref().ok_or(AmmError::TreasuryAtaAccountsMissing)?;
+        let system_program = self.system_program.as_ref().ok_or(AmmError::TreasuryAtaAccountsMissing)?;
+        let cpi_accounts = associated_token::Create {
+            payer: self.user.to_account_info(),
+            associated_token: treasury,
+            authority: owner.to_account_info(),
+            mint,
+            system_program: system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        };
+        associated_token::create(CpiContext::new(associated_token_program.to_account_info(), cpi_accounts))
+    }
+
+    /// Mint `rebate_lp` into the trader's rebate position, creating it and its vault (rent paid
+    /// by the trader) on first use. A top-up leaves pending rewards unchanged and pushes
+    /// `vesting_end` out to at least `now + trader_rebate_seconds`. Returns the new `vesting_end`,
+    /// or `None` when the rebate accounts were not passed or the position was already claimed.
+    fn credit_trader_rebate(&mut self, rebate_lp: u64, now: i64) -> Result<Option<i64>> {
+        let (stake_info, vault_info) = match (self.trader_rebate_stake.as_ref(), self.trader_rebate_vault.as_ref()) {
+            (Some(stake), Some(vault)) => (stake.to_account_info(), vault.to_account_info()),
+            _ => return Ok(None),
+        };
+        let pool_key = self.pool.key();
+        let user_key = self.user.key();
+        if stake_info.data_is_empty() {
+            let system_program = self.system_program.as_ref().ok_or(AmmError::TraderRebateAccountsMissing)?;
+            let rent = Rent::get()?;
+            let deposit_id = TRADER_REBATE_DEPOSIT_ID.to_le_bytes();
+            let stake_bump = vesting_address(&pool_key, &user_key, TRADER_REBATE_DEPOSIT_ID).1;
+            let vault_bump = vesting_vault_address(&pool_key, &user_key, TRADER_REBATE_DEPOSIT_ID).1;
+            let stake_space = 8 + 304;
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: self.user.to_account_info(),
+                        to: stake_info.clone(),
+                    },
+                    &[&[SEED_VESTING, pool_key.as_ref(), user_key.as_ref(), &deposit_id, &[stake_bump]]],
+                ),
+                rent.minimum_balance(stake_space),
+                stake_space as u64,
+                &ID,
+            )?;
+            let stake = VestingStake {
+                pool: pool_key,
+                user: user_key,
+                amount: 0,
+                vesting_end: now,
+                claimed: false,
+                deposit_id: TRADER_REBATE_DEPOSIT_ID,
+                reward_debt: 0,
+                reward_debt_a: 0,
+                reward_debt_b: 0,
+                pledgee: Pubkey::default(),
+                depositor: user_key,
+                relock_count: 0,
+                rent_payer: user_key,
+                tag: [0u8; 32],
+                reward_epoch: self.pool.reward_epoch,
+                matured: false,
+                claim_bounty: 0,
+                auto_relock: None,
+                bump: stake_bump,
+                listed: false,
+                held_since: now,
+            };
+            stake.try_serialize(&mut &mut stake_info.try_borrow_mut_data()?[..])?;
+
+            system_program::create_account(
+                CpiContext::new_with_signer(
+                    system_program.to_account_info(),
+                    system_program::CreateAccount {
+                        from: self.user.to_account_info(),
+                        to: vault_info.clone(),
+                    },
+                    &[&[SEED_VESTING_VAULT, pool_key.as_ref(), user_key.as_ref(), &deposit_id, &[vault_bump]]],
+                ),
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &token::ID,
+            )?;
+            let cpi_accounts = token::InitializeAccount3 {
+                account: vault_info.clone(),
+                mint: self.lp_mint.to_account_info(),
+                authority: stake_info.clone(),
+            };
+            token::initialize_account3(CpiContext::new(self.token_program.to_account_info(), cpi_accounts))?;
+        }
+
+        let mut stake = VestingStake::try_deserialize(&mut &stake_info.try_borrow_data()?[..])?;
+        if stake.claimed {
+            return Ok(None);
+        }
+        let cpi_accounts = MintTo {
+            mint: self.lp_mint.to_account_info(),
+            to: vault_info,
+            authority: self.pool.to_account_info(), // pool PDA is mint authority
+        };
+        token::mint_to(
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, pool_signer!(self.pool)),
+            rebate_lp,
+        )?;
+
+        let pool = &mut self.pool;
+        stake.amount = stake.amount.checked_add(rebate_lp).ok_or(AmmError::NumericOverflow)?;
+        stake.reward_debt += (u128::from(rebate_lp) * pool.acc_reward_per_lp) / REWARD_SCALE;
+        stake.reward_debt_a += (u128::from(rebate_lp) * pool.acc_reward_a_per_lp) / REWARD_SCALE;
+        stake.reward_debt_b += (u128::from(rebate_lp) * pool.acc_reward_b_per_lp) / REWARD_SCALE;
+        stake.vesting_end = stake.vesting_end.max(now + pool.trader_rebate_seconds);
+        stake.matured = false;
+        stake.held_since = now;
+        pool.total_locked_lp = pool
+            .total_locked_lp
+            .checked_add(rebate_lp)
+            .ok_or(AmmError::NumericOverflow)?;
+        stake.try_serialize(&mut &mut stake_info.try_borrow_mut_data()?[..])?;
+        Ok(Some(stake.vesting_end))
+    }
+
+    fn transfer_in_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_a.to_account_info().clone(),
+            to: self.reserve_a.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_in_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_b.to_account_info().clone(),
+            to: self.reserve_b.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_out_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.user_token_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_out_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.user_token_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_treasury_from_reserve_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.treasury_token_account_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_treasury_from_reserve_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.treasury_token_account_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reward_fee_from_reserve_a_context(&self) -> Result<CpiContext<'_, '_, '_, 'info, Transfer<'info>>> {
+        let reward_vault = self.reward_vault_a.as_ref().ok_or(AmmError::UnderlyingRewardAccountsMissing)?;
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: reward_vault.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        Ok(CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts))
+    }
+    fn transfer_reward_fee_from_reserve_b_context(&self) -> Result<CpiContext<'_, '_, '_, 'info, Transfer<'info>>> {
+        let reward_vault = self.reward_vault_b.as_ref().ok_or(AmmError::UnderlyingRewardAccountsMissing)?;
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: reward_vault.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        Ok(CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts))
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ScheduleAction<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 96,
+        seeds = [SEED_SCHEDULED_ACTION, pool.key().as_ref(), &pool.scheduled_action_nonce.to_le_bytes()],
+        bump
+    )]
+    pub scheduled_action: Account<'info, ScheduledAction>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelScheduledAction<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, close = creator, has_one = pool, has_one = creator)]
+    pub scheduled_action: Account<'info, ScheduledAction>,
+    pub authority: Signer<'info>,
+    /// CHECK: rent destination, checked against `scheduled_action.creator`
+    #[account(mut)]
+    pub creator: UncheckedAccount<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteScheduledAction<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, close = creator, has_one = pool, has_one = creator)]
+    pub scheduled_action: Account<'info, ScheduledAction>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    /// CHECK: rent destination, checked against `scheduled_action.creator`
+    #[account(mut)]
+    pub creator: UncheckedAccount<'info>,
+    pub executor: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_in_total: u64, num_slices: u16, min_out_total: u64, is_a_to_b: bool, order_id: u64)]
+pub struct SwapTwap<'info> {
+    #[account(seeds = [SEED_POOL, pool.lp_mint.as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 160,
+        seeds = [SEED_TWAP_ORDER, pool.key().as_ref(), user.key().as_ref(), &order_id.to_le_bytes()],
+        bump
+    )]
+    pub twap_order: Box<Account<'info, TwapOrder>>,
+    #[account(address = if is_a_to_b { pool.token_a_mint } else { pool.token_b_mint } @ AmmError::InvalidTwapOrder)]
+    pub input_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = user,
+        token::mint = input_mint,
+        token::authority = twap_order,
+        seeds = [SEED_TWAP_ESCROW, twap_order.key().as_ref()],
+        bump
+    )]
+    pub twap_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = input_mint, token::authority = user)]
+    pub user_token_in: Box<Account<'info, TokenAccount>>,
+    /// Receives every slice's output
+    #[account(
+        token::mint = if is_a_to_b { pool.token_b_mint } else { pool.token_a_mint },
+        token::authority = user
+    )]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: blocklist PDA for the trader; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    /// Caller allowlist and the instructions sysvar; required while `pool.caller_restricted`
+    #[account(has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Option<Box<Account<'info, AllowedCallers>>>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> SwapTwap<'info> {
+    fn transfer_to_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_in.to_account_info().clone(),
+            to: self.twap_escrow.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteTwapSlice<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, has_one = pool, has_one = owner, has_one = destination)]
+    pub twap_order: Box<Account<'info, TwapOrder>>,
+    #[account(mut, seeds = [SEED_TWAP_ESCROW, twap_order.key().as_ref()], bump)]
+    pub twap_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    /// CHECK: order owner, receives the rent once the last slice runs
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    /// Treasury token accounts (where treasury fees land); must match the ones stored on the pool
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, seeds = [SEED_POOL_STATS, pool.key().as_ref()], bump)]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    /// Underlying reward vaults; required in `REWARD_MODE_UNDERLYING`
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// TWAP observation buffer; every swap advances it (see `init_price_observations`)
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ExecuteTwapSlice<'info> {
+    fn order_swap<'a>(&'a mut self, escrow_signer: &'a [&'a [&'a [u8]]]) -> OrderSwapAccounts<'a, 'info> {
+        OrderSwapAccounts {
+            pool: &mut self.pool,
+            lp_mint: &self.lp_mint,
+            reserve_a: &mut self.reserve_a,
+            reserve_b: &mut self.reserve_b,
+            treasury_token_account_a: &self.treasury_token_account_a,
+            treasury_token_account_b: &self.treasury_token_account_b,
+            pool_stats: &mut self.pool_stats,
+            reward_vault_a: self.reward_vault_a.as_deref(),
+            reward_vault_b: self.reward_vault_b.as_deref(),
+            price_observations: &mut self.price_observations,
+            escrow: self.twap_escrow.to_account_info(),
+            escrow_authority: self.twap_order.to_account_info(),
+            escrow_signer,
+            destination: self.destination.to_account_info(),
+            token_program: &self.token_program,
+        }
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.twap_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.twap_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelTwapOrder<'info> {
+    #[account(mut, close = owner, has_one = owner)]
+    pub twap_order: Account<'info, TwapOrder>,
+    #[account(mut, seeds = [SEED_TWAP_ESCROW, twap_order.key().as_ref()], bump)]
+    pub twap_escrow: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = twap_escrow.mint, token::authority = owner)]
+    pub owner_token_in: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelTwapOrder<'info> {
+    fn transfer_escrow_to_owner_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.twap_escrow.to_account_info().clone(),
+            to: self.owner_token_in.to_account_info().clone(),
+            authority: self.twap_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.twap_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.twap_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_in: u64, min_price: u128, expiry: i64, is_a_to_b: bool, order_id: u64)]
+pub struct PlaceLimitOrder<'info> {
+    #[account(seeds = [SEED_POOL, pool.lp_mint.as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 160,
+        seeds = [SEED_LIMIT_ORDER, pool.key().as_ref(), user.key().as_ref(), &order_id.to_le_bytes()],
+        bump
+    )]
+    pub limit_order: Box<Account<'info, LimitOrder>>,
+    #[account(address = if is_a_to_b { pool.token_a_mint } else { pool.token_b_mint } @ AmmError::InvalidLimitOrder)]
+    pub input_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = user,
+        token::mint = input_mint,
+        token::authority = limit_order,
+        seeds = [SEED_LIMIT_ESCROW, limit_order.key().as_ref()],
+        bump
+    )]
+    pub limit_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = input_mint, token::authority = user)]
+    pub user_token_in: Box<Account<'info, TokenAccount>>,
+    /// Receives the output when the order fills
+    #[account(
+        token::mint = if is_a_to_b { pool.token_b_mint } else { pool.token_a_mint },
+        token::authority = user
+    )]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: blocklist PDA for the trader; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    /// Caller allowlist and the instructions sysvar; required while `pool.caller_restricted`
+    #[account(has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Option<Box<Account<'info, AllowedCallers>>>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> PlaceLimitOrder<'info> {
+    fn transfer_to_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_in.to_account_info().clone(),
+            to: self.limit_escrow.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FillLimitOrder<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, has_one = pool, has_one = owner, has_one = destination)]
+    pub limit_order: Box<Account<'info, LimitOrder>>,
+    #[account(mut, seeds = [SEED_LIMIT_ESCROW, limit_order.key().as_ref()], bump)]
+    pub limit_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    /// CHECK: order owner, receives the order and escrow rent
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    /// Treasury token accounts (where treasury fees land); must match the ones stored on the pool
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, seeds = [SEED_POOL_STATS, pool.key().as_ref()], bump)]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    /// Underlying reward vaults; required in `REWARD_MODE_UNDERLYING`
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// TWAP observation buffer; every swap advances it (see `init_price_observations`)
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+
+    /// Receives the tip
+    #[account(mut)]
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> FillLimitOrder<'info> {
+    fn order_swap<'a>(&'a mut self, escrow_signer: &'a [&'a [&'a [u8]]]) -> OrderSwapAccounts<'a, 'info> {
+        OrderSwapAccounts {
+            pool: &mut self.pool,
+            lp_mint: &self.lp_mint,
+            reserve_a: &mut self.reserve_a,
+            reserve_b: &mut self.reserve_b,
+            treasury_token_account_a: &self.treasury_token_account_a,
+            treasury_token_account_b: &self.treasury_token_account_b,
+            pool_stats: &mut self.pool_stats,
+            reward_vault_a: self.reward_vault_a.as_deref(),
+            reward_vault_b: self.reward_vault_b.as_deref(),
+            price_observations: &mut self.price_observations,
+            escrow: self.limit_escrow.to_account_info(),
+            escrow_authority: self.limit_order.to_account_info(),
+            escrow_signer,
+            destination: self.destination.to_account_info(),
+            token_program: &self.token_program,
+        }
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.limit_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.limit_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelLimitOrder<'info> {
+    #[account(mut, close = owner, has_one = owner)]
+    pub limit_order: Account<'info, LimitOrder>,
+    #[account(mut, seeds = [SEED_LIMIT_ESCROW, limit_order.key().as_ref()], bump)]
+    pub limit_escrow: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = limit_escrow.mint, token::authority = owner)]
+    pub owner_token_in: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelLimitOrder<'info> {
+    fn transfer_escrow_to_owner_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.limit_escrow.to_account_info().clone(),
+            to: self.owner_token_in.to_account_info().clone(),
+            authority: self.limit_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.limit_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.limit_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_per_interval: u64, interval: i64, total: u64, is_a_to_b: bool, order_id: u64)]
+pub struct CreateDcaOrder<'info> {
+    #[account(seeds = [SEED_POOL, pool.lp_mint.as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 192,
+        seeds = [SEED_DCA_ORDER, pool.key().as_ref(), user.key().as_ref(), &order_id.to_le_bytes()],
+        bump
+    )]
+    pub dca_order: Box<Account<'info, DcaOrder>>,
+    #[account(address = if is_a_to_b { pool.token_a_mint } else { pool.token_b_mint } @ AmmError::InvalidDcaOrder)]
+    pub input_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = user,
+        token::mint = input_mint,
+        token::authority = dca_order,
+        seeds = [SEED_DCA_ESCROW, dca_order.key().as_ref()],
+        bump
+    )]
+    pub dca_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = input_mint, token::authority = user)]
+    pub user_token_in: Box<Account<'info, TokenAccount>>,
+    /// Receives every fill's output
+    #[account(
+        token::mint = if is_a_to_b { pool.token_b_mint } else { pool.token_a_mint },
+        token::authority = user
+    )]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    /// CHECK: blocklist PDA for the trader; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    /// Caller allowlist and the instructions sysvar; required while `pool.caller_restricted`
+    #[account(has_one = pool, seeds = [SEED_ALLOWED_CALLERS, pool.key().as_ref()], bump)]
+    pub allowed_callers: Option<Box<Account<'info, AllowedCallers>>>,
+    /// CHECK: instructions sysvar, address-checked
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> CreateDcaOrder<'info> {
+    fn transfer_to_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_in.to_account_info().clone(),
+            to: self.dca_escrow.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ExecuteDcaFill<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        has_one = reserve_a,
+        has_one = reserve_b,
+        seeds = [SEED_POOL, lp_mint.key().as_ref()],
+        bump
+    )]
+    pub pool: Box<Account<'info, Pool>>,
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, has_one = pool, has_one = owner, has_one = destination)]
+    pub dca_order: Box<Account<'info, DcaOrder>>,
+    #[account(mut, seeds = [SEED_DCA_ESCROW, dca_order.key().as_ref()], bump)]
+    pub dca_escrow: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub destination: Box<Account<'info, TokenAccount>>,
+    /// CHECK: order owner, receives the rent once the last fill runs
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    /// Treasury token accounts (where treasury fees land); must match the ones stored on the pool
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, seeds = [SEED_POOL_STATS, pool.key().as_ref()], bump)]
+    pub pool_stats: Box<Account<'info, PoolStats>>,
+
+    /// Underlying reward vaults; required in `REWARD_MODE_UNDERLYING`
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_a_mint.as_ref()], bump)]
+    pub reward_vault_a: Option<Box<Account<'info, TokenAccount>>>,
+    #[account(mut, seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), pool.token_b_mint.as_ref()], bump)]
+    pub reward_vault_b: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// TWAP observation buffer; every swap advances it (see `init_price_observations`)
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ExecuteDcaFill<'info> {
+    fn order_swap<'a>(&'a mut self, escrow_signer: &'a [&'a [&'a [u8]]]) -> OrderSwapAccounts<'a, 'info> {
+        OrderSwapAccounts {
+            pool: &mut self.pool,
+            lp_mint: &self.lp_mint,
+            reserve_a: &mut self.reserve_a,
+            reserve_b: &mut self.reserve_b,
+            treasury_token_account_a: &self.treasury_token_account_a,
+            treasury_token_account_b: &self.treasury_token_account_b,
+            pool_stats: &mut self.pool_stats,
+            reward_vault_a: self.reward_vault_a.as_deref(),
+            reward_vault_b: self.reward_vault_b.as_deref(),
+            price_observations: &mut self.price_observations,
+            escrow: self.dca_escrow.to_account_info(),
+            escrow_authority: self.dca_order.to_account_info(),
+            escrow_signer,
+            destination: self.destination.to_account_info(),
+            token_program: &self.token_program,
+        }
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.dca_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.dca_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(stream_id: u64)]
+pub struct CreateTreasuryStream<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    /// A pool-held treasury account
+    #[account(
+        mut,
+        token::authority = pool,
+        constraint = [pool.treasury, pool.treasury_a, pool.treasury_b].contains(&treasury_source.key())
+            @ AmmError::InvalidTreasuryAccount
+    )]
+    pub treasury_source: Account<'info, TokenAccount>,
+    #[account(address = treasury_source.mint)]
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 192,
+        seeds = [SEED_TREASURY_STREAM, pool.key().as_ref(), &stream_id.to_le_bytes()],
+        bump
+    )]
+    pub treasury_stream: Account<'info, TreasuryStream>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = mint,
+        token::authority = treasury_stream,
+        seeds = [SEED_STREAM_ESCROW, treasury_stream.key().as_ref()],
+        bump
+    )]
+    pub stream_escrow: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> CreateTreasuryStream<'info> {
+    fn transfer_to_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.treasury_source.to_account_info().clone(),
+            to: self.stream_escrow.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimStream<'info> {
+    #[account(mut, has_one = recipient)]
+    pub treasury_stream: Account<'info, TreasuryStream>,
+    #[account(mut, seeds = [SEED_STREAM_ESCROW, treasury_stream.key().as_ref()], bump)]
+    pub stream_escrow: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = treasury_stream.mint, token::authority = recipient)]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub recipient: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ClaimStream<'info> {
+    fn transfer_to_recipient_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.stream_escrow.to_account_info().clone(),
+            to: self.recipient_token_account.to_account_info().clone(),
+            authority: self.treasury_stream.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.stream_escrow.to_account_info().clone(),
+            destination: self.recipient.to_account_info().clone(),
+            authority: self.treasury_stream.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelTreasuryStream<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, close = authority, has_one = pool)]
+    pub treasury_stream: Account<'info, TreasuryStream>,
+    #[account(mut, seeds = [SEED_STREAM_ESCROW, treasury_stream.key().as_ref()], bump)]
+    pub stream_escrow: Account<'info, TokenAccount>,
+    #[account(mut, address = treasury_stream.source @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_source: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = treasury_stream.mint, token::authority = treasury_stream.recipient)]
+    pub recipient_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelTreasuryStream<'info> {
+    fn transfer_from_escrow_context(&self, to: AccountInfo<'info>) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.stream_escrow.to_account_info().clone(),
+            to,
+            authority: self.treasury_stream.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.stream_escrow.to_account_info().clone(),
+            destination: self.authority.to_account_info().clone(),
+            authority: self.treasury_stream.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CreateBondMarket<'info> {
+    #[account(has_one = authority, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(init, payer = authority, space = 8 + 128, seeds = [SEED_BOND_MARKET, pool.key().as_ref()], bump)]
+    pub bond_market: Account<'info, BondMarket>,
+    #[account(
+        constraint = payout_mint.key() != lp_mint.key()
+            && payout_mint.key() != pool.token_a_mint
+            && payout_mint.key() != pool.token_b_mint @ AmmError::InvalidBondTerms
+    )]
+    pub payout_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = payout_mint,
+        token::authority = bond_market,
+        seeds = [SEED_BOND_VAULT, bond_market.key().as_ref()],
+        bump
+    )]
+    pub bond_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetBondTerms<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool, seeds = [SEED_BOND_MARKET, pool.key().as_ref()], bump)]
+    pub bond_market: Account<'info, BondMarket>,
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct FundBondMarket<'info> {
+    #[account(mut)]
+    pub bond_market: Account<'info, BondMarket>,
+    #[account(mut, seeds = [SEED_BOND_VAULT, bond_market.key().as_ref()], bump)]
+    pub bond_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = bond_market.payout_mint, token::authority = funder)]
+    pub funder_token_account: Account<'info, TokenAccount>,
+    pub funder: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> FundBondMarket<'info> {
+    fn transfer_to_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.funder_token_account.to_account_info().clone(),
+            to: self.bond_vault.to_account_info().clone(),
+            authority: self.funder.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amount_a: u64, amount_b: u64, lp_amount: u64, min_payout: u64, bond_id: u64)]
+pub struct BondTokens<'info> {
+    #[account(mut, has_one = lp_mint, has_one = reserve_a, has_one = reserve_b, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(mut)]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut)]
+    pub reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub reserve_b: Box<Account<'info, TokenAccount>>,
+    /// Receives all acquired LP (POL)
+    #[account(mut, token::mint = lp_mint, address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut, has_one = pool, seeds = [SEED_BOND_MARKET, pool.key().as_ref()], bump)]
+    pub bond_market: Box<Account<'info, BondMarket>>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 112,
+        seeds = [SEED_BOND, bond_market.key().as_ref(), user.key().as_ref(), &bond_id.to_le_bytes()],
+        bump
+    )]
+    pub bond: Box<Account<'info, Bond>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Box<Account<'info, TokenAccount>>,
+    /// Required when selling LP
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Allowlist record for the seller; only required to deposit tokens into a permissioned pool
+    #[account(seeds = [SEED_ALLOWLIST, pool.key().as_ref(), user.key().as_ref()], bump)]
+    pub allowlist_entry: Option<Box<Account<'info, AllowlistEntry>>>,
+
+    /// CHECK: blocklist PDA for the seller; required in compliance mode, empty when not blocked
+    #[account(seeds = [SEED_BLOCKLIST, user.key().as_ref()], bump)]
+    pub blocklist_entry: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> BondTokens<'info> {
+    fn transfer_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_a.to_account_info().clone(),
+            to: self.reserve_a.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.user_token_b.to_account_info().clone(),
+            to: self.reserve_b.to_account_info().clone(),
+            authority: self.user.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn mint_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+        let cpi_accounts = MintTo {
+            mint: self.lp_mint.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(), // pool PDA is mint authority
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RedeemBond<'info> {
+    pub bond_market: Account<'info, BondMarket>,
+    #[account(mut, seeds = [SEED_BOND_VAULT, bond_market.key().as_ref()], bump)]
+    pub bond_vault: Account<'info, TokenAccount>,
+    #[account(mut, has_one = bond_market, has_one = owner)]
+    pub bond: Account<'info, Bond>,
+    #[account(mut, token::mint = bond_market.payout_mint, token::authority = owner)]
+    pub owner_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RedeemBond<'info> {
+    fn transfer_payout_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.bond_vault.to_account_info().clone(),
+            to: self.owner_token_account.to_account_info().clone(),
+            authority: self.bond_market.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelDcaOrder<'info> {
+    #[account(mut, close = owner, has_one = owner)]
+    pub dca_order: Account<'info, DcaOrder>,
+    #[account(mut, seeds = [SEED_DCA_ESCROW, dca_order.key().as_ref()], bump)]
+    pub dca_escrow: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = dca_escrow.mint, token::authority = owner)]
+    pub owner_token_in: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CancelDcaOrder<'info> {
+    fn transfer_escrow_to_owner_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.dca_escrow.to_account_info().clone(),
+            to: self.owner_token_in.to_account_info().clone(),
+            authority: self.dca_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_escrow_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.dca_escrow.to_account_info().clone(),
+            destination: self.owner.to_account_info().clone(),
+            authority: self.dca_order.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ConvertTreasuryFees<'info> {
+    #[account(has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Account<'info, TokenAccount>,
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ConvertTreasuryFees<'info> {
+    fn transfer_treasury_a_to_reserve_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.treasury_token_account_a.to_account_info().clone(),
+            to: self.reserve_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_treasury_b_to_reserve_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.treasury_token_account_b.to_account_info().clone(),
+            to: self.reserve_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reserve_a_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.treasury_token_account_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reserve_b_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.treasury_token_account_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct OnlyAuthority<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetRelockBonus<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    /// Required once the pool tracks reward epochs; see `init_reward_epochs`
+    #[account(mut, has_one = pool, seeds = [SEED_REWARD_EPOCHS, pool.key().as_ref()], bump)]
+    pub reward_epochs: Option<Box<Account<'info, RewardEpochs>>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RouteTreasuryToRewards<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = pool,
+        constraint = reward_vault.key() != recovery_vault_address(&pool.key()).0 @ AmmError::InvalidRewardVault
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = lp_mint, address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Account<'info, TokenAccount>,
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RouteTreasuryToRewards<'info> {
+    fn transfer_treasury_to_rewards_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.treasury_lp_account.to_account_info().clone(),
+            to: self.reward_vault.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(lp_amount: u64, min_lp_out: u64, vesting_seconds: i64, deposit_id: u64)]
+pub struct RebalanceTreasuryLp<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [SEED_POOL, source_lp_mint.key().as_ref()],
+        bump
+    )]
+    pub source_pool: Box<Account<'info, Pool>>,
+    #[account(mut, address = source_pool.lp_mint)]
+    pub source_lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut, address = source_pool.reserve_a @ AmmError::ReserveMismatch)]
+    pub source_reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = source_pool.reserve_b @ AmmError::ReserveMismatch)]
+    pub source_reserve_b: Box<Account<'info, TokenAccount>>,
+    /// The source pool's LP treasury, held by the pool PDA
+    #[account(
+        mut,
+        token::mint = source_lp_mint,
+        token::authority = source_pool,
+        address = source_pool.treasury @ AmmError::InvalidTreasuryAccount
+    )]
+    pub source_treasury_lp: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [SEED_POOL, target_lp_mint.key().as_ref()],
+        bump
+    )]
+    pub target_pool: Box<Account<'info, Pool>>,
+    #[account(mut, address = target_pool.lp_mint)]
+    pub target_lp_mint: Box<Account<'info, Mint>>,
+    #[account(mut, address = target_pool.reserve_a @ AmmError::ReserveMismatch)]
+    pub target_reserve_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = target_pool.reserve_b @ AmmError::ReserveMismatch)]
+    pub target_reserve_b: Box<Account<'info, TokenAccount>>,
+    /// Receive the part of the withdrawn tokens that does not fit the target's ratio
+    #[account(mut, address = target_pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub target_treasury_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = target_pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub target_treasury_b: Box<Account<'info, TokenAccount>>,
+
+    /// The authority's deposit ids in the target pool; see `init_user_vesting_index`
+    #[account(
+        mut,
+        seeds = [SEED_USER_VESTING_INDEX, target_pool.key().as_ref(), authority.key().as_ref()],
+        bump,
+        constraint = user_vesting_index.pool == target_pool.key() @ AmmError::Unauthorized,
+        constraint = user_vesting_index.user == authority.key() @ AmmError::Unauthorized
+    )]
+    pub user_vesting_index: Box<Account<'info, UserVestingIndex>>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 304,
+        seeds = [SEED_VESTING, target_pool.key().as_ref(), authority.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Box<Account<'info, VestingStake>>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = target_lp_mint,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, target_pool.key().as_ref(), authority.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitInsuranceFund<'info> {
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        token::mint = mint,
+        token::authority = protocol_config,
+        seeds = [SEED_INSURANCE_FUND, mint.key().as_ref()],
+        bump
+    )]
+    pub insurance_fund: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RouteTreasuryToInsurance<'info> {
+    #[account(mut)]
+    pub pool: Box<Account<'info, Pool>>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_INSURANCE_FUND, pool.token_a_mint.as_ref()], bump)]
+    pub insurance_fund_a: Box<Account<'info, TokenAccount>>,
+    #[account(mut, seeds = [SEED_INSURANCE_FUND, pool.token_b_mint.as_ref()], bump)]
+    pub insurance_fund_b: Box<Account<'info, TokenAccount>>,
+    pub cranker: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> RouteTreasuryToInsurance<'info> {
+    /// Move `insurance_bps` of a pool-held treasury account into its insurance fund vault.
+    fn route_to_insurance(
+        &self,
+        treasury: &Account<'info, TokenAccount>,
+        insurance_fund: &Account<'info, TokenAccount>,
+        insurance_bps: u16,
+    ) -> Result<u64> {
+        if treasury.owner != self.pool.key() {
+            return Ok(0);
+        }
+        let amount = (u128::from(treasury.amount) * u128::from(insurance_bps) / 10_000u128) as u64;
+        if amount > 0 {
+            let cpi_accounts = Transfer {
+                from: treasury.to_account_info(),
+                to: insurance_fund.to_account_info(),
+                authority: self.pool.to_account_info(),
+            };
+            token::transfer(
+                CpiContext::new_with_signer(
+                    self.token_program.to_account_info(),
+                    cpi_accounts,
+                    pool_signer!(self.pool),
+                ),
+                amount,
+            )?;
+        }
+        Ok(amount)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitInsuranceCouncil<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(init, payer = admin, space = 8 + 160, seeds = [SEED_INSURANCE_COUNCIL], bump)]
+    pub insurance_council: Account<'info, InsuranceCouncil>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(incident_id: [u8; 32])]
+pub struct ProposeInsurancePayout<'info> {
+    #[account(seeds = [SEED_INSURANCE_COUNCIL], bump)]
+    pub insurance_council: Account<'info, InsuranceCouncil>,
+    #[account(init, payer = member, space = 8 + 112, seeds = [SEED_INSURANCE_PAYOUT, incident_id.as_ref()], bump)]
+    pub insurance_payout: Account<'info, InsurancePayout>,
+    #[account(seeds = [SEED_INSURANCE_FUND, insurance_fund.mint.as_ref()], bump)]
+    pub insurance_fund: Account<'info, TokenAccount>,
+    #[account(token::mint = insurance_fund.mint)]
+    pub recipient: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub member: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(incident_id: [u8; 32])]
+pub struct ApproveInsurancePayout<'info> {
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(seeds = [SEED_INSURANCE_COUNCIL], bump)]
+    pub insurance_council: Account<'info, InsuranceCouncil>,
+    #[account(
+        mut,
+        has_one = insurance_fund,
+        has_one = recipient,
+        seeds = [SEED_INSURANCE_PAYOUT, incident_id.as_ref()],
+        bump
+    )]
+    pub insurance_payout: Account<'info, InsurancePayout>,
+    #[account(mut)]
+    pub insurance_fund: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub recipient: Account<'info, TokenAccount>,
+    pub member: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ApproveInsurancePayout<'info> {
+    fn transfer_to_recipient_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.insurance_fund.to_account_info().clone(),
+            to: self.recipient.to_account_info().clone(),
+            authority: self.protocol_config.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitGaugeController<'info> {
+    #[account(has_one = admin, seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(init, payer = admin, space = 8 + 128, seeds = [SEED_GAUGE_CONTROLLER], bump)]
+    pub gauge_controller: Account<'info, GaugeController>,
+    pub reward_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = admin,
+        token::mint = reward_mint,
+        token::authority = gauge_controller,
+        seeds = [SEED_GAUGE_VAULT],
+        bump
+    )]
+    pub gauge_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct GaugeAdmin<'info> {
+    #[account(mut, has_one = admin, seeds = [SEED_GAUGE_CONTROLLER], bump)]
+    pub gauge_controller: Account<'info, GaugeController>,
+    pub admin: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct AddPoolGauge<'info> {
+    #[account(mut, has_one = admin, seeds = [SEED_GAUGE_CONTROLLER], bump)]
+    pub gauge_controller: Account<'info, GaugeController>,
+    pub admin: Signer<'info>,
+    /// The pool authority consents to giving up control of the pool's emission
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(init, payer = payer, space = 8 + 64, seeds = [SEED_POOL_GAUGE, pool.key().as_ref()], bump)]
+    pub pool_gauge: Account<'info, PoolGauge>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetPoolGaugeWeight<'info> {
+    #[account(mut, has_one = admin, seeds = [SEED_GAUGE_CONTROLLER], bump)]
+    pub gauge_controller: Account<'info, GaugeController>,
+    pub admin: Signer<'info>,
+    #[account(mut, seeds = [SEED_POOL_GAUGE, pool_gauge.pool.as_ref()], bump)]
+    pub pool_gauge: Account<'info, PoolGauge>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SyncPoolGauge<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(seeds = [SEED_GAUGE_CONTROLLER], bump)]
+    pub gauge_controller: Account<'info, GaugeController>,
+    #[account(mut, has_one = pool, seeds = [SEED_POOL_GAUGE, pool.key().as_ref()], bump)]
+    pub pool_gauge: Account<'info, PoolGauge>,
+    #[account(mut, seeds = [SEED_GAUGE_VAULT], bump)]
+    pub gauge_vault: Account<'info, TokenAccount>,
+    /// The pool's underlying reward vault for the gauge's reward mint
+    #[account(
+        mut,
+        seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), gauge_controller.reward_mint.as_ref()],
+        bump
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> SyncPoolGauge<'info> {
+    fn transfer_to_reward_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.gauge_vault.to_account_info(),
+            to: self.reward_vault.to_account_info(),
+            authority: self.gauge_controller.to_account_info(),
+        };
+        CpiContext::new(self.token_program.to_account_info(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetCurveType<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint, has_one = token_a_mint, has_one = token_b_mint)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitRecoveryVault<'info> {
+    #[account(has_one = lp_mint, seeds = [SEED_POOL, lp_mint.key().as_ref()], bump)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        token::mint = lp_mint,
+        token::authority = pool,
+        seeds = [SEED_RECOVERY_VAULT, pool.key().as_ref()],
+        bump
+    )]
+    pub recovery_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct InitUnderlyingRewardVaults<'info> {
+    #[account(has_one = authority, has_one = token_a_mint, has_one = token_b_mint)]
+    pub pool: Account<'info, Pool>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_a_mint,
+        token::authority = pool,
+        seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), token_a_mint.key().as_ref()],
+        bump
+    )]
+    pub reward_vault_a: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = authority,
+        token::mint = token_b_mint,
+        token::authority = pool,
+        seeds = [SEED_UNDERLYING_REWARD_VAULT, pool.key().as_ref(), token_b_mint.key().as_ref()],
+        bump
+    )]
+    pub reward_vault_b: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetTreasuryAccounts<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint, has_one = token_a_mint, has_one = token_b_mint)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    pub lp_mint: Account<'info, Mint>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+    #[account(token::mint = lp_mint)]
+    pub treasury: Account<'info, TokenAccount>,
+    #[account(token::mint = token_a_mint)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    #[account(token::mint = token_b_mint)]
+    pub treasury_token_account_b: Account<'info, TokenAccount>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateEmission<'info> {
+    #[account(
+        mut,
+        has_one = lp_mint,
+        constraint = operator.key() == pool.authority || operator.key() == pool.keeper @ AmmError::Unauthorized
+    )]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub operator: Signer<'info>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ReconcileRewardVault<'info> {
+    #[account(mut, has_one = authority, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    pub lp_mint: Account<'info, Mint>,
+    /// Must not be the recovery vault, which also holds pool-owned LP that is owed to users
+    #[account(
+        mut,
+        token::mint = lp_mint,
+        token::authority = pool,
+        constraint = reward_vault.key() != recovery_vault_address(&pool.key()).0 @ AmmError::InvalidRewardVault
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = lp_mint, address = pool.treasury @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_lp_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ReconcileRewardVault<'info> {
+    fn transfer_surplus_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reward_vault.to_account_info().clone(),
+            to: self.treasury_lp_account.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct UpdateFees<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SetEarlyUnvestPenalty<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+/// Read-only accounts for `get_pool_config`.
+#[derive(Accounts)]
+pub struct GetPoolConfig<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+}
+
+/// Read-only accounts for `get_position_value`.
+#[derive(Accounts)]
+pub struct GetPositionValue<'info> {
+    #[account(has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(has_one = pool)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    /// Approved hardship request, so the projected penalty reflects the waiver
+    #[account(has_one = vesting_stake, seeds = [SEED_HARDSHIP, vesting_stake.key().as_ref()], bump)]
+    pub hardship_request: Option<Account<'info, HardshipRequest>>,
+    /// Pool-wide penalty waiver vote, so the projected penalty reflects a passed waiver
+    #[account(has_one = pool, seeds = [SEED_PENALTY_WAIVER, pool.key().as_ref()], bump)]
+    pub penalty_waiver: Option<Box<Account<'info, PenaltyWaiverVote>>>,
+}
+
+/// Read-only accounts for `get_fair_lp_price`.
+#[derive(Accounts)]
+pub struct GetFairLpPrice<'info> {
+    #[account(has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+    /// Optional position to value (e.g. one pledged as collateral)
+    #[account(has_one = pool)]
+    pub vesting_stake: Option<Account<'info, VestingStake>>,
+}
+
+/// Read-only accounts for `quote` and `get_amm_info`.
+#[derive(Accounts)]
+pub struct QuotePool<'info> {
+    #[account(has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    /// TWAP reference for the rebalance fee when no oracle price is set
+    #[account(has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Option<Box<Account<'info, PriceObservations>>>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CheckDepeg<'info> {
+    #[account(mut, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, has_one = pool, seeds = [SEED_PRICE_OBSERVATIONS, pool.key().as_ref()], bump)]
+    pub price_observations: Box<Account<'info, PriceObservations>>,
+}
+
+/// Authority or keeper. Used for operational instructions that must not require the admin key.
+#[event_cpi]
+#[derive(Accounts)]
+pub struct OnlyOperator<'info> {
+    #[account(
+        mut,
+        constraint = operator.key() == pool.authority || operator.key() == pool.keeper @ AmmError::Unauthorized
+    )]
+    pub pool: Account<'info, Pool>,
+    pub operator: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(user: Pubkey)]
+pub struct AddToAllowlist<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 64,
+        seeds = [SEED_ALLOWLIST, pool.key().as_ref(), user.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EndSwapAllowlist<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    pub caller: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(trader: Pubkey)]
+pub struct AddMarketMaker<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 72,
+        seeds = [SEED_MARKET_MAKER, pool.key().as_ref(), trader.as_ref()],
+        bump
+    )]
+    pub market_maker_entry: Account<'info, MarketMakerEntry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveMarketMaker<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        close = authority,
+        has_one = pool,
+        seeds = [SEED_MARKET_MAKER, pool.key().as_ref(), market_maker_entry.trader.as_ref()],
+        bump
+    )]
+    pub market_maker_entry: Account<'info, MarketMakerEntry>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemoveFromAllowlist<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        close = authority,
+        has_one = pool,
+        seeds = [SEED_ALLOWLIST, pool.key().as_ref(), allowlist_entry.user.as_ref()],
+        bump
+    )]
+    pub allowlist_entry: Account<'info, AllowlistEntry>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EmergencyWithdraw<'info> {
+    #[account(mut, has_one = authority, has_one = reserve_a, has_one = reserve_b, has_one = lp_mint)]
+    pub pool: Account<'info, Pool>,
+    pub authority: Signer<'info>,
+    #[account(mut, token::mint = token_a_mint)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint)]
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_a_mint, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_b_mint, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub token_a_mint: Account<'info, Mint>,
+    pub token_b_mint: Account<'info, Mint>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+
+    /// This program's upgradeable-loader program data (holds the upgrade authority) and the
+    /// co-signer; required above `protocol_config.cosign_lp_threshold`
+    #[account(seeds = [crate::ID.as_ref()], bump, seeds::program = bpf_loader_upgradeable::ID)]
+    pub program_data: Option<Account<'info, ProgramData>>,
+    pub cosigner: Option<Signer<'info>>,
+}
+
+impl<'info> EmergencyWithdraw<'info> {
+    fn transfer_reserve_a_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.treasury_token_account_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reserve_b_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.treasury_token_account_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct StartEmergencyDrip<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 72,
+        seeds = [SEED_EMERGENCY_DRIP, pool.key().as_ref()],
+        bump
+    )]
+    pub emergency_drip: Account<'info, EmergencyDrip>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CrankEmergencyDrip<'info> {
+    #[account(
+        has_one = reserve_a,
+        has_one = reserve_b,
+        constraint = operator.key() == pool.authority || operator.key() == pool.keeper @ AmmError::Unauthorized
+    )]
+    pub pool: Account<'info, Pool>,
+    pub operator: Signer<'info>,
+    #[account(mut, has_one = pool, seeds = [SEED_EMERGENCY_DRIP, pool.key().as_ref()], bump)]
+    pub emergency_drip: Account<'info, EmergencyDrip>,
+    #[account(mut)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub reserve_b: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.treasury_a @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_a: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.treasury_b @ AmmError::InvalidTreasuryAccount)]
+    pub treasury_token_account_b: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> CrankEmergencyDrip<'info> {
+    fn transfer_reserve_a_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.treasury_token_account_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_reserve_b_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.treasury_token_account_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CancelEmergencyDrip<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, close = authority, has_one = pool, seeds = [SEED_EMERGENCY_DRIP, pool.key().as_ref()], bump)]
+    pub emergency_drip: Account<'info, EmergencyDrip>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RegisterPoolHook<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 96,
+        seeds = [SEED_POOL_HOOK, pool.key().as_ref()],
+        bump
+    )]
+    pub pool_hook: Account<'info, PoolHook>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct RemovePoolHook<'info> {
+    #[account(mut, has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, close = authority, has_one = pool, seeds = [SEED_POOL_HOOK, pool.key().as_ref()], bump)]
+    pub pool_hook: Account<'info, PoolHook>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CheckPoolHealth<'info> {
+    #[account(mut, has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    pub lp_mint: Account<'info, Mint>,
+    pub reserve_a: Account<'info, TokenAccount>,
+    pub reserve_b: Account<'info, TokenAccount>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitEmergencyCouncil<'info> {
+    #[account(has_one = authority)]
+    pub pool: Account<'info, Pool>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 288,
+        seeds = [SEED_EMERGENCY_COUNCIL, pool.key().as_ref()],
+        bump
+    )]
+    pub emergency_council: Account<'info, EmergencyCouncil>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct CouncilPropose<'info> {
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool, seeds = [SEED_EMERGENCY_COUNCIL, pool.key().as_ref()], bump)]
+    pub emergency_council: Account<'info, EmergencyCouncil>,
+    #[account(
+        init,
+        payer = member,
         space = 8 + 128,
-        seeds = [b"vesting", pool.key().as_ref(), user.key().as_ref(), &pool.vesting_nonce.to_le_bytes()],
+        seeds = [
+            SEED_COUNCIL_PROPOSAL,
+            emergency_council.key().as_ref(),
+            &emergency_council.proposal_count.to_le_bytes()
+        ],
+        bump
+    )]
+    pub council_proposal: Account<'info, CouncilProposal>,
+    #[account(mut)]
+    pub member: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(proposal_id: u64)]
+pub struct CouncilApprove<'info> {
+    #[account(mut)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, has_one = pool, seeds = [SEED_EMERGENCY_COUNCIL, pool.key().as_ref()], bump)]
+    pub emergency_council: Account<'info, EmergencyCouncil>,
+    #[account(
+        mut,
+        seeds = [SEED_COUNCIL_PROPOSAL, emergency_council.key().as_ref(), &proposal_id.to_le_bytes()],
+        bump
+    )]
+    pub council_proposal: Account<'info, CouncilProposal>,
+    pub member: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct EmergencyExit<'info> {
+    #[account(mut, has_one = lp_mint, has_one = reserve_a, has_one = reserve_b)]
+    pub pool: Account<'info, Pool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub reserve_a: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub reserve_b: Account<'info, TokenAccount>,
+
+    #[account(mut, close = rent_payer, has_one = pool, has_one = user, has_one = rent_payer)]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        mut,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, pool.key().as_ref(), vesting_stake.depositor.as_ref(), &vesting_stake.deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(mut, token::mint = pool.token_a_mint, token::authority = user)]
+    pub user_token_a: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token_b_mint, token::authority = user)]
+    pub user_token_b: Account<'info, TokenAccount>,
+
+    /// Co-signer required while the position is pledged
+    pub pledgee: Option<Signer<'info>>,
+
+    /// CHECK: rent refund destination, checked against `vesting_stake.rent_payer`
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> EmergencyExit<'info> {
+    fn burn_vesting_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let cpi_accounts = Burn {
+            mint: self.lp_mint.to_account_info().clone(),
+            from: self.vesting_token_account.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn close_vesting_vault_context(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: self.vesting_token_account.to_account_info().clone(),
+            destination: self.rent_payer.to_account_info().clone(),
+            authority: self.vesting_stake.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_a_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_a.to_account_info().clone(),
+            to: self.user_token_a.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+    fn transfer_b_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+        let cpi_accounts = Transfer {
+            from: self.reserve_b.to_account_info().clone(),
+            to: self.user_token_b.to_account_info().clone(),
+            authority: self.pool.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    }
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct InitializeMultiPool<'info> {
+    #[account(seeds = [SEED_PROTOCOL_CONFIG], bump)]
+    pub protocol_config: Account<'info, ProtocolConfig>,
+    #[account(init, payer = authority, space = 8 + 352, seeds = [SEED_MULTI_POOL, lp_mint.key().as_ref()], bump)]
+    pub multi_pool: Account<'info, MultiPool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+#[instruction(amounts: Vec<u64>, vesting_seconds: i64, min_lp_minted: u64, deposit_id: u64)]
+pub struct DepositMultiAndVest<'info> {
+    #[account(mut, has_one = lp_mint, seeds = [SEED_MULTI_POOL, lp_mint.key().as_ref()], bump)]
+    pub multi_pool: Account<'info, MultiPool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 304,
+        seeds = [SEED_VESTING, multi_pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
+        bump
+    )]
+    pub vesting_stake: Account<'info, VestingStake>,
+    #[account(
+        init,
+        payer = user,
+        token::mint = lp_mint,
+        token::authority = vesting_stake,
+        seeds = [SEED_VESTING_VAULT, multi_pool.key().as_ref(), user.key().as_ref(), &deposit_id.to_le_bytes()],
         bump
     )]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct ClaimMultiVested<'info> {
+    #[account(mut, has_one = lp_mint)]
+    pub multi_pool: Account<'info, MultiPool>,
+    pub lp_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        close = user,
+        has_one = user,
+        constraint = vesting_stake.pool == multi_pool.key() @ AmmError::InvalidVestingAccount
+    )]
     pub vesting_stake: Account<'info, VestingStake>,
+    #[account(mut, token::authority = vesting_stake)]
+    pub vesting_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct OnlyMultiPoolAuthority<'info> {
+    #[account(mut, has_one = authority)]
+    pub multi_pool: Account<'info, MultiPool>,
+    pub authority: Signer<'info>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct WithdrawMulti<'info> {
+    #[account(has_one = lp_mint)]
+    pub multi_pool: Account<'info, MultiPool>,
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = lp_mint, token::authority = user)]
+    pub user_lp_token_account: Account<'info, TokenAccount>,
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event_cpi]
+#[derive(Accounts)]
+pub struct SwapMulti<'info> {
+    pub multi_pool: Account<'info, MultiPool>,
+    pub user: Signer<'info>,
+    #[account(mut, token::authority = user)]
+    pub user_source: Account<'info, TokenAccount>,
+    #[account(mut, token::authority = user)]
+    pub user_destination: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+// ---------------------- PDAs ----------------------
+
+/// Seed constants and address derivations for every PDA the program uses. The account
+/// constraints, signer seeds and off-chain clients all go through these, so they cannot drift.
+pub mod pda {
+    use super::*;
+
+    pub const SEED_ALLOWED_CALLERS: &[u8] = b"allowed_callers";
+    pub const SEED_ALLOWLIST: &[u8] = b"allowlist";
+    pub const SEED_BLOCKLIST: &[u8] = b"blocklist";
+    pub const SEED_BOND: &[u8] = b"bond";
+    pub const SEED_BOND_MARKET: &[u8] = b"bond_market";
+    pub const SEED_BOND_VAULT: &[u8] = b"bond_vault";
+    pub const SEED_CLAIM_RECEIPT: &[u8] = b"claim_receipt";
+    pub const SEED_DCA_ESCROW: &[u8] = b"dca_escrow";
+    pub const SEED_DCA_ORDER: &[u8] = b"dca_order";
+    pub const SEED_EMERGENCY_COUNCIL: &[u8] = b"emergency_council";
+    pub const SEED_COUNCIL_PROPOSAL: &[u8] = b"council_proposal";
+    pub const SEED_EMERGENCY_DRIP: &[u8] = b"emergency_drip";
+    pub const SEED_EPOCH_SNAPSHOT: &[u8] = b"epoch_snapshot";
+    pub const SEED_FEE_VOTE: &[u8] = b"fee_vote";
+    pub const SEED_FEE_VOTE_RECORD: &[u8] = b"fee_vote_record";
+    pub const SEED_GAUGE_CONTROLLER: &[u8] = b"gauge_controller";
+    pub const SEED_GAUGE_VAULT: &[u8] = b"gauge_vault";
+    pub const SEED_GLOBAL_STATS: &[u8] = b"global_stats";
+    pub const SEED_HARDSHIP: &[u8] = b"hardship";
+    pub const SEED_INSURANCE_FUND: &[u8] = b"insurance_fund";
+    pub const SEED_INSURANCE_COUNCIL: &[u8] = b"insurance_council";
+    pub const SEED_INSURANCE_PAYOUT: &[u8] = b"insurance_payout";
+    pub const SEED_LIMIT_ESCROW: &[u8] = b"limit_escrow";
+    pub const SEED_LIMIT_ORDER: &[u8] = b"limit_order";
+    pub const SEED_LISTING: &[u8] = b"listing";
+    pub const SEED_MARKET_MAKER: &[u8] = b"market_maker";
+    pub const SEED_METADATA: &[u8] = b"metadata"; // Metaplex token-metadata seed, derived under its program id
+    pub const SEED_MULTI_POOL: &[u8] = b"multi_pool";
+    pub const SEED_NOTIFY: &[u8] = b"notify";
+    pub const SEED_PENALTY_WAIVER: &[u8] = b"penalty_waiver";
+    pub const SEED_PENALTY_WAIVER_BALLOT: &[u8] = b"penalty_waiver_ballot";
+    pub const SEED_POOL: &[u8] = b"pool";
+    pub const SEED_POOL_GAUGE: &[u8] = b"pool_gauge";
+    pub const SEED_POOL_HOOK: &[u8] = b"pool_hook";
+    pub const SEED_POOL_STATS: &[u8] = b"pool_stats";
+    pub const SEED_POOL_VIEW: &[u8] = b"pool_view";
+    pub const SEED_PORTFOLIO: &[u8] = b"portfolio";
+    pub const SEED_PRICE_OBSERVATIONS: &[u8] = b"price_observations";
+    pub const SEED_PROTOCOL_CONFIG: &[u8] = b"protocol_config";
+    pub const SEED_RECOVERY: &[u8] = b"recovery";
+    pub const SEED_RECOVERY_VAULT: &[u8] = b"recovery_vault";
+    pub const SEED_REWARD_DESTINATION: &[u8] = b"reward_destination";
+    pub const SEED_REWARD_EPOCHS: &[u8] = b"reward_epochs";
+    pub const SEED_REWARD_LEDGER: &[u8] = b"reward_ledger";
+    pub const SEED_SCHEDULED_ACTION: &[u8] = b"scheduled_action";
+    pub const SEED_STREAM_ESCROW: &[u8] = b"stream_escrow";
+    pub const SEED_TOKEN_BADGE: &[u8] = b"token_badge";
+    pub const SEED_TRADER_POINTS: &[u8] = b"trader_points";
+    pub const SEED_TREASURY_STREAM: &[u8] = b"treasury_stream";
+    pub const SEED_TWAP_ESCROW: &[u8] = b"twap_escrow";
+    pub const SEED_TWAP_ORDER: &[u8] = b"twap_order";
+    pub const SEED_UNDERLYING_REWARD_VAULT: &[u8] = b"underlying_reward_vault";
+    pub const SEED_USED_VOUCHER: &[u8] = b"used_voucher";
+    pub const SEED_USER_VESTING_INDEX: &[u8] = b"user_vesting_index";
+    pub const SEED_VESTING: &[u8] = b"vesting";
+    pub const SEED_VESTING_VAULT: &[u8] = b"vesting_vault";
+    pub const SEED_VOTER_WEIGHT: &[u8] = b"voter_weight";
+
+    pub fn protocol_config_address() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_PROTOCOL_CONFIG], &ID)
+    }
+
+    pub fn pool_address(lp_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_POOL, lp_mint.as_ref()], &ID)
+    }
+
+    /// Conventional reserve address: the pool PDA's associated token account for `mint`, which
+    /// clients can create before `initialize_pool` since the pool address is known up front.
+    pub fn reserve_address(pool: &Pubkey, mint: &Pubkey) -> Pubkey {
+        get_associated_token_address(pool, mint)
+    }
+
+    pub fn twap_order_address(pool: &Pubkey, owner: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_TWAP_ORDER, pool.as_ref(), owner.as_ref(), &order_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn twap_escrow_address(twap_order: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_TWAP_ESCROW, twap_order.as_ref()], &ID)
+    }
+
+    pub fn limit_order_address(pool: &Pubkey, owner: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_LIMIT_ORDER, pool.as_ref(), owner.as_ref(), &order_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn limit_escrow_address(limit_order: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_LIMIT_ESCROW, limit_order.as_ref()], &ID)
+    }
+
+    pub fn global_stats_address() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_GLOBAL_STATS], &ID)
+    }
+
+    pub fn gauge_controller_address() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_GAUGE_CONTROLLER], &ID)
+    }
+
+    pub fn gauge_vault_address() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_GAUGE_VAULT], &ID)
+    }
+
+    pub fn pool_gauge_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_POOL_GAUGE, pool.as_ref()], &ID)
+    }
+
+    pub fn dca_order_address(pool: &Pubkey, owner: &Pubkey, order_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_DCA_ORDER, pool.as_ref(), owner.as_ref(), &order_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn dca_escrow_address(dca_order: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_DCA_ESCROW, dca_order.as_ref()], &ID)
+    }
+
+    pub fn treasury_stream_address(pool: &Pubkey, stream_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_TREASURY_STREAM, pool.as_ref(), &stream_id.to_le_bytes()], &ID)
+    }
+
+    pub fn stream_escrow_address(treasury_stream: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_STREAM_ESCROW, treasury_stream.as_ref()], &ID)
+    }
+
+    pub fn bond_market_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_BOND_MARKET, pool.as_ref()], &ID)
+    }
+
+    pub fn bond_vault_address(bond_market: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_BOND_VAULT, bond_market.as_ref()], &ID)
+    }
+
+    pub fn bond_address(bond_market: &Pubkey, owner: &Pubkey, bond_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_BOND, bond_market.as_ref(), owner.as_ref(), &bond_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn penalty_waiver_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_PENALTY_WAIVER, pool.as_ref()], &ID)
+    }
+
+    pub fn penalty_waiver_ballot_address(penalty_waiver: &Pubkey, vesting_stake: &Pubkey, opened_at: i64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_PENALTY_WAIVER_BALLOT, penalty_waiver.as_ref(), vesting_stake.as_ref(), &opened_at.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn fee_vote_address(pool: &Pubkey, round: u32) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_FEE_VOTE, pool.as_ref(), &round.to_le_bytes()], &ID)
+    }
+
+    pub fn fee_vote_record_address(fee_vote: &Pubkey, vesting_stake: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_FEE_VOTE_RECORD, fee_vote.as_ref(), vesting_stake.as_ref()], &ID)
+    }
+
+    pub fn voter_weight_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_VOTER_WEIGHT, pool.as_ref(), user.as_ref()], &ID)
+    }
+
+    pub fn epoch_snapshot_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_EPOCH_SNAPSHOT, pool.as_ref()], &ID)
+    }
+
+    pub fn blocklist_address(address: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_BLOCKLIST, address.as_ref()], &ID)
+    }
+
+    pub fn pool_stats_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_POOL_STATS, pool.as_ref()], &ID)
+    }
+
+    pub fn vesting_address(pool: &Pubkey, user: &Pubkey, deposit_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_VESTING, pool.as_ref(), user.as_ref(), &deposit_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn vesting_vault_address(pool: &Pubkey, user: &Pubkey, deposit_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_VESTING_VAULT, pool.as_ref(), user.as_ref(), &deposit_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn allowlist_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_ALLOWLIST, pool.as_ref(), user.as_ref()], &ID)
+    }
+
+    pub fn insurance_fund_address(mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_INSURANCE_FUND, mint.as_ref()], &ID)
+    }
+
+    pub fn insurance_council_address() -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_INSURANCE_COUNCIL], &ID)
+    }
+
+    pub fn insurance_payout_address(incident_id: &[u8; 32]) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_INSURANCE_PAYOUT, incident_id.as_ref()], &ID)
+    }
+
+    pub fn recovery_vault_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_RECOVERY_VAULT, pool.as_ref()], &ID)
+    }
+
+    pub fn underlying_reward_vault_address(pool: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_UNDERLYING_REWARD_VAULT, pool.as_ref(), mint.as_ref()], &ID)
+    }
+
+    pub fn listing_address(vesting_stake: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_LISTING, vesting_stake.as_ref()], &ID)
+    }
+
+    pub fn scheduled_action_address(pool: &Pubkey, id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_SCHEDULED_ACTION, pool.as_ref(), &id.to_le_bytes()], &ID)
+    }
+
+    pub fn user_vesting_index_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_USER_VESTING_INDEX, pool.as_ref(), user.as_ref()], &ID)
+    }
+
+    pub fn pool_view_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_POOL_VIEW, pool.as_ref()], &ID)
+    }
+
+    pub fn reward_destination_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_REWARD_DESTINATION, pool.as_ref(), user.as_ref()], &ID)
+    }
+
+    pub fn reward_ledger_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_REWARD_LEDGER, pool.as_ref()], &ID)
+    }
+
+    pub fn reward_epochs_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_REWARD_EPOCHS, pool.as_ref()], &ID)
+    }
+
+    pub fn claim_receipt_address(pool: &Pubkey, depositor: &Pubkey, deposit_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_CLAIM_RECEIPT, pool.as_ref(), depositor.as_ref(), &deposit_id.to_le_bytes()],
+            &ID,
+        )
+    }
+
+    pub fn emergency_drip_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_EMERGENCY_DRIP, pool.as_ref()], &ID)
+    }
+
+    pub fn pool_hook_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_POOL_HOOK, pool.as_ref()], &ID)
+    }
+
+    pub fn token_badge_address(mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_TOKEN_BADGE, mint.as_ref()], &ID)
+    }
+
+    pub fn emergency_council_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_EMERGENCY_COUNCIL, pool.as_ref()], &ID)
+    }
+
+    pub fn council_proposal_address(council: &Pubkey, proposal_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_COUNCIL_PROPOSAL, council.as_ref(), &proposal_id.to_le_bytes()], &ID)
+    }
 
-    /// Vesting token account to hold LP tokens. Program creates it and sets authority to the vesting PDA.
-    #[account(
-        init,
-        payer = user,
-        token::mint = lp_mint,
-        token::authority = vesting_stake,
-        seeds = [b"vesting_vault", pool.key().as_ref(), user.key().as_ref(), &pool.vesting_nonce.to_le_bytes()],
-        bump
-    )]
-    pub vesting_token_account: Account<'info, TokenAccount>,
+    pub fn notify_registration_address(vesting_stake: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_NOTIFY, vesting_stake.as_ref()], &ID)
+    }
 
-    /// Reward vault (optional) where reward LP tokens are stored for distribution
-    #[account(mut, token::mint = lp_mint)]
-    pub reward_vault: Account<'info, TokenAccount>,
+    pub fn used_voucher_address(user: &Pubkey, nonce: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_USED_VOUCHER, user.as_ref(), &nonce.to_le_bytes()], &ID)
+    }
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+    pub fn allowed_callers_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_ALLOWED_CALLERS, pool.as_ref()], &ID)
+    }
+
+    pub fn trader_points_address(pool: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_TRADER_POINTS, pool.as_ref(), user.as_ref()], &ID)
+    }
+
+    pub fn portfolio_address(user: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_PORTFOLIO, user.as_ref()], &ID)
+    }
+
+    pub fn price_observations_address(pool: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_PRICE_OBSERVATIONS, pool.as_ref()], &ID)
+    }
+
+    pub fn hardship_request_address(vesting_stake: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_HARDSHIP, vesting_stake.as_ref()], &ID)
+    }
+
+    pub fn multi_pool_address(lp_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_MULTI_POOL, lp_mint.as_ref()], &ID)
+    }
+
+    pub fn lp_metadata_address(lp_mint: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_METADATA, mpl_token_metadata::ID.as_ref(), lp_mint.as_ref()],
+            &mpl_token_metadata::ID,
+        )
+    }
+
+    pub fn market_maker_address(pool: &Pubkey, trader: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[SEED_MARKET_MAKER, pool.as_ref(), trader.as_ref()], &ID)
+    }
+
+    pub fn recovery_claim_address(pool: &Pubkey, user: &Pubkey, deposit_id: u64) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[SEED_RECOVERY, pool.as_ref(), user.as_ref(), &deposit_id.to_le_bytes()],
+            &ID,
+        )
+    }
 }
 
-impl<'info> DepositAndVest<'info> {
-    fn transfer_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.user_token_a.to_account_info().clone(),
-            to: self.reserve_a.to_account_info().clone(),
-            authority: self.user.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+// ---------------------- Helpers ----------------------
+
+/// Value-conservation assertions, compiled in only with the `accounting-checks` feature for CI
+/// runs of the integration tests. An instrumented instruction snapshots the pool-side balances of
+/// token A and token B (reserve + treasury + underlying reward vault) and, where LP is minted or
+/// burned, the LP supply; after its transfers it asserts each moved by exactly what the user put
+/// in or took out. A mismatch panics, failing the transaction with both numbers in the logs.
+#[cfg(feature = "accounting-checks")]
+mod accounting {
+    use super::*;
+
+    pub struct Snapshot<'info> {
+        side_a: Vec<AccountInfo<'info>>,
+        side_b: Vec<AccountInfo<'info>>,
+        lp_mint: Option<AccountInfo<'info>>,
+        a: i128,
+        b: i128,
+        lp_supply: i128,
     }
-    fn transfer_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.user_token_b.to_account_info().clone(),
-            to: self.reserve_b.to_account_info().clone(),
-            authority: self.user.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+
+    fn token_amount(info: &AccountInfo) -> i128 {
+        if info.data_is_empty() {
+            return 0;
+        }
+        let data = info.try_borrow_data().expect("token account data borrowed");
+        TokenAccount::try_deserialize(&mut &data[..]).map_or(0, |account| i128::from(account.amount))
     }
 
-    fn mint_to_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
-        let cpi_accounts = MintTo {
-            mint: self.lp_mint.to_account_info().clone(),
-            to: self.vesting_token_account.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(), // pool PDA is mint authority
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    fn side_total(side: &[AccountInfo]) -> i128 {
+        side.iter().map(token_amount).sum()
+    }
+
+    fn mint_supply(info: Option<&AccountInfo>) -> i128 {
+        info.map_or(0, |info| {
+            let data = info.try_borrow_data().expect("mint data borrowed");
+            Mint::try_deserialize(&mut &data[..]).map_or(0, |mint| i128::from(mint.supply))
+        })
+    }
+
+    impl<'info> Snapshot<'info> {
+        pub fn take(
+            side_a: Vec<AccountInfo<'info>>,
+            side_b: Vec<AccountInfo<'info>>,
+            lp_mint: Option<AccountInfo<'info>>,
+        ) -> Self {
+            Snapshot {
+                a: side_total(&side_a),
+                b: side_total(&side_b),
+                lp_supply: mint_supply(lp_mint.as_ref()),
+                side_a,
+                side_b,
+                lp_mint,
+            }
+        }
+
+        /// Pool-side token A, token B and LP supply must have moved by exactly `a`, `b` and `lp`
+        /// (positive = into the pool / minted).
+        pub fn assert_moved(&self, label: &str, a: i128, b: i128, lp: i128) {
+            let moved_a = side_total(&self.side_a) - self.a;
+            let moved_b = side_total(&self.side_b) - self.b;
+            let moved_lp = mint_supply(self.lp_mint.as_ref()) - self.lp_supply;
+            assert_eq!(moved_a, a, "{label}: pool-side token A moved by {moved_a}, expected {a}");
+            assert_eq!(moved_b, b, "{label}: pool-side token B moved by {moved_b}, expected {b}");
+            assert_eq!(moved_lp, lp, "{label}: LP supply moved by {moved_lp}, expected {lp}");
+        }
+    }
+
+    /// Pool-side (token A, token B) movement of a trade.
+    pub fn trade_deltas(is_a_to_b: bool, amount_in: u64, amount_out: u64) -> (i128, i128) {
+        let (amount_in, amount_out) = (i128::from(amount_in), i128::from(amount_out));
+        if is_a_to_b {
+            (amount_in, -amount_out)
+        } else {
+            (-amount_out, amount_in)
+        }
     }
 }
 
-#[derive(Accounts)]
-pub struct ClaimVested<'info> {
-    #[account(mut, has_one = lp_mint, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
+/// Off-chain entry points into the curve, LP-mint and reward math for the criterion benches in
+/// `benches/`, compiled in only with the `bench` feature. Thin wrappers: the benches measure the
+/// exact code the instructions run.
+#[cfg(feature = "bench")]
+pub mod bench {
+    use super::*;
 
-    #[account(mut, close = user)]
-    pub vesting_stake: Account<'info, VestingStake>,
+    /// A pool carrying only what the pricing and reward math read: curve, a settled amp and a
+    /// `protocol_fee_bps` split 1/5 treasury, 1/5 rewards, the rest to LPs.
+    pub fn pool(curve_type: u8, protocol_fee_bps: u16, amp: u32) -> Pool {
+        let mut pool = Pool::try_deserialize_unchecked(&mut &[0u8; 8 + 768][..]).expect("zeroed pool");
+        pool.curve_type = curve_type;
+        pool.initial_amp = amp;
+        pool.target_amp = amp;
+        pool.protocol_fee_bps = protocol_fee_bps;
+        pool.treasury_fee_bps = protocol_fee_bps / 5;
+        pool.reward_fee_bps = protocol_fee_bps / 5;
+        pool.lp_fee_bps = protocol_fee_bps - pool.treasury_fee_bps - pool.reward_fee_bps;
+        pool
+    }
 
-    /// Vesting token account owned by vesting PDA
-    #[account(mut, token::authority = vesting_stake)]
-    pub vesting_token_account: Account<'info, TokenAccount>,
+    pub fn lp_mint_amount(
+        amount_a: u64,
+        amount_b: u64,
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+        decimals_a: u8,
+        decimals_b: u8,
+    ) -> Result<u64> {
+        calculate_lp_mint_amount(amount_a, amount_b, reserve_a, reserve_b, lp_supply, decimals_a, decimals_b)
+    }
 
-    /// destination LP token account of the user
-    #[account(mut, token::mint = lp_mint, token::authority = user)]
-    pub user_lp_token_account: Account<'info, TokenAccount>,
+    pub fn pending_reward(amount: u64, acc_reward_per_lp: u128, reward_debt: u128) -> Result<u64> {
+        pending_reward_amount(amount, acc_reward_per_lp, reward_debt)
+    }
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+    pub fn exited_rewards(amount: u64, exited: u64, reward_debt: u128, acc_reward_per_lp: u128) -> Result<(u64, u128)> {
+        split_exited_rewards(amount, exited, reward_debt, acc_reward_per_lp)
+    }
 
-    /// Reward vault where reward LPs are held
-    #[account(mut, token::mint = lp_mint)]
-    pub reward_vault: Account<'info, TokenAccount>,
+    pub fn accrue_emissions(pool: &mut Pool, now: i64) -> Result<()> {
+        update_pool_rewards(pool, now)
+    }
+}
+
+/// Catastrophic actions on a pool with more than `cosign_lp_threshold` LP outstanding also need the
+/// program's upgrade authority (read from its program data) or the protocol admin to sign.
+fn check_privileged_cosigner(
+    config: &ProtocolConfig,
+    lp_supply: u64,
+    program_data: Option<&ProgramData>,
+    cosigner: Option<&Signer>,
+) -> Result<()> {
+    if config.cosign_lp_threshold == 0 || lp_supply <= config.cosign_lp_threshold {
+        return Ok(());
+    }
+    let cosigner = cosigner.ok_or(AmmError::CosignerRequired)?.key();
+    let upgrade_authority = program_data.and_then(|data| data.upgrade_authority_address);
+    require!(
+        upgrade_authority == Some(cosigner) || cosigner == config.admin,
+        AmmError::CosignerRequired
+    );
+    Ok(())
+}
+
+/// Council members must be set and distinct.
+fn validate_council_members(members: &[Pubkey; COUNCIL_SIZE]) -> Result<()> {
+    for (i, member) in members.iter().enumerate() {
+        require!(*member != Pubkey::default(), AmmError::InvalidCouncilMembers);
+        require!(!members[..i].contains(member), AmmError::InvalidCouncilMembers);
+    }
+    Ok(())
+}
+
+fn replace_council_member(members: &mut [Pubkey; COUNCIL_SIZE], old_member: Pubkey, new_member: Pubkey) -> Result<()> {
+    let index = members
+        .iter()
+        .position(|m| *m == old_member)
+        .ok_or(AmmError::NotCouncilMember)?;
+    members[index] = new_member;
+    validate_council_members(members)
+}
+
+/// Move a position's escrowed claim bounty from its PDA to `to`: the cranker on `crank_claim`, the
+/// owner on every other exit.
+fn refund_claim_bounty<'info>(vesting: &mut Account<'info, VestingStake>, to: &AccountInfo<'info>) -> Result<()> {
+    let bounty = vesting.claim_bounty;
+    if bounty == 0 {
+        return Ok(());
+    }
+    vesting.claim_bounty = 0;
+    let vesting_info = vesting.to_account_info();
+    **vesting_info.try_borrow_mut_lamports()? = vesting_info
+        .lamports()
+        .checked_sub(bounty)
+        .ok_or(AmmError::NumericOverflow)?;
+    **to.try_borrow_mut_lamports()? = to.lamports().checked_add(bounty).ok_or(AmmError::NumericOverflow)?;
+    Ok(())
+}
+
+/// A reward destination must hold the pool's LP, accept transfers and not be a pool-owned vault.
+fn check_reward_destination(pool: &Account<Pool>, destination: &Account<TokenAccount>) -> Result<()> {
+    require!(
+        destination.mint == pool.lp_mint && !destination.is_frozen() && destination.owner != pool.key(),
+        AmmError::InvalidRewardDestination
+    );
+    Ok(())
+}
+
+/// A pool token must carry an admin `TokenBadge` unless it has no freeze authority, so nobody can
+/// open a pool over a token its issuer can freeze in users' hands.
+fn check_token_badge(mint: &Account<Mint>, badge: Option<&Account<TokenBadge>>) -> Result<()> {
+    require!(
+        badge.is_some() || mint.freeze_authority.is_none(),
+        AmmError::TokenNotBadged
+    );
+    Ok(())
+}
+
+/// Commitment to the ordered accounts a pool hook takes, stored as `PoolHook.accounts_hash`.
+pub fn hook_accounts_hash(accounts: &[Pubkey]) -> [u8; 32] {
+    let keys: Vec<&[u8]> = accounts.iter().map(|k| k.as_ref()).collect();
+    hashv(&keys).to_bytes()
+}
+
+/// CPI `on_position_event(payload)` into the pool's hook, if one is registered. `remaining_accounts`
+/// holds the hook program followed by the committed accounts; signer privileges are not forwarded.
+fn invoke_position_hook(
+    pool: &Pool,
+    pool_hook: Option<&PoolHook>,
+    remaining_accounts: &[AccountInfo],
+    payload: PositionHookPayload,
+) -> Result<()> {
+    if !pool.hook_enabled {
+        return Ok(());
+    }
+    let hook = pool_hook.ok_or(AmmError::PoolHookRequired)?;
+    let (program, accounts) = remaining_accounts.split_first().ok_or(AmmError::PoolHookRequired)?;
+    require!(program.key() == hook.program_id && program.executable, AmmError::PoolHookMismatch);
+    let keys: Vec<Pubkey> = accounts.iter().map(|info| info.key()).collect();
+    require!(hook_accounts_hash(&keys) == hook.accounts_hash, AmmError::PoolHookMismatch);
+
+    let mut data = hash(POSITION_HOOK_IX.as_bytes()).to_bytes()[..8].to_vec();
+    payload.serialize(&mut data)?;
+    let ix = Instruction {
+        program_id: hook.program_id,
+        accounts: accounts
+            .iter()
+            .map(|info| {
+                if info.is_writable {
+                    AccountMeta::new(info.key(), false)
+                } else {
+                    AccountMeta::new_readonly(info.key(), false)
+                }
+            })
+            .collect(),
+        data,
+    };
+    invoke(&ix, remaining_accounts)?;
+    Ok(())
+}
+
+/// Compliance-mode gate. The caller must pass their blocklist PDA; an initialized account means blocked.
+/// The rejection event is logged before the error so monitoring sees it in the failed transaction;
+/// it stays a plain `emit!` because a failed transaction records logs but no inner instructions.
+fn check_not_blocked(pool: &Account<Pool>, blocklist_entry: Option<&UncheckedAccount>, user: Pubkey) -> Result<()> {
+    if !pool.compliance_mode {
+        return Ok(());
+    }
+    let entry = blocklist_entry.ok_or(AmmError::BlocklistEntryMissing)?;
+    if !entry.data_is_empty() && entry.owner == &ID {
+        emit!(BlockedAddressRejected {
+            pool: pool.key(),
+            address: user,
+        });
+        return err!(AmmError::AddressBlocked);
+    }
+    Ok(())
+}
+
+/// Gates for adding `amounts` of liquidity, shared by `deposit_and_vest` and `bond`: the pool
+/// isn't depegged or draining, its price is on peg, the depositor is allowlisted on a permissioned
+/// pool, both amounts meet the pool minimums and the reserves are rent-exempt token accounts.
+fn check_deposit<'info>(
+    pool: &Pool,
+    (amount_a, amount_b): (u64, u64),
+    (reserve_a, reserve_b): (&Account<'info, TokenAccount>, &Account<'info, TokenAccount>),
+    lp_supply: u64,
+    allowlisted: bool,
+    now: i64,
+) -> Result<()> {
+    require!(!pool.depegged, AmmError::PoolDepegged);
+    require!(!pool.emergency_drip_active, AmmError::EmergencyDripActive);
+    if lp_supply > 0 {
+        require!(!price_off_peg(pool, reserve_a.amount, reserve_b.amount, now)?, AmmError::PoolDepegged);
+    }
+    // Permissioned pools only accept deposits from allowlisted users
+    require!(!pool.permissioned || allowlisted, AmmError::NotAllowlisted);
+    require_gte_ctx!(amount_a, pool.min_deposit_a, AmmError::DepositTooSmall);
+    require_gte_ctx!(amount_b, pool.min_deposit_b, AmmError::DepositTooSmall);
+
+    // Defensive checks: require reserve token accounts to be rent-exempt and owned by token program
+    let rent = Rent::get()?;
+    for reserve in [reserve_a.to_account_info(), reserve_b.to_account_info()] {
+        require!(rent.is_exempt(reserve.lamports(), reserve.data_len()), AmmError::NotRentExempt);
+        require!(reserve.owner == &token::ID, AmmError::InvalidTokenAccountOwner);
+    }
+    Ok(())
+}
+
+/// Caller gate for `swap` on pools with `caller_restricted`. A top-level swap needs `allow_direct`;
+/// a swap one CPI deep must come from a listed program, read from the transaction's current
+/// top-level instruction. Deeper CPI chains hide the immediate caller and are rejected.
+fn check_swap_caller(
+    pool: &Pool,
+    allowed_callers: Option<&AllowedCallers>,
+    instructions_sysvar: Option<&UncheckedAccount>,
+) -> Result<()> {
+    if !pool.caller_restricted {
+        return Ok(());
+    }
+    let callers = allowed_callers.ok_or(AmmError::CallerCheckAccountsMissing)?;
+    let stack_height = get_stack_height();
+    if stack_height == TRANSACTION_LEVEL_STACK_HEIGHT {
+        require!(callers.allow_direct, AmmError::CallerNotAllowed);
+        return Ok(());
+    }
+    require!(stack_height == TRANSACTION_LEVEL_STACK_HEIGHT + 1, AmmError::CallerNotAllowed);
+    let sysvar = instructions_sysvar.ok_or(AmmError::CallerCheckAccountsMissing)?;
+    let index = sysvar_instructions::load_current_index_checked(sysvar)?;
+    let caller = sysvar_instructions::load_instruction_at_checked(index as usize, sysvar)?.program_id;
+    require!(
+        callers.programs[..callers.count as usize].contains(&caller),
+        AmmError::CallerNotAllowed
+    );
+    Ok(())
+}
+
+/// Message a position owner signs to let `relayer` claim `vesting_stake` into `destination` once,
+/// under `nonce`, until `expires_at`:
+/// `CLAIM_VOUCHER_DOMAIN || program id || vesting_stake || relayer || destination || nonce (LE) || expires_at (LE)`.
+pub fn claim_voucher_message(
+    vesting_stake: &Pubkey,
+    relayer: &Pubkey,
+    destination: &Pubkey,
+    nonce: u64,
+    expires_at: i64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(CLAIM_VOUCHER_DOMAIN.len() + 4 * 32 + 2 * 8);
+    message.extend_from_slice(CLAIM_VOUCHER_DOMAIN);
+    message.extend_from_slice(ID.as_ref());
+    message.extend_from_slice(vesting_stake.as_ref());
+    message.extend_from_slice(relayer.as_ref());
+    message.extend_from_slice(destination.as_ref());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(&expires_at.to_le_bytes());
+    message
+}
+
+/// Require the instruction right before the current one to be an ed25519 program check of exactly
+/// one signature by `signer` over `message`, with key, signature and message all inside that
+/// instruction's own data (the native program has already verified the signature itself).
+fn verify_ed25519_voucher(instructions_sysvar: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let index = sysvar_instructions::load_current_index_checked(instructions_sysvar)?;
+    require!(index > 0, AmmError::InvalidVoucher);
+    let ix = sysvar_instructions::load_instruction_at_checked(index as usize - 1, instructions_sysvar)?;
+    require!(ix.program_id == ed25519_program::ID, AmmError::InvalidVoucher);
+
+    // Header: signature count, padding, then one 14-byte offsets record
+    let data = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1, AmmError::InvalidVoucher);
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let (sig_ix, key_offset, key_ix) = (read_u16(4), usize::from(read_u16(6)), read_u16(8));
+    let (msg_offset, msg_size, msg_ix) = (usize::from(read_u16(10)), usize::from(read_u16(12)), read_u16(14));
+    require!(
+        sig_ix == u16::MAX && key_ix == u16::MAX && msg_ix == u16::MAX,
+        AmmError::InvalidVoucher
+    );
+    let key = data.get(key_offset..key_offset + 32).ok_or(AmmError::InvalidVoucher)?;
+    let signed = data.get(msg_offset..msg_offset + msg_size).ok_or(AmmError::InvalidVoucher)?;
+    require!(key == signer.as_ref() && signed == message, AmmError::InvalidVoucher);
+    Ok(())
+}
+
+/// Reward-fee accounting shared by `swap` and TWAP slices, returning the new LP, token A and
+/// token B accumulators plus the underlying reward fee. LP mode spreads the fee over the LP supply;
+/// underlying mode spreads it over LP locked in vesting positions, and the returned amount must
+/// move from the fee-side reserve to that token's reward vault. A credit that would take an
+/// accumulator past `ACC_REWARD_PER_LP_CAP` is skipped and the fee stays in the reserves.
+fn accrue_swap_reward_fee(
+    pool: &Pool,
+    quote: &SwapQuote,
+    fee_in_a: bool,
+    lp_supply: u64,
+) -> Result<(u128, u128, u128, u64)> {
+    let reward_fee = u128::from(quote.reward_fee);
+    let underlying_rewards = pool.reward_mode == REWARD_MODE_UNDERLYING;
+    let mut acc_reward_per_lp = pool.acc_reward_per_lp;
+    if !underlying_rewards && lp_supply > 0 && reward_fee > 0 {
+        let delta = (reward_fee * REWARD_SCALE) / u128::from(lp_supply);
+        if let Some(acc) = credit_accumulator(acc_reward_per_lp, delta) {
+            acc_reward_per_lp = acc;
+        }
+    }
+
+    let mut acc_reward_a_per_lp = pool.acc_reward_a_per_lp;
+    let mut acc_reward_b_per_lp = pool.acc_reward_b_per_lp;
+    let locked_lp = pool.total_locked_lp;
+    let mut underlying_reward_fee = 0;
+    if underlying_rewards && locked_lp > 0 && quote.reward_fee > 0 {
+        let delta = (reward_fee * REWARD_SCALE) / u128::from(locked_lp);
+        let acc = if fee_in_a { &mut acc_reward_a_per_lp } else { &mut acc_reward_b_per_lp };
+        if let Some(next) = credit_accumulator(*acc, delta) {
+            *acc = next;
+            underlying_reward_fee = quote.reward_fee;
+        }
+    }
+    Ok((acc_reward_per_lp, acc_reward_a_per_lp, acc_reward_b_per_lp, underlying_reward_fee))
+}
+
+/// Pool-side accounts an escrowed order (TWAP slice, limit order, DCA fill) trades against, plus
+/// the order's escrow, its signing order PDA and the owner's output account.
+struct OrderSwapAccounts<'a, 'info> {
+    pool: &'a mut Account<'info, Pool>,
+    lp_mint: &'a Account<'info, Mint>,
+    reserve_a: &'a mut Account<'info, TokenAccount>,
+    reserve_b: &'a mut Account<'info, TokenAccount>,
+    treasury_token_account_a: &'a Account<'info, TokenAccount>,
+    treasury_token_account_b: &'a Account<'info, TokenAccount>,
+    pool_stats: &'a mut Account<'info, PoolStats>,
+    reward_vault_a: Option<&'a Account<'info, TokenAccount>>,
+    reward_vault_b: Option<&'a Account<'info, TokenAccount>>,
+    price_observations: &'a mut Account<'info, PriceObservations>,
+    escrow: AccountInfo<'info>,
+    escrow_authority: AccountInfo<'info>,
+    escrow_signer: &'a [&'a [&'a [u8]]], // order PDA seeds; empty when the escrow owner signs the transaction
+    destination: AccountInfo<'info>,
+    token_program: &'a Program<'info, Token>,
+}
+
+/// What an order fill did, for the caller's events.
+struct OrderSwapFill {
+    quote: SwapQuote,
+    fee_in_a: bool,
+    lp_supply: u64,
+    depeg_tripped: bool,
+}
+
+/// Trade `amount_in` from an order escrow into the pool and pay the output to the order's
+/// destination under `swap`'s pause, de-peg, fee, reward and invariant rules. Fails with
+/// `price_error` when the output is below `min_amount_out`.
+fn execute_order_swap<'info>(
+    accounts: OrderSwapAccounts<'_, 'info>,
+    amount_in: u64,
+    min_amount_out: u128,
+    is_a_to_b: bool,
+    price_error: AmmError,
+) -> Result<OrderSwapFill> {
+    let now = Clock::get()?.unix_timestamp;
+    let pool = accounts.pool;
+    require!(!pool.is_paused(now), AmmError::Paused);
+    require!(!pool.direction_paused(is_a_to_b), AmmError::SwapDirectionPaused);
+    require!(!(pool.depegged && pool.depeg_blocks_swaps), AmmError::PoolDepegged);
+    require!(!pool.swap_allowlist_active(now), AmmError::NotMarketMaker);
+    require!(amount_in > 0, AmmError::ZeroSwapAmount);
+
+    let lp_supply = accounts.lp_mint.supply;
+    update_pool_rewards(pool, now)?;
+
+    #[cfg(feature = "accounting-checks")]
+    let snapshot = accounting::Snapshot::take(
+        [Some(accounts.reserve_a.to_account_info()), Some(accounts.treasury_token_account_a.to_account_info())]
+            .into_iter()
+            .chain([accounts.reward_vault_a.map(|v| v.to_account_info())])
+            .flatten()
+            .collect(),
+        [Some(accounts.reserve_b.to_account_info()), Some(accounts.treasury_token_account_b.to_account_info())]
+            .into_iter()
+            .chain([accounts.reward_vault_b.map(|v| v.to_account_info())])
+            .flatten()
+            .collect(),
+        None,
+    );
+    let (reserve_a, reserve_b) = (accounts.reserve_a, accounts.reserve_b);
+    let (reserve_in_before, reserve_out_before) = if is_a_to_b {
+        (reserve_a.amount, reserve_b.amount)
+    } else {
+        (reserve_b.amount, reserve_a.amount)
+    };
+    let observations = accounts.price_observations;
+    let quote = compute_trade_quote(
+        pool,
+        Some(&**observations),
+        amount_in,
+        reserve_a.amount,
+        reserve_b.amount,
+        is_a_to_b,
+        now,
+    )?;
+    if u128::from(quote.amount_out) < min_amount_out {
+        return err_ctx!(
+            price_error,
+            "order fill of {} in pays {} out, below the order's {}",
+            amount_in,
+            quote.amount_out,
+            min_amount_out
+        );
+    }
+    let fee_in_a = pool.fee_in_token_a(is_a_to_b);
+    let (_, depeg_tripped) = observe_price(pool, observations, reserve_a.amount, reserve_b.amount, now)?;
+    let (acc_reward_per_lp, acc_reward_a_per_lp, acc_reward_b_per_lp, underlying_reward_fee) =
+        accrue_swap_reward_fee(pool, &quote, fee_in_a, lp_supply)?;
+
+    // Same transfer order as `swap`: input in, fees out of the fee-side reserve, then the output
+    let token_program = accounts.token_program.to_account_info();
+    let pool_info = pool.to_account_info();
+    let (lp_mint_key, pool_bump) = (pool.lp_mint, [pool.bump]);
+    let pool_signer: &[&[&[u8]]] = &[&[SEED_POOL, lp_mint_key.as_ref(), &pool_bump]];
+    let transfer = |from: AccountInfo<'info>,
+                    to: AccountInfo<'info>,
+                    authority: AccountInfo<'info>,
+                    signer: &[&[&[u8]]],
+                    amount: u64|
+     -> Result<()> {
+        token::transfer(
+            CpiContext::new_with_signer(token_program.clone(), Transfer { from, to, authority }, signer),
+            amount,
+        )
+    };
+    let (reserve_in, reserve_out) = if is_a_to_b {
+        (reserve_a.to_account_info(), reserve_b.to_account_info())
+    } else {
+        (reserve_b.to_account_info(), reserve_a.to_account_info())
+    };
+    let (fee_reserve, treasury, reward_vault) = if fee_in_a {
+        (reserve_a.to_account_info(), accounts.treasury_token_account_a, accounts.reward_vault_a)
+    } else {
+        (reserve_b.to_account_info(), accounts.treasury_token_account_b, accounts.reward_vault_b)
+    };
+    transfer(accounts.escrow, reserve_in, accounts.escrow_authority, accounts.escrow_signer, amount_in)?;
+    if quote.treasury_fee > 0 {
+        transfer(fee_reserve.clone(), treasury.to_account_info(), pool_info.clone(), pool_signer, quote.treasury_fee)?;
+    }
+    if underlying_reward_fee > 0 {
+        let reward_vault = reward_vault.ok_or(AmmError::UnderlyingRewardAccountsMissing)?;
+        transfer(fee_reserve, reward_vault.to_account_info(), pool_info.clone(), pool_signer, underlying_reward_fee)?;
+    }
+    transfer(reserve_out, accounts.destination, pool_info, pool_signer, quote.amount_out)?;
+    #[cfg(feature = "accounting-checks")]
+    {
+        let (a, b) = accounting::trade_deltas(is_a_to_b, amount_in, quote.amount_out);
+        snapshot.assert_moved("order swap", a, b, 0);
+    }
+
+    reserve_a.reload()?;
+    reserve_b.reload()?;
+    let (reserve_in_after, reserve_out_after) = if is_a_to_b {
+        (reserve_a.amount, reserve_b.amount)
+    } else {
+        (reserve_b.amount, reserve_a.amount)
+    };
+    check_invariant(pool, now, reserve_in_before, reserve_out_before, reserve_in_after, reserve_out_after)?;
+
+    let acc_reward_per_lp_before = pool.acc_reward_per_lp;
+    pool.acc_reward_per_lp = acc_reward_per_lp;
+    pool.acc_reward_a_per_lp = acc_reward_a_per_lp;
+    pool.acc_reward_b_per_lp = acc_reward_b_per_lp;
+    pool.book_accumulator_liability(acc_reward_per_lp_before);
+    accounts.pool_stats.record_swap(&quote, is_a_to_b, fee_in_a, now);
+
+    Ok(OrderSwapFill {
+        quote,
+        fee_in_a,
+        lp_supply,
+        depeg_tripped,
+    })
+}
+
+/// Post-swap guard for the pool's curve: k (constant product) or D (stable) must not shrink.
+fn check_invariant(
+    pool: &Pool,
+    now: i64,
+    reserve_in_before: u64,
+    reserve_out_before: u64,
+    reserve_in_after: u64,
+    reserve_out_after: u64,
+) -> Result<()> {
+    if pool.curve_type == CURVE_STABLE {
+        let amp = pool.current_amp(now);
+        let d_before = stable_invariant(amp, &[u128::from(reserve_in_before), u128::from(reserve_out_before)])?;
+        let d_after = stable_invariant(amp, &[u128::from(reserve_in_after), u128::from(reserve_out_after)])?;
+        require_gte_ctx!(d_after, d_before, AmmError::InvariantViolated);
+        return Ok(());
+    }
+    check_constant_product(reserve_in_before, reserve_out_before, reserve_in_after, reserve_out_after)
+}
+
+fn check_constant_product(
+    reserve_in_before: u64,
+    reserve_out_before: u64,
+    reserve_in_after: u64,
+    reserve_out_after: u64,
+) -> Result<()> {
+    let k_before = u128::from(reserve_in_before)
+        .checked_mul(u128::from(reserve_out_before))
+        .ok_or(AmmError::NumericOverflow)?;
+    let k_after = u128::from(reserve_in_after)
+        .checked_mul(u128::from(reserve_out_after))
+        .ok_or(AmmError::NumericOverflow)?;
+    require_gte_ctx!(k_after, k_before, AmmError::InvariantViolated);
+    Ok(())
+}
+
+/// Fee bounds shared by `initialize_pool` and `update_fees`.
+fn validate_fees(
+    config: &ProtocolConfig,
+    protocol_fee_bps: u16,
+    treasury_fee_bps: u16,
+    reward_fee_bps: u16,
+) -> Result<()> {
+    require_lte_ctx!(protocol_fee_bps, config.max_protocol_fee_bps, AmmError::FeeTooHigh);
+    // basic fee split sanity check
+    require_lte_ctx!(treasury_fee_bps.saturating_add(reward_fee_bps), protocol_fee_bps, AmmError::InvalidFeeSplit);
+    Ok(())
+}
+
+fn validate_scheduled_action(config: &ProtocolConfig, action: &ScheduledActionKind) -> Result<()> {
+    match *action {
+        ScheduledActionKind::FeeChange {
+            protocol_fee_bps,
+            treasury_fee_bps,
+            reward_fee_bps,
+        } => validate_fees(config, protocol_fee_bps, treasury_fee_bps, reward_fee_bps),
+        ScheduledActionKind::EmissionChange { reward_emission_per_second } => {
+            require_lte_ctx!(
+                reward_emission_per_second,
+                config.max_reward_emission_per_second,
+                AmmError::EmissionTooHigh
+            );
+            Ok(())
+        }
+        ScheduledActionKind::Unpause => Ok(()),
+    }
+}
+
+/// Reload the reserves and LP mint after an instruction's transfers and rewrite `view` from them.
+/// Reserve accounts other than the pool's own are rejected so a look-alike can't poison the view.
+fn reload_pool_view<'info>(
+    view: &mut PoolView,
+    pool: &Pool,
+    reserve_a: &mut Account<'info, TokenAccount>,
+    reserve_b: &mut Account<'info, TokenAccount>,
+    lp_mint: &mut Account<'info, Mint>,
+    now: i64,
+) -> Result<()> {
+    require!(
+        reserve_a.key() == pool.reserve_a && reserve_b.key() == pool.reserve_b,
+        AmmError::ReserveMismatch
+    );
+    reserve_a.reload()?;
+    reserve_b.reload()?;
+    lp_mint.reload()?;
+    view.refresh(pool, reserve_a.amount, reserve_b.amount, lp_mint.supply, now)
+}
+
+/// Fold time-based emissions since the last update into `acc_reward_per_lp`, spread over the LP
+/// locked in vesting positions (the only LP that can claim), as `finalize_reward_epoch` does.
+/// Called at the top of every instruction that reads or snapshots the accumulator, before it
+/// changes `total_locked_lp`.
+fn update_pool_rewards(pool: &mut Pool, now: i64) -> Result<()> {
+    // In epoch-settlement mode emissions are folded in by finalize_reward_epoch only
+    if now <= pool.last_reward_update_ts || pool.epoch_rewards {
+        return Ok(());
+    }
+    let elapsed = (now - pool.last_reward_update_ts) as u64;
+    let total_locked_lp = pool.total_locked_lp;
+    if total_locked_lp > 0 && pool.reward_emission_per_second > 0 {
+        let emitted = u128::from(elapsed)
+            .checked_mul(u128::from(pool.reward_emission_per_second))
+            .ok_or(AmmError::NumericOverflow)?;
+        let delta =
+            emitted.checked_mul(REWARD_SCALE).ok_or(AmmError::NumericOverflow)? / u128::from(total_locked_lp);
+        // Past the cap the period's emission is forfeited rather than failing every caller
+        if let Some(acc) = credit_accumulator(pool.acc_reward_per_lp, delta) {
+            pool.acc_reward_per_lp = acc;
+            pool.add_reward_liability(emitted.try_into().unwrap_or(u64::MAX));
+        }
+    }
+    pool.last_reward_update_ts = now;
+    Ok(())
+}
+
+/// Swap pricing as `swap` charges it: `rebalance_fee_bps` when the trade moves the pool price
+/// closer to the reference (`oracle_price`, else the TWAP from `observations`), the full fee
+/// otherwise.
+pub fn compute_trade_quote(
+    pool: &Pool,
+    observations: Option<&PriceObservations>,
+    amount_in: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    is_a_to_b: bool,
+    now: i64,
+) -> Result<SwapQuote> {
+    let (reserve_in, reserve_out) = if is_a_to_b { (reserve_a, reserve_b) } else { (reserve_b, reserve_a) };
+    let quote = compute_swap_quote(pool, amount_in, reserve_in, reserve_out, now)?;
+    if pool.rebalance_fee_bps == 0 || pool.rebalance_fee_bps >= pool.swap_fee_bps(now) {
+        return Ok(quote);
+    }
+    let reference = match (pool.oracle_price, observations.and_then(|o| o.twap_price_a())) {
+        (0, Some(twap)) => twap,
+        (0, None) => return Ok(quote),
+        (oracle_price, _) => oracle_price,
+    };
+
+    let (a_after, b_after) = if is_a_to_b {
+        (reserve_a.saturating_add(amount_in), reserve_b - quote.amount_out)
+    } else {
+        (reserve_a - quote.amount_out, reserve_b.saturating_add(amount_in))
+    };
+    if a_after == 0 || b_after == 0 {
+        return Ok(quote);
+    }
+    let before = spot_price_a(pool, reserve_a, reserve_b, now)?.abs_diff(reference);
+    let after = spot_price_a(pool, a_after, b_after, now)?.abs_diff(reference);
+    if after < before {
+        compute_swap_quote_with_fee(pool, pool.rebalance_fee_bps, amount_in, reserve_in, reserve_out, now)
+    } else {
+        Ok(quote)
+    }
+}
+
+/// Curve pricing (constant product or stable, amp taken at `now`) with the pool's fee split,
+/// charging the fee-holiday rate while one is running.
+/// Pure: callers perform the transfers.
+pub fn compute_swap_quote(
+    pool: &Pool,
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    now: i64,
+) -> Result<SwapQuote> {
+    compute_swap_quote_with_fee(pool, pool.swap_fee_bps(now), amount_in, reserve_in, reserve_out, now)
+}
+
+/// `compute_swap_quote` charging `fee_bps` instead of `protocol_fee_bps`; the treasury/reward/LP
+/// split keeps the pool's proportions.
+fn compute_swap_quote_with_fee(
+    pool: &Pool,
+    fee_bps: u16,
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    now: i64,
+) -> Result<SwapQuote> {
+    let fee_bps = u128::from(fee_bps);
+    let fee_denom = 10_000u128;
+    let reserve_in_amount = u128::from(reserve_in);
+    let reserve_out_amount = u128::from(reserve_out);
+
+    require!(
+        reserve_in_amount > 0 && reserve_out_amount > 0,
+        AmmError::InsufficientLiquidity
+    );
+
+    let amount_in_u128 = u128::from(amount_in);
+    let (amount_out_u128, total_fee) = if pool.fee_on_output {
+        // Output-side fee: price the full input, then withhold the fee from the output, rounded up
+        // so the pool never under-collects
+        let gross_out = curve_amount_out(pool, reserve_in_amount, reserve_out_amount, amount_in_u128, now)?;
+        let total_fee = gross_out
+            .checked_mul(fee_bps)
+            .ok_or(AmmError::NumericOverflow)?
+            .checked_add(fee_denom - 1)
+            .ok_or(AmmError::NumericOverflow)?
+            / fee_denom;
+        (gross_out.checked_sub(total_fee).ok_or(AmmError::NumericOverflow)?, total_fee)
+    } else {
+        let amount_in_after_fee = amount_in_u128
+            .checked_mul(fee_denom.checked_sub(fee_bps).ok_or(AmmError::NumericOverflow)?)
+            .ok_or(AmmError::NumericOverflow)?
+            / fee_denom;
+        let total_fee = amount_in_u128.checked_sub(amount_in_after_fee).ok_or(AmmError::NumericOverflow)?;
+        let amount_out = curve_amount_out(pool, reserve_in_amount, reserve_out_amount, amount_in_after_fee, now)?;
+        (amount_out, total_fee)
+    };
+    if pool.reserve_cap_active(now) {
+        require_lte_ctx!(
+            amount_out_u128 * fee_denom,
+            reserve_out_amount * u128::from(pool.max_reserve_out_bps),
+            AmmError::ReserveUtilizationExceeded
+        );
+    }
+
+    let treasury_fee = (total_fee * u128::from(pool.treasury_fee_bps))
+        / u128::from(pool.protocol_fee_bps.max(1));
+    let reward_fee = (total_fee * u128::from(pool.reward_fee_bps))
+        / u128::from(pool.protocol_fee_bps.max(1));
+    let lp_fee = total_fee
+        .checked_sub(treasury_fee)
+        .ok_or(AmmError::NumericOverflow)?
+        .checked_sub(reward_fee)
+        .ok_or(AmmError::NumericOverflow)?;
+
+    Ok(SwapQuote {
+        amount_in,
+        amount_out: amount_out_u128.try_into().map_err(|_| AmmError::NumericOverflow)?,
+        total_fee: total_fee.try_into().map_err(|_| AmmError::NumericOverflow)?,
+        lp_fee: lp_fee.try_into().map_err(|_| AmmError::NumericOverflow)?,
+        treasury_fee: treasury_fee.try_into().map_err(|_| AmmError::NumericOverflow)?,
+        reward_fee: reward_fee.try_into().map_err(|_| AmmError::NumericOverflow)?,
+    })
+}
+
+/// Output of the pool's curve for `amount_in` (fees already applied), rounded down.
+fn curve_amount_out(
+    pool: &Pool,
+    reserve_in_amount: u128,
+    reserve_out_amount: u128,
+    amount_in: u128,
+    now: i64,
+) -> Result<u128> {
+    let new_reserve_in = reserve_in_amount.checked_add(amount_in).ok_or(AmmError::NumericOverflow)?;
+    let amount_out = if pool.curve_type == CURVE_STABLE {
+        // stable calc: hold D fixed, solve for the new out reserve; the Newton loops converge to
+        // within one unit each, so round two units against the trader
+        let amp = pool.current_amp(now);
+        let balances = [reserve_in_amount, reserve_out_amount];
+        let d = stable_invariant(amp, &balances)?;
+        let new_reserve_out = stable_reserve_out(amp, &balances, 0, 1, new_reserve_in, d)?;
+        reserve_out_amount
+            .checked_sub(new_reserve_out)
+            .ok_or(AmmError::NumericOverflow)?
+            .saturating_sub(2)
+    } else {
+        // constant-product calc
+        let k = reserve_in_amount.checked_mul(reserve_out_amount).ok_or(AmmError::NumericOverflow)?;
+        let new_reserve_out = k.checked_div(new_reserve_in).ok_or(AmmError::NumericOverflow)?;
+        reserve_out_amount.checked_sub(new_reserve_out).ok_or(AmmError::NumericOverflow)?
+    };
+    Ok(amount_out)
+}
+
+/// Stable-swap invariant D over any number of reserves (Curve's Newton iteration, Ann = amp * n^n).
+/// Very large reserves combined with a high amp surface as `NumericOverflow` rather than wrapping.
+fn stable_invariant(amp: u64, balances: &[u128]) -> Result<u128> {
+    let n = balances.len() as u128;
+    let mut sum = 0u128;
+    for balance in balances {
+        sum = sum.checked_add(*balance).ok_or(AmmError::NumericOverflow)?;
+    }
+    if sum == 0 {
+        return Ok(0);
+    }
+    require!(balances.iter().all(|b| *b > 0), AmmError::InsufficientLiquidity);
+    let ann = u128::from(amp) * n.pow(n as u32);
+    let mut d = sum;
+    for _ in 0..255 {
+        let mut d_p = d;
+        for balance in balances {
+            d_p = d_p.checked_mul(d).ok_or(AmmError::NumericOverflow)? / (balance * n);
+        }
+        let d_prev = d;
+        let numerator = ann
+            .checked_mul(sum)
+            .and_then(|v| v.checked_add(d_p * n))
+            .and_then(|v| v.checked_mul(d))
+            .ok_or(AmmError::NumericOverflow)?;
+        let denominator = (ann - 1)
+            .checked_mul(d)
+            .and_then(|v| v.checked_add(d_p.checked_mul(n + 1)?))
+            .ok_or(AmmError::NumericOverflow)?;
+        d = numerator / denominator;
+        if d.abs_diff(d_prev) <= 1 {
+            break;
+        }
+    }
+    Ok(d)
+}
+
+/// Balance of reserve `j` that keeps the stable invariant at `d` once reserve `i` holds `x`.
+fn stable_reserve_out(amp: u64, balances: &[u128], i: usize, j: usize, x: u128, d: u128) -> Result<u128> {
+    let n = balances.len() as u128;
+    let ann = u128::from(amp) * n.pow(n as u32);
+    let mut c = d;
+    let mut sum = 0u128;
+    for (k, balance) in balances.iter().enumerate() {
+        if k == j {
+            continue;
+        }
+        let balance = if k == i { x } else { *balance };
+        require!(balance > 0, AmmError::InsufficientLiquidity);
+        sum = sum.checked_add(balance).ok_or(AmmError::NumericOverflow)?;
+        c = c.checked_mul(d).ok_or(AmmError::NumericOverflow)? / (balance * n);
+    }
+    let c = c.checked_mul(d).ok_or(AmmError::NumericOverflow)? / (ann * n);
+    let b = sum + d / ann;
+    let mut y = d;
+    for _ in 0..255 {
+        let y_prev = y;
+        let numerator = y
+            .checked_mul(y)
+            .and_then(|v| v.checked_add(c))
+            .ok_or(AmmError::NumericOverflow)?;
+        let denominator = (y * 2 + b).checked_sub(d).ok_or(AmmError::NumericOverflow)?;
+        y = numerator / denominator;
+        if y.abs_diff(y_prev) <= 1 {
+            break;
+        }
+    }
+    Ok(y)
+}
+
+/// `n × [user_token_account, reserve]` remaining accounts, checked against the multi-pool's
+/// reserve list and mints and against the user's ownership.
+fn load_multi_accounts<'info>(
+    multi_pool: &MultiPool,
+    remaining: &'info [AccountInfo<'info>],
+    user: &Pubkey,
+) -> Result<Vec<(Account<'info, TokenAccount>, Account<'info, TokenAccount>)>> {
+    let n = usize::from(multi_pool.n_reserves);
+    require_eq_ctx!(remaining.len(), n * 2, AmmError::InvalidMultiPoolAccounts);
+    let mut pairs = Vec::with_capacity(n);
+    for (k, chunk) in remaining.chunks(2).enumerate() {
+        let user_token: Account<'info, TokenAccount> = Account::try_from(&chunk[0])?;
+        let reserve: Account<'info, TokenAccount> = Account::try_from(&chunk[1])?;
+        require!(reserve.key() == multi_pool.reserves[k], AmmError::InvalidMultiPoolAccounts);
+        require!(
+            user_token.mint == multi_pool.mints[k] && user_token.owner == *user,
+            AmmError::InvalidTokenAccountOwner
+        );
+        pairs.push((user_token, reserve));
+    }
+    Ok(pairs)
+}
 
-    pub token_program: Program<'info, Token>,
+/// Current balances of a multi-pool's reserves, passed in order.
+fn load_multi_balances(multi_pool: &MultiPool, reserves: &[AccountInfo]) -> Result<Vec<u128>> {
+    let mut balances = Vec::with_capacity(reserves.len());
+    for (k, info) in reserves.iter().enumerate() {
+        require!(info.key() == multi_pool.reserves[k], AmmError::InvalidMultiPoolAccounts);
+        let reserve = TokenAccount::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+        balances.push(u128::from(reserve.amount));
+    }
+    Ok(balances)
 }
 
-impl<'info> ClaimVested<'info> {
-    fn transfer_from_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.vesting_token_account.to_account_info().clone(),
-            to: self.user_lp_token_account.to_account_info().clone(),
-            authority: self.vesting_stake.to_account_info().clone(),
+/// LP owed for moving a multi-pool from `old` to `new` balances (Curve's `add_liquidity`): the
+/// first deposit mints D; later ones mint pro rata to the growth in D after the pool fee is
+/// charged on each reserve's deviation from a perfectly balanced deposit.
+fn calculate_multi_lp_mint_amount(multi_pool: &MultiPool, old: &[u128], new: &[u128], lp_supply: u64) -> Result<u64> {
+    let amp = u64::from(multi_pool.amp);
+    let d1 = stable_invariant(amp, new)?;
+    if lp_supply == 0 {
+        // D is in the coins' decimals; restate it in LP_DECIMALS
+        let minted = if multi_pool.decimals <= LP_DECIMALS {
+            d1.checked_mul(10u128.pow(u32::from(LP_DECIMALS - multi_pool.decimals)))
+                .ok_or(AmmError::NumericOverflow)?
+        } else {
+            d1 / 10u128.pow(u32::from(multi_pool.decimals - LP_DECIMALS))
         };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+        let minted = u64::try_from(minted).map_err(|_| AmmError::NumericOverflow)?;
+        return Ok(minted);
     }
-    fn transfer_reward_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reward_vault.to_account_info().clone(),
-            to: self.user_lp_token_account.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    let d0 = stable_invariant(amp, old)?;
+    require_gt_ctx!(d1, d0, AmmError::ZeroDepositAmount);
+
+    let n = new.len() as u128;
+    let imbalance_fee_bps = u128::from(multi_pool.fee_bps) * n / (4 * (n - 1));
+    let mut adjusted = Vec::with_capacity(new.len());
+    for (old_balance, new_balance) in old.iter().zip(new) {
+        let ideal = d1.checked_mul(*old_balance).ok_or(AmmError::NumericOverflow)? / d0;
+        let fee = ideal
+            .abs_diff(*new_balance)
+            .checked_mul(imbalance_fee_bps)
+            .ok_or(AmmError::NumericOverflow)?
+            / 10_000;
+        adjusted.push(new_balance.checked_sub(fee).ok_or(AmmError::NumericOverflow)?);
     }
+    let d2 = stable_invariant(amp, &adjusted)?;
+    let minted = u128::from(lp_supply)
+        .checked_mul(d2.saturating_sub(d0))
+        .ok_or(AmmError::NumericOverflow)?
+        / d0;
+    let minted = u64::try_from(minted).map_err(|_| AmmError::NumericOverflow)?;
+    Ok(minted)
 }
 
-#[derive(Accounts)]
-pub struct EarlyUnvest<'info> {
-    #[account(mut, has_one = lp_mint, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
-
-    #[account(mut)]
-    pub vesting_stake: Account<'info, VestingStake>,
+/// Multi-pool swap pricing: `(amount_out, fee)` for `amount_in` of reserve `i` into reserve `j`.
+/// The curve output rounds down and the fee, withheld from it, rounds up.
+fn compute_multi_swap(multi_pool: &MultiPool, balances: &[u128], i: usize, j: usize, amount_in: u64) -> Result<(u64, u64)> {
+    let amp = u64::from(multi_pool.amp);
+    let d = stable_invariant(amp, balances)?;
+    let x = balances[i].checked_add(u128::from(amount_in)).ok_or(AmmError::NumericOverflow)?;
+    let y = stable_reserve_out(amp, balances, i, j, x, d)?;
+    let gross_out = balances[j]
+        .checked_sub(y)
+        .ok_or(AmmError::NumericOverflow)?
+        .saturating_sub(2);
+    let fee = (gross_out * u128::from(multi_pool.fee_bps)).div_ceil(10_000);
+    let amount_out = u64::try_from(gross_out - fee).map_err(|_| AmmError::NumericOverflow)?;
+    let fee = u64::try_from(fee).map_err(|_| AmmError::NumericOverflow)?;
+    Ok((amount_out, fee))
+}
 
-    /// Vesting token account owned by vesting PDA
-    #[account(mut, token::authority = vesting_stake)]
-    pub vesting_token_account: Account<'info, TokenAccount>,
+/// Marginal price of A in B (PRICE_SCALE) before fees: the reserve ratio for constant product,
+/// a one-bp probe trade for the stable curve.
+fn spot_price_a(pool: &Pool, reserve_a: u64, reserve_b: u64, now: i64) -> Result<u128> {
+    require!(reserve_a > 0 && reserve_b > 0, AmmError::InsufficientLiquidity);
+    if pool.curve_type != CURVE_STABLE {
+        return Ok(u128::from(reserve_b) * PRICE_SCALE / u128::from(reserve_a));
+    }
+    let probe = (u128::from(reserve_a) / 10_000).max(1);
+    let out = curve_amount_out(pool, u128::from(reserve_a), u128::from(reserve_b), probe, now)?;
+    Ok(out.checked_mul(PRICE_SCALE).ok_or(AmmError::NumericOverflow)? / probe)
+}
 
-    /// user's LP account
-    #[account(mut, token::mint = lp_mint, token::authority = user)]
-    pub user_lp_token_account: Account<'info, TokenAccount>,
+/// Reserves a constant-product pool would hold if its spot price equalled `price_a` (A in B,
+/// PRICE_SCALE) at the same `k`: `(sqrt(k / p), sqrt(k * p))`.
+fn fair_reserves(reserve_a: u64, reserve_b: u64, price_a: u128) -> Result<(u128, u128)> {
+    require!(reserve_a > 0 && reserve_b > 0 && price_a > 0, AmmError::InsufficientLiquidity);
+    let sqrt_k = integer_sqrt_u128(u128::from(reserve_a) * u128::from(reserve_b));
+    // sqrt(p) scaled by PRICE_SCALE
+    let sqrt_price = integer_sqrt_u128(price_a.checked_mul(PRICE_SCALE).ok_or(AmmError::NumericOverflow)?).max(1);
+    let fair_b = sqrt_k.checked_mul(sqrt_price).ok_or(AmmError::NumericOverflow)? / PRICE_SCALE;
+    let fair_a = sqrt_k.checked_mul(PRICE_SCALE).ok_or(AmmError::NumericOverflow)? / sqrt_price;
+    Ok((fair_a, fair_b))
+}
 
-    /// treasury LP token account to receive penalties
-    #[account(mut, token::mint = lp_mint)]
-    pub treasury_lp_account: Account<'info, TokenAccount>,
+/// Share of `total` vested linearly over `[start, end)` at `now`.
+fn linear_vested(total: u64, start: i64, end: i64, now: i64) -> u64 {
+    if now >= end {
+        return total;
+    }
+    let elapsed = (now - start).max(0) as u128;
+    let duration = (end - start) as u128;
+    (u128::from(total) * elapsed / duration) as u64
+}
 
-    #[account(mut)]
-    pub user: Signer<'info>,
+/// Bond terms must price something and vest within the usual lock bounds.
+fn check_bond_terms(payout_per_lp: u128, discount_bps: u16, vesting_seconds: i64) -> Result<()> {
+    require!(payout_per_lp > 0, AmmError::InvalidBondTerms);
+    require_lte_ctx!(discount_bps, 10_000, AmmError::InvalidBondTerms);
+    require!(
+        (MIN_VESTING_SECONDS..=MAX_VESTING_SECONDS).contains(&vesting_seconds),
+        AmmError::InvalidVestingPeriod
+    );
+    Ok(())
+}
 
-    pub token_program: Program<'info, Token>,
+/// Stable pools with a breaker configured: whether the internal A->B price (`spot_price_a`)
+/// deviates from 1:1, or from `oracle_price` when set, by more than the threshold.
+fn price_off_peg(pool: &Pool, reserve_a: u64, reserve_b: u64, now: i64) -> Result<bool> {
+    if pool.curve_type != CURVE_STABLE || pool.depeg_threshold_bps == 0 || reserve_a == 0 || reserve_b == 0 {
+        return Ok(false);
+    }
+    let price = spot_price_a(pool, reserve_a, reserve_b, now)?;
+    let reference = if pool.oracle_price > 0 { pool.oracle_price } else { PRICE_SCALE };
+    let deviation_bps = price
+        .abs_diff(reference)
+        .checked_mul(10_000)
+        .ok_or(AmmError::NumericOverflow)?
+        / reference;
+    Ok(deviation_bps > u128::from(pool.depeg_threshold_bps))
 }
 
-impl<'info> EarlyUnvest<'info> {
-    fn transfer_penalty_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.vesting_token_account.to_account_info().clone(),
-            to: self.treasury_lp_account.to_account_info().clone(),
-            authority: self.vesting_stake.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+/// Feed the reserves that held since the previous trade into the TWAP buffer and, when that writes a
+/// new observation, into the de-peg breaker. The breaker trips only after
+/// `DEPEG_CONFIRM_OBSERVATIONS` consecutive observations off peg, so the price must stay out of range
+/// for several `OBSERVATION_MIN_INTERVAL`s; one skewing trade, however large, can't trip it.
+/// Returns (observation written, breaker tripped).
+fn observe_price(
+    pool: &mut Pool,
+    observations: &mut PriceObservations,
+    reserve_a: u64,
+    reserve_b: u64,
+    now: i64,
+) -> Result<(bool, bool)> {
+    if !observations.record(reserve_a, reserve_b, now) {
+        return Ok((false, false));
+    }
+    if !price_off_peg(pool, reserve_a, reserve_b, now)? {
+        pool.depeg_strikes = 0;
+        return Ok((true, false));
+    }
+    pool.depeg_strikes = pool.depeg_strikes.saturating_add(1);
+    if pool.depegged || pool.depeg_strikes < DEPEG_CONFIRM_OBSERVATIONS {
+        return Ok((true, false));
     }
+    pool.depegged = true;
+    Ok((true, true))
+}
 
-    fn transfer_from_vesting_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.vesting_token_account.to_account_info().clone(),
-            to: self.user_lp_token_account.to_account_info().clone(),
-            authority: self.vesting_stake.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+/// First-deposit guard: with an oracle configured, the initial A:B ratio (which fixes the pool
+/// price) must sit within `oracle_tolerance_bps` of `oracle_price`.
+fn check_initial_ratio(pool: &Pool, amount_a: u64, amount_b: u64) -> Result<()> {
+    if pool.oracle_price == 0 {
+        return Ok(());
     }
+    let deposit_price = u128::from(amount_b)
+        .checked_mul(PRICE_SCALE)
+        .ok_or(AmmError::NumericOverflow)?
+        / u128::from(amount_a);
+    let tolerance = pool
+        .oracle_price
+        .checked_mul(u128::from(pool.oracle_tolerance_bps))
+        .ok_or(AmmError::NumericOverflow)?
+        / 10_000u128;
+    require_lte_ctx!(
+        deposit_price.abs_diff(pool.oracle_price),
+        tolerance,
+        AmmError::InitialPriceOutOfRange
+    );
+    Ok(())
 }
 
-#[derive(Accounts)]
-pub struct Withdraw<'info> {
-    #[account(mut, has_one = lp_mint, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
-    #[account(mut)]
-    pub reserve_a: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub reserve_b: Account<'info, TokenAccount>,
+/// LP reward scaled up by the position's relock streak. The bonus is paid from reward-vault
+/// `surplus` (see `Pool::reward_surplus`), never from what other positions are owed, so only the
+/// base amount counts against `total_reward_liability`.
+fn apply_relock_bonus(relock_bonus_bps: u16, relock_count: u32, reward: u64, surplus: u64) -> u64 {
+    let bonus_bps = (u64::from(relock_count) * u64::from(relock_bonus_bps)).min(MAX_RELOCK_BONUS_BPS);
+    let bonus = (u128::from(reward) * u128::from(bonus_bps) / 10_000u128) as u64;
+    reward.saturating_add(bonus.min(surplus))
+}
 
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut, token::mint = lp_mint, token::authority = user)]
-    pub user_lp_token_account: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_a_mint, token::authority = user)]
-    pub user_token_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint, token::authority = user)]
-    pub user_token_b: Account<'info, TokenAccount>,
+/// Relock bonus rate for a position: the one in force in its `reward_epoch`, so a later
+/// `set_relock_bonus` doesn't reach back. Falls back to the current rate for positions that
+/// predate epoch tracking, or whose epoch was reused after all its locks had run out (see
+/// `start_reward_epoch`).
+fn position_relock_bonus_bps(pool: &Pool, stake: &VestingStake, epochs: Option<&RewardEpochs>) -> Result<u16> {
+    if stake.reward_epoch == 0 || stake.reward_epoch == pool.reward_epoch {
+        return Ok(pool.relock_bonus_bps);
+    }
+    let epochs = epochs.ok_or(AmmError::RewardEpochsRequired)?;
+    Ok(epochs
+        .get(stake.reward_epoch)
+        .map_or(pool.relock_bonus_bps, |e| e.relock_bonus_bps))
+}
 
-    pub token_program: Program<'info, Token>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+/// LP reward settled when a matured position is claimed or relocked: (pending, pending with streak bonus).
+fn relock_reward(
+    pool: &Pool,
+    vesting: &VestingStake,
+    epochs: Option<&RewardEpochs>,
+    reward_vault_amount: u64,
+) -> Result<(u64, u64)> {
+    let pending = pending_reward_amount(vesting.amount, pool.acc_reward_per_lp, vesting.reward_debt)?;
+    let bonus_bps = position_relock_bonus_bps(pool, vesting, epochs)?;
+    Ok((
+        pending,
+        apply_relock_bonus(bonus_bps, vesting.relock_count, pending, pool.reward_surplus(reward_vault_amount)),
+    ))
 }
 
-impl<'info> Withdraw<'info> {
-    fn burn_lp_context(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
-        let cpi_accounts = Burn {
-            mint: self.lp_mint.to_account_info().clone(),
-            from: self.user_lp_token_account.to_account_info().clone(),
-            authority: self.user.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
-    }
+/// Token accounts a matured claim pays out of and into.
+struct ClaimPayout<'a, 'info> {
+    token_program: AccountInfo<'info>,
+    vesting_token_account: &'a Account<'info, TokenAccount>,
+    lp_destination: AccountInfo<'info>,
+    reward_vault: &'a Account<'info, TokenAccount>,
+    reward_destination: AccountInfo<'info>,
+    rent_payer: AccountInfo<'info>,
+}
 
-    fn transfer_a_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_a.to_account_info().clone(),
-            to: self.user_token_a.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+/// Settle a matured position for `claim_vested` and `claim_all_matured`: pay its LP, then its LP
+/// reward with the relock bonus of its reward epoch, close the emptied vault to the rent payer,
+/// release the reward entitlement and refresh the optional `GlobalStats` / `PoolView`. Token A/B
+/// rewards, receipts and closing the position itself are left to the caller. Returns the LP reward
+/// paid; fails rather than forfeiting it when the reward vault can't cover it.
+fn settle_matured_claim<'info>(
+    pool: &mut Account<'info, Pool>,
+    vesting: &Account<'info, VestingStake>,
+    epochs: Option<&RewardEpochs>,
+    accounts: ClaimPayout<'_, 'info>,
+    global_stats: Option<&mut GlobalStats>,
+    pool_view: Option<&mut PoolView>,
+    now: i64,
+) -> Result<u64> {
+    let (pending, reward_with_bonus) = relock_reward(pool, vesting, epochs, accounts.reward_vault.amount)?;
+    require_gte_ctx!(accounts.reward_vault.amount, reward_with_bonus, AmmError::RewardVaultInsufficient);
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            accounts.token_program.clone(),
+            Transfer {
+                from: accounts.vesting_token_account.to_account_info(),
+                to: accounts.lp_destination,
+                authority: vesting.to_account_info(),
+            },
+            vesting_signer!(vesting),
+        ),
+        vesting.amount,
+    )?;
+    if reward_with_bonus > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.clone(),
+                Transfer {
+                    from: accounts.reward_vault.to_account_info(),
+                    to: accounts.reward_destination,
+                    authority: pool.to_account_info(),
+                },
+                pool_signer!(pool),
+            ),
+            reward_with_bonus,
+        )?;
+    }
+    // The cached vault balance is pre-transfer, so equality means the vault is now empty
+    if accounts.vesting_token_account.amount == vesting.amount {
+        token::close_account(CpiContext::new_with_signer(
+            accounts.token_program,
+            CloseAccount {
+                account: accounts.vesting_token_account.to_account_info(),
+                destination: accounts.rent_payer,
+                authority: vesting.to_account_info(),
+            },
+            vesting_signer!(vesting),
+        ))?;
     }
 
-    fn transfer_b_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_b.to_account_info().clone(),
-            to: self.user_token_b.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    pool.release_reward_liability(pending);
+    pool.total_locked_lp = pool.total_locked_lp.saturating_sub(vesting.amount);
+    if let Some(stats) = global_stats {
+        stats.record_position_closed(now);
     }
+    if let Some(view) = pool_view {
+        view.sync_pool(pool, now);
+    }
+    Ok(reward_with_bonus)
 }
 
-#[derive(Accounts)]
-pub struct Swap<'info> {
-    #[account(mut, has_one = lp_mint, seeds = [b"pool", lp_mint.key().as_ref()], bump)]
-    pub pool: Account<'info, Pool>,
-    #[account(mut)]
-    pub lp_mint: Account<'info, Mint>,
-    #[account(mut, token::mint = token_a_mint)]
-    pub reserve_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint)]
-    pub reserve_b: Account<'info, TokenAccount>,
+/// Start a new lock of `vesting_seconds` on a matured position whose `pending` LP reward was settled.
+fn roll_position(pool: &mut Pool, vesting: &mut VestingStake, pending: u64, vesting_seconds: i64, now: i64) {
+    pool.release_reward_liability(pending);
+    vesting.reward_debt = (u128::from(vesting.amount) * pool.acc_reward_per_lp) / REWARD_SCALE;
+    vesting.reward_epoch = pool.reward_epoch;
+    vesting.vesting_end = now + vesting_seconds;
+    vesting.held_since = now;
+    vesting.matured = false;
+    vesting.relock_count = vesting.relock_count.saturating_add(1);
+}
 
-    #[account(mut)]
-    pub user: Signer<'info>,
-    #[account(mut, token::mint = token_a_mint, token::authority = user)]
-    pub user_token_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint, token::authority = user)]
-    pub user_token_b: Account<'info, TokenAccount>,
+/// Start a new reward epoch after a relock bonus change has been applied to `pool`. Once the pool
+/// tracks epochs, every such change must pass its `RewardEpochs`. The new epoch takes the ring slot
+/// of the one `REWARD_EPOCH_RING_SIZE` back, which is refused while positions stamped in that epoch
+/// may still be locked (its successor started less than `MAX_VESTING_SECONDS` ago).
+fn start_reward_epoch(pool: &mut Pool, epochs: Option<&mut RewardEpochs>, now: i64) -> Result<()> {
+    if pool.reward_epoch == 0 {
+        return Ok(());
+    }
+    let epochs = epochs.ok_or(AmmError::RewardEpochsRequired)?;
+    let next_epoch = pool.reward_epoch.checked_add(1).ok_or(AmmError::NumericOverflow)?;
+    let ring_size = REWARD_EPOCH_RING_SIZE as u32;
+    if next_epoch > ring_size {
+        if let Some(successor) = epochs.get(next_epoch - ring_size + 1) {
+            require_gte_ctx!(
+                now,
+                successor.start_ts.saturating_add(MAX_VESTING_SECONDS),
+                AmmError::RewardEpochRingFull
+            );
+        }
+    }
+    pool.reward_epoch = next_epoch;
+    epochs.record(pool, now);
+    Ok(())
+}
 
-    /// Optional treasury token accounts (where treasury fees land)
-    #[account(mut, token::mint = token_a_mint)]
-    pub treasury_token_account_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint)]
-    pub treasury_token_account_b: Account<'info, TokenAccount>,
+/// A pledged position can only move with its pledgee's signature.
+fn check_pledge(stake: &VestingStake, pledgee: Option<&Signer>) -> Result<()> {
+    if stake.pledgee == Pubkey::default() {
+        return Ok(());
+    }
+    match pledgee {
+        Some(signer) if signer.key() == stake.pledgee => Ok(()),
+        _ => err!(AmmError::NotPledgee),
+    }
+}
 
-    pub token_program: Program<'info, Token>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+/// Claims, exits and relocks would change what a buyer pays for, so they wait until an open listing
+/// is bought or cancelled.
+fn check_not_listed(stake: &VestingStake) -> Result<()> {
+    require!(!stake.listed, AmmError::PositionListed);
+    Ok(())
 }
 
-impl<'info> Swap<'info> {
-    fn transfer_in_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.user_token_a.to_account_info().clone(),
-            to: self.reserve_a.to_account_info().clone(),
-            authority: self.user.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+/// `acc + delta`, or `None` when that would pass `ACC_REWARD_PER_LP_CAP`; callers skip the credit
+/// until `rebase_reward_accumulator` brings the accumulator back down.
+fn credit_accumulator(acc: u128, delta: u128) -> Option<u128> {
+    acc.checked_add(delta).filter(|next| *next <= ACC_REWARD_PER_LP_CAP)
+}
+
+/// `reward_debt` re-expressed against an accumulator lowered from `acc` by `base`, keeping the
+/// position's pending reward unchanged.
+fn rebased_debt(amount: u64, reward_debt: u128, acc: u128, base: u128) -> Result<u128> {
+    if amount == 0 {
+        return Ok(0);
     }
-    fn transfer_in_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.user_token_b.to_account_info().clone(),
-            to: self.reserve_b.to_account_info().clone(),
-            authority: self.user.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    let before = u128::from(amount).checked_mul(acc).ok_or(AmmError::NumericOverflow)? / REWARD_SCALE;
+    let after = u128::from(amount) * (acc - base) / REWARD_SCALE;
+    Ok(reward_debt.saturating_sub(before - after))
+}
+
+/// Pending reward for a position of `amount` LP against an accumulator and its debt snapshot.
+fn pending_reward_amount(amount: u64, acc_reward_per_lp: u128, reward_debt: u128) -> Result<u64> {
+    let total_reward = u128::from(amount)
+        .checked_mul(acc_reward_per_lp)
+        .ok_or(AmmError::NumericOverflow)?
+        / REWARD_SCALE;
+    let pending = total_reward.saturating_sub(reward_debt);
+    let pending_u64: u64 = pending.try_into().map_err(|_| AmmError::NumericOverflow)?;
+    Ok(pending_u64)
+}
+
+/// Pay `amount` of an underlying-token reward from its pool vault. Errors if rewards are owed but the
+/// accounts were not passed or the vault can't cover them.
+fn transfer_underlying_reward<'info>(
+    token_program: &Program<'info, Token>,
+    pool: &Account<'info, Pool>,
+    reward_vault: Option<&Account<'info, TokenAccount>>,
+    destination: Option<&Account<'info, TokenAccount>>,
+    amount: u64,
+) -> Result<u64> {
+    if amount == 0 {
+        return Ok(0);
     }
-    fn transfer_out_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_a.to_account_info().clone(),
-            to: self.user_token_a.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    let (reward_vault, destination) = match (reward_vault, destination) {
+        (Some(v), Some(d)) => (v, d),
+        _ => return err!(AmmError::UnderlyingRewardAccountsMissing),
+    };
+    require_gte_ctx!(reward_vault.amount, amount, AmmError::RewardVaultInsufficient);
+    let cpi_accounts = Transfer {
+        from: reward_vault.to_account_info(),
+        to: destination.to_account_info(),
+        authority: pool.to_account_info(),
+    };
+    token::transfer(
+        CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, pool_signer!(pool)),
+        amount,
+    )?;
+    Ok(amount)
+}
+
+/// Pro-rata reward settlement for a partial exit of `exited` LP out of a position of `amount`.
+/// Returns (reward owed on the exited share, reward_debt attributable to the exited share).
+fn split_exited_rewards(
+    amount: u64,
+    exited: u64,
+    reward_debt: u128,
+    acc_reward_per_lp: u128,
+) -> Result<(u64, u128)> {
+    if amount == 0 || exited == 0 {
+        return Ok((0, 0));
     }
-    fn transfer_out_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_b.to_account_info().clone(),
-            to: self.user_token_b.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+    let total_reward = u128::from(amount)
+        .checked_mul(acc_reward_per_lp)
+        .ok_or(AmmError::NumericOverflow)?
+        / REWARD_SCALE;
+    let pending = total_reward.saturating_sub(reward_debt);
+    let exited_reward = pending
+        .checked_mul(u128::from(exited))
+        .ok_or(AmmError::NumericOverflow)?
+        / u128::from(amount);
+    let exited_debt = reward_debt
+        .checked_mul(u128::from(exited))
+        .ok_or(AmmError::NumericOverflow)?
+        / u128::from(amount);
+    let exited_reward_u64: u64 = exited_reward.try_into().map_err(|_| AmmError::NumericOverflow)?;
+    Ok((exited_reward_u64, exited_debt))
+}
+
+/// What an early exit of `lp_amount` from a position settles: the LP penalty and the parts of it routed
+/// to lockers and the insurance fund, and the exited share of each reward stream with the penalty taken
+/// from it.
+struct EarlyExitSettlement {
+    penalty_lp: u64,
+    penalty_to_rewards: u64,
+    penalty_to_insurance: u64,
+    amount_to_user: u64,
+    exited_reward: u64,
+    reward_penalty: u64,
+    reward_penalty_to_insurance: u64,
+    exited_reward_a: u64,
+    reward_penalty_a: u64,
+    exited_reward_b: u64,
+    reward_penalty_b: u64,
+    exited_debt: u128,
+    exited_debt_a: u128,
+    exited_debt_b: u128,
+}
+
+impl EarlyExitSettlement {
+    fn penalty_to_treasury(&self) -> u64 {
+        self.penalty_lp - self.penalty_to_rewards - self.penalty_to_insurance
     }
-    fn transfer_treasury_from_reserve_a_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_a.to_account_info().clone(),
-            to: self.treasury_token_account_a.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+
+    fn reward_penalty_to_treasury(&self) -> u64 {
+        self.reward_penalty - self.reward_penalty_to_insurance
     }
-    fn transfer_treasury_from_reserve_b_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_b.to_account_info().clone(),
-            to: self.treasury_token_account_b.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+
+    fn reward_to_user(&self) -> u64 {
+        self.exited_reward - self.reward_penalty
     }
 }
 
-#[derive(Accounts)]
-pub struct OnlyAuthority<'info> {
-    #[account(mut, has_one = authority)]
-    pub pool: Account<'info, Pool>,
-    pub authority: Signer<'info>,
+/// Shared by `early_unvest` and `early_unvest_to_tokens`: validate the exit and price it. The rate is
+/// the pool's early-unvest penalty unless `penalty_waived` or the position is within
+/// `penalty_grace_seconds` of `vesting_end`; rewards are penalized only when `pool.penalize_rewards`.
+/// `config.insurance_bps` of what the treasury would receive goes to the insurance fund instead.
+fn settle_early_exit(
+    pool: &Pool,
+    config: &ProtocolConfig,
+    vesting: &VestingStake,
+    pledgee: Option<&Signer>,
+    lp_amount: u64,
+    penalty_waived: bool,
+    now: i64,
+) -> Result<EarlyExitSettlement> {
+    require!(!vesting.claimed, AmmError::AlreadyClaimed);
+    require_lte_ctx!(lp_amount, vesting.amount, AmmError::InsufficientVestedAmount);
+    check_pledge(vesting, pledgee)?;
+    check_not_listed(vesting)?;
+
+    let grace = pool.penalty_grace_seconds;
+    let in_grace = grace > 0 && now >= vesting.vesting_end.saturating_sub(grace);
+    let penalty_bps = if penalty_waived || in_grace { 0 } else { pool.early_unvest_penalty(config) };
+    let penalty_of = |amount: u64| (u128::from(amount) * u128::from(penalty_bps) / 10_000u128) as u64;
+    let reward_penalty_of = |amount: u64| if pool.penalize_rewards { penalty_of(amount) } else { 0 };
+
+    let insurance_of = |amount: u64| (u128::from(amount) * u128::from(config.insurance_bps) / 10_000u128) as u64;
+
+    let penalty_lp = penalty_of(lp_amount);
+    let penalty_to_rewards = (u128::from(penalty_lp) * u128::from(pool.penalty_to_rewards_bps) / 10_000u128) as u64;
+    let (exited_reward, exited_debt) =
+        split_exited_rewards(vesting.amount, lp_amount, vesting.reward_debt, pool.acc_reward_per_lp)?;
+    let reward_penalty = reward_penalty_of(exited_reward);
+    let (exited_reward_a, exited_debt_a) =
+        split_exited_rewards(vesting.amount, lp_amount, vesting.reward_debt_a, pool.acc_reward_a_per_lp)?;
+    let (exited_reward_b, exited_debt_b) =
+        split_exited_rewards(vesting.amount, lp_amount, vesting.reward_debt_b, pool.acc_reward_b_per_lp)?;
+    Ok(EarlyExitSettlement {
+        penalty_lp,
+        penalty_to_rewards,
+        penalty_to_insurance: insurance_of(penalty_lp - penalty_to_rewards),
+        amount_to_user: lp_amount - penalty_lp,
+        exited_reward,
+        reward_penalty,
+        reward_penalty_to_insurance: insurance_of(reward_penalty),
+        exited_reward_a,
+        reward_penalty_a: reward_penalty_of(exited_reward_a),
+        exited_reward_b,
+        reward_penalty_b: reward_penalty_of(exited_reward_b),
+        exited_debt,
+        exited_debt_a,
+        exited_debt_b,
+    })
 }
 
-#[derive(Accounts)]
-pub struct EmergencyWithdraw<'info> {
-    #[account(mut, has_one = authority, has_one = reserve_a, has_one = reserve_b)]
-    pub pool: Account<'info, Pool>,
-    pub authority: Signer<'info>,
-    #[account(mut, token::mint = token_a_mint)]
-    pub reserve_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint)]
-    pub reserve_b: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_a_mint)]
-    pub treasury_token_account_a: Account<'info, TokenAccount>,
-    #[account(mut, token::mint = token_b_mint)]
-    pub treasury_token_account_b: Account<'info, TokenAccount>,
-    pub token_program: Program<'info, Token>,
-    pub token_a_mint: Account<'info, Mint>,
-    pub token_b_mint: Account<'info, Mint>,
+/// Pay the exited share of one underlying-token reward stream: the penalty to the treasury account,
+/// the rest to the user. Returns what the user received.
+fn pay_exited_underlying_reward<'info>(
+    token_program: &Program<'info, Token>,
+    pool: &Account<'info, Pool>,
+    reward_vault: Option<&Account<'info, TokenAccount>>,
+    treasury: Option<&Account<'info, TokenAccount>>,
+    user: Option<&Account<'info, TokenAccount>>,
+    exited_reward: u64,
+    reward_penalty: u64,
+) -> Result<u64> {
+    transfer_underlying_reward(token_program, pool, reward_vault, treasury, reward_penalty)?;
+    transfer_underlying_reward(token_program, pool, reward_vault, user, exited_reward - reward_penalty)
 }
 
-impl<'info> EmergencyWithdraw<'info> {
-    fn transfer_reserve_a_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_a.to_account_info().clone(),
-            to: self.treasury_token_account_a.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
-    }
-    fn transfer_reserve_b_to_treasury_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self.reserve_b.to_account_info().clone(),
-            to: self.treasury_token_account_b.to_account_info().clone(),
-            authority: self.pool.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+/// Book a settled early exit: release the exited share's reward liability and locked LP, and shrink
+/// the position and its debts. Returns whether the position is now fully exited.
+fn book_early_exit(
+    pool: &mut Pool,
+    vesting: &mut VestingStake,
+    lp_amount: u64,
+    settlement: &EarlyExitSettlement,
+) -> Result<bool> {
+    pool.release_reward_liability(settlement.exited_reward);
+    pool.total_locked_lp = pool.total_locked_lp.saturating_sub(lp_amount);
+    vesting.amount = vesting.amount.checked_sub(lp_amount).ok_or(AmmError::NumericOverflow)?;
+    vesting.reward_debt =
+        vesting.reward_debt.checked_sub(settlement.exited_debt).ok_or(AmmError::NumericOverflow)?;
+    vesting.reward_debt_a =
+        vesting.reward_debt_a.checked_sub(settlement.exited_debt_a).ok_or(AmmError::NumericOverflow)?;
+    vesting.reward_debt_b =
+        vesting.reward_debt_b.checked_sub(settlement.exited_debt_b).ok_or(AmmError::NumericOverflow)?;
+    let fully_exited = vesting.amount == 0;
+    if fully_exited {
+        vesting.claimed = true;
     }
+    Ok(fully_exited)
 }
 
-// ---------------------- Helpers ----------------------
-
+/// The first deposit mints `sqrt(a * b)` in whole-token terms, expressed in `LP_DECIMALS`, so the
+/// LP magnitude doesn't depend on the pair's decimals. Later deposits mint pro rata.
 fn calculate_lp_mint_amount(
     amount_a: u64,
     amount_b: u64,
     reserve_a: u64,
     reserve_b: u64,
     lp_supply: u64,
+    decimals_a: u8,
+    decimals_b: u8,
 ) -> Result<u64> {
     if lp_supply == 0 {
         let prod = u128::from(amount_a)
             .checked_mul(u128::from(amount_b))
             .ok_or(AmmError::NumericOverflow)?;
-        let minted = integer_sqrt_u128(prod) as u64;
+        // sqrt(prod * 10^e) with e = 2 * LP_DECIMALS - decimals_a - decimals_b; an odd e puts one
+        // factor of 10 under the root
+        let e = 2 * i32::from(LP_DECIMALS) - i32::from(decimals_a) - i32::from(decimals_b);
+        let odd = e.rem_euclid(2);
+        let root = integer_sqrt_u128(if odd == 1 {
+            prod.checked_mul(10).ok_or(AmmError::NumericOverflow)?
+        } else {
+            prod
+        });
+        let half = (e - odd) / 2;
+        let scale = 10u128.pow(half.unsigned_abs());
+        let minted = if half >= 0 {
+            root.checked_mul(scale).ok_or(AmmError::NumericOverflow)?
+        } else {
+            root / scale
+        };
+        let minted = u64::try_from(minted).map_err(|_| AmmError::NumericOverflow)?;
         require!(minted > 0, AmmError::InsufficientLiquidity);
         Ok(minted)
     } else {
@@ -950,4 +15015,284 @@ pub enum AmmError {
     InvalidPenalty,
     #[msg("Insufficient vested amount")]
     InsufficientVestedAmount,
+    #[msg("Depositor is not on the pool allowlist")]
+    NotAllowlisted,
+    #[msg("Abandoned-position sweeping is disabled for this pool")]
+    SweepDisabled,
+    #[msg("Position has not passed the abandonment period")]
+    NotAbandoned,
+    #[msg("Invalid abandonment period")]
+    InvalidAbandonmentPeriod,
+    #[msg("Treasury account does not match the pool")]
+    InvalidTreasuryAccount,
+    #[msg("Swap amount must be greater than zero")]
+    ZeroSwapAmount,
+    #[msg("Deposit amounts must not both be zero")]
+    ZeroDepositAmount,
+    #[msg("One-sided deposits are not supported; provide both token A and token B")]
+    OneSidedDeposit,
+    #[msg("LP amount must be greater than zero")]
+    ZeroLpAmount,
+    #[msg("Fee exceeds the protocol maximum")]
+    FeeTooHigh,
+    #[msg("Reward emission exceeds the protocol maximum")]
+    EmissionTooHigh,
+    #[msg("Invalid reward vault")]
+    InvalidRewardVault,
+    #[msg("Governance realm is not configured for this pool")]
+    GovernanceNotConfigured,
+    #[msg("Invalid vesting account")]
+    InvalidVestingAccount,
+    #[msg("Duplicate vesting account")]
+    DuplicateVestingAccount,
+    #[msg("This epoch has already been snapshotted")]
+    EpochAlreadySnapshotted,
+    #[msg("Deposit is below the pool minimum")]
+    DepositTooSmall,
+    #[msg("Swap would decrease the pool invariant")]
+    InvariantViolated,
+    #[msg("Address is on the sanctions blocklist")]
+    AddressBlocked,
+    #[msg("Blocklist account is required in compliance mode")]
+    BlocklistEntryMissing,
+    #[msg("Only registered market makers can swap during the bootstrap phase")]
+    NotMarketMaker,
+    #[msg("Swap allowlist deadline has not passed")]
+    SwapAllowlistStillActive,
+    #[msg("Deadline must be in the future")]
+    InvalidDeadline,
+    #[msg("Unknown reward mode")]
+    InvalidRewardMode,
+    #[msg("Underlying reward vault or token accounts are required")]
+    UnderlyingRewardAccountsMissing,
+    #[msg("Treasury asset must be the pool's token A or B mint")]
+    InvalidTreasuryAsset,
+    #[msg("Vesting position is pledged")]
+    PositionPledged,
+    #[msg("Vesting position is listed for sale")]
+    PositionListed,
+    #[msg("Pledgee signature required")]
+    NotPledgee,
+    #[msg("Listing is invalid or no longer matches the position")]
+    InvalidListing,
+    #[msg("Scheduled action is not active yet")]
+    ScheduledActionNotReady,
+    #[msg("Portfolio is full")]
+    PortfolioFull,
+    #[msg("An observation was recorded too recently")]
+    ObservationTooRecent,
+    #[msg("First deposit ratio is too far from the oracle price")]
+    InitialPriceOutOfRange,
+    #[msg("Reserves can only be replaced before the first deposit")]
+    ReservesAlreadyFunded,
+    #[msg("Treasury routing to rewards is disabled")]
+    TreasuryRoutingDisabled,
+    #[msg("Treasury was routed to rewards too recently")]
+    TreasuryRouteTooSoon,
+    #[msg("Unsupported curve type for this operation")]
+    InvalidCurveType,
+    #[msg("Amplification coefficient out of range")]
+    InvalidAmp,
+    #[msg("Ramp starts too soon after the previous one or ends too early")]
+    InvalidRamp,
+    #[msg("Multi-asset pools hold between 3 and 4 reserves")]
+    InvalidReserveCount,
+    #[msg("Accounts or amounts do not match the multi-asset pool's reserves")]
+    InvalidMultiPoolAccounts,
+    #[msg("LP metadata is too long or its accounts are missing")]
+    InvalidLpMetadata,
+    #[msg("Pool is de-pegged; the authority must clear it")]
+    PoolDepegged,
+    #[msg("De-peg threshold must be at most 10000 bps")]
+    InvalidDepegThreshold,
+    #[msg("Deposit id is below the user's vesting index; pick a fresh id")]
+    DepositIdTooLow,
+    #[msg("Reserve accounts do not match the pool")]
+    ReserveMismatch,
+    #[msg("Vesting vault is empty; nothing to rescue")]
+    NothingToRescue,
+    #[msg("This pool tracks reward epochs; pass its RewardEpochs account")]
+    RewardEpochsRequired,
+    #[msg("Penalty grace window must be between 0 and 7 days")]
+    InvalidPenaltyGrace,
+    #[msg("Fee holiday needs a future window and a rate no higher than the pool fee")]
+    InvalidFeeHoliday,
+    #[msg("This pool keeps claim receipts; pass the ClaimReceipt account")]
+    ClaimReceiptRequired,
+    #[msg("Claim receipt period must be between 0 and 365 days")]
+    InvalidClaimReceiptPeriod,
+    #[msg("Claim receipt is still within its retention period")]
+    ClaimReceiptNotExpired,
+    #[msg("LP mint must use LP_DECIMALS (9) decimals")]
+    InvalidLpDecimals,
+    #[msg("Swaps in this direction are paused")]
+    SwapDirectionPaused,
+    #[msg("Drip must run for at least one slot")]
+    InvalidDripSlots,
+    #[msg("Nothing has accrued since the last drip crank")]
+    DripNotDue,
+    #[msg("Pool is being wound down by an emergency drip")]
+    EmergencyDripActive,
+    #[msg("Pool is not flagged compromised")]
+    PoolNotCompromised,
+    #[msg("Invalid pool hook program")]
+    InvalidPoolHook,
+    #[msg("Pool has a hook: pass the pool_hook account and the hook program and accounts")]
+    PoolHookRequired,
+    #[msg("Hook program or accounts do not match the registered pool hook")]
+    PoolHookMismatch,
+    #[msg("Token mint has a freeze authority and no admin-approved TokenBadge")]
+    TokenNotBadged,
+    #[msg("Insurance routing is disabled")]
+    InsuranceDisabled,
+    #[msg("Insurance fund cannot cover the payout")]
+    InsufficientInsuranceFund,
+    #[msg("Trader points redemption is disabled")]
+    PointsRedemptionDisabled,
+    #[msg("No trader points to redeem, or no reward surplus to pay them")]
+    NoPointsToRedeem,
+    #[msg("Too many allowed callers")]
+    TooManyAllowedCallers,
+    #[msg("Pool restricts swap callers: pass allowed_callers and the instructions sysvar")]
+    CallerCheckAccountsMissing,
+    #[msg("Calling program is not allowed to swap on this pool")]
+    CallerNotAllowed,
+    #[msg("Position is already marked matured")]
+    AlreadyMatured,
+    #[msg("Invalid claim bounty")]
+    InvalidClaimBounty,
+    #[msg("Position needs its owner's accounts to claim; use claim_vested")]
+    ClaimNeedsOwner,
+    #[msg("Council members must be set and distinct")]
+    InvalidCouncilMembers,
+    #[msg("Signer is not a council member")]
+    NotCouncilMember,
+    #[msg("Council membership changed since the proposal was opened")]
+    CouncilProposalStale,
+    #[msg("No matching open council proposal")]
+    CouncilProposalNotFound,
+    #[msg("Council proposal expired")]
+    CouncilProposalExpired,
+    #[msg("Member already approved this proposal")]
+    CouncilAlreadyApproved,
+    #[msg("Reserve accounts must start empty")]
+    ReserveNotEmpty,
+    #[msg("Token A and token B must be different mints")]
+    IdenticalMints,
+    #[msg("Reserve A and reserve B must be different accounts")]
+    ReservesNotDistinct,
+    #[msg("The LP mint cannot be one of the pool's tokens")]
+    LpMintIsPoolToken,
+    #[msg("Fee vote needs 1..=MAX_FEE_VOTE_OPTIONS options and a valid choice")]
+    InvalidFeeVoteOptions,
+    #[msg("Fee vote is closed")]
+    FeeVoteClosed,
+    #[msg("Fee vote is still open")]
+    FeeVoteOpen,
+    #[msg("Fee vote already finalized")]
+    FeeVoteFinalized,
+    #[msg("Only still-locked positions can vote")]
+    PositionNotLocked,
+    #[msg("TWAP order needs 1..=MAX_TWAP_SLICES slices, at least one input unit per slice and pool-matching mints")]
+    InvalidTwapOrder,
+    #[msg("Next TWAP slice is not due yet")]
+    TwapSliceTooSoon,
+    #[msg("Limit order needs a non-zero amount and price and pool-matching mints")]
+    InvalidLimitOrder,
+    #[msg("Limit order expired")]
+    LimitOrderExpired,
+    #[msg("Pool does not pay the limit price yet")]
+    LimitPriceNotReached,
+    #[msg("DCA order needs a non-zero amount and interval, total >= amount_per_interval and pool-matching mints")]
+    InvalidDcaOrder,
+    #[msg("Next DCA fill is not due yet")]
+    DcaFillTooSoon,
+    #[msg("Gauge epoch duration must be positive")]
+    InvalidGaugeEpoch,
+    #[msg("Pool already pulled its gauge share this epoch")]
+    GaugeAlreadySynced,
+    #[msg("Pool emission is set by the gauge controller")]
+    EmissionSetByGauge,
+    #[msg("Reserve utilization cap must be at most 10_000 bps")]
+    InvalidReserveCap,
+    #[msg("Trade takes more of the output reserve than the pool allows")]
+    ReserveUtilizationExceeded,
+    #[msg("LP destination must be the user's LP token account, or their not-yet-created LP ATA")]
+    InvalidLpDestination,
+    #[msg("Creating the LP ATA needs the associated token and system programs")]
+    AtaProgramsRequired,
+    #[msg("Creating the treasury ATA needs the treasury owner and the associated token and system programs")]
+    TreasuryAtaAccountsMissing,
+    #[msg("Pool is above the co-sign threshold: the upgrade authority or protocol admin must co-sign")]
+    CosignerRequired,
+    #[msg("Fair LP pricing is only defined for constant-product pools")]
+    FairPriceUnsupportedCurve,
+    #[msg("Price observations do not span any time yet; no TWAP available")]
+    TwapUnavailable,
+    #[msg("Position has no auto-relock duration set")]
+    AutoRelockNotSet,
+    #[msg("Position is set to auto-relock; its owner must clear that before it can be crank-claimed")]
+    AutoRelockEnabled,
+    #[msg("Trader rebate must be at most 10_000 bps")]
+    InvalidTraderRebate,
+    #[msg("Creating the trader's rebate position needs the system program")]
+    TraderRebateAccountsMissing,
+    #[msg("Pools must be distinct and trade the same token pair")]
+    PoolPairMismatch,
+    #[msg("Invalid bond market terms or payout mint")]
+    InvalidBondTerms,
+    #[msg("Bond market is not active")]
+    BondMarketInactive,
+    #[msg("Bond payout exceeds the market's remaining capacity")]
+    BondCapacityExceeded,
+    #[msg("Nothing vested to redeem yet")]
+    NothingToRedeem,
+    #[msg("Stream needs a positive total and start <= cliff <= end with start < end")]
+    InvalidStream,
+    #[msg("Epoch reward settlement is already enabled for this pool")]
+    EpochRewardsEnabled,
+    #[msg("The next epoch to finalize has not ended yet")]
+    EpochNotEnded,
+    #[msg("No reward accumulator has reached the rebase threshold")]
+    RebaseNotNeeded,
+    #[msg("Rebase must include every open position of the pool")]
+    RebaseIncomplete,
+    #[msg("Reward accumulator is at its cap; rebase it first")]
+    RewardAccumulatorCapped,
+    #[msg("Claim voucher has expired")]
+    VoucherExpired,
+    #[msg("Missing or invalid ed25519 claim voucher")]
+    InvalidVoucher,
+    #[msg("Penalty waiver must last 1..=MAX_PENALTY_WAIVER_SECONDS")]
+    InvalidWaiverDuration,
+    #[msg("Penalty waiver voting has ended")]
+    WaiverVoteClosed,
+    #[msg("Penalty waiver vote is still open")]
+    WaiverVoteOpen,
+    #[msg("Penalty waiver vote already finalized")]
+    WaiverVoteFinalized,
+    #[msg("Penalty waiver is still running")]
+    WaiverActive,
+    #[msg("Reward destination must be an unfrozen LP token account not owned by the pool")]
+    InvalidRewardDestination,
+    #[msg("The registered reward destination account must be passed")]
+    RewardDestinationMissing,
+    #[msg("Position has no pending rewards")]
+    NoPendingRewards,
+    #[msg("Reward vault cannot cover the pending reward")]
+    RewardVaultInsufficient,
+    #[msg("Vesting vault is owned by neither the position nor the pool")]
+    VaultAuthorityUnknown,
+    #[msg("Reward epoch ring is full: positions of the oldest epoch may still be locked")]
+    RewardEpochRingFull,
+    #[msg("Position was opened, topped up, relocked or bought after the vote opened")]
+    PositionTooNew,
+    #[msg("Gauged pools must trade the gauge controller's reward mint")]
+    GaugeRewardMintNotInPool,
+    #[msg("Gauge vault cannot cover the pool's epoch share")]
+    GaugeVaultInsufficient,
+    #[msg("Insurance payout already paid")]
+    InsurancePayoutPaid,
+    #[msg("The insurance fund for the LP mint must be passed while insurance_bps is set")]
+    InsuranceFundMissing,
 }
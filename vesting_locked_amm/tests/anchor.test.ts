@@ -12,11 +12,15 @@ vesting_locked_amm - initialize / pause / unpause
 
 import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
-import { PublicKey, SystemProgram, Keypair, Connection } from "@solana/web3.js";
+import { PublicKey, SystemProgram, Keypair, Connection, BPF_LOADER_UPGRADEABLE_PROGRAM_ID } from "@solana/web3.js";
 import assert from "assert";
 import {
+  createAccount,
   createMint,
-  getOrCreateAssociatedTokenAccount,
+  getAccount,
+  getMint,
+  mintTo,
+  transfer,
   TOKEN_PROGRAM_ID,
 } from "@solana/spl-token";
 
@@ -30,109 +34,942 @@ const program = pg.program as Program;
 const provider = anchor.AnchorProvider.local();
 anchor.setProvider(provider);
 
+const pda = (...seeds: Array<Buffer | Uint8Array>) => PublicKey.findProgramAddressSync(seeds, program.programId)[0];
+const u64Le = (n: number | bigint) => {
+  const buf = Buffer.alloc(8);
+  buf.writeBigUInt64LE(BigInt(n));
+  return buf;
+};
+
+type PoolFees = { protocolFeeBps: number; treasuryFeeBps: number; rewardFeeBps: number; earlyUnvestPenaltyBps?: number };
+
+// Fresh mints, a pool over them with pool-owned reserves, payer-owned treasuries and the TWAP buffer
+// every swap path requires, and payer token accounts funded with `userFunds` of each side. The protocol
+// config is created on the first run.
+async function createPoolFixture(fees: PoolFees, userFunds = 0n) {
+  const connection: Connection = provider.connection;
+  const payer = (provider.wallet as any).payer as Keypair;
+  const owner = payer.publicKey;
+
+  const tokenA = await createMint(connection, payer, owner, null, 6);
+  const tokenB = await createMint(connection, payer, owner, null, 6);
+  const lpMint = await createMint(connection, payer, owner, null, 9); // LP mints must use LP_DECIMALS
+
+  const treasuryLp = await createAccount(connection, payer, lpMint, owner, Keypair.generate());
+  const treasuryA = await createAccount(connection, payer, tokenA, owner, Keypair.generate());
+  const treasuryB = await createAccount(connection, payer, tokenB, owner, Keypair.generate());
+
+  const protocolConfig = pda(Buffer.from("protocol_config"));
+  // Only the upgrade authority (the Playground wallet that deployed the program) may create it
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+  );
+  if (!(await connection.getAccountInfo(protocolConfig))) {
+    await program.methods
+      .initializeProtocolConfig(1_000, 5_000, new anchor.BN(1_000_000))
+      .accounts({ protocolConfig, admin: owner, programData, systemProgram: SystemProgram.programId })
+      .rpc({ commitment: "confirmed" });
+  }
+
+  const pool = pda(Buffer.from("pool"), lpMint.toBuffer());
+  const poolStats = pda(Buffer.from("pool_stats"), pool.toBuffer());
+  const reserveA = await createAccount(connection, payer, tokenA, pool, Keypair.generate());
+  const reserveB = await createAccount(connection, payer, tokenB, pool, Keypair.generate());
+
+  await program.methods
+    .initializePool(fees.protocolFeeBps, fees.treasuryFeeBps, fees.rewardFeeBps, fees.earlyUnvestPenaltyBps ?? 500, null)
+    .accounts({
+      protocolConfig,
+      pool,
+      poolStats,
+      authority: owner,
+      tokenAMint: tokenA,
+      tokenBMint: tokenB,
+      lpMint,
+      reserveA,
+      reserveB,
+      treasury: treasuryLp,
+      treasuryTokenAccountA: treasuryA,
+      treasuryTokenAccountB: treasuryB,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    })
+    .rpc({ commitment: "confirmed" });
+
+  const priceObservations = pda(Buffer.from("price_observations"), pool.toBuffer());
+  await program.methods
+    .initPriceObservations()
+    .accounts({ pool, priceObservations, payer: owner, systemProgram: SystemProgram.programId })
+    .rpc({ commitment: "confirmed" });
+
+  const userTokenA = await createAccount(connection, payer, tokenA, owner, Keypair.generate());
+  const userTokenB = await createAccount(connection, payer, tokenB, owner, Keypair.generate());
+  const userLp = await createAccount(connection, payer, lpMint, owner, Keypair.generate());
+  if (userFunds > 0n) {
+    await mintTo(connection, payer, tokenA, userTokenA, payer, userFunds);
+    await mintTo(connection, payer, tokenB, userTokenB, payer, userFunds);
+  }
+
+  return {
+    connection,
+    payer,
+    owner,
+    tokenA,
+    tokenB,
+    lpMint,
+    protocolConfig,
+    pool,
+    poolStats,
+    priceObservations,
+    reserveA,
+    reserveB,
+    treasuryLp,
+    treasuryA,
+    treasuryB,
+    userTokenA,
+    userTokenB,
+    userLp,
+  };
+}
+
+type PoolFixture = Awaited<ReturnType<typeof createPoolFixture>>;
+
+const balance = async (fx: PoolFixture, account: PublicKey) =>
+  (await getAccount(fx.connection, account)).amount;
+
+// Deposit both sides into vesting position `depositId` of the payer (90 days), creating the payer's
+// vesting index on first use. Returns the position and its LP vault.
+async function openPosition(fx: PoolFixture, rewardVault: PublicKey, depositId: number, amountA = 2_000_000_000) {
+  const userVestingIndex = pda(Buffer.from("user_vesting_index"), fx.pool.toBuffer(), fx.owner.toBuffer());
+  if (!(await fx.connection.getAccountInfo(userVestingIndex))) {
+    await program.methods
+      .initUserVestingIndex()
+      .accounts({ pool: fx.pool, userVestingIndex, user: fx.owner, systemProgram: SystemProgram.programId })
+      .rpc({ commitment: "confirmed" });
+  }
+  const vestingStake = pda(Buffer.from("vesting"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+  const vestingVault = pda(Buffer.from("vesting_vault"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+  await program.methods
+    .depositAndVest(
+      new anchor.BN(amountA),
+      new anchor.BN(amountA / 2),
+      new anchor.BN(90 * 24 * 60 * 60),
+      Array(32).fill(0),
+      new anchor.BN(depositId)
+    )
+    .accounts({
+      pool: fx.pool,
+      lpMint: fx.lpMint,
+      reserveA: fx.reserveA,
+      reserveB: fx.reserveB,
+      user: fx.owner,
+      userTokenA: fx.userTokenA,
+      userTokenB: fx.userTokenB,
+      userVestingIndex,
+      vestingStake,
+      vestingTokenAccount: vestingVault,
+      rewardVault,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      tokenAMint: fx.tokenA,
+      tokenBMint: fx.tokenB,
+      rentPayer: fx.owner,
+    })
+    .rpc({ commitment: "confirmed" });
+  return { vestingStake, vestingVault };
+}
+
+const REWARD_SCALE = 1_000_000_000_000n;
+const bigint = (n: anchor.BN) => BigInt(n.toString());
+const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+const errorCode = (code: string) => (err: any) => {
+  assert.equal(err?.error?.errorCode?.code, code);
+  return true;
+};
+
 describe("vesting_locked_amm - initialize / pause / unpause", () => {
   it("initialize_pool -> pause -> unpause", async () => {
-    const connection: Connection = provider.connection;
-    const payerKeypair = (provider.wallet as any).payer as Keypair;
-    const payerPubkey = payerKeypair.publicKey;
-
-    // 1) Create SPL mints: tokenA, tokenB, lpMint
-    // createMint(connection, payerKeypair, mintAuthorityPubkey, freezeAuthorityPubkey | null, decimals)
-    const decimals = 6;
-    const tokenA: PublicKey = await createMint(connection, payerKeypair, payerPubkey, null, decimals);
-    const tokenB: PublicKey = await createMint(connection, payerKeypair, payerPubkey, null, decimals);
-    const lpMint: PublicKey = await createMint(connection, payerKeypair, payerPubkey, null, decimals);
-
-    // 2) Create reserve token accounts (ATAs) for pool reserves (owned by payer for test)
-    const reserveAAccount = await getOrCreateAssociatedTokenAccount(connection, payerKeypair, tokenA, payerPubkey);
-    const reserveBAccount = await getOrCreateAssociatedTokenAccount(connection, payerKeypair, tokenB, payerPubkey);
-
-    // 3) Create treasury LP ATA to receive fees/penalties
-    const treasuryLpAta = await getOrCreateAssociatedTokenAccount(connection, payerKeypair, lpMint, payerPubkey);
-
-    // 4) Compute pool PDA (seeds: [b"pool", lp_mint.as_ref()])
-    const [poolPda] = await PublicKey.findProgramAddress([Buffer.from("pool"), lpMint.toBuffer()], program.programId);
-
-    // 5) Call initializePool
-    const protocolFeeBps = 30; // example: 0.30%
-    const treasuryFeeBps = 10;
-    const rewardFeeBps = 20;
-
-    // initializePool (Rust: initialize_pool) -> Anchor JS auto-camel-cases
-    const tx = await program.methods
-      .initializePool(protocolFeeBps, treasuryFeeBps, rewardFeeBps)
+    const fees = { protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 20, earlyUnvestPenaltyBps: 750 };
+    const fx = await createPoolFixture(fees);
+
+    // Fetch the Pool account and assert contents
+    const poolAccount = (await program.account.pool.fetch(fx.pool)) as any;
+    assert.ok(poolAccount, "Pool account should exist");
+    assert.equal(new PublicKey(poolAccount.lpMint).toBase58(), fx.lpMint.toBase58());
+    assert.equal(new PublicKey(poolAccount.tokenAMint).toBase58(), fx.tokenA.toBase58());
+    assert.equal(new PublicKey(poolAccount.tokenBMint).toBase58(), fx.tokenB.toBase58());
+    assert.equal(new PublicKey(poolAccount.treasury).toBase58(), fx.treasuryLp.toBase58());
+    assert.equal(new PublicKey(poolAccount.treasuryA).toBase58(), fx.treasuryA.toBase58());
+    assert.equal(new PublicKey(poolAccount.treasuryB).toBase58(), fx.treasuryB.toBase58());
+    assert.equal(poolAccount.protocolFeeBps, fees.protocolFeeBps);
+    assert.equal(poolAccount.treasuryFeeBps, fees.treasuryFeeBps);
+    assert.equal(poolAccount.rewardFeeBps, fees.rewardFeeBps);
+    assert.equal(poolAccount.lpFeeBps, fees.protocolFeeBps - fees.treasuryFeeBps - fees.rewardFeeBps);
+    assert.equal(poolAccount.earlyUnvestPenaltyBps, fees.earlyUnvestPenaltyBps);
+    assert.equal(Number(poolAccount.vestingNonce), 0);
+    assert.equal(poolAccount.paused, false);
+
+    // Pause the pool (authority-only)
+    await program.methods
+      .pause()
+      .accounts({ pool: fx.pool, operator: fx.owner })
+      .rpc({ commitment: "confirmed" });
+    const poolAfterPause = (await program.account.pool.fetch(fx.pool)) as any;
+    assert.equal(poolAfterPause.paused, true, "Pool should be paused after pause()");
+
+    // Unpause the pool
+    await program.methods
+      .unpause()
+      .accounts({ pool: fx.pool, operator: fx.owner })
+      .rpc({ commitment: "confirmed" });
+    const poolAfterUnpause = (await program.account.pool.fetch(fx.pool)) as any;
+    assert.equal(poolAfterUnpause.paused, false, "Pool should be unpaused after unpause()");
+  }).timeout(90_000);
+});
+
+const SWAP_CASES: Array<[bigint, boolean]> = [
+  [1n, true],
+  [997n, false],
+  [1_000_000n, true],
+  [250_000_000n, false],
+  [4_999_999_999n, true],
+];
+
+// Run one on-chain swap and return the reserves reloaded before and after it, and what the user received.
+async function executeSwap(fx: PoolFixture, amountIn: bigint, isAToB: boolean) {
+  const [reserveA, reserveB] = [await balance(fx, fx.reserveA), await balance(fx, fx.reserveB)];
+  const userOut = isAToB ? fx.userTokenB : fx.userTokenA;
+  const outBefore = await balance(fx, userOut);
+  await program.methods
+    .swap(new anchor.BN(amountIn.toString()), new anchor.BN(0), isAToB, null)
+    .accounts({
+      pool: fx.pool,
+      lpMint: fx.lpMint,
+      reserveA: fx.reserveA,
+      reserveB: fx.reserveB,
+      user: fx.owner,
+      userTokenA: fx.userTokenA,
+      userTokenB: fx.userTokenB,
+      treasuryTokenAccountA: fx.treasuryA,
+      treasuryTokenAccountB: fx.treasuryB,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      tokenAMint: fx.tokenA,
+      tokenBMint: fx.tokenB,
+      poolStats: fx.poolStats,
+      priceObservations: fx.priceObservations,
+    })
+    .rpc({ commitment: "confirmed" });
+  return {
+    before: { reserveA, reserveB },
+    after: { reserveA: await balance(fx, fx.reserveA), reserveB: await balance(fx, fx.reserveB) },
+    received: (await balance(fx, userOut)) - outBefore,
+  };
+}
+
+// Regression: pricing must come from a pre-transfer reserve snapshot and never shrink k. Reserves are
+// funded by minting straight into them after init; each case is quoted, then swapped on-chain and k
+// is checked on the reloaded reserves.
+describe("vesting_locked_amm - swap invariant", () => {
+  it("swap pays the expected x*y=k output and k never decreases", async () => {
+    const fees = { protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 };
+    const fx = await createPoolFixture(fees, 10_000_000_000n);
+    await mintTo(fx.connection, fx.payer, fx.tokenA, fx.reserveA, fx.payer, 5_000_000_000n);
+    await mintTo(fx.connection, fx.payer, fx.tokenB, fx.reserveB, fx.payer, 2_000_000_000n);
+
+    for (const [amountIn, isAToB] of SWAP_CASES) {
+      const quote = (await program.methods
+        .quote(new anchor.BN(amountIn.toString()), isAToB)
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint, reserveA: fx.reserveA, reserveB: fx.reserveB })
+        .view()) as any;
+
+      const { before, after, received } = await executeSwap(fx, amountIn, isAToB);
+      const rIn = isAToB ? before.reserveA : before.reserveB;
+      const rOut = isAToB ? before.reserveB : before.reserveA;
+
+      // Mirror of compute_swap_quote
+      const afterFee = (amountIn * BigInt(10_000 - fees.protocolFeeBps)) / 10_000n;
+      const totalFee = amountIn - afterFee;
+      const treasuryFee = (totalFee * BigInt(fees.treasuryFeeBps)) / BigInt(fees.protocolFeeBps);
+      const expectedOut = rOut - (rIn * rOut) / (rIn + afterFee);
+
+      const label = `${amountIn} (a_to_b=${isAToB})`;
+      assert.equal(BigInt(quote.amountOut.toString()).toString(), expectedOut.toString(), `quote for ${label}`);
+      assert.equal(BigInt(quote.treasuryFee.toString()).toString(), treasuryFee.toString());
+      assert.equal(received.toString(), expectedOut.toString(), `amount received for ${label}`);
+
+      const kBefore = before.reserveA * before.reserveB;
+      const kAfter = after.reserveA * after.reserveB;
+      assert.ok(kAfter >= kBefore, `k decreased for ${label}: ${kAfter} < ${kBefore}`);
+    }
+  }).timeout(120_000);
+});
+
+// Fee-on-output pools price the full input, then withhold the fee (rounded up) from the output.
+describe("vesting_locked_amm - fee on output", () => {
+  it("swap withholds the fee from the output and k never decreases", async () => {
+    const fees = { protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 };
+    const fx = await createPoolFixture(fees, 10_000_000_000n);
+    await program.methods
+      .setFeeOnOutput(true)
+      .accounts({ pool: fx.pool, authority: fx.owner })
+      .rpc({ commitment: "confirmed" });
+    await mintTo(fx.connection, fx.payer, fx.tokenA, fx.reserveA, fx.payer, 5_000_000_000n);
+    await mintTo(fx.connection, fx.payer, fx.tokenB, fx.reserveB, fx.payer, 2_000_000_000n);
+
+    for (const [amountIn, isAToB] of SWAP_CASES) {
+      const quote = (await program.methods
+        .quote(new anchor.BN(amountIn.toString()), isAToB)
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint, reserveA: fx.reserveA, reserveB: fx.reserveB })
+        .view()) as any;
+
+      const { before, after, received } = await executeSwap(fx, amountIn, isAToB);
+      const rIn = isAToB ? before.reserveA : before.reserveB;
+      const rOut = isAToB ? before.reserveB : before.reserveA;
+
+      // Mirror of compute_swap_quote with fee_on_output: fee rounds up, output rounds down
+      const grossOut = rOut - (rIn * rOut) / (rIn + amountIn);
+      const totalFee = (grossOut * BigInt(fees.protocolFeeBps) + 9_999n) / 10_000n;
+      const expectedOut = grossOut - totalFee;
+      const treasuryFee = (totalFee * BigInt(fees.treasuryFeeBps)) / BigInt(fees.protocolFeeBps);
+
+      const label = `${amountIn} (a_to_b=${isAToB})`;
+      assert.equal(BigInt(quote.amountOut.toString()).toString(), expectedOut.toString(), `quote for ${label}`);
+      assert.equal(BigInt(quote.totalFee.toString()).toString(), totalFee.toString());
+      assert.equal(BigInt(quote.treasuryFee.toString()).toString(), treasuryFee.toString());
+      assert.equal(received.toString(), expectedOut.toString(), `amount received for ${label}`);
+
+      const kBefore = before.reserveA * before.reserveB;
+      const kAfter = after.reserveA * after.reserveB;
+      assert.ok(kAfter >= kBefore, `k decreased for ${label}: ${kAfter} < ${kBefore}`);
+    }
+  }).timeout(120_000);
+});
+
+// Mirror of stable_invariant / stable_reserve_out (two coins, Ann = amp * 4) and curve_amount_out
+const stableD = (amp: bigint, x: bigint, y: bigint) => {
+  const ann = amp * 4n;
+  const sum = x + y;
+  let d = sum;
+  for (let i = 0; i < 255; i++) {
+    const dP = (((d * d) / (x * 2n)) * d) / (y * 2n);
+    const prev = d;
+    d = ((ann * sum + dP * 2n) * d) / ((ann - 1n) * d + dP * 3n);
+    if ((d > prev ? d - prev : prev - d) <= 1n) break;
+  }
+  return d;
+};
+const stableOut = (amp: bigint, reserveIn: bigint, reserveOut: bigint, amountIn: bigint) => {
+  const ann = amp * 4n;
+  const d = stableD(amp, reserveIn, reserveOut);
+  const x = reserveIn + amountIn;
+  const c = (((d * d) / (x * 2n)) * d) / (ann * 2n);
+  const b = x + d / ann;
+  let y = d;
+  for (let i = 0; i < 255; i++) {
+    const prev = y;
+    y = (y * y + c) / (y * 2n + b - d);
+    if ((y > prev ? y - prev : prev - y) <= 1n) break;
+  }
+  const out = reserveOut - y;
+  return out > 2n ? out - 2n : 0n;
+};
+
+// Stable curve Newton solver, quoted through `quote` on fee-free pools whose reserves are minted
+// directly: the on-chain iteration must match the mirror exactly, across the amp range and on
+// balanced and lopsided reserves.
+describe("vesting_locked_amm - stable solver", () => {
+  const stableFixture = async (amp: number, reserveA: bigint, reserveB: bigint) => {
+    const fx = await createPoolFixture({ protocolFeeBps: 0, treasuryFeeBps: 0, rewardFeeBps: 0 });
+    await program.methods
+      .setCurveType(1, amp)
+      .accounts({ pool: fx.pool, lpMint: fx.lpMint, tokenAMint: fx.tokenA, tokenBMint: fx.tokenB, authority: fx.owner })
+      .rpc({ commitment: "confirmed" });
+    await mintTo(fx.connection, fx.payer, fx.tokenA, fx.reserveA, fx.payer, reserveA);
+    await mintTo(fx.connection, fx.payer, fx.tokenB, fx.reserveB, fx.payer, reserveB);
+    return fx;
+  };
+  const quoteOut = async (fx: PoolFixture, amountIn: bigint, isAToB: boolean) => {
+    const quote = (await program.methods
+      .quote(new anchor.BN(amountIn.toString()), isAToB)
+      .accounts({ pool: fx.pool, lpMint: fx.lpMint, reserveA: fx.reserveA, reserveB: fx.reserveB })
+      .view()) as any;
+    return BigInt(quote.amountOut.toString());
+  };
+
+  it("converges to the mirrored solution and stays within rounding of 1:1 at balance", async () => {
+    const reserve = 1_000_000_000_000n;
+    const fx = await stableFixture(100, reserve, reserve);
+    for (const amountIn of [1n, 1_000n, 1_000_000n, 10_000_000_000n, 500_000_000_000n]) {
+      const out = await quoteOut(fx, amountIn, true);
+      assert.equal(out.toString(), stableOut(100n, reserve, reserve, amountIn).toString(), `quote for ${amountIn}`);
+      assert.ok(out <= amountIn, `balanced pool paid ${out} for ${amountIn}`);
+    }
+    // Small trades at balance lose at most the two rounding units
+    assert.ok((await quoteOut(fx, 1_000_000n, true)) >= 1_000_000n - 3n);
+  }).timeout(120_000);
+
+  it("amp 1 and MAX_AMP quote without overflow and bracket the curve", async () => {
+    const reserve = 1_000_000_000_000_000n;
+    const amountIn = 100_000_000_000_000n; // 10% of a reserve
+    const flat = await stableFixture(1, reserve, reserve);
+    const steep = await stableFixture(10_000, reserve, reserve);
+    const outFlat = await quoteOut(flat, amountIn, true);
+    const outSteep = await quoteOut(steep, amountIn, false);
+    assert.equal(outFlat.toString(), stableOut(1n, reserve, reserve, amountIn).toString());
+    assert.equal(outSteep.toString(), stableOut(10_000n, reserve, reserve, amountIn).toString());
+
+    // Even amp 1 beats constant product; a high amp approaches 1:1
+    const constantProduct = reserve - (reserve * reserve) / (reserve + amountIn);
+    assert.ok(outFlat > constantProduct, `amp 1 paid ${outFlat}, constant product ${constantProduct}`);
+    assert.ok(outSteep > outFlat && outSteep <= amountIn);
+    assert.ok(amountIn - outSteep < amountIn / 1_000n, `MAX_AMP slippage too high: ${outSteep}`);
+  }).timeout(120_000);
+
+  it("prices imbalanced reserves toward the scarce side and never drains the out reserve", async () => {
+    const reserveA = 900_000_000_000n;
+    const reserveB = 100_000_000_000n;
+    const fx = await stableFixture(50, reserveA, reserveB);
+
+    // Selling the abundant side pays less than 1:1, selling the scarce side more
+    const aToB = await quoteOut(fx, 1_000_000n, true);
+    const bToA = await quoteOut(fx, 1_000_000n, false);
+    assert.equal(aToB.toString(), stableOut(50n, reserveA, reserveB, 1_000_000n).toString());
+    assert.equal(bToA.toString(), stableOut(50n, reserveB, reserveA, 1_000_000n).toString());
+    assert.ok(aToB < 1_000_000n && bToA > 1_000_000n, `a->b ${aToB}, b->a ${bToA}`);
+
+    // Output grows with input but stays below the out reserve however large the trade
+    let previous = 0n;
+    for (const amountIn of [1_000_000_000n, 100_000_000_000n, 10_000_000_000_000n]) {
+      const out = await quoteOut(fx, amountIn, true);
+      assert.equal(out.toString(), stableOut(50n, reserveA, reserveB, amountIn).toString());
+      assert.ok(out > previous && out < reserveB, `a->b ${amountIn} paid ${out}`);
+      previous = out;
+    }
+  }).timeout(180_000);
+});
+
+// Every token CPI whose authority is a program PDA (the pool, a vesting position, an order) has to sign
+// with that PDA's seeds. These run the signed paths that need no clock warp; claims and sweeps are
+// time-gated by MIN_VESTING_SECONDS and can't be reached on a live validator.
+describe("vesting_locked_amm - PDA-signed transfers", () => {
+  it("pool mints and pays reserves, the vesting PDA pays and closes its vault", async () => {
+    const fx = await createPoolFixture({ protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 }, 10_000_000_000n);
+    const rewardVault = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.owner, Keypair.generate());
+
+    const userVestingIndex = pda(Buffer.from("user_vesting_index"), fx.pool.toBuffer(), fx.owner.toBuffer());
+    await program.methods
+      .initUserVestingIndex()
+      .accounts({ pool: fx.pool, userVestingIndex, user: fx.owner, systemProgram: SystemProgram.programId })
+      .rpc({ commitment: "confirmed" });
+
+    // Deposit: the pool PDA mints LP into the position vault
+    const depositId = 0;
+    const vestingStake = pda(Buffer.from("vesting"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+    const vestingVault = pda(Buffer.from("vesting_vault"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+    await program.methods
+      .depositAndVest(
+        new anchor.BN(2_000_000_000),
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(90 * 24 * 60 * 60),
+        Array(32).fill(0),
+        new anchor.BN(depositId)
+      )
       .accounts({
-        pool: poolPda,
-        authority: payerPubkey,
-        tokenAMint: tokenA,
-        tokenBMint: tokenB,
-        lpMint: lpMint,
-        reserveA: reserveAAccount.address,
-        reserveB: reserveBAccount.address,
-        treasury: treasuryLpAta.address,
+        pool: fx.pool,
+        lpMint: fx.lpMint,
+        reserveA: fx.reserveA,
+        reserveB: fx.reserveB,
+        user: fx.owner,
+        userTokenA: fx.userTokenA,
+        userTokenB: fx.userTokenB,
+        userVestingIndex,
+        vestingStake,
+        vestingTokenAccount: vestingVault,
+        rewardVault,
         tokenProgram: TOKEN_PROGRAM_ID,
         systemProgram: SystemProgram.programId,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenAMint: fx.tokenA,
+        tokenBMint: fx.tokenB,
+        rentPayer: fx.owner,
       })
-      // payer is creating the pool / is current mint authority in test
-      .signers([payerKeypair])
-      .rpc();
+      .rpc({ commitment: "confirmed" });
+    const vested = await balance(fx, vestingVault);
+    assert.ok(vested > 0n, "deposit should mint LP into the vesting vault");
 
-    console.log("initialize_pool tx:", tx);
-    await connection.confirmTransaction(tx, "confirmed");
+    // Early exit of the whole position: the vesting PDA pays out, then closes its vault
+    await program.methods
+      .earlyUnvest(new anchor.BN(vested.toString()))
+      .accounts({
+        pool: fx.pool,
+        protocolConfig: fx.protocolConfig,
+        lpMint: fx.lpMint,
+        vestingStake,
+        vestingTokenAccount: vestingVault,
+        userLpTokenAccount: fx.userLp,
+        treasuryLpAccount: fx.treasuryLp,
+        user: fx.owner,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rentPayer: fx.owner,
+      })
+      .rpc({ commitment: "confirmed" });
+    const unlocked = await balance(fx, fx.userLp);
+    assert.ok(unlocked > 0n && unlocked < vested, "early exit should pay the LP minus the penalty");
+    assert.ok(await balance(fx, fx.treasuryLp) > 0n, "penalty should reach the treasury");
+    assert.equal(await fx.connection.getAccountInfo(vestingVault), null, "vault should be closed on full exit");
 
-    // 6) Fetch the Pool account and assert contents
-    const poolAccount = (await program.account.pool.fetch(poolPda)) as any;
-    assert.ok(poolAccount, "Pool account should exist");
+    // Withdraw: the pool PDA pays both reserves out
+    const [aBefore, bBefore] = [await balance(fx, fx.userTokenA), await balance(fx, fx.userTokenB)];
+    await program.methods
+      .withdrawUnlocked(new anchor.BN(unlocked.toString()))
+      .accounts({
+        pool: fx.pool,
+        lpMint: fx.lpMint,
+        reserveA: fx.reserveA,
+        reserveB: fx.reserveB,
+        user: fx.owner,
+        userLpTokenAccount: fx.userLp,
+        userTokenA: fx.userTokenA,
+        userTokenB: fx.userTokenB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        tokenAMint: fx.tokenA,
+        tokenBMint: fx.tokenB,
+      })
+      .rpc({ commitment: "confirmed" });
+    assert.ok(await balance(fx, fx.userTokenA) > aBefore, "withdraw should pay token A");
+    assert.ok(await balance(fx, fx.userTokenB) > bBefore, "withdraw should pay token B");
+  }).timeout(120_000);
 
-    // Convert on-chain pubkey-like values to PublicKey to call toBase58()
-    const lpMintOnChain = new PublicKey(poolAccount.lpMint as string);
-    const tokenAOnChain = new PublicKey(poolAccount.tokenAMint as string);
-    const tokenBOnChain = new PublicKey(poolAccount.tokenBMint as string);
-    const treasuryOnChain = new PublicKey(poolAccount.treasury as string);
-
-    assert.equal(lpMintOnChain.toBase58(), lpMint.toBase58());
-    assert.equal(tokenAOnChain.toBase58(), tokenA.toBase58());
-    assert.equal(tokenBOnChain.toBase58(), tokenB.toBase58());
-    assert.equal(treasuryOnChain.toBase58(), treasuryLpAta.address.toBase58());
-    assert.equal(poolAccount.protocolFeeBps, protocolFeeBps);
-    assert.equal(poolAccount.treasuryFeeBps, treasuryFeeBps);
-    assert.equal(poolAccount.rewardFeeBps, rewardFeeBps);
-
-    // vestingNonce may be a BN-like; handle safely
-    const vestingNonceNum =
-      poolAccount.vestingNonce && typeof (poolAccount.vestingNonce as any).toNumber === "function"
-        ? (poolAccount.vestingNonce as any).toNumber()
-        : Number(poolAccount.vestingNonce);
-    assert.equal(vestingNonceNum, 0);
-    assert.equal(poolAccount.paused, false);
+  it("limit order escrow is paid out by the order PDA on fill and on cancel", async () => {
+    const fx = await createPoolFixture({ protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 }, 1_000_000_000n);
+    await mintTo(fx.connection, fx.payer, fx.tokenA, fx.reserveA, fx.payer, 5_000_000_000n);
+    await mintTo(fx.connection, fx.payer, fx.tokenB, fx.reserveB, fx.payer, 2_000_000_000n);
 
-    // 7) Pause the pool (authority-only)
-    const txPause = await program.methods
-      .pause()
+    const expiry = new anchor.BN(Math.floor(Date.now() / 1000) + 3_600);
+    const placeOrder = async (orderId: number) => {
+      const limitOrder = pda(Buffer.from("limit_order"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(orderId));
+      const limitEscrow = pda(Buffer.from("limit_escrow"), limitOrder.toBuffer());
+      await program.methods
+        .placeLimitOrder(
+          new anchor.BN(1_000_000),
+          new anchor.BN(1),
+          expiry,
+          true,
+          new anchor.BN(orderId),
+          new anchor.BN(0)
+        )
+        .accounts({
+          pool: fx.pool,
+          limitOrder,
+          inputMint: fx.tokenA,
+          limitEscrow,
+          userTokenIn: fx.userTokenA,
+          destination: fx.userTokenB,
+          user: fx.owner,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        })
+        .rpc({ commitment: "confirmed" });
+      return { limitOrder, limitEscrow };
+    };
+
+    // Fill: the order PDA moves its escrow into the pool, the pool PDA pays the output
+    const filled = await placeOrder(0);
+    const outBefore = await balance(fx, fx.userTokenB);
+    await program.methods
+      .fillLimitOrder()
       .accounts({
-        pool: poolPda,
-        authority: payerPubkey,
+        pool: fx.pool,
+        lpMint: fx.lpMint,
+        reserveA: fx.reserveA,
+        reserveB: fx.reserveB,
+        limitOrder: filled.limitOrder,
+        limitEscrow: filled.limitEscrow,
+        destination: fx.userTokenB,
+        owner: fx.owner,
+        treasuryTokenAccountA: fx.treasuryA,
+        treasuryTokenAccountB: fx.treasuryB,
+        poolStats: fx.poolStats,
+        priceObservations: fx.priceObservations,
+        cranker: fx.owner,
+        tokenProgram: TOKEN_PROGRAM_ID,
       })
-      .rpc();
-    await connection.confirmTransaction(txPause, "confirmed");
+      .rpc({ commitment: "confirmed" });
+    assert.ok(await balance(fx, fx.userTokenB) > outBefore, "fill should pay the output to the destination");
+    assert.equal(await fx.connection.getAccountInfo(filled.limitEscrow), null, "escrow should close on fill");
 
-    const poolAfterPause = (await program.account.pool.fetch(poolPda)) as any;
-    assert.equal(poolAfterPause.paused, true, "Pool should be paused after pause()");
+    // Cancel: the order PDA refunds its escrow
+    const cancelled = await placeOrder(1);
+    const inBefore = await balance(fx, fx.userTokenA);
+    await program.methods
+      .cancelLimitOrder()
+      .accounts({
+        limitOrder: cancelled.limitOrder,
+        limitEscrow: cancelled.limitEscrow,
+        ownerTokenIn: fx.userTokenA,
+        owner: fx.owner,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+    assert.equal(await balance(fx, fx.userTokenA), inBefore + 1_000_000n, "cancel should refund the escrow");
+  }).timeout(120_000);
+});
 
-    // 8) Unpause the pool
-    const txUnpause = await program.methods
-      .unpause()
+// A claim voucher is an ed25519 signature by the position owner over
+// `domain || program || vesting_stake || relayer || destination || nonce || expires_at`, checked in the
+// instruction right before the claim. Positions can't mature on a live validator, so a genuine voucher is
+// shown to get past the voucher check and stop at maturity, while forged or re-targeted ones never do.
+describe("vesting_locked_amm - claim voucher", () => {
+  const voucherMessage = (
+    vestingStake: PublicKey,
+    relayer: PublicKey,
+    destination: PublicKey,
+    nonce: number,
+    expiresAt: number
+  ) => {
+    const expires = Buffer.alloc(8);
+    expires.writeBigInt64LE(BigInt(expiresAt));
+    return Buffer.concat([
+      Buffer.from("vesting_locked_amm:claim_voucher"),
+      program.programId.toBuffer(),
+      vestingStake.toBuffer(),
+      relayer.toBuffer(),
+      destination.toBuffer(),
+      u64Le(nonce),
+      expires,
+    ]);
+  };
+
+  it("refuses forged vouchers and vouchers replayed to another destination or nonce", async () => {
+    const fx = await createPoolFixture({ protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 }, 10_000_000_000n);
+    const rewardVault = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.owner, Keypair.generate());
+
+    const userVestingIndex = pda(Buffer.from("user_vesting_index"), fx.pool.toBuffer(), fx.owner.toBuffer());
+    await program.methods
+      .initUserVestingIndex()
+      .accounts({ pool: fx.pool, userVestingIndex, user: fx.owner, systemProgram: SystemProgram.programId })
+      .rpc({ commitment: "confirmed" });
+
+    const depositId = 0;
+    const vestingStake = pda(Buffer.from("vesting"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+    const vestingVault = pda(Buffer.from("vesting_vault"), fx.pool.toBuffer(), fx.owner.toBuffer(), u64Le(depositId));
+    await program.methods
+      .depositAndVest(
+        new anchor.BN(2_000_000_000),
+        new anchor.BN(1_000_000_000),
+        new anchor.BN(90 * 24 * 60 * 60),
+        Array(32).fill(0),
+        new anchor.BN(depositId)
+      )
+      .accounts({
+        pool: fx.pool,
+        lpMint: fx.lpMint,
+        reserveA: fx.reserveA,
+        reserveB: fx.reserveB,
+        user: fx.owner,
+        userTokenA: fx.userTokenA,
+        userTokenB: fx.userTokenB,
+        userVestingIndex,
+        vestingStake,
+        vestingTokenAccount: vestingVault,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenAMint: fx.tokenA,
+        tokenBMint: fx.tokenB,
+        rentPayer: fx.owner,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    // The wallet relays its own vouchers here; the relayer is still bound into the signed message
+    const relayer = fx.owner;
+    const otherDestination = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.owner, Keypair.generate());
+    const expiresAt = Math.floor(Date.now() / 1000) + 3_600;
+    const relay = (signer: Keypair, signed: { destination: PublicKey; nonce: number }, sent: typeof signed) =>
+      program.methods
+        .claimVestedWithVoucher(new anchor.BN(sent.nonce), new anchor.BN(expiresAt))
+        .accounts({
+          pool: fx.pool,
+          lpMint: fx.lpMint,
+          vestingStake,
+          vestingTokenAccount: vestingVault,
+          user: fx.owner,
+          destination: sent.destination,
+          rewardVault,
+          usedVoucher: pda(Buffer.from("used_voucher"), fx.owner.toBuffer(), u64Le(sent.nonce)),
+          relayer,
+          rentPayer: fx.owner,
+          instructionsSysvar: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([
+          anchor.web3.Ed25519Program.createInstructionWithPrivateKey({
+            privateKey: signer.secretKey,
+            message: voucherMessage(vestingStake, relayer, signed.destination, signed.nonce, expiresAt),
+          }),
+        ])
+        .rpc({ commitment: "confirmed" });
+
+    const voucher = { destination: fx.userLp, nonce: 0 };
+    // Forged: a valid signature, but not by the position owner
+    await assert.rejects(relay(Keypair.generate(), voucher, voucher), errorCode("InvalidVoucher"));
+    // Replayed: the owner's voucher re-pointed at another LP account, or sent under another nonce
+    await assert.rejects(
+      relay(fx.payer, voucher, { ...voucher, destination: otherDestination }),
+      errorCode("InvalidVoucher")
+    );
+    await assert.rejects(relay(fx.payer, voucher, { ...voucher, nonce: 1 }), errorCode("InvalidVoucher"));
+    // Genuine: accepted by the voucher check, then held back only by maturity, so the nonce stays unused
+    await assert.rejects(relay(fx.payer, voucher, voucher), errorCode("VestingNotFinished"));
+    const usedVoucher = pda(Buffer.from("used_voucher"), fx.owner.toBuffer(), u64Le(voucher.nonce));
+    assert.equal(await fx.connection.getAccountInfo(usedVoucher), null, "a failed claim must not burn the nonce");
+  }).timeout(120_000);
+});
+
+// Only LP locked in vesting positions can claim emissions, so they are spread over `total_locked_lp`
+// rather than the LP supply; otherwise unlocked LP would dilute every position and strand the rest.
+describe("vesting_locked_amm - reward emissions", () => {
+  it("accrues emissions over locked LP only while unlocked LP is outstanding", async () => {
+    const fx = await createPoolFixture({ protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 }, 10_000_000_000n);
+    const rewardVault = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.owner, Keypair.generate());
+
+    // Position 0 stays locked; position 1 is exited at once, leaving its LP unlocked in the wallet
+    await openPosition(fx, rewardVault, 0);
+    const exited = await openPosition(fx, rewardVault, 1);
+    await program.methods
+      .earlyUnvest(new anchor.BN((await balance(fx, exited.vestingVault)).toString()))
       .accounts({
-        pool: poolPda,
-        authority: payerPubkey,
+        pool: fx.pool,
+        protocolConfig: fx.protocolConfig,
+        lpMint: fx.lpMint,
+        vestingStake: exited.vestingStake,
+        vestingTokenAccount: exited.vestingVault,
+        userLpTokenAccount: fx.userLp,
+        treasuryLpAccount: fx.treasuryLp,
+        user: fx.owner,
+        rewardVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        rentPayer: fx.owner,
       })
-      .rpc();
-    await connection.confirmTransaction(txUnpause, "confirmed");
+      .rpc({ commitment: "confirmed" });
+    assert.ok(await balance(fx, fx.userLp) > 0n, "the exited position should leave unlocked LP outstanding");
 
-    const poolAfterUnpause = (await program.account.pool.fetch(poolPda)) as any;
-    assert.equal(poolAfterUnpause.paused, false, "Pool should be unpaused after unpause()");
+    const rate = 1_000;
+    const setEmission = (perSecond: number) =>
+      program.methods
+        .setRewardEmission(new anchor.BN(perSecond))
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint, operator: fx.owner, protocolConfig: fx.protocolConfig })
+        .rpc({ commitment: "confirmed" });
+    await setEmission(rate);
+    const before = await program.account.pool.fetch(fx.pool);
+    await sleep(3_000);
+    await setEmission(0);
+    const after = await program.account.pool.fetch(fx.pool);
 
-    console.log("initialize -> pause -> unpause test passed");
-  }).timeout(90_000);
+    const locked = bigint(before.totalLockedLp);
+    const supply = (await getMint(fx.connection, fx.lpMint)).supply;
+    assert.ok(locked > 0n && locked < supply, "part of the LP supply should be unlocked");
+    const elapsed = bigint(after.lastRewardUpdateTs) - bigint(before.lastRewardUpdateTs);
+    assert.ok(elapsed > 0n, "the emission window should span at least one second");
+    assert.equal(
+      bigint(after.accRewardPerLp) - bigint(before.accRewardPerLp),
+      (BigInt(rate) * elapsed * REWARD_SCALE) / locked,
+      "emissions should be spread over the locked LP, not the LP supply"
+    );
+  }).timeout(120_000);
+});
+
+// With epoch rewards on, emissions stop crediting the accumulator as they accrue and `finalize_reward_epoch`
+// pays the unaccounted part of the epoch in one step, at the rate in force over the LP locked at finalization:
+// what continuous accrual credits at a constant rate and locked LP. Epochs last a day, so on a live validator
+// two identical pools emit side by side from the same instant and the epoch pool is shown to leave the window
+// owed, with nothing credited or dropped, for exactly the amount the continuous pool accrued.
+describe("vesting_locked_amm - epoch reward settlement", () => {
+  it("leaves the epoch's emission owed for the amount continuous accrual credits", async () => {
+    const fees = { protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 };
+    const continuous = await createPoolFixture(fees, 10_000_000_000n);
+    const epoch = await createPoolFixture(fees, 10_000_000_000n);
+    for (const fx of [continuous, epoch]) {
+      const rewardVault = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.owner, Keypair.generate());
+      await openPosition(fx, rewardVault, 0);
+    }
+
+    const rate = 1_000;
+    const setEmission = (fx: PoolFixture, perSecond: number) =>
+      program.methods
+        .setRewardEmission(new anchor.BN(perSecond))
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint, operator: fx.owner, protocolConfig: fx.protocolConfig })
+        .instruction();
+    const rewardLedger = pda(Buffer.from("reward_ledger"), epoch.pool.toBuffer());
+    // One transaction, so both pools start emitting at the same clock
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        await setEmission(continuous, rate),
+        await setEmission(epoch, rate),
+        await program.methods
+          .enableEpochRewards()
+          .accounts({
+            pool: epoch.pool,
+            lpMint: epoch.lpMint,
+            rewardLedger,
+            authority: epoch.owner,
+            systemProgram: SystemProgram.programId,
+          })
+          .instruction()
+      ),
+      [],
+      { commitment: "confirmed" }
+    );
+    const continuousBefore = await program.account.pool.fetch(continuous.pool);
+    const epochBefore = await program.account.pool.fetch(epoch.pool);
+    assert.equal(bigint(epochBefore.lastRewardUpdateTs), bigint(continuousBefore.lastRewardUpdateTs));
+    assert.equal(bigint(epochBefore.totalLockedLp), bigint(continuousBefore.totalLockedLp));
+
+    await sleep(3_000);
+    await provider.sendAndConfirm(new anchor.web3.Transaction().add(await setEmission(continuous, 0)), [], {
+      commitment: "confirmed",
+    });
+    await assert.rejects(
+      program.methods
+        .finalizeRewardEpoch()
+        .accounts({ pool: epoch.pool, rewardLedger })
+        .rpc({ commitment: "confirmed" }),
+      errorCode("EpochNotEnded")
+    );
+    const continuousAfter = await program.account.pool.fetch(continuous.pool);
+    const epochAfter = await program.account.pool.fetch(epoch.pool);
+    const ledger = await program.account.rewardLedger.fetch(rewardLedger);
+
+    const start = bigint(epochBefore.lastRewardUpdateTs);
+    const end = bigint(continuousAfter.lastRewardUpdateTs);
+    assert.ok(end > start, "the emission window should span at least one second");
+    assert.equal(bigint(ledger.startEpoch), start / 86_400n);
+    assert.equal(bigint(ledger.lastFinalizedEpoch), bigint(ledger.startEpoch) - 1n);
+    // Nothing credited during the epoch, and the checkpoint still marks the start of the unpaid window
+    assert.equal(bigint(epochAfter.accRewardPerLp), bigint(epochBefore.accRewardPerLp));
+    assert.equal(bigint(epochAfter.lastRewardUpdateTs), start);
+
+    // finalize_reward_epoch's settlement of that window: (epoch_end - last_reward_update_ts) at the current
+    // rate over the locked LP, here cut at the continuous pool's checkpoint
+    assert.equal(bigint(epochAfter.rewardEmissionPerSecond), BigInt(rate));
+    const settled = ((end - bigint(epochAfter.lastRewardUpdateTs)) * BigInt(rate) * REWARD_SCALE) /
+      bigint(epochAfter.totalLockedLp);
+    assert.equal(
+      settled,
+      bigint(continuousAfter.accRewardPerLp) - bigint(continuousBefore.accRewardPerLp),
+      "epoch settlement should credit what continuous accrual credited over the same window"
+    );
+  }).timeout(180_000);
+});
+
+// `rebase_reward_accumulator` lowers the accumulator by the lowest entry point among the pool's positions and
+// re-expresses every debt against it. Two positions of 1 LP each at the protocol's maximum emission rate push
+// the accumulator past ACC_REWARD_REBASE_THRESHOLD within seconds; each harvests at a different point first so
+// neither entry point is zero and the rebase actually moves the accumulator.
+describe("vesting_locked_amm - reward accumulator rebase", () => {
+  it("lowers the accumulator and leaves every position's pending reward unchanged", async () => {
+    const fx = await createPoolFixture({ protocolFeeBps: 30, treasuryFeeBps: 10, rewardFeeBps: 5 }, 10_000_000_000n);
+    // Pool-owned, so harvests can be paid from it
+    const rewardVault = await createAccount(fx.connection, fx.payer, fx.lpMint, fx.pool, Keypair.generate());
+    const positions = [await openPosition(fx, rewardVault, 0), await openPosition(fx, rewardVault, 1)];
+    for (const { vestingStake, vestingVault } of positions) {
+      await program.methods
+        .earlyUnvest(new anchor.BN(((await balance(fx, vestingVault)) - 1n).toString()))
+        .accounts({
+          pool: fx.pool,
+          protocolConfig: fx.protocolConfig,
+          lpMint: fx.lpMint,
+          vestingStake,
+          vestingTokenAccount: vestingVault,
+          userLpTokenAccount: fx.userLp,
+          treasuryLpAccount: fx.treasuryLp,
+          user: fx.owner,
+          rewardVault,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          rentPayer: fx.owner,
+        })
+        .rpc({ commitment: "confirmed" });
+    }
+    assert.equal(bigint((await program.account.pool.fetch(fx.pool)).totalLockedLp), 2n);
+    await transfer(fx.connection, fx.payer, fx.userLp, rewardVault, fx.payer, 100_000_000n);
+
+    const setEmission = (perSecond: number) =>
+      program.methods
+        .setRewardEmission(new anchor.BN(perSecond))
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint, operator: fx.owner, protocolConfig: fx.protocolConfig })
+        .rpc({ commitment: "confirmed" });
+    const harvest = (vestingStake: PublicKey) =>
+      program.methods
+        .harvestRewards()
+        .accounts({
+          pool: fx.pool,
+          lpMint: fx.lpMint,
+          vestingStake,
+          user: fx.owner,
+          rewardVault,
+          destination: fx.userLp,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+    // 1_000_000 / s over 2 LP adds 5e17 per second: the threshold (2^63) is passed after ~18 s and the cap
+    // (2^64 + 1), past which a whole update is skipped, only after ~37 s
+    await setEmission(1_000_000);
+    await sleep(5_000);
+    await harvest(positions[0].vestingStake);
+    await sleep(3_000);
+    await harvest(positions[1].vestingStake);
+    await sleep(12_000);
+    await setEmission(0);
+
+    const threshold = (2n ** 128n - 1n) / (2n ** 64n - 1n) / 2n;
+    const before = await program.account.pool.fetch(fx.pool);
+    const acc = bigint(before.accRewardPerLp);
+    assert.ok(acc >= threshold, `the accumulator should have reached the rebase threshold, got ${acc}`);
+
+    const pending = async () => {
+      const values = [];
+      for (const { vestingStake } of positions) {
+        const value = await program.methods
+          .getPositionValue()
+          .accounts({
+            pool: fx.pool,
+            protocolConfig: fx.protocolConfig,
+            lpMint: fx.lpMint,
+            reserveA: fx.reserveA,
+            reserveB: fx.reserveB,
+            vestingStake,
+          })
+          .view();
+        values.push(bigint(value.pendingReward));
+      }
+      return values;
+    };
+    const pendingBefore = await pending();
+    assert.ok(pendingBefore.every((p) => p > 0n), "both positions should have rewards pending");
+    const stakes = await Promise.all(positions.map((p) => program.account.vestingStake.fetch(p.vestingStake)));
+    const entryPoints = stakes.map((stake) => (bigint(stake.rewardDebt) * REWARD_SCALE) / bigint(stake.amount));
+    const base = entryPoints.reduce((a, b) => (a < b ? a : b));
+    assert.ok(base > 0n, "both positions should have settled at a non-zero entry point");
+
+    const rebase = (stakeKeys: PublicKey[]) =>
+      program.methods
+        .rebaseRewardAccumulator()
+        .accounts({ pool: fx.pool, lpMint: fx.lpMint })
+        .remainingAccounts(stakeKeys.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false })))
+        .rpc({ commitment: "confirmed" });
+    // Every open position must be passed, or a skipped one would keep a debt against the old scale
+    await assert.rejects(rebase([positions[0].vestingStake]), errorCode("RebaseIncomplete"));
+    await rebase(positions.map((p) => p.vestingStake));
+
+    const after = await program.account.pool.fetch(fx.pool);
+    assert.equal(bigint(after.accRewardPerLp), acc - base, "the accumulator should drop by the lowest entry point");
+    assert.deepStrictEqual(await pending(), pendingBefore, "a rebase must not change any position's pending reward");
+  }).timeout(180_000);
 });